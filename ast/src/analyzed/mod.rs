@@ -177,12 +177,28 @@ impl<T> Analyzed<T> {
         .flatten()
         .collect();
 
-        // We assume for now that intermediate columns are not removed.
-        for (poly, _) in self.intermediate_columns.values() {
-            let poly_id: PolyID = poly.into();
-            assert!(!to_remove.contains(&poly_id));
-            replacements.insert(poly_id, poly_id);
-        }
+        // Intermediate columns are never arrays and their IDs are contiguous.
+        let mut intermediate_ids = self
+            .intermediate_columns
+            .values()
+            .map(|(poly, _)| PolyID::from(poly))
+            .collect::<Vec<_>>();
+        intermediate_ids.sort();
+        replacements.extend(
+            intermediate_ids
+                .into_iter()
+                .filter(|poly_id| !to_remove.contains(poly_id))
+                .enumerate()
+                .map(|(id, poly_id)| {
+                    (
+                        poly_id,
+                        PolyID {
+                            id: id as u64,
+                            ..poly_id
+                        },
+                    )
+                }),
+        );
 
         let mut names_to_remove: HashSet<String> = Default::default();
         self.definitions.retain(|name, (poly, _def)| {
@@ -195,6 +211,14 @@ impl<T> Analyzed<T> {
                 true
             }
         });
+        self.intermediate_columns.retain(|name, (poly, _def)| {
+            if to_remove.contains(&(poly as &Symbol).into()) {
+                names_to_remove.insert(name.clone());
+                false
+            } else {
+                true
+            }
+        });
         self.source_order.retain(|s| {
             if let StatementIdentifier::Definition(name) = s {
                 if names_to_remove.contains(name) {
@@ -210,6 +234,17 @@ impl<T> Analyzed<T> {
                 poly.id = replacements[&poly_id].id;
             }
         });
+        self.intermediate_columns
+            .values_mut()
+            .for_each(|(poly, _def)| {
+                poly.id = replacements[&PolyID::from(poly as &Symbol)].id;
+            });
+        self.public_declarations.values_mut().for_each(|decl| {
+            decl.polynomial.poly_id = decl.polynomial.poly_id.map(|poly_id| {
+                assert!(!to_remove.contains(&poly_id));
+                replacements[&poly_id]
+            });
+        });
         let visitor = &mut |expr: &mut Expression<_>| {
             if let Expression::Reference(Reference::Poly(poly)) = expr {
                 poly.poly_id = poly.poly_id.map(|poly_id| {
@@ -229,6 +264,9 @@ impl<T> Analyzed<T> {
     }
 
     /// Adds a polynomial identity and returns the ID.
+    /// The ID is one more than the largest ID of all existing identities.
+    /// The identity is appended to the source order by its index in `identities`,
+    /// which is how the source order refers to identities.
     pub fn append_polynomial_identity(
         &mut self,
         identity: AlgebraicExpression<T>,
@@ -241,6 +279,7 @@ impl<T> Analyzed<T> {
             .max()
             .unwrap_or_default()
             + 1;
+        let index = self.identities.len();
         self.identities.push(Identity {
             id,
             kind: IdentityKind::Polynomial,
@@ -254,11 +293,75 @@ impl<T> Analyzed<T> {
                 expressions: vec![],
             },
         });
-        self.source_order
-            .push(StatementIdentifier::Identity(id as usize));
+        self.source_order.push(StatementIdentifier::Identity(index));
         id
     }
 
+    /// Adds a new (non-array) witness column with the given absolute name
+    /// and returns a reference to it.
+    pub fn append_witness_column(&mut self, name: String, source: SourceRef) -> AlgebraicReference {
+        assert!(
+            !self.definitions.contains_key(&name) && !self.intermediate_columns.contains_key(&name),
+            "Symbol {name} already defined."
+        );
+        let poly_id = PolyID {
+            id: self.commitment_count() as u64,
+            ptype: PolynomialType::Committed,
+        };
+        let symbol = Symbol {
+            id: poly_id.id,
+            source,
+            absolute_name: name.clone(),
+            kind: SymbolKind::Poly(PolynomialType::Committed),
+            length: None,
+        };
+        self.definitions.insert(name.clone(), (symbol, None));
+        self.source_order
+            .push(StatementIdentifier::Definition(name.clone()));
+        AlgebraicReference {
+            name,
+            poly_id,
+            next: false,
+        }
+    }
+
+    /// Adds a new (non-array) intermediate column with the given absolute name and definition
+    /// at position `source_order_index` in the source order and returns a reference to it.
+    pub fn insert_intermediate_column(
+        &mut self,
+        source_order_index: usize,
+        name: String,
+        source: SourceRef,
+        definition: AlgebraicExpression<T>,
+    ) -> AlgebraicReference {
+        assert!(
+            !self.definitions.contains_key(&name) && !self.intermediate_columns.contains_key(&name),
+            "Symbol {name} already defined."
+        );
+        let poly_id = PolyID {
+            id: self.intermediate_count() as u64,
+            ptype: PolynomialType::Intermediate,
+        };
+        let symbol = Symbol {
+            id: poly_id.id,
+            source,
+            absolute_name: name.clone(),
+            kind: SymbolKind::Poly(PolynomialType::Intermediate),
+            length: None,
+        };
+        self.intermediate_columns
+            .insert(name.clone(), (symbol, definition));
+        self.source_order.insert(
+            source_order_index,
+            StatementIdentifier::Definition(name.clone()),
+        );
+        AlgebraicReference {
+            name,
+            poly_id,
+            next: false,
+        }
+    }
+
     /// Remove some identities by their index (not their ID).
     /// Does not re-allocate IDs.
     pub fn remove_identities(&mut self, to_remove: &BTreeSet<usize>) {
@@ -468,7 +571,7 @@ pub enum SymbolKind {
     Other(),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FunctionValueDefinition<T> {
     Array(Vec<RepeatedArray<T>>),
     Query(Expression<T>),
@@ -476,7 +579,7 @@ pub enum FunctionValueDefinition<T> {
 }
 
 /// An array of elements that might be repeated.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RepeatedArray<T> {
     /// The pattern to be repeated
     pattern: Vec<Expression<T>>,
@@ -583,7 +686,7 @@ impl<T> SelectedExpressions<AlgebraicExpression<T>> {
 
 pub type Expression<T> = parsed::Expression<T, Reference>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Reference {
     LocalVar(u64, String),
     Poly(PolynomialReference),
@@ -650,7 +753,7 @@ pub enum AlgebraicExpression<T> {
     UnaryOperation(AlgebraicUnaryOperator, Box<AlgebraicExpression<T>>),
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub enum AlgebraicBinaryOperator {
     Add,
     Sub,
@@ -686,7 +789,7 @@ impl TryFrom<BinaryOperator> for AlgebraicBinaryOperator {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub enum AlgebraicUnaryOperator {
     Minus,
 }
@@ -773,7 +876,7 @@ impl<T> From<T> for AlgebraicExpression<T> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PolynomialReference {
     /// Name of the polynomial - just for informational purposes.
     /// Comparisons are based on polynomial ID.
@@ -92,7 +92,7 @@ impl<T: FieldElement> BackendImpl<T> for Halo2Mock {
     }
 
     fn verify(&self, _proof: &Proof, _instances: &[Vec<T>]) -> Result<(), Error> {
-        unimplemented!("Halo2Mock backend does not yet support separate proof verification");
+        Err(Error::NoVerificationAvailable)
     }
 
     fn prove(
@@ -2,6 +2,7 @@
 
 #[cfg(feature = "halo2")]
 mod halo2_impl;
+mod mock;
 mod pilstark;
 
 use powdr_ast::analyzed::Analyzed;
@@ -21,6 +22,8 @@ pub enum BackendType {
     EStark,
     #[strum(serialize = "pil-stark-cli")]
     PilStarkCli,
+    #[strum(serialize = "mock")]
+    Mock,
 }
 
 impl BackendType {
@@ -35,6 +38,7 @@ impl BackendType {
             WithoutSetupFactory(PhantomData);
         const PIL_STARK_CLI_FACTORY: WithoutSetupFactory<pilstark::PilStarkCli> =
             WithoutSetupFactory(PhantomData);
+        const MOCK_FACTORY: WithoutSetupFactory<mock::Mock> = WithoutSetupFactory(PhantomData);
 
         match self {
             #[cfg(feature = "halo2")]
@@ -43,6 +47,20 @@ impl BackendType {
             BackendType::Halo2Mock => &HALO2_MOCK_FACTORY,
             BackendType::PilStarkCli => &PIL_STARK_CLI_FACTORY,
             BackendType::EStark => &ESTARK_FACTORY,
+            BackendType::Mock => &MOCK_FACTORY,
+        }
+    }
+
+    /// @returns the maximum degree of constraints the backend supports,
+    /// or None if there is no limit.
+    pub fn max_constraint_degree(&self) -> Option<usize> {
+        match self {
+            // pil-stark splits constraints of a higher degree into its own
+            // intermediate polynomials.
+            BackendType::EStark | BackendType::PilStarkCli => None,
+            #[cfg(feature = "halo2")]
+            BackendType::Halo2 | BackendType::Halo2Mock => None,
+            BackendType::Mock => None,
         }
     }
 }
@@ -169,6 +187,8 @@ pub enum Error {
     EmptyWitness,
     #[error("the backend has no setup operations")]
     NoSetupAvailable,
+    #[error("the backend does not support separate proof verification")]
+    NoVerificationAvailable,
     #[error("proof generation failed")]
     ProofFailed(String),
     #[error("proof verification failed")]
@@ -179,6 +199,24 @@ pub enum Error {
 
 pub type Proof = Vec<u8>;
 
+/// Evaluates the public declarations of `pil` on the witness, in source order.
+pub fn public_values<T: FieldElement>(
+    pil: &Analyzed<T>,
+    witness: &[(String, Vec<T>)],
+) -> Vec<(String, T)> {
+    pil.public_declarations_in_source_order()
+        .into_iter()
+        .map(|(name, public_declaration)| {
+            let column = public_declaration.referenced_poly_name();
+            let (_, values) = witness
+                .iter()
+                .find(|(witness_name, _)| *witness_name == column)
+                .unwrap_or_else(|| panic!("Column {column} of public {name} not found."));
+            (name.clone(), values[public_declaration.index as usize])
+        })
+        .collect()
+}
+
 /*
     Bellow are the public interface traits. They are implemented in this
     module, wrapping the traits implemented by each backend.
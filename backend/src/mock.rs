@@ -0,0 +1,87 @@
+use powdr_ast::analyzed::Analyzed;
+use powdr_executor::constraint_checker::ConstraintChecker;
+use powdr_number::{DegreeType, FieldElement};
+
+use crate::{public_values, BackendImpl, Error, Proof};
+
+/// A backend that does not generate a proof, but checks all identities
+/// directly on the fixed and witness columns. Works for any field.
+/// Its "proof" only contains the public values, so that verification can
+/// check the public values it is given.
+pub struct Mock;
+
+impl<T: FieldElement> BackendImpl<T> for Mock {
+    fn new(_degree: DegreeType) -> Self {
+        Self
+    }
+
+    fn add_verification_key(
+        &mut self,
+        _pil: &Analyzed<T>,
+        _fixed: &[(String, Vec<T>)],
+        _vkey: Vec<u8>,
+    ) {
+        // The mock backend does not use verification keys.
+    }
+
+    fn verification_key(
+        &self,
+        _pil: &Analyzed<T>,
+        _fixed: &[(String, Vec<T>)],
+    ) -> Result<Vec<u8>, Error> {
+        Err(Error::VerificationKeyFailed(
+            "Mock backend does not have verification keys".to_string(),
+        ))
+    }
+
+    fn verify(&self, proof: &Proof, instances: &[Vec<T>]) -> Result<(), Error> {
+        let publics: Vec<String> = serde_json::from_slice(proof)
+            .map_err(|e| Error::VerificationFailed(format!("Invalid mock proof: {e}")))?;
+        let publics = publics
+            .iter()
+            .map(|value| value.parse())
+            .collect::<Result<Vec<T>, _>>()
+            .map_err(Error::VerificationFailed)?;
+        match instances {
+            [instance] if *instance == publics => Ok(()),
+            _ => Err(Error::VerificationFailed(format!(
+                "The public values {instances:?} do not match the proven public values {publics:?}."
+            ))),
+        }
+    }
+
+    fn prove(
+        &self,
+        pil: &Analyzed<T>,
+        fixed: &[(String, Vec<T>)],
+        witness: &[(String, Vec<T>)],
+        prev_proof: Option<Proof>,
+    ) -> Result<(Proof, Option<String>), Error> {
+        if prev_proof.is_some() {
+            unimplemented!("Mock backend does not support aggregation");
+        }
+        if witness.is_empty() {
+            return Err(Error::ProofFailed(
+                "Mock backend needs a complete witness".to_string(),
+            ));
+        }
+
+        let checker = ConstraintChecker::new(pil, fixed, witness).map_err(Error::ProofFailed)?;
+        let failures = checker.check().map_err(Error::ProofFailed)?;
+        if failures.is_empty() {
+            log::info!("All {} identities are satisfied.", pil.identities.len());
+            let publics = public_values(pil, witness)
+                .into_iter()
+                .map(|(_, value)| value.to_string())
+                .collect::<Vec<_>>();
+            Ok((serde_json::to_vec(&publics).unwrap(), None))
+        } else {
+            Err(Error::ProofFailed(format!(
+                "{} of {} identities are not satisfied:\n{}",
+                failures.len(),
+                pil.identities.len(),
+                checker.report(&failures)
+            )))
+        }
+    }
+}
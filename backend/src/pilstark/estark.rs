@@ -66,7 +66,7 @@ impl<F: FieldElement> BackendImpl<F> for EStark {
     }
 
     fn verify(&self, _proof: &crate::Proof, _instances: &[Vec<F>]) -> Result<(), Error> {
-        unimplemented!("eSTARK backend does not yet support separate proof verification");
+        Err(Error::NoVerificationAvailable)
     }
 
     fn prove(
@@ -30,7 +30,7 @@ impl<T: FieldElement> BackendImpl<T> for PilStarkCli {
     }
 
     fn verify(&self, _proof: &Proof, _instances: &[Vec<T>]) -> Result<(), Error> {
-        unimplemented!("pil-stark CLI backend does not yet support separate proof verification");
+        Err(Error::NoVerificationAvailable)
     }
 
     fn prove(
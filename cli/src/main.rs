@@ -2,16 +2,17 @@
 
 mod util;
 
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use env_logger::fmt::Color;
 use env_logger::{Builder, Target};
 use log::LevelFilter;
 use powdr_backend::{Backend, BackendType};
 use powdr_number::{read_polys_csv_file, CsvRenderMode};
 use powdr_number::{Bn254Field, FieldElement, GoldilocksField};
-use powdr_pipeline::{Pipeline, Stage};
+use powdr_pipeline::{CheckpointSettings, OptLevel, OptimizerPass, Pipeline, Stage};
 use powdr_riscv::continuations::{rust_continuations, rust_continuations_dry_run};
-use powdr_riscv::{compile_riscv_asm, compile_rust};
+use powdr_riscv::{compile_riscv_asm, compile_riscv_elf, compile_rust};
+use powdr_riscv_executor::{GuestExit, InstructionHooks};
 use std::io::{self, BufReader, BufWriter, Read};
 use std::path::PathBuf;
 use std::{borrow::Cow, fs, io::Write, path::Path};
@@ -28,6 +29,8 @@ fn bind_cli_args<F: FieldElement>(
     witness_values: Option<String>,
     export_csv: bool,
     csv_mode: CsvRenderModeCLI,
+    optimizer_passes: Vec<OptimizerPass>,
+    witgen: WitgenArgs,
 ) -> Pipeline<F> {
     let witness_values = witness_values
         .map(|csv_path| {
@@ -48,6 +51,10 @@ fn bind_cli_args<F: FieldElement>(
         .add_external_witness_values(witness_values.clone())
         .with_witness_csv_settings(export_csv, csv_mode)
         .with_prover_inputs(inputs.clone())
+        .with_optimizer_passes(optimizer_passes)
+        .with_witgen_checkpoints(witgen.checkpoints())
+        .with_witgen_profile(witgen.witgen_profile)
+        .with_witgen_resume(witgen.witgen_resume)
 }
 
 #[derive(Clone, EnumString, EnumVariantNames, Display)]
@@ -68,6 +75,168 @@ pub enum CsvRenderModeCLI {
     Hex,
 }
 
+#[derive(Clone, Copy, EnumString, EnumVariantNames, Display)]
+pub enum StatsStage {
+    #[strum(serialize = "analyzed")]
+    Analyzed,
+    #[strum(serialize = "optimized")]
+    Optimized,
+}
+
+#[derive(Clone, Copy, EnumString, EnumVariantNames, Display)]
+pub enum StatsFormat {
+    #[strum(serialize = "table")]
+    Table,
+    #[strum(serialize = "json")]
+    Json,
+}
+
+/// Arguments that select the optimizer passes.
+#[derive(Args)]
+struct OptimizerArgs {
+    /// The optimization level: 0 (none), 1 (remove constant columns and simplify)
+    /// or 2 (all optimizer passes, including degree reduction and common subexpression
+    /// extraction for the backends that need them).
+    #[arg(long)]
+    #[arg(default_value_t = OptLevel::Basic)]
+    #[arg(value_parser = clap_enum_variants!(OptLevel))]
+    opt_level: OptLevel,
+
+    /// Comma-separated list of optimizer passes to run, in order.
+    /// Overrides the optimization level.
+    #[arg(long)]
+    #[arg(value_delimiter = ',')]
+    #[arg(value_parser = clap_enum_variants!(OptimizerPass))]
+    passes: Option<Vec<OptimizerPass>>,
+}
+
+impl OptimizerArgs {
+    fn passes(self) -> Vec<OptimizerPass> {
+        self.passes.unwrap_or_else(|| self.opt_level.passes())
+    }
+}
+
+/// Arguments of the commands that run RISC-V programs.
+#[derive(Args)]
+struct RiscvArgs {
+    /// The field to use
+    #[arg(long)]
+    #[arg(default_value_t = FieldArgument::Gl)]
+    #[arg(value_parser = clap_enum_variants!(FieldArgument))]
+    field: FieldArgument,
+
+    /// Comma-separated list of free inputs (numbers).
+    #[arg(short, long)]
+    #[arg(default_value_t = String::new())]
+    inputs: String,
+
+    /// Directory for output files.
+    #[arg(short, long)]
+    #[arg(default_value_t = String::from("."))]
+    output_directory: String,
+
+    /// Force overwriting of files in output directory.
+    #[arg(short, long)]
+    #[arg(default_value_t = false)]
+    force: bool,
+
+    /// Generate a proof with a given backend.
+    #[arg(short, long)]
+    #[arg(value_parser = clap_enum_variants!(BackendType))]
+    prove_with: Option<BackendType>,
+
+    /// Generate a CSV file containing the fixed and witness column values. Useful for debugging purposes.
+    #[arg(long)]
+    #[arg(default_value_t = false)]
+    export_csv: bool,
+
+    /// How to render field elements in the csv file
+    #[arg(long)]
+    #[arg(default_value_t = CsvRenderModeCLI::Hex)]
+    #[arg(value_parser = clap_enum_variants!(CsvRenderModeCLI))]
+    csv_mode: CsvRenderModeCLI,
+
+    /// Comma-separated list of coprocessors. Entries ending in `.json` are
+    /// files declaring custom coprocessors.
+    #[arg(long)]
+    coprocessors: Option<String>,
+
+    /// Just execute in the RISCV/Powdr executor
+    #[arg(short, long)]
+    #[arg(default_value_t = false)]
+    just_execute: bool,
+
+    /// Run a long execution in chunks (Experimental and not sound!)
+    #[arg(short, long)]
+    #[arg(default_value_t = false)]
+    continuations: bool,
+
+    /// Solve the main machine row by row instead of taking the register
+    /// and pc columns from the execution trace of the RISC-V executor.
+    /// This is also done if the executor cannot run all co-processors.
+    #[arg(long)]
+    #[arg(default_value_t = false)]
+    no_trace_witgen: bool,
+
+    #[command(flatten)]
+    optimizer: OptimizerArgs,
+
+    #[command(flatten)]
+    witgen: WitgenArgs,
+}
+
+impl RiscvArgs {
+    fn coprocessors(&self) -> Result<powdr_riscv::CoProcessors, Vec<String>> {
+        match &self.coprocessors {
+            Some(list) => powdr_riscv::CoProcessors::try_from(list.split(',').collect::<Vec<_>>())
+                .map_err(|e| vec![e]),
+            None => Ok(powdr_riscv::CoProcessors::base()),
+        }
+    }
+
+    /// Whether the program can only be run in the RISCV executor, which has to
+    /// know how to execute the instructions of all coprocessors.
+    fn uses_executor(&self) -> bool {
+        self.just_execute || self.continuations
+    }
+}
+
+/// Arguments that control witness generation.
+#[derive(Args)]
+struct WitgenArgs {
+    /// Write a profile of the time spent in each machine during witness generation
+    /// to this file: Chrome Trace Event JSON (viewable in Perfetto) if the file
+    /// ends in `.json`, folded stacks for flamegraph tools otherwise.
+    #[arg(long)]
+    witgen_profile: Option<PathBuf>,
+
+    /// Periodically write the state of witness generation to this file,
+    /// so that it can be resumed with `--witgen-resume`.
+    #[arg(long)]
+    witgen_checkpoint: Option<PathBuf>,
+
+    /// Number of rows of the main machine between two checkpoints.
+    #[arg(long)]
+    #[arg(default_value_t = 100000)]
+    witgen_checkpoint_interval: u64,
+
+    /// Resume witness generation from a checkpoint file written by a previous
+    /// run with the same program and inputs.
+    #[arg(long)]
+    witgen_resume: Option<PathBuf>,
+}
+
+impl WitgenArgs {
+    fn checkpoints(&self) -> Option<CheckpointSettings> {
+        self.witgen_checkpoint
+            .clone()
+            .map(|path| CheckpointSettings {
+                path,
+                interval: self.witgen_checkpoint_interval,
+            })
+    }
+}
+
 #[derive(Parser)]
 #[command(name = "powdr", author, version, about, long_about = None)]
 struct Cli {
@@ -138,6 +307,12 @@ enum Commands {
         #[arg(short, long)]
         #[arg(default_value_t = false)]
         continuations: bool,
+
+        #[command(flatten)]
+        optimizer: OptimizerArgs,
+
+        #[command(flatten)]
+        witgen: WitgenArgs,
     },
     /// Compiles (no-std) rust code to riscv assembly, then to powdr assembly
     /// and finally to PIL and generates fixed and witness columns.
@@ -146,56 +321,8 @@ enum Commands {
         /// Input file (rust source file) or directory (containing a crate).
         file: String,
 
-        /// The field to use
-        #[arg(long)]
-        #[arg(default_value_t = FieldArgument::Gl)]
-        #[arg(value_parser = clap_enum_variants!(FieldArgument))]
-        field: FieldArgument,
-
-        /// Comma-separated list of free inputs (numbers).
-        #[arg(short, long)]
-        #[arg(default_value_t = String::new())]
-        inputs: String,
-
-        /// Directory for  output files.
-        #[arg(short, long)]
-        #[arg(default_value_t = String::from("."))]
-        output_directory: String,
-
-        /// Force overwriting of files in output directory.
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        force: bool,
-
-        /// Generate a proof with a given backend
-        #[arg(short, long)]
-        #[arg(value_parser = clap_enum_variants!(BackendType))]
-        prove_with: Option<BackendType>,
-
-        /// Generate a CSV file containing the fixed and witness column values. Useful for debugging purposes.
-        #[arg(long)]
-        #[arg(default_value_t = false)]
-        export_csv: bool,
-
-        /// How to render field elements in the csv file
-        #[arg(long)]
-        #[arg(default_value_t = CsvRenderModeCLI::Hex)]
-        #[arg(value_parser = clap_enum_variants!(CsvRenderModeCLI))]
-        csv_mode: CsvRenderModeCLI,
-
-        /// Comma-separated list of coprocessors.
-        #[arg(long)]
-        coprocessors: Option<String>,
-
-        /// Just execute in the RISCV/Powdr executor
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        just_execute: bool,
-
-        /// Run a long execution in chunks (Experimental and not sound!)
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        continuations: bool,
+        #[command(flatten)]
+        riscv: RiscvArgs,
     },
 
     /// Compiles riscv assembly to powdr assembly and then to PIL
@@ -205,56 +332,18 @@ enum Commands {
         #[arg(required = true)]
         files: Vec<String>,
 
-        /// The field to use
-        #[arg(long)]
-        #[arg(default_value_t = FieldArgument::Gl)]
-        #[arg(value_parser = clap_enum_variants!(FieldArgument))]
-        field: FieldArgument,
-
-        /// Comma-separated list of free inputs (numbers).
-        #[arg(short, long)]
-        #[arg(default_value_t = String::new())]
-        inputs: String,
-
-        /// Directory for output files.
-        #[arg(short, long)]
-        #[arg(default_value_t = String::from("."))]
-        output_directory: String,
-
-        /// Force overwriting of files in output directory.
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        force: bool,
-
-        /// Generate a proof with a given backend.
-        #[arg(short, long)]
-        #[arg(value_parser = clap_enum_variants!(BackendType))]
-        prove_with: Option<BackendType>,
-
-        /// Generate a CSV file containing the fixed and witness column values. Useful for debugging purposes.
-        #[arg(long)]
-        #[arg(default_value_t = false)]
-        export_csv: bool,
-
-        /// How to render field elements in the csv file
-        #[arg(long)]
-        #[arg(default_value_t = CsvRenderModeCLI::Hex)]
-        #[arg(value_parser = clap_enum_variants!(CsvRenderModeCLI))]
-        csv_mode: CsvRenderModeCLI,
-
-        /// Comma-separated list of coprocessors.
-        #[arg(long)]
-        coprocessors: Option<String>,
+        #[command(flatten)]
+        riscv: RiscvArgs,
+    },
 
-        /// Just execute in the RISCV/Powdr executor
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        just_execute: bool,
+    /// Compiles a statically linked RISC-V ELF executable to powdr assembly
+    /// and then to PIL and generates fixed and witness columns.
+    Elf {
+        /// Input file
+        file: String,
 
-        /// Run a long execution in chunks (Experimental and not sound!)
-        #[arg(short, long)]
-        #[arg(default_value_t = false)]
-        continuations: bool,
+        #[command(flatten)]
+        riscv: RiscvArgs,
     },
 
     Prove {
@@ -290,6 +379,24 @@ enum Commands {
         params: Option<String>,
     },
 
+    /// Checks all identities of a PIL file on previously generated
+    /// fixed and witness column data files, independently of a backend.
+    Check {
+        /// Input PIL file
+        file: String,
+
+        /// Directory to find the committed and fixed values
+        #[arg(short, long)]
+        #[arg(default_value_t = String::from("."))]
+        dir: String,
+
+        /// The field to use
+        #[arg(long)]
+        #[arg(default_value_t = FieldArgument::Gl)]
+        #[arg(value_parser = clap_enum_variants!(FieldArgument))]
+        field: FieldArgument,
+    },
+
     Verify {
         /// Input PIL file
         file: String,
@@ -316,11 +423,15 @@ enum Commands {
 
         /// File containing the verification ley.
         #[arg(long)]
-        vkey: String,
+        vkey: Option<String>,
 
         /// File containing the params.
         #[arg(long)]
         params: Option<String>,
+
+        /// File containing the public values, as written next to the proof.
+        #[arg(long)]
+        publics: Option<String>,
     },
 
     VerificationKey {
@@ -386,6 +497,42 @@ enum Commands {
         #[arg(default_value_t = FieldArgument::Gl)]
         #[arg(value_parser = clap_enum_variants!(FieldArgument))]
         field: FieldArgument,
+
+        #[command(flatten)]
+        optimizer: OptimizerArgs,
+    },
+
+    /// Prints statistics about the columns and constraints of a .pil or .asm file.
+    Stats {
+        /// Input file
+        file: String,
+
+        /// The field to use
+        #[arg(long)]
+        #[arg(default_value_t = FieldArgument::Gl)]
+        #[arg(value_parser = clap_enum_variants!(FieldArgument))]
+        field: FieldArgument,
+
+        /// The pipeline stage after which the statistics are computed.
+        #[arg(long)]
+        #[arg(default_value_t = StatsStage::Optimized)]
+        #[arg(value_parser = clap_enum_variants!(StatsStage))]
+        stage: StatsStage,
+
+        /// Apply the backend-specific optimizations of the given backend,
+        /// which are only run with `--opt-level 2`.
+        #[arg(short, long)]
+        #[arg(value_parser = clap_enum_variants!(BackendType))]
+        backend: Option<BackendType>,
+
+        /// The output format.
+        #[arg(long)]
+        #[arg(default_value_t = StatsFormat::Table)]
+        #[arg(value_parser = clap_enum_variants!(StatsFormat))]
+        format: StatsFormat,
+
+        #[command(flatten)]
+        optimizer: OptimizerArgs,
     },
 }
 
@@ -441,79 +588,23 @@ fn main() -> Result<(), io::Error> {
 #[allow(clippy::print_stderr)]
 fn run_command(command: Commands) {
     let result = match command {
-        Commands::Rust {
-            file,
-            field,
-            inputs,
-            output_directory,
-            force,
-            prove_with,
-            export_csv,
-            csv_mode,
-            coprocessors,
-            just_execute,
-            continuations,
-        } => {
-            let coprocessors = match coprocessors {
-                Some(list) => {
-                    powdr_riscv::CoProcessors::try_from(list.split(',').collect::<Vec<_>>())
-                        .unwrap()
-                }
-                None => powdr_riscv::CoProcessors::base(),
-            };
-            call_with_field!(run_rust::<field>(
-                &file,
-                split_inputs(&inputs),
-                Path::new(&output_directory),
-                force,
-                prove_with,
-                export_csv,
-                csv_mode,
-                coprocessors,
-                just_execute,
-                continuations
-            ))
+        Commands::Rust { file, riscv } => {
+            let field = riscv.field.clone();
+            call_with_field!(run_rust::<field>(&file, riscv))
         }
-        Commands::RiscvAsm {
-            files,
-            field,
-            inputs,
-            output_directory,
-            force,
-            prove_with,
-            export_csv,
-            csv_mode,
-            coprocessors,
-            just_execute,
-            continuations,
-        } => {
+        Commands::RiscvAsm { files, riscv } => {
             assert!(!files.is_empty());
             let name = if files.len() == 1 {
                 Cow::Owned(files[0].clone())
             } else {
                 Cow::Borrowed("output")
             };
-
-            let coprocessors = match coprocessors {
-                Some(list) => {
-                    powdr_riscv::CoProcessors::try_from(list.split(',').collect::<Vec<_>>())
-                        .unwrap()
-                }
-                None => powdr_riscv::CoProcessors::base(),
-            };
-            call_with_field!(run_riscv_asm::<field>(
-                &name,
-                files.into_iter(),
-                split_inputs(&inputs),
-                Path::new(&output_directory),
-                force,
-                prove_with,
-                export_csv,
-                csv_mode,
-                coprocessors,
-                just_execute,
-                continuations
-            ))
+            let field = riscv.field.clone();
+            call_with_field!(run_riscv_asm::<field>(&name, files.into_iter(), riscv))
+        }
+        Commands::Elf { file, riscv } => {
+            let field = riscv.field.clone();
+            call_with_field!(run_elf::<field>(&file, riscv))
         }
         Commands::Reformat { file } => {
             let contents = fs::read_to_string(&file).unwrap();
@@ -523,10 +614,32 @@ fn run_command(command: Commands) {
             };
             Ok(())
         }
-        Commands::OptimizePIL { file, field } => {
-            call_with_field!(optimize_and_output::<field>(&file));
+        Commands::OptimizePIL {
+            file,
+            field,
+            optimizer,
+        } => {
+            let optimizer_passes = optimizer.passes();
+            call_with_field!(optimize_and_output::<field>(&file, optimizer_passes));
             Ok(())
         }
+        Commands::Stats {
+            file,
+            field,
+            stage,
+            backend,
+            format,
+            optimizer,
+        } => {
+            let optimizer_passes = optimizer.passes();
+            call_with_field!(print_stats::<field>(
+                &file,
+                stage,
+                backend,
+                format,
+                optimizer_passes
+            ))
+        }
         Commands::Pil {
             file,
             field,
@@ -539,7 +652,10 @@ fn run_command(command: Commands) {
             csv_mode,
             just_execute,
             continuations,
+            optimizer,
+            witgen,
         } => {
+            let optimizer_passes = optimizer.passes();
             call_with_field!(run_pil::<field>(
                 file,
                 output_directory,
@@ -550,7 +666,9 @@ fn run_command(command: Commands) {
                 export_csv,
                 csv_mode,
                 just_execute,
-                continuations
+                continuations,
+                optimizer_passes,
+                witgen
             ))
         }
         Commands::Prove {
@@ -568,6 +686,11 @@ fn run_command(command: Commands) {
                 pil, dir, &backend, proof, vkey, params
             ))
         }
+        Commands::Check { file, dir, field } => {
+            let pil = Path::new(&file);
+            let dir = Path::new(&dir);
+            call_with_field!(read_and_check::<field>(pil, dir))
+        }
         Commands::Verify {
             file,
             dir,
@@ -576,11 +699,12 @@ fn run_command(command: Commands) {
             proof,
             params,
             vkey,
+            publics,
         } => {
             let pil = Path::new(&file);
             let dir = Path::new(&dir);
             call_with_field!(read_and_verify::<field>(
-                pil, dir, &backend, proof, params, vkey
+                pil, dir, &backend, proof, params, vkey, publics
             ))
         }
         Commands::VerificationKey {
@@ -653,85 +777,108 @@ fn write_backend_to_fs<F: FieldElement>(be: &dyn Backend<F>, output_dir: &Path)
     log::info!("Wrote params.bin.");
 }
 
-#[allow(clippy::too_many_arguments)]
-fn run_rust<F: FieldElement>(
-    file_name: &str,
-    inputs: Vec<F>,
-    output_dir: &Path,
-    force_overwrite: bool,
-    prove_with: Option<BackendType>,
-    export_csv: bool,
-    csv_mode: CsvRenderModeCLI,
-    coprocessors: powdr_riscv::CoProcessors,
-    just_execute: bool,
-    continuations: bool,
-) -> Result<(), Vec<String>> {
+fn run_rust<F: FieldElement>(file_name: &str, riscv: RiscvArgs) -> Result<(), Vec<String>> {
+    let coprocessors = riscv.coprocessors()?;
     let (asm_file_path, asm_contents) = compile_rust(
         file_name,
-        output_dir,
-        force_overwrite,
+        Path::new(&riscv.output_directory),
+        riscv.force,
         &coprocessors,
-        continuations,
+        riscv.continuations,
     )
     .ok_or_else(|| vec!["could not compile rust".to_string()])?;
-
-    let pipeline = Pipeline::<F>::default().from_asm_string(
-        asm_contents.clone(),
-        Some(PathBuf::from(asm_file_path.to_str().unwrap())),
-    );
-
-    let pipeline = bind_cli_args(
-        pipeline,
-        inputs.clone(),
-        output_dir.to_path_buf(),
-        force_overwrite,
-        None,
-        export_csv,
-        csv_mode,
-    );
-    run(pipeline, inputs, prove_with, just_execute, continuations)?;
-    Ok(())
+    run_riscv::<F>(asm_file_path, asm_contents, &coprocessors, riscv)
 }
 
-#[allow(clippy::too_many_arguments)]
 fn run_riscv_asm<F: FieldElement>(
     original_file_name: &str,
     file_names: impl Iterator<Item = String>,
-    inputs: Vec<F>,
-    output_dir: &Path,
-    force_overwrite: bool,
-    prove_with: Option<BackendType>,
-    export_csv: bool,
-    csv_mode: CsvRenderModeCLI,
-    coprocessors: powdr_riscv::CoProcessors,
-    just_execute: bool,
-    continuations: bool,
+    riscv: RiscvArgs,
 ) -> Result<(), Vec<String>> {
+    let coprocessors = riscv.coprocessors()?;
     let (asm_file_path, asm_contents) = compile_riscv_asm(
         original_file_name,
         file_names,
-        output_dir,
-        force_overwrite,
+        Path::new(&riscv.output_directory),
+        riscv.force,
         &coprocessors,
-        continuations,
+        riscv.continuations,
     )
     .ok_or_else(|| vec!["could not compile RISC-V assembly".to_string()])?;
+    run_riscv::<F>(asm_file_path, asm_contents, &coprocessors, riscv)
+}
 
-    let pipeline = Pipeline::<F>::default().from_asm_string(
-        asm_contents.clone(),
-        Some(PathBuf::from(asm_file_path.to_str().unwrap())),
-    );
+fn run_elf<F: FieldElement>(file_name: &str, riscv: RiscvArgs) -> Result<(), Vec<String>> {
+    let coprocessors = riscv.coprocessors()?;
+    let (asm_file_path, asm_contents) = compile_riscv_elf(
+        file_name,
+        Path::new(&riscv.output_directory),
+        riscv.force,
+        &coprocessors,
+        riscv.continuations,
+    )
+    .ok_or_else(|| vec!["could not compile RISC-V ELF file".to_string()])?;
+    run_riscv::<F>(asm_file_path, asm_contents, &coprocessors, riscv)
+}
 
+/// Runs the powdr assembly that a RISC-V program was compiled to.
+fn run_riscv<F: FieldElement>(
+    asm_file_path: PathBuf,
+    asm_contents: String,
+    coprocessors: &powdr_riscv::CoProcessors,
+    riscv: RiscvArgs,
+) -> Result<(), Vec<String>> {
+    if riscv.uses_executor() {
+        coprocessors.check_executable().map_err(|e| {
+            vec![
+                e,
+                "Add them through the library API with an executor hook, or run without \
+                 --just-execute and --continuations."
+                    .to_string(),
+            ]
+        })?;
+    }
+    let hooks = coprocessors.instruction_hooks();
+    let inputs = split_inputs::<F>(&riscv.inputs);
     let pipeline = bind_cli_args(
-        pipeline,
+        Pipeline::<F>::default().from_asm_string(asm_contents, Some(asm_file_path)),
         inputs.clone(),
-        output_dir.to_path_buf(),
-        force_overwrite,
+        PathBuf::from(&riscv.output_directory),
+        riscv.force,
         None,
-        export_csv,
-        csv_mode,
+        riscv.export_csv,
+        riscv.csv_mode,
+        riscv.optimizer.passes(),
+        riscv.witgen,
     );
-    run(pipeline, inputs, prove_with, just_execute, continuations)?;
+    // Check how the guest exits before witness generation. With --just-execute
+    // and --continuations, `run` executes the guest anyway.
+    let pipeline = if riscv.just_execute || riscv.continuations {
+        pipeline
+    } else if coprocessors.check_executable().is_err() {
+        log::warn!(
+            "The guest is not executed before witness generation and the main machine is \
+             solved row by row, because the RISCV executor cannot run all co-processors."
+        );
+        pipeline
+    } else if !riscv.no_trace_witgen {
+        let (pipeline, exit) =
+            powdr_riscv::trace_witgen::with_execution_trace_and_exit(pipeline, &hooks)?;
+        exit_if_guest_failed(exit.as_ref());
+        pipeline
+    } else {
+        let (pipeline, exit) = powdr_riscv::trace_witgen::guest_exit(pipeline, &hooks)?;
+        exit_if_guest_failed(exit.as_ref());
+        pipeline
+    };
+    run(
+        pipeline,
+        inputs,
+        &hooks,
+        riscv.prove_with,
+        riscv.just_execute,
+        riscv.continuations,
+    )?;
     Ok(())
 }
 
@@ -747,6 +894,8 @@ fn run_pil<F: FieldElement>(
     csv_mode: CsvRenderModeCLI,
     just_execute: bool,
     continuations: bool,
+    optimizer_passes: Vec<OptimizerPass>,
+    witgen: WitgenArgs,
 ) -> Result<(), Vec<String>> {
     let inputs = split_inputs::<F>(&inputs);
 
@@ -758,28 +907,48 @@ fn run_pil<F: FieldElement>(
         witness_values,
         export_csv,
         csv_mode,
+        optimizer_passes,
+        witgen,
     );
-    run(pipeline, inputs, prove_with, just_execute, continuations)?;
+    run(
+        pipeline,
+        inputs,
+        &Default::default(),
+        prove_with,
+        just_execute,
+        continuations,
+    )?;
     Ok(())
 }
 
 fn run<F: FieldElement>(
     mut pipeline: Pipeline<F>,
     inputs: Vec<F>,
+    hooks: &InstructionHooks,
     prove_with: Option<BackendType>,
     just_execute: bool,
     continuations: bool,
 ) -> Result<(), Vec<String>> {
     let bootloader_inputs = if continuations {
         pipeline = pipeline.with_prover_inputs(inputs.clone());
-        rust_continuations_dry_run(&mut pipeline)
+        match rust_continuations_dry_run(&mut pipeline, hooks) {
+            Ok(bootloader_inputs) => bootloader_inputs,
+            Err(exit) => exit_with_guest_failure(&exit),
+        }
     } else {
         vec![]
     };
 
     let generate_witness_and_prove_maybe = |mut pipeline: Pipeline<F>| -> Result<(), Vec<String>> {
+        // The backend has to be known before optimizing, so that the constraint
+        // degree can be reduced to the maximum degree it supports.
+        if let Some(backend) = prove_with {
+            pipeline = pipeline.with_backend(backend);
+        }
         pipeline.advance_to(Stage::GeneratedWitness)?;
-        prove_with.map(|backend| pipeline.with_backend(backend).proof().unwrap());
+        if prove_with.is_some() {
+            pipeline.proof().unwrap();
+        }
         Ok(())
     };
 
@@ -791,12 +960,14 @@ fn run<F: FieldElement>(
             let mut pipeline = pipeline.with_prover_inputs(inputs);
             pipeline.advance_to(Stage::AsmString).unwrap();
             let program = pipeline.artifact().unwrap().to_asm_string().unwrap();
-            powdr_riscv_executor::execute::<F>(
+            let (trace, _) = powdr_riscv_executor::execute::<F>(
                 program,
                 pipeline.data_callback().unwrap(),
                 &[],
+                hooks,
                 powdr_riscv_executor::ExecMode::Fast,
             );
+            exit_if_guest_failed(trace.exit.as_ref());
         }
         (false, true) => {
             rust_continuations(
@@ -812,6 +983,23 @@ fn run<F: FieldElement>(
     Ok(())
 }
 
+/// Exits with the exit code of the guest, unless it exited successfully.
+fn exit_if_guest_failed(exit: Option<&GuestExit>) {
+    if let Some(exit) = exit.filter(|exit| !exit.is_success()) {
+        exit_with_guest_failure(exit);
+    }
+}
+
+/// Exits with the exit code of the guest. A panicking guest has already printed
+/// its panic message, otherwise the exit code is printed.
+#[allow(clippy::print_stderr)]
+fn exit_with_guest_failure(exit: &GuestExit) -> ! {
+    if exit.panic_message.is_none() {
+        eprintln!("{exit}");
+    }
+    std::process::exit(exit.code as i32);
+}
+
 fn read_and_prove<T: FieldElement>(
     file: &Path,
     dir: &Path,
@@ -832,16 +1020,27 @@ fn read_and_prove<T: FieldElement>(
     Ok(())
 }
 
+#[allow(clippy::print_stdout)]
+fn read_and_check<T: FieldElement>(file: &Path, dir: &Path) -> Result<(), Vec<String>> {
+    Pipeline::<T>::default()
+        .from_file(file.to_path_buf())
+        .read_generated_witness(dir)
+        .with_backend(BackendType::Mock)
+        .proof()?;
+    println!("All identities are satisfied.");
+    Ok(())
+}
+
 fn read_and_verify<T: FieldElement>(
     file: &Path,
     dir: &Path,
     backend_type: &BackendType,
     proof: String,
     params: Option<String>,
-    vkey: String,
+    vkey: Option<String>,
+    publics: Option<String>,
 ) -> Result<(), Vec<String>> {
     let proof = Path::new(&proof);
-    let vkey = Path::new(&vkey).to_path_buf();
 
     let proof = {
         let mut buf = Vec::new();
@@ -856,30 +1055,75 @@ fn read_and_verify<T: FieldElement>(
         .from_file(file.to_path_buf())
         .read_constants(dir)
         .with_setup_file(params.map(PathBuf::from))
-        .with_vkey_file(Some(vkey))
+        .with_vkey_file(vkey.map(PathBuf::from))
         .with_backend(*backend_type);
 
-    // TODO add support for publics
-    pipeline.verify(proof, &[vec![]])?;
+    let publics = match publics {
+        Some(publics) => {
+            let publics: Vec<(String, String)> =
+                serde_json::from_str(&fs::read_to_string(publics).unwrap())
+                    .map_err(|e| vec![format!("Invalid publics file: {e}")])?;
+            publics
+                .into_iter()
+                .map(|(name, value)| {
+                    value
+                        .parse::<T>()
+                        .map_err(|e| vec![format!("Invalid value for public {name}: {e}")])
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        }
+        None => vec![],
+    };
+    pipeline.verify(proof, &[publics])?;
     println!("Proof is valid!");
 
     Ok(())
 }
 
 #[allow(clippy::print_stdout)]
-fn optimize_and_output<T: FieldElement>(file: &str) {
+fn optimize_and_output<T: FieldElement>(file: &str, optimizer_passes: Vec<OptimizerPass>) {
     println!(
         "{}",
         Pipeline::<T>::default()
             .from_file(PathBuf::from(file))
+            .with_optimizer_passes(optimizer_passes)
             .optimized_pil()
             .unwrap()
     );
 }
 
+#[allow(clippy::print_stdout)]
+fn print_stats<T: FieldElement>(
+    file: &str,
+    stage: StatsStage,
+    backend: Option<BackendType>,
+    format: StatsFormat,
+    optimizer_passes: Vec<OptimizerPass>,
+) -> Result<(), Vec<String>> {
+    let mut pipeline = Pipeline::<T>::default()
+        .from_file(PathBuf::from(file))
+        .with_optimizer_passes(optimizer_passes);
+    if let Some(backend) = backend {
+        pipeline = pipeline.with_backend(backend);
+    }
+    let pil = match stage {
+        StatsStage::Analyzed => pipeline.analyzed_pil()?,
+        StatsStage::Optimized => pipeline.optimized_pil()?,
+    };
+    let stats = powdr_pilopt::stats::circuit_stats(&pil);
+    match format {
+        StatsFormat::Table => println!("{stats}"),
+        StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats).unwrap()),
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
-    use crate::{run_command, Commands, CsvRenderModeCLI, FieldArgument};
+    use crate::{
+        run_command, Commands, CsvRenderModeCLI, FieldArgument, OptLevel, OptimizerArgs,
+        StatsFormat, StatsStage,
+    };
     use powdr_backend::BackendType;
 
     #[test]
@@ -903,8 +1147,44 @@ mod test {
             csv_mode: CsvRenderModeCLI::Hex,
             just_execute: false,
             continuations: false,
+            optimizer: OptimizerArgs {
+                opt_level: OptLevel::Full,
+                passes: None,
+            },
+            witgen: WitgenArgs {
+                witgen_profile: Some(output_dir.path().join("witgen_profile.json")),
+                witgen_checkpoint: None,
+                witgen_checkpoint_interval: 100000,
+                witgen_resume: None,
+            },
         };
         run_command(pil_command);
+        assert!(output_dir.path().join("witgen_profile.json").exists());
+
+        let file = output_dir
+            .path()
+            .join("simple_sum.pil")
+            .to_string_lossy()
+            .to_string();
+        let check_command = Commands::Check {
+            file: file.clone(),
+            dir: output_dir_str.clone(),
+            field: FieldArgument::Bn254,
+        };
+        run_command(check_command);
+
+        let stats_command = Commands::Stats {
+            file,
+            field: FieldArgument::Bn254,
+            stage: StatsStage::Optimized,
+            backend: None,
+            format: StatsFormat::Json,
+            optimizer: OptimizerArgs {
+                opt_level: OptLevel::Basic,
+                passes: None,
+            },
+        };
+        run_command(stats_command);
 
         #[cfg(feature = "halo2")]
         {
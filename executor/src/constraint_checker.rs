@@ -0,0 +1,593 @@
+//! Checks that a complete witness satisfies all identities of a PIL file,
+//! independently of any proving backend.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use itertools::Itertools;
+use num_bigint::BigUint;
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperator, AlgebraicExpression as Expression, AlgebraicReference,
+    AlgebraicUnaryOperator, Analyzed, Identity, IdentityKind, PolyID, PolynomialType,
+};
+use powdr_ast::parsed::visitor::ExpressionVisitable;
+use powdr_ast::parsed::SelectedExpressions;
+use powdr_number::{BigInt, DegreeType, FieldElement, KnownField};
+
+/// The maximum number of failing rows per identity that are included in a report.
+const MAX_REPORTED_ROWS: usize = 10;
+
+/// An identity that does not hold, together with the rows on which it fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityFailure {
+    /// The index of the identity in `Analyzed::identities`.
+    pub index: usize,
+    /// The rows on which the identity does not hold.
+    pub rows: Vec<DegreeType>,
+}
+
+/// Evaluates all identities of a PIL file on concrete fixed and witness column values.
+pub struct ConstraintChecker<'a, T> {
+    analyzed: &'a Analyzed<T>,
+    /// The identities of `analyzed`, with intermediate polynomials inlined.
+    identities: Vec<Identity<Expression<T>>>,
+    degree: DegreeType,
+    fixed: Vec<&'a [T]>,
+    witness: Vec<Vec<T>>,
+}
+
+impl<'a, T: FieldElement> ConstraintChecker<'a, T> {
+    /// Creates a new constraint checker. The fixed and witness columns are matched
+    /// to the columns of `analyzed` by name.
+    /// @returns an error if a column is unknown, missing or has the wrong length.
+    pub fn new(
+        analyzed: &'a Analyzed<T>,
+        fixed_col_values: &'a [(String, Vec<T>)],
+        witness_col_values: &[(String, Vec<T>)],
+    ) -> Result<Self, String> {
+        let degree = analyzed.degree();
+        let column_ids = column_ids(analyzed);
+
+        let mut fixed: Vec<Option<&[T]>> = vec![None; analyzed.constant_count()];
+        for (name, values) in fixed_col_values {
+            let id = column_id(&column_ids, name, PolynomialType::Constant)?;
+            check_length(name, values, degree)?;
+            fixed[id] = Some(values);
+        }
+        let mut witness = vec![None; analyzed.commitment_count()];
+        for (name, values) in witness_col_values {
+            let id = column_id(&column_ids, name, PolynomialType::Committed)?;
+            check_length(name, values, degree)?;
+            witness[id] = Some(values.clone());
+        }
+
+        let missing = column_ids
+            .iter()
+            .filter(|(_, poly_id)| match poly_id.ptype {
+                PolynomialType::Constant => fixed[poly_id.id as usize].is_none(),
+                PolynomialType::Committed => witness[poly_id.id as usize].is_none(),
+                PolynomialType::Intermediate => false,
+            })
+            .map(|(name, _)| name)
+            .sorted()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(format!("Missing columns: {}", missing.iter().format(", ")));
+        }
+
+        Ok(ConstraintChecker {
+            analyzed,
+            identities: analyzed.identities_with_inlined_intermediate_polynomials(),
+            degree,
+            fixed: fixed.into_iter().map(Option::unwrap).collect(),
+            witness: witness.into_iter().map(Option::unwrap).collect(),
+        })
+    }
+
+    pub fn degree(&self) -> DegreeType {
+        self.degree
+    }
+
+    /// @returns the identities that are checked, with intermediate polynomials inlined.
+    pub fn identities(&self) -> &[Identity<Expression<T>>] {
+        &self.identities
+    }
+
+    /// @returns the value of a witness column at a given row.
+    pub fn witness_value(&self, poly_id: &PolyID, row: DegreeType) -> T {
+        assert_eq!(poly_id.ptype, PolynomialType::Committed);
+        self.witness[poly_id.id as usize][row as usize]
+    }
+
+    /// Sets the value of a witness column at a given row.
+    pub fn set_witness_value(&mut self, poly_id: &PolyID, row: DegreeType, value: T) {
+        assert_eq!(poly_id.ptype, PolynomialType::Committed);
+        self.witness[poly_id.id as usize][row as usize] = value;
+    }
+
+    /// Checks all identities on all rows.
+    /// @returns the failing identities, in the order of `Analyzed::identities`, or an error
+    /// if an identity cannot be checked.
+    pub fn check(&self) -> Result<Vec<IdentityFailure>, String> {
+        (0..self.identities.len())
+            .filter_map(|index| match self.check_identity(index) {
+                Ok(rows) => (!rows.is_empty()).then_some(Ok(IdentityFailure { index, rows })),
+                Err(e) => Some(Err(e)),
+            })
+            .collect()
+    }
+
+    /// Checks a single identity on all rows.
+    /// @returns the rows on which the identity fails, in ascending order, or an error
+    /// if the identity cannot be checked.
+    pub fn check_identity(&self, index: usize) -> Result<Vec<DegreeType>, String> {
+        let identity = &self.identities[index];
+        Ok(match identity.kind {
+            IdentityKind::Polynomial => self.check_polynomial_identity(identity, 0..self.degree),
+            IdentityKind::Plookup => self.check_plookup(identity),
+            IdentityKind::Permutation => self.check_permutation(identity),
+            IdentityKind::Connect => self.check_connect(identity)?,
+        })
+    }
+
+    /// Checks a polynomial identity only on the given rows.
+    /// @returns the rows on which the identity fails.
+    pub fn check_polynomial_identity(
+        &self,
+        identity: &Identity<Expression<T>>,
+        rows: impl IntoIterator<Item = DegreeType>,
+    ) -> Vec<DegreeType> {
+        let expression = identity.expression_for_poly_id();
+        rows.into_iter()
+            .filter(|row| !self.evaluate(expression, *row).is_zero())
+            .collect()
+    }
+
+    fn check_plookup(&self, identity: &Identity<Expression<T>>) -> Vec<DegreeType> {
+        let rhs = self
+            .selected_tuples(&identity.right)
+            .map(|(_, tuple)| tuple)
+            .collect::<BTreeSet<_>>();
+        self.selected_tuples(&identity.left)
+            .filter(|(_, tuple)| !rhs.contains(tuple))
+            .map(|(row, _)| row)
+            .collect()
+    }
+
+    /// Checks that the selected tuples on both sides are equal as multisets.
+    /// @returns the rows (on either side) that could not be matched.
+    fn check_permutation(&self, identity: &Identity<Expression<T>>) -> Vec<DegreeType> {
+        let mut rhs: HashMap<Vec<T>, Vec<DegreeType>> = HashMap::new();
+        for (row, tuple) in self.selected_tuples(&identity.right) {
+            rhs.entry(tuple).or_default().push(row);
+        }
+        let mut failing_rows = self
+            .selected_tuples(&identity.left)
+            .filter(|(_, tuple)| rhs.get_mut(tuple).and_then(|rows| rows.pop()).is_none())
+            .map(|(row, _)| row)
+            .collect::<BTreeSet<_>>();
+        failing_rows.extend(rhs.into_values().flatten());
+        failing_rows.into_iter().collect()
+    }
+
+    /// Checks a connect identity (copy constraints), using the encoding of pil-stark:
+    /// The cell in column `i` and row `j` is identified by `k^i * omega^j`, where `omega` is
+    /// a primitive root of unity of order `degree` and `k` generates a coset of the group of
+    /// roots of unity. The fixed columns on the right hand side contain, for each cell of
+    /// the left hand side, the identifier of the cell it is connected to.
+    /// This encoding is only defined for the Goldilocks field and degrees that are powers
+    /// of two, other connect identities cannot be checked.
+    /// @returns the rows on which a cell differs from the cell it is connected to.
+    fn check_connect(&self, identity: &Identity<Expression<T>>) -> Result<Vec<DegreeType>, String> {
+        let width = identity.left.expressions.len();
+        assert_eq!(width, identity.right.expressions.len());
+        let Some((k, omega)) = connect_encoding::<T>(self.degree) else {
+            return Err(format!(
+                "Cannot check connect identity {identity}: Connect identities can only be \
+                 checked over the Goldilocks field and for a degree that is a power of two."
+            ));
+        };
+        let mut cells = HashMap::new();
+        let mut column_shift = T::one();
+        for column in 0..width {
+            let mut label = column_shift;
+            for row in 0..self.degree {
+                cells.insert(label, (column, row));
+                label = label * omega;
+            }
+            column_shift = column_shift * k;
+        }
+
+        let left = &identity.left.expressions;
+        Ok((0..self.degree)
+            .filter(|row| {
+                left.iter()
+                    .zip(&identity.right.expressions)
+                    .any(|(value, connection)| {
+                        let connection = self.evaluate(connection, *row);
+                        match cells.get(&connection) {
+                            Some((column, other_row)) => {
+                                self.evaluate(value, *row)
+                                    != self.evaluate(&left[*column], *other_row)
+                            }
+                            None => true,
+                        }
+                    })
+            })
+            .collect())
+    }
+
+    /// @returns the row and the values of the expressions for every row on which the selector
+    /// is non-zero.
+    fn selected_tuples<'b>(
+        &'b self,
+        selected: &'b SelectedExpressions<Expression<T>>,
+    ) -> impl Iterator<Item = (DegreeType, Vec<T>)> + 'b {
+        (0..self.degree).filter_map(move |row| {
+            let selected_row = selected
+                .selector
+                .as_ref()
+                .map_or(true, |s| !self.evaluate(s, row).is_zero());
+            selected_row.then(|| {
+                (
+                    row,
+                    selected
+                        .expressions
+                        .iter()
+                        .map(|e| self.evaluate(e, row))
+                        .collect(),
+                )
+            })
+        })
+    }
+
+    /// Evaluates an expression on a given row.
+    pub fn evaluate(&self, expr: &Expression<T>, row: DegreeType) -> T {
+        match expr {
+            Expression::Reference(poly) => self.value(poly, row),
+            Expression::PublicReference(name) => {
+                let public = &self.analyzed.public_declarations[name];
+                let poly_id = public.polynomial.poly_id.unwrap();
+                let id = poly_id.id + public.array_index.unwrap_or_default() as u64;
+                self.column(&PolyID { id, ..poly_id })[public.index as usize]
+            }
+            Expression::Number(n) => *n,
+            Expression::BinaryOperation(left, op, right) => {
+                let left = self.evaluate(left, row);
+                let right = self.evaluate(right, row);
+                match op {
+                    AlgebraicBinaryOperator::Add => left + right,
+                    AlgebraicBinaryOperator::Sub => left - right,
+                    AlgebraicBinaryOperator::Mul => left * right,
+                    AlgebraicBinaryOperator::Pow => left.pow(right.to_integer()),
+                }
+            }
+            Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, e) => -self.evaluate(e, row),
+        }
+    }
+
+    /// @returns the value of a fixed or witness column reference, taking the "next" flag
+    /// into account (wrapping around at the end).
+    pub fn value(&self, poly: &AlgebraicReference, row: DegreeType) -> T {
+        let row = if poly.next {
+            (row + 1) % self.degree
+        } else {
+            row
+        };
+        self.column(&poly.poly_id)[row as usize]
+    }
+
+    fn column(&self, poly_id: &PolyID) -> &[T] {
+        match poly_id.ptype {
+            PolynomialType::Committed => &self.witness[poly_id.id as usize],
+            PolynomialType::Constant => self.fixed[poly_id.id as usize],
+            PolynomialType::Intermediate => {
+                panic!("Intermediate polynomials should have been inlined.")
+            }
+        }
+    }
+}
+
+impl<'a, T: FieldElement> ConstraintChecker<'a, T> {
+    /// @returns the names and values of all columns referenced by an identity on the given row.
+    /// Columns referenced with the "next" operator are suffixed by `'`.
+    pub fn referenced_values(&self, index: usize, row: DegreeType) -> Vec<(String, T)> {
+        let mut references = BTreeSet::new();
+        self.identities[index].pre_visit_expressions(&mut |e| {
+            if let Expression::Reference(poly) = e {
+                references.insert(poly.clone());
+            }
+        });
+        references
+            .into_iter()
+            .map(|poly| {
+                let name = if poly.next {
+                    format!("{}'", poly.name)
+                } else {
+                    poly.name.clone()
+                };
+                (name, self.value(&poly, row))
+            })
+            .collect()
+    }
+
+    /// Formats a human-readable report of the given failures, including the values of the
+    /// referenced columns for the first few failing rows of each identity.
+    pub fn report(&self, failures: &[IdentityFailure]) -> String {
+        let mut report = String::new();
+        for failure in failures {
+            let identity = &self.analyzed.identities[failure.index];
+            writeln!(
+                report,
+                "Identity fails on {} row(s): {identity}",
+                failure.rows.len()
+            )
+            .unwrap();
+            let source = &identity.source;
+            writeln!(
+                report,
+                "    (defined at {}:{}:{})",
+                source.file.as_deref().unwrap_or("<unknown>"),
+                source.line,
+                source.col
+            )
+            .unwrap();
+            for row in failure.rows.iter().take(MAX_REPORTED_ROWS) {
+                writeln!(
+                    report,
+                    "    Row {row}: {}",
+                    self.referenced_values(failure.index, *row)
+                        .iter()
+                        .map(|(name, value)| format!("{name} = {value}"))
+                        .format(", ")
+                )
+                .unwrap();
+            }
+            if failure.rows.len() > MAX_REPORTED_ROWS {
+                writeln!(
+                    report,
+                    "    ... and {} more row(s)",
+                    failure.rows.len() - MAX_REPORTED_ROWS
+                )
+                .unwrap();
+            }
+        }
+        report
+    }
+}
+
+/// @returns the coset shift `k` and the root of unity `omega` of order `degree`
+/// used by pil-stark to encode connect identities, if they are known for this field.
+fn connect_encoding<T: FieldElement>(degree: DegreeType) -> Option<(T, T)> {
+    let (generator, k) = match T::known_field()? {
+        KnownField::GoldilocksField => (7u64, 12275445934081160404u64),
+        KnownField::Bn254Field => return None,
+    };
+    if !degree.is_power_of_two() {
+        return None;
+    }
+    let exponent = (T::modulus().to_arbitrary_integer() - 1u32) / BigUint::from(degree);
+    let omega = T::from(generator).pow(exponent.try_into().unwrap());
+    Some((T::from(k), omega))
+}
+
+/// @returns a map from column name (including array elements) to poly ID.
+fn column_ids<T>(analyzed: &Analyzed<T>) -> HashMap<String, PolyID> {
+    analyzed
+        .committed_polys_in_source_order()
+        .into_iter()
+        .chain(analyzed.constant_polys_in_source_order())
+        .flat_map(|(symbol, _)| symbol.array_elements())
+        .collect()
+}
+
+fn column_id(
+    column_ids: &HashMap<String, PolyID>,
+    name: &str,
+    ptype: PolynomialType,
+) -> Result<usize, String> {
+    match column_ids.get(name) {
+        Some(poly_id) if poly_id.ptype == ptype => Ok(poly_id.id as usize),
+        _ => Err(format!("Column {name} is not a {ptype} column.")),
+    }
+}
+
+fn check_length<T>(name: &str, values: &[T], degree: DegreeType) -> Result<(), String> {
+    if values.len() as DegreeType == degree {
+        Ok(())
+    } else {
+        Err(format!(
+            "Column {name} has {} rows instead of {degree}.",
+            values.len()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::{Bn254Field, GoldilocksField};
+    use powdr_pil_analyzer::analyze_string;
+
+    use crate::constant_evaluator::generate;
+
+    use super::*;
+
+    fn check(src: &str, witness: Vec<(&str, Vec<u64>)>) -> Vec<IdentityFailure> {
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let fixed = generate(&analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect::<Vec<_>>();
+        let witness = witness
+            .into_iter()
+            .map(|(n, v)| (n.to_string(), v.into_iter().map(Into::into).collect()))
+            .collect::<Vec<_>>();
+        ConstraintChecker::new(&analyzed, &fixed, &witness)
+            .unwrap()
+            .check()
+            .unwrap()
+    }
+
+    const FIBONACCI: &str = r#"
+        namespace Fibonacci(4);
+            col fixed ISLAST = [0, 0, 0, 1];
+            col witness x, y;
+            (1 - ISLAST) * (x' - y) = 0;
+            (1 - ISLAST) * (y' - (x + y)) = 0;
+    "#;
+
+    #[test]
+    fn polynomial_identity() {
+        let witness = vec![
+            ("Fibonacci.x", vec![1, 1, 2, 3]),
+            ("Fibonacci.y", vec![1, 2, 3, 5]),
+        ];
+        assert_eq!(check(FIBONACCI, witness), vec![]);
+
+        let witness = vec![
+            ("Fibonacci.x", vec![1, 1, 2, 3]),
+            ("Fibonacci.y", vec![1, 2, 4, 5]),
+        ];
+        assert_eq!(
+            check(FIBONACCI, witness),
+            vec![
+                IdentityFailure {
+                    index: 0,
+                    rows: vec![2]
+                },
+                IdentityFailure {
+                    index: 1,
+                    rows: vec![1, 2]
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn connect() {
+        let src = r#"
+            namespace N(4);
+                pol constant S1, S2;
+                col witness a, b;
+                { a, b } connect { S1, S2 };
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let (k, omega) = connect_encoding::<GoldilocksField>(4).unwrap();
+        let label = |column: u64, row: u64| k.pow(column.into()) * omega.pow(row.into());
+        // Connect a[1] with b[2], all other cells are only connected to themselves.
+        let s1 = vec![label(0, 0), label(1, 2), label(0, 2), label(0, 3)];
+        let s2 = vec![label(1, 0), label(1, 1), label(0, 1), label(1, 3)];
+        let fixed = vec![("N.S1".to_string(), s1), ("N.S2".to_string(), s2)];
+        let witness = |b2: u64| {
+            vec![
+                ("N.a".to_string(), [1, 7, 3, 4].map(Into::into).to_vec()),
+                ("N.b".to_string(), [5, 6, b2, 8].map(Into::into).to_vec()),
+            ]
+        };
+
+        let checker = ConstraintChecker::new(&analyzed, &fixed, &witness(7)).unwrap();
+        assert_eq!(checker.check(), Ok(vec![]));
+        let checker = ConstraintChecker::new(&analyzed, &fixed, &witness(9)).unwrap();
+        assert_eq!(
+            checker.check(),
+            Ok(vec![IdentityFailure {
+                index: 0,
+                rows: vec![1, 2]
+            }])
+        );
+    }
+
+    #[test]
+    fn connect_unknown_encoding() {
+        let src = r#"
+            namespace N(4);
+                pol constant S1;
+                col witness a;
+                { a } connect { S1 };
+        "#;
+        let analyzed = analyze_string::<Bn254Field>(src);
+        let fixed = vec![("N.S1".to_string(), [1, 2, 3, 4].map(Into::into).to_vec())];
+        let witness = vec![("N.a".to_string(), [1, 2, 3, 4].map(Into::into).to_vec())];
+        let checker = ConstraintChecker::new(&analyzed, &fixed, &witness).unwrap();
+        assert!(checker
+            .check()
+            .unwrap_err()
+            .starts_with("Cannot check connect identity"));
+    }
+
+    #[test]
+    fn report() {
+        let analyzed = analyze_string::<GoldilocksField>(FIBONACCI);
+        let fixed = generate(&analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect::<Vec<_>>();
+        let witness = [("Fibonacci.x", [1, 1, 2, 3]), ("Fibonacci.y", [1, 2, 4, 5])]
+            .map(|(n, v)| (n.to_string(), v.map(Into::into).to_vec()))
+            .to_vec();
+        let checker = ConstraintChecker::new(&analyzed, &fixed, &witness).unwrap();
+        let report = checker.report(&checker.check().unwrap()[..1]);
+        assert_eq!(
+            report.lines().filter(|l| !l.contains("defined at")).join("\n"),
+            "Identity fails on 1 row(s): ((1 - Fibonacci.ISLAST) * (Fibonacci.x' - Fibonacci.y)) = 0;\n    \
+             Row 2: Fibonacci.x' = 3, Fibonacci.ISLAST = 0, Fibonacci.y = 4"
+        );
+    }
+
+    #[test]
+    fn invalid_columns() {
+        let analyzed = analyze_string::<GoldilocksField>(FIBONACCI);
+        let fixed = generate(&analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect::<Vec<_>>();
+        let column = |name: &str, len: u64| (name.to_string(), (0..len).map(Into::into).collect());
+
+        let witness = vec![column("Fibonacci.x", 4)];
+        assert_eq!(
+            ConstraintChecker::new(&analyzed, &fixed[1..], &witness).err(),
+            Some("Missing columns: Fibonacci.ISLAST, Fibonacci.y".to_string())
+        );
+
+        let witness = vec![column("Fibonacci.x", 4), column("Fibonacci.y", 3)];
+        assert_eq!(
+            ConstraintChecker::new(&analyzed, &fixed, &witness).err(),
+            Some("Column Fibonacci.y has 3 rows instead of 4.".to_string())
+        );
+
+        let witness = vec![column("Fibonacci.z", 4)];
+        assert!(ConstraintChecker::new(&analyzed, &fixed, &witness)
+            .err()
+            .unwrap()
+            .starts_with("Column Fibonacci.z is not a"));
+    }
+
+    #[test]
+    fn plookup_and_permutation() {
+        let src = r#"
+            namespace N(4);
+                col fixed BYTE = [1, 2, 3, 4];
+                col fixed SEL = [1, 1, 0, 0];
+                col witness a, b;
+                SEL { a } in { BYTE };
+                { a } is { b };
+        "#;
+        let witness = vec![("N.a", vec![4, 1, 7, 2]), ("N.b", vec![1, 2, 4, 7])];
+        assert_eq!(check(src, witness), vec![]);
+
+        let witness = vec![("N.a", vec![5, 1, 7, 2]), ("N.b", vec![1, 2, 4, 7])];
+        assert_eq!(
+            check(src, witness),
+            vec![
+                IdentityFailure {
+                    index: 0,
+                    rows: vec![0]
+                },
+                IdentityFailure {
+                    index: 1,
+                    rows: vec![0, 2]
+                }
+            ]
+        );
+    }
+}
@@ -3,4 +3,5 @@
 #![deny(clippy::print_stdout)]
 
 pub mod constant_evaluator;
+pub mod constraint_checker;
 pub mod witgen;
@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use powdr_number::{DegreeType, FieldElement};
+
+/// Named columns of field elements that describe the state of one component
+/// (a machine or the fixed lookup indices) during witness generation.
+pub type StateColumns<T> = BTreeMap<String, Vec<T>>;
+
+/// Component name of the fixed lookup machine in a [Checkpoint].
+pub(crate) const FIXED_LOOKUP_NAME: &str = "FixedLookup";
+
+const MAGIC: &[u8; 8] = b"PWDRWGCP";
+const VERSION: u64 = 1;
+
+/// Where and how often to write checkpoints during witness generation.
+#[derive(Clone, Debug)]
+pub struct CheckpointSettings {
+    /// The file the latest checkpoint is written to.
+    pub path: PathBuf,
+    /// The number of rows of the main machine between two checkpoints.
+    pub interval: DegreeType,
+}
+
+/// A snapshot of witness generation, taken between two rows of the main machine.
+/// It contains the rows of the main machine computed so far, the state of all
+/// secondary machines and the indices of the fixed lookup machine.
+#[derive(Debug, PartialEq)]
+pub struct Checkpoint<T> {
+    /// The row of the main machine at which processing continues.
+    pub row: DegreeType,
+    /// The degree of the program the checkpoint was taken for.
+    pub degree: DegreeType,
+    /// The state of each component, by name.
+    pub components: BTreeMap<String, StateColumns<T>>,
+}
+
+impl<T: FieldElement> Checkpoint<T> {
+    /// Removes the state of the given component from the checkpoint.
+    /// Panics if the checkpoint does not contain the component.
+    pub(crate) fn take_component(&mut self, name: &str) -> StateColumns<T> {
+        self.components.remove(name).unwrap_or_else(|| {
+            panic!("Checkpoint does not contain a state for {name}. Was it taken for a different program?")
+        })
+    }
+
+    /// Writes the checkpoint to the given file. The file is replaced atomically, so that
+    /// a crash while writing does not destroy the previous checkpoint.
+    pub fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        self.write(&mut writer)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp_path, path)
+    }
+
+    pub fn read_from_file(path: &Path) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        write_u64(writer, VERSION)?;
+        write_u64(writer, T::BITS as u64)?;
+        write_u64(writer, self.row)?;
+        write_u64(writer, self.degree)?;
+        write_u64(writer, self.components.len() as u64)?;
+        for (component, columns) in &self.components {
+            write_string(writer, component)?;
+            write_u64(writer, columns.len() as u64)?;
+            for (name, values) in columns {
+                write_string(writer, name)?;
+                write_u64(writer, values.len() as u64)?;
+                for v in values {
+                    writer.write_all(&v.to_bytes_le())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u64(reader)? != VERSION {
+            return Err(invalid_data("Not a witgen checkpoint file"));
+        }
+        if read_u64(reader)? != T::BITS as u64 {
+            return Err(invalid_data(
+                "Checkpoint was taken for a different field".to_string(),
+            ));
+        }
+        let width = T::zero().to_bytes_le().len();
+        let row = read_u64(reader)?;
+        let degree = read_u64(reader)?;
+        let components = (0..read_u64(reader)?)
+            .map(|_| {
+                let component = read_string(reader)?;
+                let columns = (0..read_u64(reader)?)
+                    .map(|_| {
+                        let name = read_string(reader)?;
+                        let len = read_u64(reader)? as usize;
+                        let mut bytes = vec![0u8; len * width];
+                        reader.read_exact(&mut bytes)?;
+                        let values = bytes.chunks(width).map(T::from_bytes_le).collect();
+                        Ok((name, values))
+                    })
+                    .collect::<io::Result<_>>()?;
+                Ok((component, columns))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Checkpoint {
+            row,
+            degree,
+            components,
+        })
+    }
+}
+
+/// Removes a column from the state, panicking with a helpful message if it does not exist.
+pub(crate) fn take_column<T>(state: &mut StateColumns<T>, name: &str) -> Vec<T> {
+    state
+        .remove(name)
+        .unwrap_or_else(|| panic!("Column {name} not found in checkpoint."))
+}
+
+/// Stores rows of optional values (of the given width) as `values.<i>` and `known.<i>` columns.
+pub(crate) fn store_optional_rows<'x, T: FieldElement>(
+    state: &mut StateColumns<T>,
+    width: usize,
+    rows: impl Iterator<Item = &'x [Option<T>]>,
+) {
+    let mut values = vec![vec![]; width];
+    let mut known = vec![vec![]; width];
+    for row in rows {
+        assert_eq!(row.len(), width);
+        for (i, v) in row.iter().enumerate() {
+            values[i].push(v.unwrap_or_default());
+            known[i].push(T::from(v.is_some()));
+        }
+    }
+    for (i, (values, known)) in values.into_iter().zip(known).enumerate() {
+        state.insert(format!("values.{i}"), values);
+        state.insert(format!("known.{i}"), known);
+    }
+}
+
+/// Reads `len` rows of optional values stored by [store_optional_rows].
+pub(crate) fn take_optional_rows<T: FieldElement>(
+    state: &mut StateColumns<T>,
+    width: usize,
+    len: usize,
+) -> Vec<Vec<Option<T>>> {
+    let columns = (0..width)
+        .map(|i| {
+            let values = take_column(state, &format!("values.{i}"));
+            let known = take_column(state, &format!("known.{i}"));
+            assert_eq!(values.len(), len);
+            assert_eq!(known.len(), len);
+            (values, known)
+        })
+        .collect::<Vec<_>>();
+    (0..len)
+        .map(|row| {
+            columns
+                .iter()
+                .map(|(values, known)| (!known[row].is_zero()).then_some(values[row]))
+                .collect()
+        })
+        .collect()
+}
+
+fn write_u64(writer: &mut impl Write, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_string(writer: &mut impl Write, s: &str) -> io::Result<()> {
+    write_u64(writer, s.len() as u64)?;
+    writer.write_all(s.as_bytes())
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let mut bytes = vec![0u8; read_u64(reader)? as usize];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(invalid_data)
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::{Bn254Field, GoldilocksField};
+    use powdr_pil_analyzer::analyze_string;
+
+    use crate::constant_evaluator::generate;
+    use crate::witgen::{unused_query_callback, WitnessGenerator};
+
+    use super::*;
+
+    #[test]
+    fn write_and_read() {
+        let checkpoint = Checkpoint::<GoldilocksField> {
+            row: 7,
+            degree: 16,
+            components: [
+                (
+                    "main".to_string(),
+                    [
+                        ("main.x".to_string(), vec![1.into(), (-1).into()]),
+                        ("main.x.known".to_string(), vec![1.into(), 0.into()]),
+                    ]
+                    .into_iter()
+                    .collect(),
+                ),
+                ("empty".to_string(), Default::default()),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let mut bytes = vec![];
+        checkpoint.write(&mut bytes).unwrap();
+        assert_eq!(
+            Checkpoint::<GoldilocksField>::read(&mut bytes.as_slice()).unwrap(),
+            checkpoint
+        );
+        assert!(Checkpoint::<Bn254Field>::read(&mut bytes.as_slice()).is_err());
+        assert!(Checkpoint::<GoldilocksField>::read(&mut &bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn optional_rows() {
+        let rows: Vec<Vec<Option<GoldilocksField>>> =
+            vec![vec![Some(1.into()), None], vec![None, Some(0.into())]];
+        let mut state = StateColumns::default();
+        store_optional_rows(&mut state, 2, rows.iter().map(|r| r.as_slice()));
+        assert_eq!(take_optional_rows(&mut state, 2, 2), rows);
+        assert!(state.is_empty());
+    }
+
+    #[test]
+    fn resume() {
+        let src = r#"
+            namespace Square(256);
+                col fixed RESET(i) { if i % 2 == 1 { 1 } else { 0 } };
+                col witness x, y;
+                (1 - RESET) * (x' - x) = 0;
+                RESET * (y - x * x) = 0;
+            namespace Main(256);
+                col fixed FIRST = [1] + [0]*;
+                col fixed LAST(i) { if i == 255 { 1 } else { 0 } };
+                col fixed NTH(i) { if i % 8 == 0 { 1 } else { 0 } };
+                col fixed BYTE(i) { i & 0xff };
+                col fixed DOUBLE(i) { (i & 0xff) * 2 };
+                col witness x, d, s;
+                FIRST * x = 0;
+                (1 - LAST) * (x' - x - 1) = 0;
+                { x, d } in { BYTE, DOUBLE };
+                NTH { x, s } in Square.RESET { Square.x, Square.y };
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let fixed = generate(&analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect::<Vec<_>>();
+        let query_callback = unused_query_callback();
+        let path = std::env::temp_dir().join(format!("witgen_checkpoint_{}", std::process::id()));
+
+        let witness = WitnessGenerator::new(&analyzed, &fixed, &query_callback)
+            .with_checkpoints(CheckpointSettings {
+                path: path.clone(),
+                interval: 100,
+            })
+            .generate();
+
+        let checkpoint = Checkpoint::<GoldilocksField>::read_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(checkpoint.row, 200);
+        assert_eq!(checkpoint.degree, 256);
+        assert_eq!(
+            checkpoint.components.keys().collect::<Vec<_>>(),
+            vec![
+                FIXED_LOOKUP_NAME,
+                "Main Machine",
+                "Secondary machine 0: Square (BlockMachine)"
+            ]
+        );
+
+        let resumed = WitnessGenerator::new(&analyzed, &fixed, &query_callback)
+            .with_resume_from(checkpoint)
+            .generate();
+        assert_eq!(resumed, witness);
+    }
+}
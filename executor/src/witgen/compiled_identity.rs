@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperator, AlgebraicExpression as Expression, AlgebraicReference,
+    AlgebraicUnaryOperator, Identity, IdentityKind,
+};
+use powdr_number::{DegreeType, FieldElement};
+
+use super::{
+    affine_expression::AffineExpression, rows::RowPair, Constraint, EvalError, EvalResult,
+    EvalValue, FixedData, IncompleteCause,
+};
+
+/// The result of evaluating a compiled expression on a row pair.
+/// Constants and expressions with a single unknown are represented without allocation.
+#[derive(Debug, Clone)]
+pub enum Evaluated<'a, T> {
+    Known(T),
+    /// `coeff * var + offset`, where `coeff` is nonzero.
+    Single {
+        var: &'a AlgebraicReference,
+        coeff: T,
+        offset: T,
+    },
+    Affine(AffineExpression<&'a AlgebraicReference, T>),
+}
+
+type CompiledResult<'a, T> = Result<Evaluated<'a, T>, IncompleteCause<&'a AlgebraicReference>>;
+
+type CompiledExpression<'a, T> =
+    Box<dyn Fn(&RowPair<'_, 'a, T>) -> CompiledResult<'a, T> + Send + Sync + 'a>;
+
+/// A polynomial identity compiled into a tree of closures. All decisions that only depend on the
+/// structure of the expression (column kinds, fixed column lookups, operators) are taken once
+/// at compile time, so that evaluating it on a row only has to look at the cell values.
+/// Evaluation is equivalent to [RowPair::evaluate] on the expression of the identity.
+pub struct CompiledIdentity<'a, T: FieldElement> {
+    expression: CompiledExpression<'a, T>,
+}
+
+impl<'a, T: FieldElement> CompiledIdentity<'a, T> {
+    pub fn new(fixed_data: &'a FixedData<'a, T>, identity: &'a Identity<Expression<T>>) -> Self {
+        assert_eq!(identity.kind, IdentityKind::Polynomial);
+        Self {
+            expression: compile(fixed_data, identity.expression_for_poly_id()),
+        }
+    }
+
+    pub fn evaluate(&self, rows: &RowPair<'_, 'a, T>) -> CompiledResult<'a, T> {
+        (self.expression)(rows)
+    }
+
+    /// Evaluates the identity and tries to solve it for the unknown cells.
+    /// Has the same result as processing the identity with the `IdentityProcessor`,
+    /// but avoids building an [AffineExpression] if there is at most one unknown.
+    pub fn process(&self, rows: &RowPair<'_, 'a, T>) -> EvalResult<'a, T> {
+        match self.evaluate(rows) {
+            Err(incomplete_cause) => Ok(EvalValue::incomplete(incomplete_cause)),
+            Ok(evaluated) => evaluated.solve(rows),
+        }
+    }
+}
+
+/// The compiled polynomial identities of a machine.
+#[derive(Default)]
+pub struct CompiledIdentities<'a, T: FieldElement> {
+    identities: HashMap<(u64, IdentityKind), CompiledIdentity<'a, T>>,
+}
+
+impl<'a, T: FieldElement> CompiledIdentities<'a, T> {
+    /// Compiles all polynomial identities among the given ones.
+    pub fn new(
+        fixed_data: &'a FixedData<'a, T>,
+        identities: &[&'a Identity<Expression<T>>],
+    ) -> Self {
+        Self {
+            identities: identities
+                .iter()
+                .filter(|identity| identity.kind == IdentityKind::Polynomial)
+                .map(|identity| {
+                    (
+                        (identity.id, identity.kind),
+                        CompiledIdentity::new(fixed_data, identity),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, identity: &Identity<Expression<T>>) -> Option<&CompiledIdentity<'a, T>> {
+        self.identities.get(&(identity.id, identity.kind))
+    }
+}
+
+fn compile<'a, T: FieldElement>(
+    fixed_data: &'a FixedData<'a, T>,
+    expr: &'a Expression<T>,
+) -> CompiledExpression<'a, T> {
+    match expr {
+        Expression::Reference(poly) if poly.is_witness() => {
+            Box::new(move |rows| match rows.get_value(poly) {
+                Some(v) => Ok(Evaluated::Known(v)),
+                None => Ok(Evaluated::Single {
+                    var: poly,
+                    coeff: T::one(),
+                    offset: T::zero(),
+                }),
+            })
+        }
+        Expression::Reference(poly) => {
+            let values = fixed_data.fixed_cols[&poly.poly_id].values.as_slice();
+            let next = poly.next as DegreeType;
+            Box::new(move |rows| {
+                let row = (rows.current_row_index + next) % (values.len() as DegreeType);
+                Ok(Evaluated::Known(values[row as usize]))
+            })
+        }
+        Expression::Number(n) => {
+            let n = *n;
+            Box::new(move |_| Ok(Evaluated::Known(n)))
+        }
+        Expression::BinaryOperation(left, op, right) => {
+            compile_binary_operation(compile(fixed_data, left), *op, compile(fixed_data, right))
+        }
+        Expression::UnaryOperation(AlgebraicUnaryOperator::Minus, expr) => {
+            let expr = compile(fixed_data, expr);
+            Box::new(move |rows| expr(rows).map(|v| -v))
+        }
+        e => {
+            let e = e.to_string();
+            Box::new(move |_| {
+                Err(IncompleteCause::ExpressionEvaluationUnimplemented(
+                    e.clone(),
+                ))
+            })
+        }
+    }
+}
+
+/// Combines the compiled operands with the same semantics as the `ExpressionEvaluator`,
+/// including short-circuiting multiplications by zero.
+fn compile_binary_operation<'a, T: FieldElement>(
+    left: CompiledExpression<'a, T>,
+    op: AlgebraicBinaryOperator,
+    right: CompiledExpression<'a, T>,
+) -> CompiledExpression<'a, T> {
+    match op {
+        AlgebraicBinaryOperator::Add => {
+            Box::new(move |rows| combine_results(left(rows), right(rows), |l, r| Ok(l + r)))
+        }
+        AlgebraicBinaryOperator::Sub => {
+            Box::new(move |rows| combine_results(left(rows), right(rows), |l, r| Ok(l + -r)))
+        }
+        AlgebraicBinaryOperator::Mul => Box::new(move |rows| {
+            let left = left(rows);
+            if let Ok(l) = &left {
+                if l.is_zero() {
+                    return left;
+                }
+            }
+            match right(rows) {
+                Ok(r) if r.is_zero() => Ok(r),
+                right => combine_results(left, right, |l, r| {
+                    if let Some(f) = l.constant_value() {
+                        Ok(r * f)
+                    } else if let Some(f) = r.constant_value() {
+                        Ok(l * f)
+                    } else {
+                        Err(IncompleteCause::QuadraticTerm)
+                    }
+                }),
+            }
+        }),
+        AlgebraicBinaryOperator::Pow => Box::new(move |rows| {
+            combine_results(left(rows), right(rows), |l, r| {
+                match (l.constant_value(), r.constant_value()) {
+                    (Some(l), Some(r)) => Ok(Evaluated::Known(l.pow(r.to_integer()))),
+                    _ => Err(IncompleteCause::ExponentiationTerm),
+                }
+            })
+        }),
+    }
+}
+
+fn combine_results<'a, T>(
+    left: CompiledResult<'a, T>,
+    right: CompiledResult<'a, T>,
+    f: impl FnOnce(Evaluated<'a, T>, Evaluated<'a, T>) -> CompiledResult<'a, T>,
+) -> CompiledResult<'a, T> {
+    match (left, right) {
+        (Ok(left), Ok(right)) => f(left, right),
+        (Ok(_), Err(reason)) | (Err(reason), Ok(_)) => Err(reason),
+        (Err(r1), Err(r2)) => Err(r1.combine(r2)),
+    }
+}
+
+impl<'a, T: FieldElement> Evaluated<'a, T> {
+    pub fn constant_value(&self) -> Option<T> {
+        match self {
+            Evaluated::Known(v) => Some(*v),
+            Evaluated::Single { .. } => None,
+            Evaluated::Affine(a) => a.constant_value(),
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.constant_value().map(|v| v.is_zero()) == Some(true)
+    }
+
+    pub fn into_affine(self) -> AffineExpression<&'a AlgebraicReference, T> {
+        match self {
+            Evaluated::Known(v) => v.into(),
+            Evaluated::Single { var, coeff, offset } => {
+                AffineExpression::from_variable_id(var) * coeff + offset.into()
+            }
+            Evaluated::Affine(a) => a,
+        }
+    }
+
+    /// Tries to solve "self = 0", see [AffineExpression::solve_with_range_constraints].
+    pub fn solve(self, rows: &RowPair<'_, 'a, T>) -> EvalResult<'a, T> {
+        match self {
+            Evaluated::Known(v) if v.is_zero() => Ok(EvalValue::complete([])),
+            Evaluated::Known(v) => Err(EvalError::ConstraintUnsatisfiable(
+                AffineExpression::<&AlgebraicReference, T>::from(v).to_string(),
+            )),
+            // coeff * var + offset = 0 <=> var = -offset / coeff
+            Evaluated::Single { var, coeff, offset } => Ok(EvalValue::complete([(
+                var,
+                Constraint::Assignment(if coeff.is_one() {
+                    -offset
+                } else if coeff == -T::one() {
+                    offset
+                } else {
+                    -offset / coeff
+                }),
+            )])),
+            Evaluated::Affine(a) => a.solve_with_range_constraints(rows),
+        }
+    }
+}
+
+impl<'a, T: FieldElement> std::ops::Add for Evaluated<'a, T> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        match (self, rhs) {
+            (Evaluated::Known(l), Evaluated::Known(r)) => Evaluated::Known(l + r),
+            (Evaluated::Known(v), Evaluated::Single { var, coeff, offset })
+            | (Evaluated::Single { var, coeff, offset }, Evaluated::Known(v)) => {
+                Evaluated::Single {
+                    var,
+                    coeff,
+                    offset: offset + v,
+                }
+            }
+            (l, r) => Evaluated::Affine(l.into_affine() + r.into_affine()),
+        }
+    }
+}
+
+impl<'a, T: FieldElement> std::ops::Neg for Evaluated<'a, T> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        match self {
+            Evaluated::Known(v) => Evaluated::Known(-v),
+            Evaluated::Single { var, coeff, offset } => Evaluated::Single {
+                var,
+                coeff: -coeff,
+                offset: -offset,
+            },
+            Evaluated::Affine(a) => Evaluated::Affine(-a),
+        }
+    }
+}
+
+impl<'a, T: FieldElement> std::ops::Mul<T> for Evaluated<'a, T> {
+    type Output = Self;
+
+    fn mul(self, f: T) -> Self {
+        match self {
+            Evaluated::Known(v) => Evaluated::Known(v * f),
+            // Keep the coefficient nonzero.
+            Evaluated::Single { .. } if f.is_zero() => Evaluated::Known(T::zero()),
+            Evaluated::Single { var, coeff, offset } => Evaluated::Single {
+                var,
+                coeff: coeff * f,
+                offset: offset * f,
+            },
+            Evaluated::Affine(a) => Evaluated::Affine(a * f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use crate::constant_evaluator::generate;
+    use crate::witgen::{
+        data_structures::column_map::FixedColumnMap,
+        global_constraints::GlobalConstraints,
+        rows::{CellValue, RowFactory, UnknownStrategy},
+    };
+
+    use super::*;
+
+    #[test]
+    fn same_result_as_expression_evaluator() {
+        let src = r#"
+            namespace N(8);
+                col fixed FIRST = [1] + [0]*;
+                col fixed STEP(i) { i };
+                col witness a, b, c, d;
+                a = 3;
+                FIRST * (b - 7) = 0;
+                (1 - FIRST) * (b' - b - STEP) = 0;
+                c * b = 5;
+                (a + b) * (c - d) = 0;
+                2 * d - a = 0;
+                -d + STEP' = 1;
+                a * a = b ** 2;
+                b ** 2 - d * 0 = a;
+                d * (a - c) = 1;
+                a - a = 0;
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let constants = generate(&analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect::<Vec<_>>();
+        let fixed_data = FixedData::new(&analyzed, &constants, vec![]);
+        let global_range_constraints = GlobalConstraints {
+            witness_constraints: fixed_data.witness_map_with(None),
+            fixed_constraints: FixedColumnMap::new(None, fixed_data.fixed_cols.len()),
+        };
+        let row_factory = RowFactory::new(&fixed_data, global_range_constraints);
+        let identities = analyzed.identities.iter().collect::<Vec<_>>();
+        let compiled = CompiledIdentities::new(&fixed_data, &identities);
+
+        // Try all combinations of known and unknown witness cells in the current row
+        // and the first cell in the next row.
+        let witness_count = fixed_data.witness_cols.len();
+        for known in 0..(1u64 << (witness_count + 1)) {
+            for row_index in [0, 3, 7] {
+                let mut current = row_factory.fresh_row(row_index);
+                let mut next = row_factory.fresh_row(row_index + 1);
+                for (i, poly_id) in fixed_data.witness_cols.keys().enumerate() {
+                    if known & (1 << i) != 0 {
+                        current[&poly_id].value = CellValue::Known((i as u64 + 2).into());
+                    }
+                    if i == 1 && known & (1 << witness_count) != 0 {
+                        next[&poly_id].value = CellValue::Known(12.into());
+                    }
+                }
+                for strategy in [UnknownStrategy::Unknown, UnknownStrategy::Zero] {
+                    let rows = RowPair::new(&current, &next, row_index, &fixed_data, strategy);
+                    for identity in &identities {
+                        let compiled = compiled.get(identity).unwrap();
+                        let expected = rows.evaluate(identity.expression_for_poly_id());
+                        let actual = compiled.evaluate(&rows).map(|e| e.into_affine());
+                        assert_eq!(actual, expected, "{identity}");
+                        let expected = match expected {
+                            Err(cause) => Ok(EvalValue::incomplete(cause)),
+                            Ok(e) => e.solve_with_range_constraints(&rows),
+                        };
+                        assert_eq!(compiled.process(&rows), expected, "{identity}");
+                    }
+                }
+            }
+        }
+    }
+}
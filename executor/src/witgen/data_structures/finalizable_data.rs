@@ -5,9 +5,11 @@ use std::{
 
 use bit_vec::BitVec;
 use powdr_ast::analyzed::PolyID;
-use powdr_number::FieldElement;
+use powdr_number::{DegreeType, FieldElement};
 
-use crate::witgen::rows::Row;
+use crate::witgen::checkpoint::{take_column, StateColumns};
+use crate::witgen::rows::{CellValue, Row, RowFactory};
+use crate::witgen::FixedData;
 
 /// A row entry in [FinalizableData].
 #[derive(Clone)]
@@ -176,6 +178,91 @@ impl<'a, T: FieldElement> FinalizableData<'a, T> {
     }
 }
 
+impl<'a, T: FieldElement> FinalizableData<'a, T> {
+    /// Returns the values of all rows as columns, so that they can be stored in a checkpoint.
+    /// For each column, a `<name>.known` column stores whether the cells are known, and a
+    /// `finalized` column stores which rows have already been finalized.
+    pub fn to_state(&self, fixed_data: &FixedData<'a, T>) -> StateColumns<T> {
+        let mut values = vec![Vec::with_capacity(self.data.len()); self.column_ids.len()];
+        let mut known = vec![Vec::with_capacity(self.data.len()); self.column_ids.len()];
+        let mut finalized = Vec::with_capacity(self.data.len());
+        for entry in &self.data {
+            match entry {
+                Entry::InProgress(row) => {
+                    for (i, c) in self.column_ids.iter().enumerate() {
+                        values[i].push(row[c].value.unwrap_or_default());
+                        known[i].push(T::from(row[c].value.is_known()));
+                    }
+                    finalized.push(T::from(false));
+                }
+                Entry::Finalized(row, known_cells) => {
+                    for (i, (value, is_known)) in row.iter().zip(known_cells).enumerate() {
+                        values[i].push(*value);
+                        known[i].push(T::from(is_known));
+                    }
+                    finalized.push(T::from(true));
+                }
+            }
+        }
+        let mut state = StateColumns::new();
+        for ((id, values), known) in self.column_ids.iter().zip(values).zip(known) {
+            let name = fixed_data.column_name(id);
+            state.insert(name.to_string(), values);
+            state.insert(format!("{name}.known"), known);
+        }
+        state.insert("finalized".to_string(), finalized);
+        state
+    }
+
+    /// Restores the rows stored by [FinalizableData::to_state]. Rows that were still in
+    /// progress start out as fresh rows with the stored values.
+    pub fn from_state(
+        column_ids: &HashSet<PolyID>,
+        mut state: StateColumns<T>,
+        fixed_data: &FixedData<'a, T>,
+        row_factory: &RowFactory<'a, T>,
+    ) -> Self {
+        let mut result = Self::new(column_ids);
+        let finalized = take_column(&mut state, "finalized");
+        let columns = result
+            .column_ids
+            .iter()
+            .map(|id| {
+                let name = fixed_data.column_name(id);
+                let values = take_column(&mut state, name);
+                let known = take_column(&mut state, &format!("{name}.known"));
+                assert_eq!(values.len(), finalized.len());
+                assert_eq!(known.len(), finalized.len());
+                (id, values, known)
+            })
+            .collect::<Vec<_>>();
+        result.data = finalized
+            .iter()
+            .enumerate()
+            .map(|(i, is_finalized)| {
+                if is_finalized.is_one() {
+                    Entry::Finalized(
+                        columns.iter().map(|(_, values, _)| values[i]).collect(),
+                        columns
+                            .iter()
+                            .map(|(_, _, known)| known[i].is_one())
+                            .collect(),
+                    )
+                } else {
+                    let mut row = row_factory.fresh_row(i as DegreeType);
+                    for (id, values, known) in &columns {
+                        if known[i].is_one() {
+                            row[id].value = CellValue::Known(values[i]);
+                        }
+                    }
+                    Entry::InProgress(row)
+                }
+            })
+            .collect();
+        result
+    }
+}
+
 impl<'a, T: FieldElement> Index<usize> for FinalizableData<'a, T> {
     type Output = Row<'a, T>;
 
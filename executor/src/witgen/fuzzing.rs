@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+
+use itertools::Itertools;
+use powdr_ast::analyzed::{AlgebraicExpression as Expression, Analyzed, IdentityKind, PolyID};
+use powdr_ast::parsed::visitor::ExpressionVisitable;
+use powdr_number::{DegreeType, FieldElement};
+
+use crate::constraint_checker::ConstraintChecker;
+
+/// Configuration for fuzzing a witness, see [fuzz_witness].
+#[derive(Debug, Clone)]
+pub struct FuzzingConfig<T> {
+    /// The rows whose cells are perturbed.
+    pub rows: Range<DegreeType>,
+    /// The names of the witness columns whose cells are perturbed.
+    /// If `None`, all witness columns are perturbed.
+    pub columns: Option<Vec<String>>,
+    /// The values that are added to each cell, one at a time.
+    pub perturbations: Vec<T>,
+}
+
+impl<T: FieldElement> FuzzingConfig<T> {
+    /// Creates a configuration that perturbs all witness columns in the given rows
+    /// by adding and subtracting one.
+    pub fn new(rows: Range<DegreeType>) -> Self {
+        FuzzingConfig {
+            rows,
+            columns: None,
+            perturbations: vec![1.into(), -T::from(1)],
+        }
+    }
+
+    pub fn with_columns(self, columns: Vec<String>) -> Self {
+        FuzzingConfig {
+            columns: Some(columns),
+            ..self
+        }
+    }
+
+    pub fn with_perturbations(self, perturbations: Vec<T>) -> Self {
+        FuzzingConfig {
+            perturbations,
+            ..self
+        }
+    }
+}
+
+/// A witness cell that can be changed without violating any identity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnderconstrainedCell<T> {
+    pub column: String,
+    pub row: DegreeType,
+    /// The value in the generated witness.
+    pub value: T,
+    /// A different value that also satisfies all identities.
+    pub alternative: T,
+}
+
+/// A generated witness together with the cells that could be changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzedWitness<T> {
+    /// The witness columns, in source order.
+    pub witness: Vec<(String, Vec<T>)>,
+    /// The cells that can be changed without violating any identity.
+    pub underconstrained: Vec<UnderconstrainedCell<T>>,
+}
+
+/// The reason why a witness could not be fuzzed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FuzzingError {
+    /// The witness does not satisfy all identities, with a report of the failing ones.
+    InvalidWitness(String),
+    /// The identities could not be checked.
+    CheckFailed(String),
+}
+
+impl fmt::Display for FuzzingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FuzzingError::InvalidWitness(report) => write!(f, "Invalid witness:\n{report}"),
+            FuzzingError::CheckFailed(e) => write!(f, "Could not check the witness: {e}"),
+        }
+    }
+}
+
+/// The identities a witness column is referenced in.
+struct ColumnUsage {
+    /// Index of the identity.
+    identity: usize,
+    kind: IdentityKind,
+    /// The column is referenced without the "next" operator.
+    current: bool,
+    /// The column is referenced with the "next" operator.
+    next: bool,
+}
+
+/// Perturbs individual cells of a valid witness and re-checks all identities
+/// affected by the change. Cells that can be changed without any identity failing
+/// indicate that the witness is not uniquely determined by the constraints.
+///
+/// @returns the cells that could be changed, ordered by column and row, or an error with
+/// a report of the failing identities if the provided witness does not satisfy all identities.
+pub fn fuzz_witness<T: FieldElement>(
+    analyzed: &Analyzed<T>,
+    fixed_col_values: &[(String, Vec<T>)],
+    witness_col_values: &[(String, Vec<T>)],
+    config: &FuzzingConfig<T>,
+) -> Result<Vec<UnderconstrainedCell<T>>, FuzzingError> {
+    let mut checker = ConstraintChecker::new(analyzed, fixed_col_values, witness_col_values)
+        .map_err(FuzzingError::CheckFailed)?;
+    let failures = checker.check().map_err(FuzzingError::CheckFailed)?;
+    if !failures.is_empty() {
+        return Err(FuzzingError::InvalidWitness(checker.report(&failures)));
+    }
+
+    let usages = column_usages(&checker);
+    let columns = columns_to_fuzz(analyzed, config.columns.as_ref());
+    let degree = checker.degree();
+    let rows = config.rows.start..config.rows.end.min(degree);
+
+    let mut cells = vec![];
+    for (name, poly_id) in columns {
+        let usages = usages
+            .get(&poly_id)
+            .map(|u| u.as_slice())
+            .unwrap_or_default();
+        for row in rows.clone() {
+            let value = checker.witness_value(&poly_id, row);
+            let alternative = config.perturbations.iter().find_map(|delta| {
+                let alternative = value + *delta;
+                checker.set_witness_value(&poly_id, row, alternative);
+                let accepted = usages.iter().all(|usage| {
+                    if usage.kind == IdentityKind::Polynomial {
+                        // Only the rows that reference the changed cell can be affected.
+                        let affected_rows = [
+                            usage.current.then_some(row),
+                            usage.next.then_some((row + degree - 1) % degree),
+                        ];
+                        let identity = &checker.identities()[usage.identity];
+                        checker
+                            .check_polynomial_identity(
+                                identity,
+                                affected_rows.into_iter().flatten(),
+                            )
+                            .is_empty()
+                    } else {
+                        // Identities that cannot be checked do not accept any change.
+                        checker
+                            .check_identity(usage.identity)
+                            .is_ok_and(|rows| rows.is_empty())
+                    }
+                });
+                checker.set_witness_value(&poly_id, row, value);
+                accepted.then_some(alternative)
+            });
+            if let Some(alternative) = alternative {
+                cells.push(UnderconstrainedCell {
+                    column: name.clone(),
+                    row,
+                    value,
+                    alternative,
+                });
+            }
+        }
+    }
+
+    for (column, cells) in &cells.iter().group_by(|cell| &cell.column) {
+        let rows = cells.map(|cell| cell.row).collect::<Vec<_>>();
+        log::warn!(
+            "Witness column {column} is not uniquely determined in {} row(s): {}",
+            rows.len(),
+            rows.iter().format(", ")
+        );
+    }
+    Ok(cells)
+}
+
+/// @returns, for each witness column, the identities it is referenced in.
+fn column_usages<T: FieldElement>(
+    checker: &ConstraintChecker<T>,
+) -> BTreeMap<PolyID, Vec<ColumnUsage>> {
+    let mut usages: BTreeMap<PolyID, Vec<ColumnUsage>> = BTreeMap::new();
+    for (index, identity) in checker.identities().iter().enumerate() {
+        let mut references = BTreeSet::new();
+        identity.pre_visit_expressions(&mut |e| {
+            if let Expression::Reference(poly) = e {
+                if poly.is_witness() {
+                    references.insert((poly.poly_id, poly.next));
+                }
+            }
+        });
+        for (poly_id, refs) in &references.into_iter().group_by(|(poly_id, _)| *poly_id) {
+            let next_flags = refs.map(|(_, next)| next).collect::<Vec<_>>();
+            usages.entry(poly_id).or_default().push(ColumnUsage {
+                identity: index,
+                kind: identity.kind,
+                current: next_flags.contains(&false),
+                next: next_flags.contains(&true),
+            });
+        }
+    }
+    usages
+}
+
+/// @returns the names and IDs of the witness columns selected by `columns`
+/// (all witness columns if `None`), in source order.
+fn columns_to_fuzz<T>(
+    analyzed: &Analyzed<T>,
+    columns: Option<&Vec<String>>,
+) -> Vec<(String, PolyID)> {
+    let all_columns = analyzed
+        .committed_polys_in_source_order()
+        .into_iter()
+        .flat_map(|(symbol, _)| symbol.array_elements())
+        .collect::<Vec<_>>();
+    match columns {
+        None => all_columns,
+        Some(columns) => {
+            let unknown = columns
+                .iter()
+                .filter(|name| !all_columns.iter().any(|(n, _)| n == *name))
+                .collect::<Vec<_>>();
+            assert!(
+                unknown.is_empty(),
+                "Cannot fuzz unknown witness columns: {}",
+                unknown.iter().format(", ")
+            );
+            all_columns
+                .into_iter()
+                .filter(|(name, _)| columns.contains(name))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use crate::constant_evaluator::generate;
+    use crate::witgen::{unused_query_callback, WitnessGenerator};
+
+    use super::*;
+
+    fn fixed_columns(analyzed: &Analyzed<GoldilocksField>) -> Vec<(String, Vec<GoldilocksField>)> {
+        generate(analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect()
+    }
+
+    #[test]
+    fn fibonacci_is_deterministic() {
+        let src = r#"
+            namespace Fibonacci(8);
+                col fixed ISFIRST = [1] + [0]*;
+                col fixed ISLAST = [0]* + [1];
+                col witness x, y;
+                ISFIRST * (y - 1) = 0;
+                ISFIRST * (x - 1) = 0;
+                (1-ISLAST) * (x' - y) = 0;
+                (1-ISLAST) * (y' - (x + y)) = 0;
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let fixed = fixed_columns(&analyzed);
+        let query_callback = unused_query_callback();
+        let fuzzed = WitnessGenerator::new(&analyzed, &fixed, &query_callback)
+            .generate_and_fuzz(&FuzzingConfig::new(0..8))
+            .unwrap();
+        assert_eq!(fuzzed.witness.len(), 2);
+        assert_eq!(fuzzed.underconstrained, vec![]);
+    }
+
+    #[test]
+    fn detects_underconstrained_cells() {
+        let src = r#"
+            namespace N(4);
+                col fixed LAST = [0, 0, 0, 1];
+                col witness a, flag, unused;
+                (1 - LAST) * (a' - a - 1) = 0;
+                flag * (1 - flag) = 0;
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let fixed = fixed_columns(&analyzed);
+        let witness = [
+            ("N.a", [0, 1, 2, 3]),
+            ("N.flag", [0, 1, 1, 0]),
+            ("N.unused", [0, 0, 0, 0]),
+        ]
+        .into_iter()
+        .map(|(n, v)| (n.to_string(), v.into_iter().map(Into::into).collect()))
+        .collect::<Vec<_>>();
+
+        let cells = fuzz_witness(&analyzed, &fixed, &witness, &FuzzingConfig::new(1..3));
+        let cells = cells
+            .unwrap()
+            .into_iter()
+            .map(|cell| (cell.column, cell.row))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cells,
+            [
+                ("N.flag", 1),
+                ("N.flag", 2),
+                ("N.unused", 1),
+                ("N.unused", 2)
+            ]
+            .map(|(n, r)| (n.to_string(), r))
+        );
+
+        let config = FuzzingConfig::new(0..4).with_columns(vec!["N.a".to_string()]);
+        let cells = fuzz_witness(&analyzed, &fixed, &witness, &config);
+        assert_eq!(cells, Ok(vec![]));
+    }
+
+    #[test]
+    fn invalid_witness() {
+        let src = r#"
+            namespace N(4);
+                col witness a;
+                a * (1 - a) = 0;
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let fixed = fixed_columns(&analyzed);
+        let witness = vec![("N.a".to_string(), [0, 2, 1, 3].map(Into::into).to_vec())];
+        let report = fuzz_witness(&analyzed, &fixed, &witness, &FuzzingConfig::new(0..4));
+        let Err(FuzzingError::InvalidWitness(report)) = report else {
+            panic!("The witness should be invalid.");
+        };
+        assert!(report.starts_with("Identity fails on 2 row(s): (N.a * (1 - N.a)) = 0;"));
+    }
+}
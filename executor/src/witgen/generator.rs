@@ -13,6 +13,8 @@ use crate::witgen::EvalValue;
 
 use super::affine_expression::AffineExpression;
 use super::block_processor::BlockProcessor;
+use super::checkpoint::{CheckpointSettings, StateColumns};
+use super::compiled_identity::CompiledIdentities;
 use super::data_structures::column_map::WitnessColumnMap;
 use super::global_constraints::GlobalConstraints;
 use super::machines::{FixedLookup, Machine};
@@ -29,11 +31,15 @@ struct ProcessResult<'a, T: FieldElement> {
 pub struct Generator<'a, T: FieldElement> {
     fixed_data: &'a FixedData<'a, T>,
     identities: Vec<&'a Identity<Expression<T>>>,
+    /// The polynomial identities compiled once for all rows, unless disabled.
+    compiled_identities: Option<CompiledIdentities<'a, T>>,
     witnesses: HashSet<PolyID>,
     global_range_constraints: GlobalConstraints<T>,
     data: FinalizableData<'a, T>,
     latch: Option<Expression<T>>,
     name: String,
+    /// Where to write checkpoints when running as the main machine.
+    checkpoints: Option<CheckpointSettings>,
 }
 
 impl<'a, T: FieldElement> Machine<'a, T> for Generator<'a, T> {
@@ -105,6 +111,16 @@ impl<'a, T: FieldElement> Machine<'a, T> for Generator<'a, T> {
             .map(|(id, (values, _))| (self.fixed_data.column_name(&id).to_string(), values))
             .collect()
     }
+
+    fn state(&self) -> StateColumns<T> {
+        self.data.to_state(self.fixed_data)
+    }
+
+    fn restore_state(&mut self, state: StateColumns<T>) {
+        let row_factory = RowFactory::new(self.fixed_data, self.global_range_constraints.clone());
+        self.data =
+            FinalizableData::from_state(&self.witnesses, state, self.fixed_data, &row_factory);
+    }
 }
 
 impl<'a, T: FieldElement> Generator<'a, T> {
@@ -121,10 +137,29 @@ impl<'a, T: FieldElement> Generator<'a, T> {
             name,
             fixed_data,
             identities: identities.to_vec(),
+            compiled_identities: Some(CompiledIdentities::new(fixed_data, identities)),
             witnesses,
             global_range_constraints: global_range_constraints.clone(),
             data,
             latch,
+            checkpoints: None,
+        }
+    }
+
+    /// Writes checkpoints while running the machine as the main machine.
+    pub fn with_checkpoints(self, checkpoints: CheckpointSettings) -> Self {
+        Self {
+            checkpoints: Some(checkpoints),
+            ..self
+        }
+    }
+
+    /// Enables or disables evaluating polynomial identities through their compiled form.
+    /// Disabling it is only useful to compare the performance.
+    pub fn with_compiled_identities(self, enabled: bool) -> Self {
+        Self {
+            compiled_identities: self.compiled_identities.filter(|_| enabled),
+            ..self
         }
     }
 
@@ -137,6 +172,22 @@ impl<'a, T: FieldElement> Generator<'a, T> {
         record_end(self.name());
     }
 
+    /// Like [Generator::run], but continues from the rows stored in a checkpoint.
+    /// The other machines need to be restored from the same checkpoint.
+    pub fn resume<'b, Q: QueryCallback<T>>(
+        &mut self,
+        mutable_state: &mut MutableState<'a, 'b, T, Q>,
+        state: StateColumns<T>,
+    ) {
+        record_start(self.name());
+        assert!(self.data.is_empty());
+        self.restore_state(state);
+        let data = std::mem::replace(&mut self.data, FinalizableData::new(&self.witnesses));
+        log::info!("Resuming main machine at row {}", data.len() - 1);
+        self.data = self.process_data(data, 0, mutable_state, None, true).block;
+        record_end(self.name());
+    }
+
     fn fill_remaining_rows<Q: QueryCallback<T>>(
         &mut self,
         mutable_state: &mut MutableState<'a, '_, T, Q>,
@@ -207,11 +258,23 @@ impl<'a, T: FieldElement> Generator<'a, T> {
         log::trace!(
             "Running main machine from row {row_offset} with the following initial values in the first row:\n{}", first_row.render_values(false, None)
         );
-        let row_factory = RowFactory::new(self.fixed_data, self.global_range_constraints.clone());
         let data = FinalizableData::with_initial_rows_in_progress(
             &self.witnesses,
             [first_row].into_iter(),
         );
+        self.process_data(data, row_offset, mutable_state, outer_query, is_main_run)
+    }
+
+    /// Continues processing after the last row of `data`.
+    fn process_data<Q: QueryCallback<T>>(
+        &self,
+        data: FinalizableData<'a, T>,
+        row_offset: DegreeType,
+        mutable_state: &mut MutableState<'a, '_, T, Q>,
+        outer_query: Option<OuterQuery<'a, T>>,
+        is_main_run: bool,
+    ) -> ProcessResult<'a, T> {
+        let row_factory = RowFactory::new(self.fixed_data, self.global_range_constraints.clone());
         let mut processor = VmProcessor::new(
             row_offset,
             self.fixed_data,
@@ -224,6 +287,12 @@ impl<'a, T: FieldElement> Generator<'a, T> {
         if let Some(outer_query) = outer_query {
             processor = processor.with_outer_query(outer_query);
         }
+        if let Some(compiled_identities) = &self.compiled_identities {
+            processor = processor.with_compiled_identities(compiled_identities);
+        }
+        if let (true, Some(checkpoints)) = (is_main_run, &self.checkpoints) {
+            processor = processor.with_checkpoints(self.name.clone(), checkpoints.clone());
+        }
         let eval_value = processor.run(is_main_run);
         let block = processor.finish();
         ProcessResult { eval_value, block }
@@ -11,8 +11,9 @@ use powdr_number::FieldElement;
 use crate::witgen::machines::Machine;
 
 use super::{
-    affine_expression::AffineExpression, machines::KnownMachine, rows::RowPair, EvalResult,
-    EvalValue, FixedData, IncompleteCause, MutableState, QueryCallback,
+    affine_expression::AffineExpression, compiled_identity::CompiledIdentity,
+    machines::KnownMachine, rows::RowPair, EvalResult, EvalValue, FixedData, IncompleteCause,
+    MutableState, QueryCallback,
 };
 
 /// A list of mutable references to machines.
@@ -48,6 +49,17 @@ impl<'a, 'b, T: FieldElement> Machines<'a, 'b, T> {
         self.machines.len()
     }
 
+    /// See [Machine::set_deferring_calls].
+    pub fn set_deferring_calls(&mut self, enabled: bool) {
+        for machine in self.machines.iter_mut() {
+            machine.set_deferring_calls(enabled);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &KnownMachine<'a, T>> {
+        self.machines.iter().map(|m| &**m)
+    }
+
     pub fn iter_mut(&'b mut self) -> impl Iterator<Item = &'b mut KnownMachine<'a, T>> {
         self.machines.iter_mut().map(|m| &mut **m)
     }
@@ -110,6 +122,19 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> IdentityProcessor<'a, 'b,
         result
     }
 
+    /// Like [IdentityProcessor::process_identity], but evaluates a polynomial identity
+    /// through its compiled form.
+    pub fn process_compiled_identity(
+        &self,
+        identity: &'a Identity<Expression<T>>,
+        compiled: &CompiledIdentity<'a, T>,
+        rows: &RowPair<'_, 'a, T>,
+    ) -> EvalResult<'a, T> {
+        let result = compiled.process(rows);
+        report_identity_solving(identity, &result);
+        result
+    }
+
     fn process_polynomial_identity(
         &self,
         identity: &'a Identity<Expression<T>>,
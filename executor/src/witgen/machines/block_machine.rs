@@ -1,9 +1,11 @@
 use std::collections::{BTreeSet, HashMap, HashSet};
 
+use super::profiling::{add_worker_events, record_end, record_in_worker, record_start};
 use super::{EvalResult, FixedData, FixedLookup};
 use crate::witgen::affine_expression::AffineExpression;
 
 use crate::witgen::block_processor::BlockProcessor;
+use crate::witgen::checkpoint::{take_column, StateColumns};
 use crate::witgen::data_structures::finalizable_data::FinalizableData;
 use crate::witgen::global_constraints::GlobalConstraints;
 use crate::witgen::identity_processor::IdentityProcessor;
@@ -13,12 +15,15 @@ use crate::witgen::sequence_iterator::{ProcessingSequenceCache, ProcessingSequen
 use crate::witgen::util::try_to_simple_poly;
 use crate::witgen::{machines::Machine, EvalError, EvalValue, IncompleteCause};
 use crate::witgen::{MutableState, QueryCallback};
+use itertools::Itertools;
 use powdr_ast::analyzed::{
     AlgebraicExpression as Expression, AlgebraicReference, Identity, IdentityKind, PolyID,
     PolynomialType,
 };
 use powdr_ast::parsed::SelectedExpressions;
 use powdr_number::{DegreeType, FieldElement};
+use powdr_parser_util::lines::indent;
+use rayon::prelude::*;
 
 enum ProcessResult<'a, T: FieldElement> {
     Success(FinalizableData<'a, T>, EvalValue<&'a AlgebraicReference, T>),
@@ -41,6 +46,10 @@ impl<'a, T: FieldElement> ProcessResult<'a, T> {
     }
 }
 
+/// A call to the machine whose left hand side was fully known: The values of the
+/// left hand side and the right hand side of the connecting identity.
+type RecordedCall<'a, T> = (Vec<T>, &'a SelectedExpressions<Expression<T>>);
+
 /// A machine that produces multiple rows (one block) per query.
 /// TODO we do not actually "detect" the machine yet, we just check if
 /// the lookup has a binary selector that is 1 every k rows for some k
@@ -49,7 +58,7 @@ pub struct BlockMachine<'a, T: FieldElement> {
     block_size: usize,
     /// The right-hand side of the connecting identity, needed to identify
     /// when this machine is responsible.
-    connecting_rhs: BTreeSet<SelectedExpressions<Expression<T>>>,
+    connecting_rhs: BTreeSet<&'a SelectedExpressions<Expression<T>>>,
     /// The internal identities
     identities: Vec<&'a Identity<Expression<T>>>,
     /// The row factory
@@ -61,6 +70,14 @@ pub struct BlockMachine<'a, T: FieldElement> {
     /// Cache that states the order in which to evaluate identities
     /// to make progress most quickly.
     processing_sequence_cache: ProcessingSequenceCache,
+    /// Whether calls with a fully known left hand side are recorded and processed
+    /// when the machine is finalized. This is only possible if the machine does not
+    /// call other machines apart from fixed lookups.
+    can_record_calls: bool,
+    /// Whether calls are currently recorded, see [Machine::set_deferring_calls].
+    deferring_calls: bool,
+    /// Calls that still need to be processed, see [BlockMachine::process_recorded_calls].
+    recorded_calls: Vec<RecordedCall<'a, T>>,
     fixed_data: &'a FixedData<'a, T>,
     name: String,
 }
@@ -93,7 +110,7 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
                 // This is used later to decide to which lookup the machine should respond.
                 let connecting_rhs = connecting_identities
                     .iter()
-                    .map(|id| id.right.clone())
+                    .map(|id| &id.right)
                     .collect::<BTreeSet<_>>();
 
                 for rhs in connecting_rhs.iter() {
@@ -133,12 +150,32 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
                         block_size,
                         identities.len(),
                     ),
+                    can_record_calls: identities.iter().all(|id| is_self_contained(id)),
+                    deferring_calls: true,
+                    recorded_calls: vec![],
                     fixed_data,
                 }
             })
     }
 }
 
+/// @returns true if the identity can be processed without calling other machines,
+/// i.e. it is a polynomial identity or a lookup into fixed columns.
+fn is_self_contained<T: FieldElement>(identity: &Identity<Expression<T>>) -> bool {
+    match identity.kind {
+        IdentityKind::Polynomial => true,
+        IdentityKind::Plookup => {
+            identity.right.selector.is_none()
+                && identity
+                    .right
+                    .expressions
+                    .iter()
+                    .all(|e| !e.contains_witness_ref())
+        }
+        _ => false,
+    }
+}
+
 /// Check if `expr` is a reference to a function of the form
 /// f(i) { if (i + 1) % k == 0 { 1 } else { 0 } }
 /// for some k
@@ -189,7 +226,7 @@ impl<'a, T: FieldElement> Machine<'a, T> for BlockMachine<'a, T> {
         left: &[AffineExpression<&'a AlgebraicReference, T>],
         right: &'a SelectedExpressions<Expression<T>>,
     ) -> Option<EvalResult<'a, T>> {
-        if !self.connecting_rhs.contains(right) || kind != IdentityKind::Plookup {
+        if !self.connecting_rhs.contains(&right) || kind != IdentityKind::Plookup {
             return None;
         }
         let previous_len = self.rows() as usize;
@@ -209,11 +246,37 @@ impl<'a, T: FieldElement> Machine<'a, T> for BlockMachine<'a, T> {
         &self.name
     }
 
+    fn set_deferring_calls(&mut self, enabled: bool) {
+        self.deferring_calls = enabled;
+    }
+
+    fn process_deferred_calls<'b, Q: QueryCallback<T>>(
+        &mut self,
+        fixed_lookup: &'b mut FixedLookup<T>,
+        query_callback: &'b mut Q,
+    ) -> Result<(), EvalError<T>> {
+        if self.recorded_calls.is_empty() {
+            return Ok(());
+        }
+        record_start(self.name());
+        let result = self.process_recorded_calls(fixed_lookup, query_callback);
+        record_end(self.name());
+        result
+    }
+
     fn take_witness_col_values<'b, Q: QueryCallback<T>>(
         &mut self,
         _fixed_lookup: &'b mut FixedLookup<T>,
         _query_callback: &'b mut Q,
     ) -> HashMap<String, Vec<T>> {
+        self.try_take_witness_col_values().unwrap()
+    }
+
+    fn try_take_witness_col_values(&mut self) -> Option<HashMap<String, Vec<T>>> {
+        assert!(
+            self.recorded_calls.is_empty(),
+            "The recorded calls have to be processed first."
+        );
         if self.data.len() < 2 * self.block_size {
             log::warn!(
                 "Filling empty blocks with zeros, because the block machine is never used. \
@@ -263,9 +326,56 @@ impl<'a, T: FieldElement> Machine<'a, T> for BlockMachine<'a, T> {
             })
             .collect();
         self.handle_last_row(&mut data);
-        data.into_iter()
-            .map(|(id, values)| (self.fixed_data.column_name(&id).to_string(), values))
-            .collect()
+        Some(
+            data.into_iter()
+                .map(|(id, values)| (self.fixed_data.column_name(&id).to_string(), values))
+                .collect(),
+        )
+    }
+
+    fn state(&self) -> StateColumns<T> {
+        let mut state = self.data.to_state(self.fixed_data);
+        let (rhs, values): (Vec<_>, Vec<_>) = self
+            .recorded_calls
+            .iter()
+            .map(|(values, right)| {
+                let rhs_index = self
+                    .connecting_rhs
+                    .iter()
+                    .position(|rhs| rhs == right)
+                    .unwrap();
+                (T::from(rhs_index as u64), values)
+            })
+            .unzip();
+        state.insert("recorded_calls.rhs".to_string(), rhs);
+        state.insert(
+            "recorded_calls.values".to_string(),
+            values.into_iter().flatten().cloned().collect(),
+        );
+        state
+    }
+
+    fn restore_state(&mut self, mut state: StateColumns<T>) {
+        let rhs = take_column(&mut state, "recorded_calls.rhs");
+        let mut values = take_column(&mut state, "recorded_calls.values").into_iter();
+        self.recorded_calls = rhs
+            .into_iter()
+            .map(|rhs_index| {
+                let right = *self
+                    .connecting_rhs
+                    .iter()
+                    .nth(rhs_index.to_degree() as usize)
+                    .unwrap();
+                let values = values.by_ref().take(right.expressions.len()).collect();
+                (values, right)
+            })
+            .collect();
+        self.data = FinalizableData::from_state(
+            &self.witness_cols,
+            state,
+            self.fixed_data,
+            &self.row_factory,
+        );
     }
 }
 
@@ -340,6 +450,156 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
             }
         }
 
+        if self.can_record_calls && self.deferring_calls && left.iter().all(|v| v.is_constant()) {
+            return self.record_call(left, right);
+        }
+
+        self.solve_and_append(mutable_state, left, right)
+    }
+
+    /// Records a call whose left hand side is fully known, so that it can be processed
+    /// together with all other recorded calls after the main pass, see
+    /// [BlockMachine::process_recorded_calls].
+    fn record_call(
+        &mut self,
+        left: &[AffineExpression<&'a AlgebraicReference, T>],
+        right: &'a SelectedExpressions<Expression<T>>,
+    ) -> EvalResult<'a, T> {
+        let values: Vec<_> = left.iter().map(|v| v.constant_value().unwrap()).collect();
+        // Only plookup links are connected to block machines, so a call that repeats
+        // the previous one is satisfied by the same block.
+        if matches!(self.recorded_calls.last(), Some((last_values, last_right)) if *last_values == values && *last_right == right)
+        {
+            log::trace!("Call to block machine '{}' already recorded", self.name());
+            return Ok(EvalValue::complete(vec![]));
+        }
+        let recorded_rows = (self.recorded_calls.len() + 1) * self.block_size;
+        if self.rows() + recorded_rows as DegreeType >= self.fixed_data.degree {
+            return Err(EvalError::RowsExhausted);
+        }
+        log::trace!("Recording call to block machine '{}'", self.name());
+        self.recorded_calls.push((values, right));
+        Ok(EvalValue::complete(vec![]))
+    }
+
+    /// Processes the calls recorded during the main pass. Each call is first solved
+    /// concurrently in a block of its own. The blocks are appended in the order in which the
+    /// calls were made, so that the result is deterministic. Calls that cannot be solved
+    /// without the last row of the previous block are solved again after appending it.
+    /// @returns an error for every call that fails.
+    fn process_recorded_calls<Q: QueryCallback<T>>(
+        &mut self,
+        fixed_lookup: &mut FixedLookup<T>,
+        query_callback: &mut Q,
+    ) -> Result<(), EvalError<T>> {
+        let calls = std::mem::take(&mut self.recorded_calls)
+            .into_iter()
+            .map(|(values, right)| {
+                let left = values.into_iter().map(AffineExpression::from).collect();
+                (left, right)
+            })
+            .collect::<Vec<(Vec<_>, _)>>();
+        if calls.is_empty() {
+            return Ok(());
+        }
+        log::debug!(
+            "Processing {} recorded calls to block machine '{}'",
+            calls.len(),
+            self.name()
+        );
+
+        // Fixed columns are periodic, so all blocks can be solved at the same position.
+        let row_offset = self.rows() - 1;
+        // Every worker uses its own clone of the fixed lookup machine, which shares the
+        // existing indices. The indices the workers build are merged back afterwards.
+        let workers = {
+            let machine = &*self;
+            let fixed_lookup = &*fixed_lookup;
+            let query_callback = &*query_callback;
+            calls
+                .par_iter()
+                .enumerate()
+                .fold(
+                    || (fixed_lookup.clone(), vec![]),
+                    |(mut fixed_lookup, mut results), (i, (left, right))| {
+                        let mut query_callback = query_callback;
+                        let mut mutable_state = MutableState {
+                            fixed_lookup: &mut fixed_lookup,
+                            machines: [].into_iter().into(),
+                            query_callback: &mut query_callback,
+                        };
+                        let mut sequence_iterator = machine
+                            .processing_sequence_cache
+                            .get_processing_sequence(left);
+                        // The profiling events of the worker are added to the profile
+                        // of the current thread below.
+                        let (result, events) = record_in_worker(|| {
+                            machine.process(
+                                &mut mutable_state,
+                                left,
+                                right,
+                                &mut sequence_iterator,
+                                row_offset,
+                            )
+                        });
+                        results.push((i, result, events));
+                        (fixed_lookup, results)
+                    },
+                )
+                .collect::<Vec<_>>()
+        };
+        let mut results = vec![];
+        let mut worker_events = vec![];
+        for (worker_fixed_lookup, worker_results) in workers {
+            fixed_lookup.merge(worker_fixed_lookup);
+            for (i, result, events) in worker_results {
+                results.push((i, result));
+                worker_events.push(events);
+            }
+        }
+        add_worker_events(worker_events);
+        results.sort_by_key(|(i, _)| *i);
+
+        let mut errors = vec![];
+        for ((i, (left, right)), (_, result)) in calls.iter().enumerate().zip(results) {
+            let appended = match result {
+                Ok(ProcessResult::Success(new_block, _)) => self.append_block(new_block).is_ok(),
+                _ => false,
+            };
+            if appended {
+                continue;
+            }
+            let mut mutable_state = MutableState {
+                fixed_lookup: &mut *fixed_lookup,
+                machines: [].into_iter().into(),
+                query_callback: &mut *query_callback,
+            };
+            let error = match self.solve_and_append(&mut mutable_state, left, right) {
+                Ok(result) if result.is_complete() => continue,
+                Ok(_) => "The call could not be completed.".to_string(),
+                Err(e) => e.to_string(),
+            };
+            errors.push(EvalError::Generic(format!(
+                "Call {i} to block machine '{}' with inputs ({}) failed:\n{}",
+                self.name(),
+                left.iter().join(", "),
+                indent(&error, "    ")
+            )));
+        }
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.pop().unwrap()),
+            _ => Err(EvalError::Multiple(errors)),
+        }
+    }
+
+    /// Solves the call in a new block after the last row and appends the block if successful.
+    fn solve_and_append<'b, Q: QueryCallback<T>>(
+        &mut self,
+        mutable_state: &mut MutableState<'a, 'b, T, Q>,
+        left: &[AffineExpression<&'a AlgebraicReference, T>],
+        right: &'a SelectedExpressions<Expression<T>>,
+    ) -> EvalResult<'a, T> {
         // TODO this assumes we are always using the same lookup for this machine.
         let mut sequence_iterator = self.processing_sequence_cache.get_processing_sequence(left);
 
@@ -354,7 +614,14 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
             ));
         }
 
-        let process_result = self.process(mutable_state, left, right, &mut sequence_iterator)?;
+        let row_offset = self.rows() - 1;
+        let process_result = self.process(
+            mutable_state,
+            left,
+            right,
+            &mut sequence_iterator,
+            row_offset,
+        )?;
 
         let process_result = if sequence_iterator.is_cached() && !process_result.is_success() {
             log::debug!("The cached sequence did not complete the block machine. \
@@ -363,7 +630,13 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
             let mut sequence_iterator = self
                 .processing_sequence_cache
                 .get_default_sequence_iterator();
-            self.process(mutable_state, left, right, &mut sequence_iterator)?
+            self.process(
+                mutable_state,
+                left,
+                right,
+                &mut sequence_iterator,
+                row_offset,
+            )?
         } else {
             process_result
         };
@@ -398,9 +671,9 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
         left: &[AffineExpression<&'a AlgebraicReference, T>],
         right: &'a SelectedExpressions<Expression<T>>,
         sequence_iterator: &mut ProcessingSequenceIterator,
+        row_offset: DegreeType,
     ) -> Result<ProcessResult<'a, T>, EvalError<T>> {
         // We start at the last row of the previous block.
-        let row_offset = self.rows() - 1;
         // Make the block two rows larger than the block size, it includes the last row of the previous block
         // and the first row of the next block.
         let block = FinalizableData::with_initial_rows_in_progress(
@@ -430,7 +703,9 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
     /// This is necessary to handle non-rectangular block machines, which already use
     /// unused cells in the previous block.
     fn append_block(&mut self, mut new_block: FinalizableData<'a, T>) -> Result<(), EvalError<T>> {
-        if self.rows() + self.block_size as DegreeType >= self.fixed_data.degree {
+        // Leave space for the recorded calls.
+        let required_rows = (self.recorded_calls.len() + 1) * self.block_size;
+        if self.rows() + required_rows as DegreeType >= self.fixed_data.degree {
             return Err(EvalError::RowsExhausted);
         }
 
@@ -467,3 +742,146 @@ impl<'a, T: FieldElement> BlockMachine<'a, T> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use crate::constant_evaluator::generate;
+    use crate::constraint_checker::ConstraintChecker;
+    use crate::witgen::{unused_query_callback, WitnessGenerator};
+
+    #[test]
+    fn recorded_calls() {
+        // All values of the lookup into the block machine are known in the main machine,
+        // so the calls are recorded and processed when the block machine is finalized.
+        let src = r#"
+            namespace Square(256);
+                col fixed RESET(i) { if i % 2 == 1 { 1 } else { 0 } };
+                col fixed BYTE(i) { i & 0xff };
+                col witness x, y;
+                (1 - RESET) * (x' - x) = 0;
+                { x } in { BYTE };
+                RESET * (y - x * x) = 0;
+            namespace Main(256);
+                col fixed a(i) { i % 24 };
+                col fixed b(i) { (i % 24) * (i % 24) };
+                col fixed NTH(i) { if i % 8 == 0 { 1 } else { 0 } };
+                col witness w;
+                w = a + 1;
+                NTH { a, b } in Square.RESET { Square.x, Square.y };
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let fixed = generate(&analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect::<Vec<_>>();
+        let query_callback = unused_query_callback();
+        let witness = WitnessGenerator::new(&analyzed, &fixed, &query_callback).generate();
+
+        let checker = ConstraintChecker::new(&analyzed, &fixed, &witness).unwrap();
+        assert_eq!(checker.check(), Ok(vec![]));
+
+        // The blocks are in the order of the calls, after the initial dummy block.
+        let (_, x) = witness.iter().find(|(name, _)| name == "Square.x").unwrap();
+        let expected = (0..256)
+            .step_by(8)
+            .flat_map(|i| [i % 24, i % 24])
+            .map(GoldilocksField::from)
+            .collect::<Vec<_>>();
+        assert_eq!(x[2..2 + expected.len()], expected);
+    }
+
+    #[test]
+    #[should_panic = "Witness generation failed."]
+    fn failing_recorded_call() {
+        // The main machine asks for the wrong square, which is only noticed when
+        // the recorded calls are processed.
+        let src = r#"
+            namespace Square(256);
+                col fixed RESET(i) { if i % 2 == 1 { 1 } else { 0 } };
+                col fixed BYTE(i) { i & 0xff };
+                col witness x, y;
+                (1 - RESET) * (x' - x) = 0;
+                { x } in { BYTE };
+                RESET * (y - x * x) = 0;
+            namespace Main(256);
+                col fixed a(i) { i % 24 };
+                col fixed b(i) { if i == 80 { 7 } else { (i % 24) * (i % 24) } };
+                col fixed NTH(i) { if i % 8 == 0 { 1 } else { 0 } };
+                col witness w;
+                w = a + 1;
+                NTH { a, b } in Square.RESET { Square.x, Square.y };
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let fixed = generate(&analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect::<Vec<_>>();
+        let query_callback = unused_query_callback();
+        WitnessGenerator::new(&analyzed, &fixed, &query_callback).generate();
+    }
+
+    #[test]
+    fn loop_with_constant_calls() {
+        // The main machine makes the same call in every row, so its rows repeat and the
+        // calls of the rows proposed by the loop detection are checked.
+        let src = r#"
+            namespace Square(1024);
+                col fixed RESET(i) { if i % 2 == 1 { 1 } else { 0 } };
+                col fixed BYTE(i) { i & 0xff };
+                col witness x, y;
+                (1 - RESET) * (x' - x) = 0;
+                { x } in { BYTE };
+                RESET * (y - x * x) = 0;
+            namespace Main(1024);
+                col witness a, b;
+                a = 3;
+                b = a * a;
+                { a, b } in Square.RESET { Square.x, Square.y };
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let fixed = generate(&analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect::<Vec<_>>();
+        let query_callback = unused_query_callback();
+        let witness = WitnessGenerator::new(&analyzed, &fixed, &query_callback).generate();
+
+        let checker = ConstraintChecker::new(&analyzed, &fixed, &witness).unwrap();
+        assert_eq!(checker.check(), Ok(vec![]));
+    }
+
+    #[test]
+    fn loop_with_failing_proposal() {
+        // The rows proposed by the loop detection are only wrong in the call in row 500,
+        // so the proposal has to be rejected by the block machine.
+        let src = r#"
+            namespace Inc(1024);
+                col fixed RESET(i) { if i % 2 == 1 { 1 } else { 0 } };
+                col fixed BYTE(i) { i & 0xff };
+                col witness x, y;
+                (1 - RESET) * (x' - x) = 0;
+                { x } in { BYTE };
+                RESET * (y - x - 1) = 0;
+            namespace Main(1024);
+                col fixed C(i) { if i == 500 { 4 } else { 3 } };
+                col witness b;
+                { C, b } in Inc.RESET { Inc.x, Inc.y };
+        "#;
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let fixed = generate(&analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect::<Vec<_>>();
+        let query_callback = unused_query_callback();
+        let witness = WitnessGenerator::new(&analyzed, &fixed, &query_callback).generate();
+
+        let checker = ConstraintChecker::new(&analyzed, &fixed, &witness).unwrap();
+        assert_eq!(checker.check(), Ok(vec![]));
+        let (_, b) = witness.iter().find(|(name, _)| name == "Main.b").unwrap();
+        assert_eq!(b[499], GoldilocksField::from(4));
+        assert_eq!(b[500], GoldilocksField::from(5));
+    }
+}
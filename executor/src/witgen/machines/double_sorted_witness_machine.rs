@@ -7,6 +7,7 @@ use powdr_ast::parsed::SelectedExpressions;
 
 use super::{FixedLookup, Machine};
 use crate::witgen::affine_expression::AffineExpression;
+use crate::witgen::checkpoint::{take_column, StateColumns};
 use crate::witgen::global_constraints::GlobalConstraints;
 use crate::witgen::util::is_simple_poly_of_name;
 use crate::witgen::{EvalResult, FixedData, MutableState, QueryCallback};
@@ -181,6 +182,10 @@ impl<'a, T: FieldElement> Machine<'a, T> for DoubleSortedWitnesses<T> {
         _fixed_lookup: &'b mut FixedLookup<T>,
         _query_callback: &'b mut Q,
     ) -> HashMap<String, Vec<T>> {
+        self.try_take_witness_col_values().unwrap()
+    }
+
+    fn try_take_witness_col_values(&mut self) -> Option<HashMap<String, Vec<T>>> {
         let mut addr = vec![];
         let mut step = vec![];
         let mut value = vec![];
@@ -279,19 +284,75 @@ impl<'a, T: FieldElement> Machine<'a, T> for DoubleSortedWitnesses<T> {
             vec![]
         };
 
+        Some(
+            [
+                (self.namespaced("m_value"), value),
+                (self.namespaced("m_addr"), addr),
+                (self.namespaced("m_step"), step),
+                (self.namespaced("m_change"), change),
+                (self.namespaced("m_is_write"), is_normal_write),
+                (self.namespaced("m_is_read"), is_read),
+            ]
+            .into_iter()
+            .chain(diff_columns)
+            .chain(is_bootloader_write)
+            .collect(),
+        )
+    }
+
+    fn state(&self) -> StateColumns<T> {
+        let ((addr, step), operations): ((Vec<_>, Vec<_>), Vec<_>) = self
+            .trace
+            .iter()
+            .map(|(&(addr, step), o)| ((addr, step), o))
+            .unzip();
         [
-            (self.namespaced("m_value"), value),
-            (self.namespaced("m_addr"), addr),
-            (self.namespaced("m_step"), step),
-            (self.namespaced("m_change"), change),
-            (self.namespaced("m_is_write"), is_normal_write),
-            (self.namespaced("m_is_read"), is_read),
+            ("trace.addr", addr),
+            ("trace.step", step),
+            ("trace.value", operations.iter().map(|o| o.value).collect()),
+            (
+                "trace.is_normal_write",
+                operations
+                    .iter()
+                    .map(|o| o.is_normal_write.into())
+                    .collect(),
+            ),
+            (
+                "trace.is_bootloader_write",
+                operations
+                    .iter()
+                    .map(|o| o.is_bootloader_write.into())
+                    .collect(),
+            ),
+            ("data.addr", self.data.keys().cloned().collect()),
+            ("data.value", self.data.values().cloned().collect()),
         ]
         .into_iter()
-        .chain(diff_columns)
-        .chain(is_bootloader_write)
+        .map(|(name, values)| (name.to_string(), values))
         .collect()
     }
+
+    fn restore_state(&mut self, mut state: StateColumns<T>) {
+        let addr = take_column(&mut state, "trace.addr");
+        let step = take_column(&mut state, "trace.step");
+        let value = take_column(&mut state, "trace.value");
+        let is_normal_write = take_column(&mut state, "trace.is_normal_write");
+        let is_bootloader_write = take_column(&mut state, "trace.is_bootloader_write");
+        self.trace = (0..addr.len())
+            .map(|i| {
+                let operation = Operation {
+                    is_normal_write: is_normal_write[i].is_one(),
+                    is_bootloader_write: is_bootloader_write[i].is_one(),
+                    value: value[i],
+                };
+                ((addr[i], step[i]), operation)
+            })
+            .collect();
+        self.data = take_column(&mut state, "data.addr")
+            .into_iter()
+            .zip(take_column(&mut state, "data.value"))
+            .collect();
+    }
 }
 
 impl<T: FieldElement> DoubleSortedWitnesses<T> {
@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::mem;
 use std::num::NonZeroUsize;
+use std::sync::Arc;
 
 use itertools::Itertools;
 use powdr_ast::analyzed::{
@@ -8,8 +9,10 @@ use powdr_ast::analyzed::{
 };
 use powdr_ast::parsed::SelectedExpressions;
 use powdr_number::FieldElement;
+use tiny_keccak::{Hasher, Keccak};
 
 use crate::witgen::affine_expression::AffineExpression;
+use crate::witgen::checkpoint::{take_column, StateColumns};
 use crate::witgen::global_constraints::{GlobalConstraints, RangeConstraintSet};
 use crate::witgen::machines::record_start;
 use crate::witgen::range_constraints::RangeConstraint;
@@ -23,7 +26,7 @@ use super::record_end;
 type Application = (Vec<PolyID>, Vec<PolyID>);
 type Index<T> = BTreeMap<Vec<T>, IndexValue>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct IndexValue(Option<NonZeroUsize>);
 
 impl IndexValue {
@@ -43,9 +46,15 @@ impl IndexValue {
 /// Indices for applications of fixed columns. For each application `(INPUT_COLS, OUTPUT_COLS)`, stores
 /// - `(V, None)` if there exists two different rows where `INPUT_COLS == V` match but `OUTPUT_COLS` differ. TODO: store bitmasks of all possible outputs instead.
 /// - `(V, Some(row)` if the value of `OUTPUT_COLS` is unique when `INPUT_COLS == V`, and `row` is the first row where `INPUT_COLS ==V`
-#[derive(Default)]
+///
+/// The indices are shared, so that cloning is cheap.
+#[derive(Default, Clone)]
 pub struct IndexedColumns<T> {
-    indices: HashMap<Application, Index<T>>,
+    indices: HashMap<Application, Arc<Index<T>>>,
+    /// Indices loaded from a cache, by their cache key. They are moved to `indices` on first use.
+    cached: HashMap<String, Arc<Index<T>>>,
+    /// Whether any index was built instead of being taken from the cache.
+    built_new_index: bool,
 }
 
 impl<T: FieldElement> IndexedColumns<T> {
@@ -82,6 +91,16 @@ impl<T: FieldElement> IndexedColumns<T> {
             return;
         }
 
+        if !self.cached.is_empty() {
+            let key = cache_key(fixed_data, sorted_fixed_columns);
+            if let Some(index) = self.cached.remove(&key) {
+                log::trace!("Using cached index {key}");
+                self.indices.insert(sorted_fixed_columns.clone(), index);
+                return;
+            }
+        }
+        self.built_new_index = true;
+
         let (sorted_input_fixed_columns, sorted_output_fixed_columns) = &sorted_fixed_columns;
 
         // create index for this lookup
@@ -157,12 +176,174 @@ impl<T: FieldElement> IndexedColumns<T> {
                 sorted_input_fixed_columns.clone(),
                 sorted_output_fixed_columns.clone(),
             ),
-            index,
+            Arc::new(index),
         );
     }
+
+    /// Adds the indices of `other`, a clone of `self` that was used independently.
+    fn merge(&mut self, other: IndexedColumns<T>) {
+        for (application, index) in other.indices {
+            self.indices.entry(application).or_insert(index);
+        }
+        // The cached indices that `other` used are in `indices` now.
+        self.cached.retain(|key, _| other.cached.contains_key(key));
+        self.built_new_index |= other.built_new_index;
+    }
+}
+
+impl<T: FieldElement> IndexedColumns<T> {
+    /// Returns the indices as columns. For the `k`-th index, `k.inputs` and `k.outputs`
+    /// contain the IDs of the fixed columns, `k.keys` the concatenated input values and
+    /// `k.rows` the matching row plus one, or zero if there are multiple matches.
+    fn state(&self) -> StateColumns<T> {
+        let mut state = StateColumns::new();
+        let sorted_indices = self.indices.iter().sorted_by(|(a, _), (b, _)| a.cmp(b));
+        for (k, ((inputs, outputs), index)) in sorted_indices.enumerate() {
+            let ids = |polys: &[PolyID]| polys.iter().map(|p| T::from(p.id)).collect();
+            state.insert(format!("{k}.inputs"), ids(inputs));
+            state.insert(format!("{k}.outputs"), ids(outputs));
+            store_index(&mut state, &format!("{k}."), index);
+        }
+        state
+    }
+
+    fn restore_state(&mut self, mut state: StateColumns<T>) {
+        let fixed_poly = |id: &T| PolyID {
+            id: id.to_degree(),
+            ptype: PolynomialType::Constant,
+        };
+        self.indices = (0..)
+            .map_while(|k| {
+                let inputs = state.remove(&format!("{k}.inputs"))?;
+                let outputs = take_column(&mut state, &format!("{k}.outputs"));
+                let index = Arc::new(take_index(&mut state, &format!("{k}."), inputs.len()));
+                let application = (
+                    inputs.iter().map(fixed_poly).collect(),
+                    outputs.iter().map(fixed_poly).collect(),
+                );
+                Some((application, index))
+            })
+            .collect();
+    }
+
+    /// Returns the indices in the format of [IndexedColumns::state], but keyed by a hash
+    /// of the values of their fixed columns instead of the column IDs, so that they stay
+    /// valid as long as the fixed columns do not change.
+    /// Indices that were loaded from a cache but not used are included as well.
+    fn index_cache(&self, fixed_data: &FixedData<T>) -> BTreeMap<String, StateColumns<T>> {
+        let cached = self.cached.iter().map(|(key, index)| {
+            // All keys of an index have the same length.
+            let width = index.keys().next().map_or(0, |key| key.len());
+            (key.clone(), cache_entry(width, index))
+        });
+        self.indices
+            .iter()
+            .map(|(application, index)| {
+                (
+                    cache_key(fixed_data, application),
+                    cache_entry(application.0.len(), index),
+                )
+            })
+            .chain(cached)
+            .collect()
+    }
+
+    /// Loads indices from a cache written by [IndexedColumns::index_cache].
+    /// Malformed entries are ignored, so that their indices are built again.
+    fn load_index_cache(&mut self, cache: BTreeMap<String, StateColumns<T>>) {
+        self.cached = cache
+            .into_iter()
+            .filter_map(|(key, state)| {
+                let index = load_cache_entry(state);
+                if index.is_none() {
+                    log::warn!("Ignoring malformed entry {key} in the fixed lookup cache.");
+                }
+                Some((key, Arc::new(index?)))
+            })
+            .collect();
+    }
+}
+
+fn cache_entry<T: FieldElement>(width: usize, index: &Index<T>) -> StateColumns<T> {
+    let mut state = StateColumns::new();
+    state.insert("width".to_string(), vec![T::from(width as u64)]);
+    store_index(&mut state, "", index);
+    state
+}
+
+fn load_cache_entry<T: FieldElement>(mut state: StateColumns<T>) -> Option<Index<T>> {
+    let width = match state.get("width")?.as_slice() {
+        [width] => width.to_degree() as usize,
+        _ => return None,
+    };
+    let (keys, rows) = (state.get("keys")?, state.get("rows")?);
+    if keys.len() != rows.len() * width {
+        return None;
+    }
+    Some(take_index(&mut state, "", width))
+}
+
+/// The key of an index in the cache: A hash of the number of input and output columns
+/// and the values of all columns. Column IDs are not included, since they can change
+/// without affecting the index. Keccak is used so that the key is stable across runs
+/// and collisions are not a concern.
+fn cache_key<T: FieldElement>(
+    fixed_data: &FixedData<T>,
+    (inputs, outputs): &Application,
+) -> String {
+    let mut hasher = Keccak::v256();
+    hasher.update(&(inputs.len() as u64).to_le_bytes());
+    hasher.update(&(outputs.len() as u64).to_le_bytes());
+    for id in inputs.iter().chain(outputs) {
+        let values = &fixed_data.fixed_cols[id].values;
+        hasher.update(&(values.len() as u64).to_le_bytes());
+        for value in values.iter() {
+            hasher.update(&value.to_bytes_le());
+        }
+    }
+    let mut hash = [0u8; 32];
+    hasher.finalize(&mut hash);
+    hash.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Stores the index as the columns `<prefix>keys` (the concatenated input values) and
+/// `<prefix>rows` (the matching row plus one, or zero if there are multiple matches).
+fn store_index<T: FieldElement>(state: &mut StateColumns<T>, prefix: &str, index: &Index<T>) {
+    state.insert(
+        format!("{prefix}keys"),
+        index.keys().flatten().cloned().collect(),
+    );
+    state.insert(
+        format!("{prefix}rows"),
+        index
+            .values()
+            .map(|v| v.row().map_or(0, |row| row as u64 + 1).into())
+            .collect(),
+    );
+}
+
+fn take_index<T: FieldElement>(
+    state: &mut StateColumns<T>,
+    prefix: &str,
+    width: usize,
+) -> Index<T> {
+    let keys = take_column(state, &format!("{prefix}keys"));
+    let rows = take_column(state, &format!("{prefix}rows"));
+    rows.iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let key = keys[i * width..(i + 1) * width].to_vec();
+            let value = match row.to_degree() {
+                0 => IndexValue::multiple_matches(),
+                row => IndexValue::single_row(row as usize - 1),
+            };
+            (key, value)
+        })
+        .collect()
 }
 
 /// Machine to perform a lookup in fixed columns only.
+#[derive(Clone)]
 pub struct FixedLookup<T: FieldElement> {
     global_constraints: GlobalConstraints<T>,
     indices: IndexedColumns<T>,
@@ -176,6 +357,38 @@ impl<T: FieldElement> FixedLookup<T> {
         }
     }
 
+    /// Returns the indices created so far, so that they can be stored in a checkpoint.
+    pub fn state(&self) -> StateColumns<T> {
+        self.indices.state()
+    }
+
+    pub fn restore_state(&mut self, state: StateColumns<T>) {
+        self.indices.restore_state(state)
+    }
+
+    /// Returns the indices created so far, keyed by a hash of the fixed column values
+    /// and the shape of the lookup, so that they can be reused in later runs.
+    pub fn index_cache(&self, fixed_data: &FixedData<T>) -> BTreeMap<String, StateColumns<T>> {
+        self.indices.index_cache(fixed_data)
+    }
+
+    /// Uses indices from a previous run (see [FixedLookup::index_cache]) instead of
+    /// building them again. Entries whose key does not match any lookup are ignored.
+    pub fn load_index_cache(&mut self, cache: BTreeMap<String, StateColumns<T>>) {
+        self.indices.load_index_cache(cache)
+    }
+
+    /// Adds the indices built by `other`, a clone of this machine that was used
+    /// independently, e.g. in another thread.
+    pub fn merge(&mut self, other: FixedLookup<T>) {
+        self.indices.merge(other.indices)
+    }
+
+    /// Whether any index had to be built because it was not found in the cache.
+    pub fn built_new_index(&self) -> bool {
+        self.indices.built_new_index
+    }
+
     pub fn process_plookup_timed<'b>(
         &mut self,
         fixed_data: &FixedData<T>,
@@ -347,3 +560,101 @@ impl<T: FieldElement> RangeConstraintSet<&AlgebraicReference, T>
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use crate::constant_evaluator::generate;
+
+    use super::*;
+
+    fn with_fixed_data(src: &str, f: impl FnOnce(&FixedData<GoldilocksField>)) {
+        let analyzed = analyze_string::<GoldilocksField>(src);
+        let constants = generate(&analyzed)
+            .into_iter()
+            .map(|(n, c)| (n.to_string(), c))
+            .collect::<Vec<_>>();
+        f(&FixedData::new(&analyzed, &constants, vec![]))
+    }
+
+    fn lookup_row(
+        indices: &mut IndexedColumns<GoldilocksField>,
+        fixed_data: &FixedData<GoldilocksField>,
+        value: u64,
+    ) -> Option<usize> {
+        let (input, output) = (
+            fixed_data.column_by_name["N.B"],
+            fixed_data.column_by_name["N.A"],
+        );
+        indices
+            .get_match(fixed_data, vec![(input, value.into())], vec![output])
+            .and_then(|v| v.row())
+    }
+
+    #[test]
+    fn index_cache() {
+        let mut cache = Default::default();
+        with_fixed_data(
+            r#"namespace N(16);
+                col fixed A(i) { i % 4 };
+                col fixed B(i) { i * 2 };
+            "#,
+            |fixed_data| {
+                let mut indices = IndexedColumns::default();
+                assert_eq!(lookup_row(&mut indices, fixed_data, 6), Some(3));
+                assert!(indices.built_new_index);
+                cache = indices.index_cache(fixed_data);
+
+                // The cached index is used for the same fixed columns.
+                let mut indices = IndexedColumns::default();
+                indices.load_index_cache(cache.clone());
+                assert_eq!(lookup_row(&mut indices, fixed_data, 6), Some(3));
+                assert!(!indices.built_new_index);
+            },
+        );
+        with_fixed_data(
+            r#"namespace N(16);
+                col fixed A(i) { i % 4 };
+                col fixed B(i) { i * 3 };
+            "#,
+            |fixed_data| {
+                // The values of B changed, so the index has to be built again.
+                let mut indices = IndexedColumns::default();
+                indices.load_index_cache(cache.clone());
+                assert_eq!(lookup_row(&mut indices, fixed_data, 6), Some(2));
+                assert!(indices.built_new_index);
+
+                // The unused index is kept in the cache.
+                let new_cache = indices.index_cache(fixed_data);
+                assert_eq!(new_cache.len(), 2);
+                assert!(cache.keys().all(|key| new_cache.contains_key(key)));
+            },
+        );
+    }
+
+    #[test]
+    fn malformed_index_cache() {
+        with_fixed_data(
+            r#"namespace N(16);
+                col fixed A(i) { i % 4 };
+                col fixed B(i) { i * 2 };
+            "#,
+            |fixed_data| {
+                let mut indices = IndexedColumns::default();
+                assert_eq!(lookup_row(&mut indices, fixed_data, 6), Some(3));
+                let mut cache = indices.index_cache(fixed_data);
+                for state in cache.values_mut() {
+                    state.remove("width");
+                }
+
+                // The malformed index is built again.
+                let mut indices = IndexedColumns::default();
+                indices.load_index_cache(cache);
+                assert_eq!(lookup_row(&mut indices, fixed_data, 6), Some(3));
+                assert!(indices.built_new_index);
+            },
+        );
+    }
+}
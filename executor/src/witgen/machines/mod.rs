@@ -15,7 +15,9 @@ use self::write_once_memory::WriteOnceMemory;
 use powdr_ast::analyzed::IdentityKind;
 
 use super::affine_expression::AffineExpression;
+use super::checkpoint::StateColumns;
 use super::generator::Generator;
+use super::EvalError;
 use super::EvalResult;
 use super::FixedData;
 use super::MutableState;
@@ -62,12 +64,41 @@ pub trait Machine<'a, T: FieldElement>: Send + Sync {
         right: &'a SelectedExpressions<Expression<T>>,
     ) -> Option<EvalResult<'a, T>>;
 
+    /// Sets whether the machine may defer calls until after the main pass. Calls that are
+    /// made to check proposed values are not deferred, so that a failing call rejects the
+    /// proposal and a rejected proposal does not leave a call behind.
+    fn set_deferring_calls(&mut self, _enabled: bool) {}
+
+    /// Processes the calls whose processing the machine deferred until after the main pass.
+    /// Returns an error if one of them fails.
+    fn process_deferred_calls<'b, Q: QueryCallback<T>>(
+        &mut self,
+        _fixed_lookup: &'b mut FixedLookup<T>,
+        _query_callback: &'b mut Q,
+    ) -> Result<(), EvalError<T>> {
+        Ok(())
+    }
+
     /// Returns the final values of the witness columns.
     fn take_witness_col_values<'b, Q: QueryCallback<T>>(
         &mut self,
         fixed_lookup: &'b mut FixedLookup<T>,
         query_callback: &'b mut Q,
     ) -> HashMap<String, Vec<T>>;
+
+    /// Like [Machine::take_witness_col_values], but returns None if finalizing the machine
+    /// requires access to the fixed lookup machine or the query callback.
+    /// Machines that do not need them can be finalized concurrently.
+    fn try_take_witness_col_values(&mut self) -> Option<HashMap<String, Vec<T>>> {
+        None
+    }
+
+    /// Returns the data collected by the machine so far, so that it can be stored in a
+    /// checkpoint. Only called between calls to the machine.
+    fn state(&self) -> StateColumns<T>;
+
+    /// Replaces the data of the machine by the state stored in a checkpoint.
+    fn restore_state(&mut self, state: StateColumns<T>);
 }
 
 /// All known implementations of [Machine].
@@ -110,6 +141,36 @@ impl<'a, T: FieldElement> Machine<'a, T> for KnownMachine<'a, T> {
         }
     }
 
+    fn set_deferring_calls(&mut self, enabled: bool) {
+        match self {
+            KnownMachine::SortedWitnesses(m) => m.set_deferring_calls(enabled),
+            KnownMachine::DoubleSortedWitnesses(m) => m.set_deferring_calls(enabled),
+            KnownMachine::WriteOnceMemory(m) => m.set_deferring_calls(enabled),
+            KnownMachine::BlockMachine(m) => m.set_deferring_calls(enabled),
+            KnownMachine::Vm(m) => m.set_deferring_calls(enabled),
+        }
+    }
+
+    fn process_deferred_calls<'b, Q: QueryCallback<T>>(
+        &mut self,
+        fixed_lookup: &'b mut FixedLookup<T>,
+        query_callback: &'b mut Q,
+    ) -> Result<(), EvalError<T>> {
+        match self {
+            KnownMachine::SortedWitnesses(m) => {
+                m.process_deferred_calls(fixed_lookup, query_callback)
+            }
+            KnownMachine::DoubleSortedWitnesses(m) => {
+                m.process_deferred_calls(fixed_lookup, query_callback)
+            }
+            KnownMachine::WriteOnceMemory(m) => {
+                m.process_deferred_calls(fixed_lookup, query_callback)
+            }
+            KnownMachine::BlockMachine(m) => m.process_deferred_calls(fixed_lookup, query_callback),
+            KnownMachine::Vm(m) => m.process_deferred_calls(fixed_lookup, query_callback),
+        }
+    }
+
     fn take_witness_col_values<'b, Q: QueryCallback<T>>(
         &mut self,
         fixed_lookup: &'b mut FixedLookup<T>,
@@ -131,4 +192,34 @@ impl<'a, T: FieldElement> Machine<'a, T> for KnownMachine<'a, T> {
             KnownMachine::Vm(m) => m.take_witness_col_values(fixed_lookup, query_callback),
         }
     }
+
+    fn try_take_witness_col_values(&mut self) -> Option<HashMap<String, Vec<T>>> {
+        match self {
+            KnownMachine::SortedWitnesses(m) => m.try_take_witness_col_values(),
+            KnownMachine::DoubleSortedWitnesses(m) => m.try_take_witness_col_values(),
+            KnownMachine::WriteOnceMemory(m) => m.try_take_witness_col_values(),
+            KnownMachine::BlockMachine(m) => m.try_take_witness_col_values(),
+            KnownMachine::Vm(m) => m.try_take_witness_col_values(),
+        }
+    }
+
+    fn state(&self) -> StateColumns<T> {
+        match self {
+            KnownMachine::SortedWitnesses(m) => m.state(),
+            KnownMachine::DoubleSortedWitnesses(m) => m.state(),
+            KnownMachine::WriteOnceMemory(m) => m.state(),
+            KnownMachine::BlockMachine(m) => m.state(),
+            KnownMachine::Vm(m) => m.state(),
+        }
+    }
+
+    fn restore_state(&mut self, state: StateColumns<T>) {
+        match self {
+            KnownMachine::SortedWitnesses(m) => m.restore_state(state),
+            KnownMachine::DoubleSortedWitnesses(m) => m.restore_state(state),
+            KnownMachine::WriteOnceMemory(m) => m.restore_state(state),
+            KnownMachine::BlockMachine(m) => m.restore_state(state),
+            KnownMachine::Vm(m) => m.restore_state(state),
+        }
+    }
 }
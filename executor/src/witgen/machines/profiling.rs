@@ -1,21 +1,30 @@
 use std::{
     cell::RefCell,
     collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
     time::{Duration, Instant},
 };
 
+use itertools::Itertools;
+
 #[derive(PartialEq, Debug, Copy, Clone)]
 enum Event {
     Start,
     End,
 }
 
+type EventLog = Vec<(Event, usize, Instant)>;
+
 thread_local! {
     /// The event log is a list of (event, <ID>, time) tuples.
-    static EVENT_LOG: RefCell<Vec<(Event, usize, Instant)>> = RefCell::new(Vec::new());
+    static EVENT_LOG: RefCell<EventLog> = RefCell::new(Vec::new());
     /// Maps a machine name (assumed to be globally unique) to an ID.
     /// This is done so that we can use a usize in the event log.
     static NAME_TO_ID: RefCell<BTreeMap<String, usize>> = RefCell::new(BTreeMap::new());
+    /// Event logs of computations that other threads ran on behalf of this thread.
+    static WORKER_LOGS: RefCell<Vec<WorkerLog>> = RefCell::new(Vec::new());
 }
 
 /// Returns the ID for a given machine name, creating a new one if necessary.
@@ -42,71 +51,179 @@ pub fn record_end(name: &str) {
     EVENT_LOG.with(|s| s.borrow_mut().push((Event::End, id, Instant::now())));
 }
 
-pub fn reset_and_print_profile_summary() {
-    EVENT_LOG.with(|event_log| {
-        let id_to_name = NAME_TO_ID.with(|name_to_id| {
-            let name_to_id = name_to_id.borrow();
-            name_to_id
-                .iter()
-                .map(|(name, id)| (*id, name.clone()))
-                .collect::<BTreeMap<_, _>>()
-        });
-
-        // Taking the events out is actually important, because there might be
-        // multiple (consecutive) runs of witgen in the same thread.
-        let event_log = std::mem::take(&mut (*event_log.borrow_mut()));
-        log::debug!("\n == Witgen profile ({} events)", event_log.len());
+/// The events that a computation recorded in a worker thread, see [record_in_worker].
+pub struct WorkerEvents {
+    thread: Option<usize>,
+    start: Instant,
+    end: Instant,
+    events: EventLog,
+    id_to_name: BTreeMap<usize, String>,
+}
 
-        // Aggregate time spent in each machine.
-        let mut time_by_machine = BTreeMap::new();
-        assert_eq!(event_log[0].0, Event::Start);
-        let mut current_time = event_log[0].2;
-        let mut call_stack = vec![event_log[0].1];
-
-        for (i, &(event, id, time)) in event_log.iter().enumerate().skip(1) {
-            // We expect one top-level call, so we should never have an empty call stack.
-            let current_machine_id = *call_stack.last().unwrap_or_else(|| {
-                panic!(
-                    "Call stack is empty at index {} (event: {:?}, name: {}, time: {:?})",
-                    i, event, id, time
-                )
-            });
+/// Runs `f` and takes the events it records in the current thread, so that they can be
+/// added to the profile of the thread that waits for the result, see [add_worker_events].
+pub fn record_in_worker<R>(f: impl FnOnce() -> R) -> (R, WorkerEvents) {
+    // The worker might be the waiting thread itself, so we only take the new events.
+    let first_event = EVENT_LOG.with(|event_log| event_log.borrow().len());
+    let start = Instant::now();
+    let result = f();
+    let end = Instant::now();
+    let events = EVENT_LOG.with(|event_log| event_log.borrow_mut().split_off(first_event));
+    let id_to_name = NAME_TO_ID.with(|name_to_id| {
+        name_to_id
+            .borrow()
+            .iter()
+            .filter(|(_, id)| events.iter().any(|(_, event_id, _)| event_id == *id))
+            .map(|(name, id)| (*id, name.clone()))
+            .collect()
+    });
+    let events = WorkerEvents {
+        thread: rayon::current_thread_index(),
+        start,
+        end,
+        events,
+        id_to_name,
+    };
+    (result, events)
+}
 
-            // Finish the execution of the currently running machine.
-            let duration = time.duration_since(current_time);
-            *time_by_machine
-                .entry(current_machine_id)
-                .or_insert(Duration::default()) += duration;
-            current_time = time;
+/// An event log recorded in a worker thread.
+struct WorkerLog {
+    /// The rayon index of the worker thread, if any.
+    thread: Option<usize>,
+    /// The call stack of the waiting thread when the events were added.
+    /// The events are nested in it.
+    call_stack: Vec<usize>,
+    start: Instant,
+    end: Instant,
+    events: EventLog,
+}
 
-            // Update the call stack.
+/// Adds the events of computations that other threads ran on behalf of the current thread
+/// to its profile, nested in the machine that is currently running.
+pub fn add_worker_events(workers: impl IntoIterator<Item = WorkerEvents>) {
+    let call_stack = EVENT_LOG.with(|event_log| {
+        let mut call_stack = vec![];
+        for (event, id, _) in event_log.borrow().iter() {
             match event {
-                Event::Start => {
-                    assert!(current_machine_id != id, "Unexpected recursive call!");
-                    call_stack.push(id);
-                }
+                Event::Start => call_stack.push(*id),
                 Event::End => {
-                    assert_eq!(current_machine_id, id, "Unexpected end of call!");
-                    call_stack.pop().unwrap();
+                    call_stack.pop();
                 }
             }
         }
+        call_stack
+    });
+    assert!(
+        !call_stack.is_empty(),
+        "Worker events have to be added inside of a call."
+    );
+    let logs = workers
+        .into_iter()
+        .map(|worker| WorkerLog {
+            thread: worker.thread,
+            call_stack: call_stack.clone(),
+            start: worker.start,
+            end: worker.end,
+            // The IDs are local to the worker thread.
+            events: worker
+                .events
+                .into_iter()
+                .map(|(event, id, time)| (event, id_from_name(&worker.id_to_name[&id]), time))
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    WORKER_LOGS.with(|worker_logs| worker_logs.borrow_mut().extend(logs));
+}
+
+/// The event log of a witgen run, taken out of the thread-local storage.
+pub struct Profile {
+    events: EventLog,
+    workers: Vec<WorkerLog>,
+    id_to_name: BTreeMap<usize, String>,
+}
+
+/// Takes the event log recorded so far, so that the next witgen run in the same
+/// thread starts with an empty log.
+pub fn take_profile() -> Profile {
+    let id_to_name = NAME_TO_ID.with(|name_to_id| {
+        let name_to_id = name_to_id.borrow();
+        name_to_id
+            .iter()
+            .map(|(name, id)| (*id, name.clone()))
+            .collect::<BTreeMap<_, _>>()
+    });
+
+    // Taking the events out is actually important, because there might be
+    // multiple (consecutive) runs of witgen in the same thread.
+    let events = EVENT_LOG.with(|event_log| std::mem::take(&mut (*event_log.borrow_mut())));
+    let workers = WORKER_LOGS.with(|worker_logs| std::mem::take(&mut (*worker_logs.borrow_mut())));
+    Profile {
+        events,
+        workers,
+        id_to_name,
+    }
+}
 
+impl Profile {
+    /// Computes the time spent in each call stack, excluding the time spent in nested calls.
+    /// The time spent in worker threads is added to the call stacks they ran in, so the
+    /// total can be larger than the wall-clock time.
+    fn self_time_by_stack(&self) -> BTreeMap<Vec<usize>, Duration> {
+        let event_log = &self.events;
+        let mut time_by_stack = BTreeMap::new();
+        assert_eq!(event_log[0].0, Event::Start);
+        let (call_stack, _) = add_self_times(
+            &mut time_by_stack,
+            &event_log[1..],
+            vec![event_log[0].1],
+            event_log[0].2,
+        );
         assert!(
             call_stack.is_empty(),
             "Call stack is not empty: {:?}",
             call_stack
         );
 
+        for worker in &self.workers {
+            let (call_stack, current_time) = add_self_times(
+                &mut time_by_stack,
+                &worker.events,
+                worker.call_stack.clone(),
+                worker.start,
+            );
+            assert_eq!(call_stack, worker.call_stack, "Unexpected end of call!");
+            *time_by_stack.entry(call_stack).or_default() +=
+                worker.end.duration_since(current_time);
+        }
+        time_by_stack
+    }
+
+    pub fn print_summary(&self) {
+        log::debug!("\n == Witgen profile ({} events)", self.events.len());
+
+        // Aggregate time spent in each machine.
+        let mut time_by_machine = BTreeMap::new();
+        for (stack, duration) in self.self_time_by_stack() {
+            *time_by_machine
+                .entry(*stack.last().unwrap())
+                .or_insert(Duration::default()) += duration;
+        }
+
         // Sort by time, descending.
         let mut time_by_machine = time_by_machine.into_iter().collect::<Vec<_>>();
         time_by_machine.sort_by(|a, b| b.1.cmp(&a.1));
 
         let total_time = time_by_machine.iter().map(|(_, d)| *d).sum::<Duration>();
-        assert_eq!(
-            event_log.last().unwrap().2.duration_since(event_log[0].2),
-            total_time
-        );
+        let wall_time = self
+            .events
+            .last()
+            .unwrap()
+            .2
+            .duration_since(self.events[0].2);
+        if self.workers.is_empty() {
+            assert_eq!(wall_time, total_time);
+        }
 
         for (id, duration) in time_by_machine {
             let percentage = (duration.as_secs_f64() / total_time.as_secs_f64()) * 100.0;
@@ -114,11 +231,244 @@ pub fn reset_and_print_profile_summary() {
                 "  {:>5.1}% ({:>8.1?}): {}",
                 percentage,
                 duration,
-                id_to_name[&id]
+                self.id_to_name[&id]
             );
         }
         log::debug!("  ---------------------------");
         log::debug!("    ==> Total: {:?}", total_time);
+        if !self.workers.is_empty() {
+            log::debug!("        (including worker threads, wall-clock time: {wall_time:?})");
+        }
         log::debug!("\n");
-    });
+    }
+
+    /// Writes the event log in the Chrome Trace Event format, which can be loaded
+    /// into Perfetto or `chrome://tracing`.
+    pub fn write_chrome_trace(&self, mut writer: impl Write) -> io::Result<()> {
+        let Some((_, _, start)) = self.events.first() else {
+            return writeln!(writer, "[]");
+        };
+        let entry = |event: Event, id: usize, time: Instant, thread: usize| {
+            let phase = match event {
+                Event::Start => "B",
+                Event::End => "E",
+            };
+            format!(
+                r#"  {{"name": "{}", "ph": "{phase}", "ts": {:.3}, "pid": 0, "tid": {thread}}}"#,
+                escape_json(&self.id_to_name[&id]),
+                time.duration_since(*start).as_secs_f64() * 1_000_000.0,
+            )
+        };
+        let main_entries = self
+            .events
+            .iter()
+            .map(|&(event, id, time)| entry(event, id, time, 0));
+        // Each worker log is shown on the track of its thread, wrapped in a span
+        // named after the machine it ran in.
+        let worker_entries = self.workers.iter().flat_map(|worker| {
+            let thread = worker.thread.map_or(0, |t| t + 1);
+            let parent = *worker.call_stack.last().unwrap();
+            std::iter::once(entry(Event::Start, parent, worker.start, thread))
+                .chain(
+                    worker
+                        .events
+                        .iter()
+                        .map(move |&(event, id, time)| entry(event, id, time, thread)),
+                )
+                .chain(std::iter::once(entry(
+                    Event::End,
+                    parent,
+                    worker.end,
+                    thread,
+                )))
+        });
+        writeln!(writer, "[")?;
+        writeln!(writer, "{}", main_entries.chain(worker_entries).join(",\n"))?;
+        writeln!(writer, "]")
+    }
+
+    /// Writes the time spent in each call stack in the "folded stacks" format,
+    /// which is the input format of flamegraph tools like `inferno` or `flamegraph.pl`.
+    /// Times are given in microseconds.
+    pub fn write_folded_stacks(&self, mut writer: impl Write) -> io::Result<()> {
+        if self.events.is_empty() {
+            return Ok(());
+        }
+        for (stack, duration) in self.self_time_by_stack() {
+            writeln!(
+                writer,
+                "{} {}",
+                stack
+                    .iter()
+                    .map(|id| self.id_to_name[id].replace(';', ":"))
+                    .join(";"),
+                duration.as_micros()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the profile to the given file. Files ending in `.json` are written
+    /// in the Chrome Trace Event format, all others as folded stacks.
+    pub fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        if path.extension().is_some_and(|ext| ext == "json") {
+            self.write_chrome_trace(&mut writer)?;
+        } else {
+            self.write_folded_stacks(&mut writer)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Adds the time between consecutive events to the call stack that was active in between,
+/// starting with `call_stack` at `current_time`.
+/// @returns the call stack and the time after the last event.
+fn add_self_times(
+    time_by_stack: &mut BTreeMap<Vec<usize>, Duration>,
+    events: &[(Event, usize, Instant)],
+    mut call_stack: Vec<usize>,
+    mut current_time: Instant,
+) -> (Vec<usize>, Instant) {
+    for (i, &(event, id, time)) in events.iter().enumerate() {
+        // We expect one top-level call, so we should never have an empty call stack.
+        let current_machine_id = *call_stack.last().unwrap_or_else(|| {
+            panic!(
+                "Call stack is empty at index {} (event: {:?}, name: {}, time: {:?})",
+                i, event, id, time
+            )
+        });
+
+        // Finish the execution of the currently running machine.
+        let duration = time.duration_since(current_time);
+        *time_by_stack.entry(call_stack.clone()).or_default() += duration;
+        current_time = time;
+
+        // Update the call stack.
+        match event {
+            Event::Start => {
+                assert!(current_machine_id != id, "Unexpected recursive call!");
+                call_stack.push(id);
+            }
+            Event::End => {
+                assert_eq!(current_machine_id, id, "Unexpected end of call!");
+                call_stack.pop().unwrap();
+            }
+        }
+    }
+    (call_stack, current_time)
+}
+
+fn escape_json(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '"' => vec!['\\', '"'],
+            '\\' => vec!['\\', '\\'],
+            c if c.is_control() => format!("\\u{:04x}", c as u32).chars().collect(),
+            c => vec![c],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn nested_profile() -> Profile {
+        let names = ["outer", "Main Machine", "FixedLookup"];
+        let start = Instant::now();
+        let at = |micros| start + Duration::from_micros(micros);
+        Profile {
+            events: vec![
+                (Event::Start, 0, at(0)),
+                (Event::Start, 1, at(10)),
+                (Event::Start, 2, at(15)),
+                (Event::End, 2, at(25)),
+                (Event::End, 1, at(40)),
+                (Event::Start, 2, at(42)),
+                (Event::End, 2, at(47)),
+                (Event::End, 0, at(50)),
+            ],
+            workers: vec![],
+            id_to_name: names
+                .iter()
+                .enumerate()
+                .map(|(id, name)| (id, name.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn folded_stacks() {
+        let mut output = vec![];
+        nested_profile().write_folded_stacks(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "outer 15\nouter;Main Machine 20\nouter;Main Machine;FixedLookup 10\nouter;FixedLookup 5\n"
+        );
+    }
+
+    #[test]
+    fn chrome_trace() {
+        let mut output = vec![];
+        nested_profile().write_chrome_trace(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "[");
+        assert_eq!(
+            lines[2],
+            r#"  {"name": "Main Machine", "ph": "B", "ts": 10.000, "pid": 0, "tid": 0},"#
+        );
+        assert_eq!(
+            lines[8],
+            r#"  {"name": "outer", "ph": "E", "ts": 50.000, "pid": 0, "tid": 0}"#
+        );
+        assert_eq!(lines[9], "]");
+    }
+
+    #[test]
+    fn worker_events() {
+        let mut profile = nested_profile();
+        let start = profile.events[0].2;
+        let at = |micros| start + Duration::from_micros(micros);
+        // A worker that ran a FixedLookup call while the main machine was running.
+        profile.workers.push(WorkerLog {
+            thread: Some(2),
+            call_stack: vec![0, 1],
+            start: at(26),
+            end: at(38),
+            events: vec![(Event::Start, 2, at(30)), (Event::End, 2, at(36))],
+        });
+
+        let mut output = vec![];
+        profile.write_folded_stacks(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "outer 15\nouter;Main Machine 26\nouter;Main Machine;FixedLookup 16\nouter;FixedLookup 5\n"
+        );
+
+        let mut output = vec![];
+        profile.write_chrome_trace(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 14);
+        assert_eq!(
+            lines[8],
+            r#"  {"name": "outer", "ph": "E", "ts": 50.000, "pid": 0, "tid": 0},"#
+        );
+        assert_eq!(
+            lines[10],
+            r#"  {"name": "FixedLookup", "ph": "B", "ts": 30.000, "pid": 0, "tid": 3},"#
+        );
+        assert_eq!(
+            lines[12],
+            r#"  {"name": "Main Machine", "ph": "E", "ts": 38.000, "pid": 0, "tid": 3}"#
+        );
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(escape_json(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
 }
@@ -6,6 +6,9 @@ use powdr_ast::parsed::SelectedExpressions;
 use super::super::affine_expression::AffineExpression;
 use super::{EvalResult, FixedData};
 use super::{FixedLookup, Machine};
+use crate::witgen::checkpoint::{
+    store_optional_rows, take_column, take_optional_rows, StateColumns,
+};
 use crate::witgen::{
     expression_evaluator::ExpressionEvaluator, fixed_evaluator::FixedEvaluator,
     symbolic_evaluator::SymbolicEvaluator,
@@ -165,6 +168,10 @@ impl<'a, T: FieldElement> Machine<'a, T> for SortedWitnesses<'a, T> {
         _fixed_lookup: &'b mut FixedLookup<T>,
         _query_callback: &'b mut Q,
     ) -> HashMap<String, Vec<T>> {
+        self.try_take_witness_col_values().unwrap()
+    }
+
+    fn try_take_witness_col_values(&mut self) -> Option<HashMap<String, Vec<T>>> {
         let mut result = HashMap::new();
 
         let (mut keys, mut values): (Vec<_>, Vec<_>) =
@@ -186,7 +193,24 @@ impl<'a, T: FieldElement> Machine<'a, T> for SortedWitnesses<'a, T> {
             result.insert(self.fixed_data.column_name(col).to_string(), col_values);
         }
 
-        result
+        Some(result)
+    }
+
+    fn state(&self) -> StateColumns<T> {
+        let mut state = StateColumns::new();
+        store_optional_rows(
+            &mut state,
+            self.witness_positions.len(),
+            self.data.values().map(|values| values.as_slice()),
+        );
+        state.insert("keys".to_string(), self.data.keys().cloned().collect());
+        state
+    }
+
+    fn restore_state(&mut self, mut state: StateColumns<T>) {
+        let keys = take_column(&mut state, "keys");
+        let values = take_optional_rows(&mut state, self.witness_positions.len(), keys.len());
+        self.data = keys.into_iter().zip(values).collect();
     }
 }
 
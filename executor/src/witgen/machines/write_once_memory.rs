@@ -10,8 +10,10 @@ use powdr_ast::{
 use powdr_number::{DegreeType, FieldElement};
 
 use crate::witgen::{
-    affine_expression::AffineExpression, util::try_to_simple_poly, EvalError, EvalResult,
-    EvalValue, FixedData, IncompleteCause, MutableState, QueryCallback,
+    affine_expression::AffineExpression,
+    checkpoint::{store_optional_rows, take_column, take_optional_rows, StateColumns},
+    util::try_to_simple_poly,
+    EvalError, EvalResult, EvalValue, FixedData, IncompleteCause, MutableState, QueryCallback,
 };
 
 use super::{FixedLookup, Machine};
@@ -220,27 +222,57 @@ impl<'a, T: FieldElement> Machine<'a, T> for WriteOnceMemory<'a, T> {
         _fixed_lookup: &'b mut FixedLookup<T>,
         _query_callback: &'b mut Q,
     ) -> HashMap<String, Vec<T>> {
-        self.value_polys
-            .iter()
-            .enumerate()
-            .map(|(value_index, poly)| {
-                let column = self.fixed_data.witness_cols[poly]
-                    .external_values
-                    .clone()
-                    .map(|mut external_values| {
-                        // External witness values might only be provided partially.
-                        external_values.resize(self.fixed_data.degree as usize, T::zero());
-                        external_values
-                    })
-                    .unwrap_or_else(|| {
-                        let mut column = vec![T::zero(); self.fixed_data.degree as usize];
-                        for (row, values) in self.data.iter() {
-                            column[*row as usize] = values[value_index].unwrap_or_default();
-                        }
-                        column
-                    });
-                (self.fixed_data.column_name(poly).to_string(), column)
-            })
-            .collect()
+        self.try_take_witness_col_values().unwrap()
+    }
+
+    fn try_take_witness_col_values(&mut self) -> Option<HashMap<String, Vec<T>>> {
+        Some(
+            self.value_polys
+                .iter()
+                .enumerate()
+                .map(|(value_index, poly)| {
+                    let column = self.fixed_data.witness_cols[poly]
+                        .external_values
+                        .clone()
+                        .map(|mut external_values| {
+                            // External witness values might only be provided partially.
+                            external_values.resize(self.fixed_data.degree as usize, T::zero());
+                            external_values
+                        })
+                        .unwrap_or_else(|| {
+                            let mut column = vec![T::zero(); self.fixed_data.degree as usize];
+                            for (row, values) in self.data.iter() {
+                                column[*row as usize] = values[value_index].unwrap_or_default();
+                            }
+                            column
+                        });
+                    (self.fixed_data.column_name(poly).to_string(), column)
+                })
+                .collect(),
+        )
+    }
+
+    fn state(&self) -> StateColumns<T> {
+        let mut state = StateColumns::new();
+        store_optional_rows(
+            &mut state,
+            self.value_polys.len(),
+            self.data.values().map(|values| values.as_slice()),
+        );
+        state.insert(
+            "rows".to_string(),
+            self.data.keys().map(|row| T::from(*row)).collect(),
+        );
+        state
+    }
+
+    fn restore_state(&mut self, mut state: StateColumns<T>) {
+        let rows = take_column(&mut state, "rows");
+        let values = take_optional_rows(&mut state, self.value_polys.len(), rows.len());
+        self.data = rows
+            .into_iter()
+            .map(|row| row.to_degree())
+            .zip(values)
+            .collect();
     }
 }
@@ -1,34 +1,48 @@
 use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use itertools::Itertools;
 use powdr_ast::analyzed::{
     AlgebraicReference, Analyzed, Expression, FunctionValueDefinition, PolyID, PolynomialType,
     SymbolKind,
 };
 use powdr_number::{DegreeType, FieldElement};
+use powdr_parser_util::lines::indent;
+use rayon::prelude::*;
 
+pub use self::checkpoint::{Checkpoint, CheckpointSettings, StateColumns};
 use self::data_structures::column_map::{FixedColumnMap, WitnessColumnMap};
 pub use self::eval_result::{
     Constraint, Constraints, EvalError, EvalResult, EvalStatus, EvalValue, IncompleteCause,
 };
+pub use self::fuzzing::{
+    fuzz_witness, FuzzedWitness, FuzzingConfig, FuzzingError, UnderconstrainedCell,
+};
 use self::generator::Generator;
+pub use self::query::Query;
 
+use self::checkpoint::FIXED_LOOKUP_NAME;
 use self::identity_processor::Machines;
 use self::machines::machine_extractor::ExtractionOutput;
-use self::machines::profiling::{record_end, record_start, reset_and_print_profile_summary};
+use self::machines::profiling::{record_end, record_start, take_profile};
 use self::machines::{FixedLookup, Machine};
 
 mod affine_expression;
 mod block_processor;
+mod checkpoint;
+mod compiled_identity;
 mod data_structures;
 mod eval_result;
 mod expression_evaluator;
 pub mod fixed_evaluator;
+mod fuzzing;
 mod generator;
 mod global_constraints;
 mod identity_processor;
 mod machines;
 mod processor;
+mod query;
 mod query_processor;
 mod range_constraints;
 mod rows;
@@ -40,8 +54,20 @@ mod vm_processor;
 
 static OUTER_CODE_NAME: &str = "witgen (outer code)";
 
-pub trait QueryCallback<T>: Fn(&str) -> Result<Option<T>, String> + Send + Sync {}
-impl<T, F> QueryCallback<T> for F where F: Fn(&str) -> Result<Option<T>, String> + Send + Sync {}
+/// Answers prover queries. Returns `Ok(None)` if the query is not handled by this callback.
+pub trait QueryCallback<T>: Fn(&Query<T>) -> Result<Option<T>, String> + Send + Sync {}
+impl<T, F> QueryCallback<T> for F where F: Fn(&Query<T>) -> Result<Option<T>, String> + Send + Sync {}
+
+/// A query callback that receives the query formatted as a string, e.g. `("input", 3)`.
+pub trait StringQueryCallback<T>: Fn(&str) -> Result<Option<T>, String> + Send + Sync {}
+impl<T, F> StringQueryCallback<T> for F where F: Fn(&str) -> Result<Option<T>, String> + Send + Sync {}
+
+/// Turns a callback that operates on formatted queries into a [QueryCallback].
+pub fn string_query_callback<T: FieldElement>(
+    callback: impl StringQueryCallback<T>,
+) -> impl QueryCallback<T> {
+    move |query: &Query<T>| callback(&query.to_string())
+}
 
 pub fn chain_callbacks<T: FieldElement>(
     c1: Arc<dyn QueryCallback<T>>,
@@ -67,6 +93,11 @@ pub struct WitnessGenerator<'a, 'b, T: FieldElement> {
     fixed_col_values: &'b [(String, Vec<T>)],
     query_callback: &'b dyn QueryCallback<T>,
     external_witness_values: Vec<(String, Vec<T>)>,
+    profile_output: Option<PathBuf>,
+    checkpoints: Option<CheckpointSettings>,
+    resume_from: Option<Checkpoint<T>>,
+    compiled_identities: bool,
+    fixed_lookup_cache: Option<PathBuf>,
 }
 
 impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
@@ -80,6 +111,11 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
             fixed_col_values,
             query_callback,
             external_witness_values: Vec::new(),
+            profile_output: None,
+            checkpoints: None,
+            resume_from: None,
+            compiled_identities: true,
+            fixed_lookup_cache: None,
         }
     }
 
@@ -93,9 +129,57 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
         }
     }
 
+    /// Writes the time spent in each machine to the given file after witness generation.
+    /// Files ending in `.json` are written in the Chrome Trace Event format (loadable
+    /// in Perfetto), all others as folded stacks for flamegraph tools.
+    pub fn with_profile_output(self, profile_output: PathBuf) -> Self {
+        WitnessGenerator {
+            profile_output: Some(profile_output),
+            ..self
+        }
+    }
+
+    /// Periodically writes the state of witness generation to a file while
+    /// running the main machine.
+    pub fn with_checkpoints(self, checkpoints: CheckpointSettings) -> Self {
+        WitnessGenerator {
+            checkpoints: Some(checkpoints),
+            ..self
+        }
+    }
+
+    /// Continues witness generation from a checkpoint instead of starting at the first row.
+    /// The checkpoint has to be taken for the same program and the same inputs.
+    pub fn with_resume_from(self, checkpoint: Checkpoint<T>) -> Self {
+        WitnessGenerator {
+            resume_from: Some(checkpoint),
+            ..self
+        }
+    }
+
+    /// Loads the indices of the fixed lookup machine from the given file if it exists and
+    /// writes them back after witness generation if new indices had to be built.
+    /// Cached indices are only used if the fixed columns they were built from did not change.
+    pub fn with_fixed_lookup_cache(self, path: PathBuf) -> Self {
+        WitnessGenerator {
+            fixed_lookup_cache: Some(path),
+            ..self
+        }
+    }
+
+    /// Enables or disables evaluating the polynomial identities of the main machine
+    /// through closures compiled once before witness generation (enabled by default).
+    /// Disabling it is only useful to compare the performance.
+    pub fn with_compiled_identities(self, compiled_identities: bool) -> Self {
+        WitnessGenerator {
+            compiled_identities,
+            ..self
+        }
+    }
+
     /// Generates the committed polynomial values
     /// @returns the values (in source order) and the degree of the polynomials.
-    pub fn generate(self) -> Vec<(String, Vec<T>)> {
+    pub fn generate(mut self) -> Vec<(String, Vec<T>)> {
         record_start(OUTER_CODE_NAME);
         let fixed = FixedData::new(
             self.analyzed,
@@ -122,6 +206,19 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
             retained_identities,
             &constraints,
         );
+        if let Some(path) = &self.fixed_lookup_cache {
+            load_fixed_lookup_cache(&mut fixed_lookup, path);
+        }
+        if let Some(checkpoint) = &mut self.resume_from {
+            assert_eq!(
+                checkpoint.degree, fixed.degree,
+                "Checkpoint was taken for a different degree."
+            );
+            fixed_lookup.restore_state(checkpoint.take_component(FIXED_LOOKUP_NAME));
+            for m in &mut machines {
+                m.restore_state(checkpoint.take_component(m.name()));
+            }
+        }
         let mut query_callback = self.query_callback;
         let mut mutable_state = MutableState {
             fixed_lookup: &mut fixed_lookup,
@@ -138,25 +235,75 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
             // Instead, the main VM will be computed in one block, directly continuing into the
             // infinite loop after the first return.
             None,
-        );
+        )
+        .with_compiled_identities(self.compiled_identities);
+        if let Some(checkpoints) = self.checkpoints {
+            generator = generator.with_checkpoints(checkpoints);
+        }
 
-        generator.run(&mut mutable_state);
+        match self.resume_from {
+            Some(mut checkpoint) => {
+                let state = checkpoint.take_component(generator.name());
+                generator.resume(&mut mutable_state, state);
+            }
+            None => generator.run(&mut mutable_state),
+        }
 
         // Get columns from machines
         let main_columns = generator
             .take_witness_col_values(mutable_state.fixed_lookup, mutable_state.query_callback);
-        let mut columns = mutable_state
-            .machines
+        // Calls that the machines deferred are processed in a batch now. They were
+        // accepted during the main pass, so a failure means that witness generation fails.
+        let errors = machines
             .iter_mut()
-            .flat_map(|m| {
-                m.take_witness_col_values(mutable_state.fixed_lookup, mutable_state.query_callback)
-                    .into_iter()
+            .filter_map(|m| {
+                m.process_deferred_calls(&mut fixed_lookup, &mut query_callback)
+                    .err()
+            })
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            log::error!(
+                "\nError: Deferred machine calls failed:\n{}\n",
+                errors
+                    .iter()
+                    .map(|e| indent(&e.to_string(), "    "))
+                    .join("\n")
+            );
+            panic!("Witness generation failed.");
+        }
+        // Most secondary machines only need to pad the data they recorded during the main pass,
+        // which is done concurrently.
+        let independent_columns = machines
+            .par_iter_mut()
+            .map(|m| m.try_take_witness_col_values())
+            .collect::<Vec<_>>();
+        // The others need access to the fixed lookup machine and the query callback.
+        let mut columns = machines
+            .iter_mut()
+            .zip(independent_columns)
+            .flat_map(|(m, columns)| {
+                columns.unwrap_or_else(|| {
+                    m.take_witness_col_values(&mut fixed_lookup, &mut query_callback)
+                })
             })
             .chain(main_columns)
             .collect::<BTreeMap<_, _>>();
 
+        if let Some(path) = &self.fixed_lookup_cache {
+            if fixed_lookup.built_new_index() {
+                store_fixed_lookup_cache(&fixed_lookup, &fixed, path);
+            }
+        }
+
         record_end(OUTER_CODE_NAME);
-        reset_and_print_profile_summary();
+        let profile = take_profile();
+        profile.print_summary();
+        if let Some(path) = &self.profile_output {
+            match profile.write_to_file(path) {
+                Ok(()) => log::info!("Wrote witgen profile to {}", path.display()),
+                Err(e) => log::error!("Could not write witgen profile to {}: {e}", path.display()),
+            }
+        }
 
         log::debug!("Publics:");
         for (name, public_declaration) in self.analyzed.public_declarations_in_source_order() {
@@ -178,6 +325,59 @@ impl<'a, 'b, T: FieldElement> WitnessGenerator<'a, 'b, T> {
             })
             .collect()
     }
+
+    /// Generates the committed polynomial values and afterwards perturbs the cells
+    /// selected by `config` to detect witness cells that are not uniquely determined
+    /// by the constraints (see [fuzz_witness]).
+    /// @returns the values (in source order) and the cells that could be changed,
+    /// or an error if the generated witness is not valid.
+    pub fn generate_and_fuzz(
+        self,
+        config: &FuzzingConfig<T>,
+    ) -> Result<FuzzedWitness<T>, FuzzingError> {
+        let (analyzed, fixed_col_values) = (self.analyzed, self.fixed_col_values);
+        let witness = self.generate();
+        let underconstrained = fuzz_witness(analyzed, fixed_col_values, &witness, config)?;
+        Ok(FuzzedWitness {
+            witness,
+            underconstrained,
+        })
+    }
+}
+
+/// Loads the indices of a previous run, stored in the checkpoint format. Failures are not
+/// fatal, since the indices can always be built again.
+fn load_fixed_lookup_cache<T: FieldElement>(fixed_lookup: &mut FixedLookup<T>, path: &Path) {
+    if !path.exists() {
+        return;
+    }
+    match Checkpoint::<T>::read_from_file(path) {
+        Ok(cache) => {
+            log::info!(
+                "Loaded {} fixed lookup indices from {}",
+                cache.components.len(),
+                path.display()
+            );
+            fixed_lookup.load_index_cache(cache.components);
+        }
+        Err(e) => log::warn!("Could not read fixed lookup cache {}: {e}", path.display()),
+    }
+}
+
+fn store_fixed_lookup_cache<T: FieldElement>(
+    fixed_lookup: &FixedLookup<T>,
+    fixed: &FixedData<T>,
+    path: &Path,
+) {
+    let cache = Checkpoint {
+        row: 0,
+        degree: fixed.degree,
+        components: fixed_lookup.index_cache(fixed),
+    };
+    match cache.write_to_file(path) {
+        Ok(()) => log::info!("Wrote fixed lookup cache to {}", path.display()),
+        Err(e) => log::warn!("Could not write fixed lookup cache {}: {e}", path.display()),
+    }
 }
 
 /// Data that is fixed for witness generation.
@@ -7,10 +7,13 @@ use powdr_ast::{
 use powdr_number::{DegreeType, FieldElement};
 use powdr_parser_util::lines::indent;
 
+use crate::witgen::machines::Machine;
 use crate::witgen::{query_processor::QueryProcessor, util::try_to_simple_poly, Constraint};
 
 use super::{
     affine_expression::AffineExpression,
+    checkpoint::{Checkpoint, FIXED_LOOKUP_NAME},
+    compiled_identity::CompiledIdentities,
     data_structures::{column_map::WitnessColumnMap, finalizable_data::FinalizableData},
     identity_processor::IdentityProcessor,
     rows::{CellValue, Row, RowPair, RowUpdater, UnknownStrategy},
@@ -66,6 +69,9 @@ pub struct Processor<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> {
     is_relevant_witness: WitnessColumnMap<bool>,
     /// The outer query, if any. If there is none, processing an outer query will fail.
     outer_query: Option<OuterQuery<'a, T>>,
+    /// Polynomial identities compiled for faster evaluation, if available.
+    /// Identities not found here are processed by the [IdentityProcessor].
+    compiled_identities: Option<&'c CompiledIdentities<'a, T>>,
     inputs: BTreeMap<PolyID, T>,
     previously_set_inputs: BTreeMap<PolyID, usize>,
 }
@@ -92,6 +98,7 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> Processor<'a, 'b, 'c, T,
             witness_cols,
             is_relevant_witness,
             outer_query: None,
+            compiled_identities: None,
             inputs: BTreeMap::new(),
             previously_set_inputs: BTreeMap::new(),
         }
@@ -115,6 +122,16 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> Processor<'a, 'b, 'c, T,
         }
     }
 
+    pub fn with_compiled_identities(
+        self,
+        compiled_identities: &'c CompiledIdentities<'a, T>,
+    ) -> Processor<'a, 'b, 'c, T, Q> {
+        Processor {
+            compiled_identities: Some(compiled_identities),
+            ..self
+        }
+    }
+
     pub fn finshed_outer_query(&self) -> bool {
         self.outer_query
             .as_ref()
@@ -181,24 +198,29 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> Processor<'a, 'b, 'c, T,
 
         // Compute updates
         let mut identity_processor = IdentityProcessor::new(self.fixed_data, self.mutable_state);
-        let updates = identity_processor
-            .process_identity(identity, &row_pair)
-            .map_err(|e| -> EvalError<T> {
-                log::warn!("Error in identity: {identity}");
+        let compiled = self.compiled_identities.and_then(|c| c.get(identity));
+        let updates = match compiled {
+            Some(compiled) => {
+                identity_processor.process_compiled_identity(identity, compiled, &row_pair)
+            }
+            None => identity_processor.process_identity(identity, &row_pair),
+        }
+        .map_err(|e| -> EvalError<T> {
+            log::warn!("Error in identity: {identity}");
+            log::warn!(
+                "Known values in current row (local: {row_index}, global {global_row_index}):\n{}",
+                self.data[row_index].render_values(false, Some(self.witness_cols)),
+            );
+            if identity.contains_next_ref() {
                 log::warn!(
-                    "Known values in current row (local: {row_index}, global {global_row_index}):\n{}",
-                    self.data[row_index].render_values(false, Some(self.witness_cols)),
+                    "Known values in next row (local: {}, global {}):\n{}",
+                    row_index + 1,
+                    global_row_index + 1,
+                    self.data[row_index + 1].render_values(false, Some(self.witness_cols)),
                 );
-                if identity.contains_next_ref() {
-                    log::warn!(
-                        "Known values in next row (local: {}, global {}):\n{}",
-                        row_index + 1,
-                        global_row_index + 1,
-                        self.data[row_index + 1].render_values(false, Some(self.witness_cols)),
-                    );
-                }
-                format!("{identity}:\n{}", indent(&format!("{e}"), "    ")).into()
-            })?;
+            }
+            format!("{identity}:\n{}", indent(&format!("{e}"), "    ")).into()
+        })?;
 
         if unknown_strategy == UnknownStrategy::Zero {
             assert!(updates.constraints.is_empty());
@@ -341,6 +363,27 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> Processor<'a, 'b, 'c, T,
         self.data.finalize_range(range)
     }
 
+    /// Returns a checkpoint that stores the rows processed so far as the state of the
+    /// machine `name`, together with the state of all other machines.
+    pub fn checkpoint(&self, name: &str, row: DegreeType) -> Checkpoint<T> {
+        let mut components = self
+            .mutable_state
+            .machines
+            .iter()
+            .map(|m| (m.name().to_string(), m.state()))
+            .collect::<BTreeMap<_, _>>();
+        components.insert(
+            FIXED_LOOKUP_NAME.to_string(),
+            self.mutable_state.fixed_lookup.state(),
+        );
+        components.insert(name.to_string(), self.data.to_state(self.fixed_data));
+        Checkpoint {
+            row,
+            degree: self.fixed_data.degree,
+            components,
+        }
+    }
+
     pub fn row(&self, i: usize) -> &Row<'a, T> {
         &self.data[i]
     }
@@ -368,6 +411,9 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> Processor<'a, 'b, 'c, T,
         // This could be computed from the identity, but should be pre-computed for performance reasons.
         has_next_reference: bool,
     ) -> bool {
+        // Calls to other machines have to be processed now, otherwise the proposed row
+        // could be accepted although a call fails.
+        self.mutable_state.machines.set_deferring_calls(false);
         let mut identity_processor = IdentityProcessor::new(self.fixed_data, self.mutable_state);
         let row_pair = match has_next_reference {
             // Check whether identities with a reference to the next row are satisfied
@@ -393,10 +439,11 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> Processor<'a, 'b, 'c, T,
             ),
         };
 
-        if identity_processor
+        let is_satisfied = identity_processor
             .process_identity(identity, &row_pair)
-            .is_err()
-        {
+            .is_ok();
+        self.mutable_state.machines.set_deferring_calls(true);
+        if !is_satisfied {
             log::debug!("Previous {:?}", &self.data[row_index - 1]);
             log::debug!("Proposed {:?}", proposed_row);
             log::debug!("Failed on identity: {}", identity);
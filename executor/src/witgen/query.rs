@@ -0,0 +1,100 @@
+use std::fmt::Display;
+
+use itertools::Itertools;
+use powdr_ast::parsed::display::quote;
+use powdr_number::{BigInt, FieldElement};
+
+/// A prover query, i.e. the value a query expression evaluates to.
+/// Queries are usually tuples whose first element is a string
+/// identifying the kind of query, e.g. `("input", 3)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Query<T> {
+    String(String),
+    Integer(num_bigint::BigInt),
+    FieldElement(T),
+    Tuple(Vec<Query<T>>),
+}
+
+impl<T: FieldElement> Query<T> {
+    /// @returns the elements of the query if it is a tuple.
+    pub fn as_tuple(&self) -> Result<&[Query<T>], String> {
+        match self {
+            Query::Tuple(items) => Ok(items),
+            _ => Err(format!("Prover query has to be a tuple, but got {self}")),
+        }
+    }
+
+    /// @returns the string if the query is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Query::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Converts the query to a field element. Only works for integers in the
+    /// range of the field and field elements.
+    pub fn to_field_element(&self) -> Result<T, String> {
+        match self {
+            Query::FieldElement(x) => Ok(*x),
+            Query::Integer(x) => match x.to_biguint() {
+                Some(x) if x < T::modulus().to_arbitrary_integer() => Ok(T::from(x)),
+                _ => Err(format!("Integer {x} is not a valid field element")),
+            },
+            _ => Err(format!("Expected a number but got {self}")),
+        }
+    }
+
+    /// Converts the query to a `u64`. Only works for integers and field elements
+    /// that fit into 64 bits.
+    pub fn to_u64(&self) -> Result<u64, String> {
+        match self {
+            Query::FieldElement(x) => {
+                let x = x.to_arbitrary_integer();
+                u64::try_from(&x).map_err(|_| format!("Value {x} does not fit into 64 bits"))
+            }
+            Query::Integer(x) => {
+                u64::try_from(x).map_err(|_| format!("Value {x} does not fit into 64 bits"))
+            }
+            _ => Err(format!("Expected a number but got {self}")),
+        }
+    }
+}
+
+/// Formats the query the way it was passed to callbacks as a string,
+/// i.e. strings are quoted and tuple items are separated by `", "`.
+impl<T: Display> Display for Query<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Query::String(s) => write!(f, "{}", quote(s)),
+            Query::Integer(x) => write!(f, "{x}"),
+            Query::FieldElement(x) => write!(f, "{x}"),
+            Query::Tuple(items) => write!(f, "({})", items.iter().format(", ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+
+    use super::*;
+
+    #[test]
+    fn format() {
+        let query: Query<GoldilocksField> = Query::Tuple(vec![
+            Query::String("data, with \"comma\"".to_string()),
+            Query::Integer(7.into()),
+            Query::FieldElement(3.into()),
+        ]);
+        assert_eq!(query.to_string(), r#"("data, with \"comma\"", 7, 3)"#);
+        let items = query.as_tuple().unwrap();
+        assert_eq!(items[0].as_str(), Some("data, with \"comma\""));
+        assert_eq!(items[1].to_u64(), Ok(7));
+        assert_eq!(items[2].to_field_element(), Ok(3.into()));
+        assert!(items[0].to_u64().is_err());
+        assert!(Query::<GoldilocksField>::Integer((-1).into())
+            .to_field_element()
+            .is_err());
+    }
+}
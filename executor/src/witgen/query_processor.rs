@@ -8,7 +8,7 @@ use powdr_ast::analyzed::{
 use powdr_number::{DegreeType, FieldElement};
 use powdr_pil_analyzer::evaluator::{self, Custom, EvalError, SymbolLookup, Value};
 
-use super::{rows::RowPair, Constraint, EvalResult, EvalValue, FixedData, IncompleteCause};
+use super::{rows::RowPair, Constraint, EvalResult, EvalValue, FixedData, IncompleteCause, Query};
 
 /// Computes value updates that result from a query.
 pub struct QueryProcessor<'a, 'b, T: FieldElement, QueryCallback: Send + Sync> {
@@ -44,7 +44,7 @@ impl<'a, 'b, T: FieldElement, QueryCallback: super::QueryCallback<T>>
         poly: &'a AlgebraicReference,
         rows: &RowPair<T>,
     ) -> EvalResult<'a, T> {
-        let query_str = match self.interpolate_query(query, rows) {
+        let query_value = match self.interpolate_query(query, rows) {
             Ok(query) => query,
             Err(e) => {
                 return match e {
@@ -63,12 +63,12 @@ impl<'a, 'b, T: FieldElement, QueryCallback: super::QueryCallback<T>>
         };
         Ok(
             if let Some(value) =
-                (self.query_callback)(&query_str).map_err(super::EvalError::ProverQueryError)?
+                (self.query_callback)(&query_value).map_err(super::EvalError::ProverQueryError)?
             {
                 EvalValue::complete(vec![(poly, Constraint::Assignment(value))])
             } else {
                 EvalValue::incomplete(IncompleteCause::NoQueryAnswer(
-                    query_str,
+                    query_value.to_string(),
                     poly.name.to_string(),
                 ))
             },
@@ -79,7 +79,7 @@ impl<'a, 'b, T: FieldElement, QueryCallback: super::QueryCallback<T>>
         &self,
         query: &'a Expression<T>,
         rows: &RowPair<T>,
-    ) -> Result<String, EvalError> {
+    ) -> Result<Query<T>, EvalError> {
         let arguments = vec![Rc::new(Value::Integer(num_bigint::BigInt::from(
             rows.current_row_index,
         )))];
@@ -88,10 +88,25 @@ impl<'a, 'b, T: FieldElement, QueryCallback: super::QueryCallback<T>>
             rows,
         };
         let fun = evaluator::evaluate(query, &symbols)?;
-        evaluator::evaluate_function_call(fun, arguments, &symbols).map(|v| v.to_string())
+        evaluator::evaluate_function_call(fun, arguments, &symbols).and_then(to_query)
     }
 }
 
+/// Converts the result of evaluating a query expression into a [Query].
+fn to_query<T: FieldElement, C: Custom>(value: Value<'_, T, C>) -> Result<Query<T>, EvalError> {
+    Ok(match value {
+        Value::String(s) => Query::String(s),
+        Value::Integer(x) => Query::Integer(x),
+        Value::FieldElement(x) => Query::FieldElement(x),
+        Value::Tuple(items) => {
+            Query::Tuple(items.into_iter().map(to_query).collect::<Result<_, _>>()?)
+        }
+        v => Err(EvalError::TypeError(format!(
+            "Expected string, number or tuple in prover query, but got {v}"
+        )))?,
+    })
+}
+
 #[derive(Clone)]
 struct Symbols<'a, T: FieldElement> {
     fixed_data: &'a FixedData<'a, T>,
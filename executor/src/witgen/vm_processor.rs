@@ -12,6 +12,8 @@ use std::time::Instant;
 use crate::witgen::identity_processor::{self};
 use crate::witgen::IncompleteCause;
 
+use super::checkpoint::CheckpointSettings;
+use super::compiled_identity::CompiledIdentities;
 use super::data_structures::finalizable_data::FinalizableData;
 use super::processor::{OuterQuery, Processor};
 
@@ -60,6 +62,8 @@ pub struct VmProcessor<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> {
     row_factory: RowFactory<'a, T>,
     processor: Processor<'a, 'b, 'c, T, Q>,
     progress_bar: ProgressBar,
+    /// The name of the machine and where to write checkpoints, if enabled.
+    checkpoints: Option<(String, CheckpointSettings)>,
 }
 
 impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'b, 'c, T, Q> {
@@ -96,6 +100,7 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'b, 'c, T
             last_report_time: Instant::now(),
             processor,
             progress_bar,
+            checkpoints: None,
         }
     }
 
@@ -104,14 +109,32 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'b, 'c, T
         Self { processor, ..self }
     }
 
+    /// Evaluates polynomial identities through their compiled form.
+    pub fn with_compiled_identities(
+        self,
+        compiled_identities: &'c CompiledIdentities<'a, T>,
+    ) -> Self {
+        let processor = self.processor.with_compiled_identities(compiled_identities);
+        Self { processor, ..self }
+    }
+
+    /// Periodically writes the state of witness generation to a file, so that it can be resumed.
+    pub fn with_checkpoints(self, name: String, settings: CheckpointSettings) -> Self {
+        Self {
+            checkpoints: Some((name, settings)),
+            ..self
+        }
+    }
+
     pub fn finish(self) -> FinalizableData<'a, T> {
         self.processor.finish()
     }
 
-    /// Starting out with a single row (at a given offset), iteratively append rows
+    /// Starting out with the last row of the data (usually the only one), iteratively append rows
     /// until we have exhausted the rows or the latch expression (if available) evaluates to 1.
     pub fn run(&mut self, is_main_run: bool) -> EvalValue<&'a AlgebraicReference, T> {
-        assert!(self.processor.len() == 1);
+        assert!(self.processor.len() > 0);
+        let start_row = self.processor.len() as DegreeType - 1;
 
         if is_main_run {
             log::info!("Running main machine for {} rows", self.fixed_data.degree);
@@ -131,11 +154,15 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'b, 'c, T
         };
         let rows_left = self.fixed_data.degree - self.row_offset + 1;
         let mut finalize_start = 1;
-        for row_index in 0..rows_left {
+        for row_index in start_row..rows_left {
             if is_main_run {
                 self.maybe_log_performance(row_index);
             }
 
+            if looping_period.is_none() && row_index > start_row {
+                self.maybe_write_checkpoint(row_index);
+            }
+
             if (row_index + 1) % 10000 == 0 {
                 // Periodically make sure most rows are finalized.
                 // Row 0 and the last MAX_PERIOD rows might be needed later, so they are not finalized.
@@ -497,6 +524,32 @@ impl<'a, 'b, 'c, T: FieldElement, Q: QueryCallback<T>> VmProcessor<'a, 'b, 'c, T
         constraints_valid
     }
 
+    /// Writes a checkpoint if checkpoints are enabled and `row_index` is a multiple of
+    /// the checkpoint interval. All rows before `row_index` have to be processed.
+    fn maybe_write_checkpoint(&self, row_index: DegreeType) {
+        let Some((name, settings)) = &self.checkpoints else {
+            return;
+        };
+        if row_index % settings.interval != 0 {
+            return;
+        }
+        assert_eq!(self.processor.len() as DegreeType, row_index + 1);
+        let start = Instant::now();
+        let checkpoint = self.processor.checkpoint(name, row_index + self.row_offset);
+        match checkpoint.write_to_file(&settings.path) {
+            Ok(()) => log::info!(
+                "Wrote checkpoint at row {} to {} in {:.2}s",
+                row_index + self.row_offset,
+                settings.path.display(),
+                start.elapsed().as_secs_f64()
+            ),
+            Err(e) => log::error!(
+                "Could not write checkpoint to {}: {e}",
+                settings.path.display()
+            ),
+        }
+    }
+
     fn maybe_log_performance(&mut self, row_index: DegreeType) {
         if row_index >= self.last_report + REPORT_FREQUENCY {
             let duration = self.last_report_time.elapsed();
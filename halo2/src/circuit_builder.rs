@@ -223,7 +223,7 @@ fn copy_constraints<T: FieldElement>(pil: &Analyzed<T>, cd: &CircuitData<T>) ->
     // |  2  | *2* |  7  |        |
     // |  3  |  4  |  8  |        |
     let mut copies = vec![];
-    for public_declaration in pil.public_declarations.values() {
+    for (_, public_declaration) in pil.public_declarations_in_source_order() {
         let witness_name = public_declaration.referenced_poly_name();
         let witness_col = cd.col(&witness_name);
         let witness_offset = public_declaration.index as usize;
@@ -286,7 +286,7 @@ fn public_values<T: FieldElement>(pil: &Analyzed<T>, witness: &[(String, Vec<T>)
     let eval_witness = |name: &String, row: usize| -> T { witness_map.get(name).unwrap()[row] };
 
     let mut publics = vec![];
-    for public_declaration in pil.public_declarations.values() {
+    for (_, public_declaration) in pil.public_declarations_in_source_order() {
         let witness_name = public_declaration.referenced_poly_name();
         let witness_offset = public_declaration.index as usize;
 
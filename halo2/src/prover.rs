@@ -293,8 +293,12 @@ impl Halo2Prover {
             })
             .collect_vec();
 
+        let vkey = self
+            .vkey
+            .as_ref()
+            .ok_or_else(|| "A verification key is needed for verification.".to_string())?;
         self.verify_inner::<_, aggregation::PoseidonTranscript<NativeLoader, _>>(
-            self.vkey.as_ref().unwrap(),
+            vkey,
             &self.params,
             proof,
             &instances,
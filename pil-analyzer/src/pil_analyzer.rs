@@ -583,4 +583,24 @@ namespace N(16);
         let formatted = analyze_string::<GoldilocksField>(input).to_string();
         assert_eq!(formatted, input);
     }
+
+    #[test]
+    fn append_polynomial_identity() {
+        let input = r#"namespace N(16);
+    col witness x;
+    col witness y;
+    N.x = N.y;
+    { N.x } in { N.y };
+    N.x' = N.y;
+"#;
+        let mut analyzed = analyze_string::<GoldilocksField>(input);
+        let identity = analyzed.identities[0].expression_for_poly_id().clone();
+        let source = analyzed.identities[0].source.clone();
+        let id = analyzed.append_polynomial_identity(identity, source);
+        assert_eq!(id, 2);
+        // The new identity is printed after all others, even though its ID
+        // is not its index.
+        let expected = format!("{input}    N.x = N.y;\n");
+        assert_eq!(analyzed.to_string(), expected);
+    }
 }
@@ -0,0 +1,684 @@
+//! Extracts common subexpressions into intermediate polynomials.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperator, AlgebraicExpression, AlgebraicReference, AlgebraicUnaryOperator,
+    Analyzed, IdentityKind, PolyID, PolynomialType, StatementIdentifier,
+};
+use powdr_ast::SourceRef;
+use powdr_number::FieldElement;
+
+use crate::degree_reduction::{expression_degree, namespace_of};
+
+/// Describes how expensive intermediate polynomials are for a backend, compared to
+/// repeating an expression. All costs are measured in expression nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CseCostModel {
+    /// The cost of defining an intermediate polynomial, in addition to its definition.
+    pub intermediate_cost: usize,
+    /// The additional cost of an intermediate polynomial whose degree is larger than one,
+    /// e.g. because the backend commits to it.
+    /// If `None`, such intermediate polynomials are not created.
+    pub nonlinear_intermediate_cost: Option<usize>,
+}
+
+impl CseCostModel {
+    /// @returns by how much the cost decreases if an expression of the given size and
+    /// degree that occurs `occurrences` times is replaced by an intermediate polynomial,
+    /// or None if it does not decrease.
+    fn saving(&self, size: usize, degree: usize, occurrences: usize) -> Option<usize> {
+        let intermediate_cost = if degree > 1 {
+            self.intermediate_cost + self.nonlinear_intermediate_cost?
+        } else {
+            self.intermediate_cost
+        };
+        // Before: `occurrences * size`, after: the definition, one reference
+        // per occurrence and the intermediate itself.
+        (occurrences * size)
+            .checked_sub(size + occurrences + intermediate_cost)
+            .filter(|saving| *saving > 0)
+    }
+}
+
+/// Repeatedly replaces the non-trivial subexpression whose extraction decreases the cost
+/// the most by a new intermediate polynomial, until no extraction decreases the cost.
+///
+/// The new intermediate polynomials are inserted right before their first use.
+pub fn extract_common_subexpressions<T: FieldElement>(
+    pil_file: &mut Analyzed<T>,
+    cost_model: &CseCostModel,
+) {
+    let mut intermediate_degrees = BTreeMap::new();
+    for (symbol, definition) in pil_file.intermediate_polys_in_source_order() {
+        let degree = expression_degree(definition, &intermediate_degrees);
+        intermediate_degrees.insert(PolyID::from(symbol), degree);
+    }
+
+    let mut subexpressions = Subexpressions::new(intermediate_degrees);
+    // The nodes of the expressions of every statement, in source order.
+    let statement_roots = pil_file
+        .source_order
+        .iter()
+        .map(|statement| {
+            let mut roots = vec![];
+            visit_statement_expressions(pil_file, statement, &mut |e| {
+                roots.push(subexpressions.insert(e))
+            });
+            roots
+        })
+        .collect::<Vec<_>>();
+    subexpressions.count_occurrences(statement_roots.iter().flatten());
+
+    let extracted = subexpressions.extract_all(cost_model);
+    if extracted.is_empty() {
+        return;
+    }
+
+    // Every new intermediate polynomial is inserted right before the first statement
+    // that uses it, after the new intermediate polynomials it uses itself.
+    let mut new_source_order = vec![];
+    let mut ordered = HashSet::new();
+    for (index, roots) in statement_roots.iter().enumerate() {
+        for node in subexpressions.extracted_operands(roots, &extracted) {
+            subexpressions.order_intermediates(
+                node,
+                index,
+                &extracted,
+                &mut ordered,
+                &mut new_source_order,
+            );
+        }
+        new_source_order.push(SourceOrderItem::Statement);
+    }
+
+    let mut used_names: HashSet<String> = pil_file
+        .definitions
+        .keys()
+        .chain(pil_file.intermediate_columns.keys())
+        .cloned()
+        .collect();
+    let mut next_name_id = 0;
+    let mut references = HashMap::new();
+    for item in &new_source_order {
+        let SourceOrderItem::Intermediate(node, _) = item else {
+            continue;
+        };
+        let namespace = subexpressions
+            .first_reference(*node)
+            .map(|poly| namespace_of(&AlgebraicExpression::<T>::Reference(poly.clone())))
+            .unwrap_or_default();
+        let name = loop {
+            let name = format!("{namespace}cse_{next_name_id}");
+            next_name_id += 1;
+            if used_names.insert(name.clone()) {
+                break name;
+            }
+        };
+        let poly_id = PolyID {
+            id: (pil_file.intermediate_count() + references.len()) as u64,
+            ptype: PolynomialType::Intermediate,
+        };
+        references.insert(
+            *node,
+            AlgebraicReference {
+                name,
+                poly_id,
+                next: false,
+            },
+        );
+    }
+
+    let statements = pil_file.source_order.clone();
+    for (statement, roots) in statements.iter().zip(&statement_roots) {
+        let mut roots = roots.iter();
+        visit_statement_expressions_mut(pil_file, statement, &mut |e| {
+            *e = subexpressions.expression(*roots.next().unwrap(), &references);
+        });
+    }
+
+    for (index, item) in new_source_order.into_iter().enumerate() {
+        let SourceOrderItem::Intermediate(node, first_use) = item else {
+            continue;
+        };
+        let reference = &references[&node];
+        let definition = subexpressions.definition(node, &references);
+        log::debug!(
+            "Extracting {definition} into intermediate polynomial {}.",
+            reference.name
+        );
+        let source = statement_source(pil_file, &statements[first_use]).clone();
+        let column =
+            pil_file.insert_intermediate_column(index, reference.name.clone(), source, definition);
+        assert_eq!(&column, reference);
+    }
+    log::info!(
+        "Extracted {} common subexpressions into intermediate polynomials.",
+        extracted.len()
+    );
+}
+
+/// An item of the source order after extracting common subexpressions.
+enum SourceOrderItem {
+    /// The next statement of the previous source order.
+    Statement,
+    /// A new intermediate polynomial for the given node, which is first used
+    /// by the statement with the given index in the previous source order.
+    Intermediate(usize, usize),
+}
+
+/// A node of an expression, where the operands are identified by their node index.
+/// References are compared by their polynomial ID.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Node<T> {
+    Reference(AlgebraicReference),
+    PublicReference(String),
+    Number(T),
+    BinaryOperation(usize, AlgebraicBinaryOperator, usize),
+    UnaryOperation(AlgebraicUnaryOperator, usize),
+}
+
+/// All distinct subexpressions of a PIL file, where equal subexpressions share
+/// a node. The operands of a node always have a smaller index than the node itself.
+struct Subexpressions<T> {
+    nodes: Vec<Node<T>>,
+    indices: HashMap<Node<T>, usize>,
+    /// The nodes that have the node as an operand.
+    parents: Vec<Vec<usize>>,
+    /// The number of occurrences of the node in all expressions.
+    occurrences: Vec<usize>,
+    /// The number of expression nodes, where extracted operands count as one node.
+    sizes: Vec<usize>,
+    degrees: Vec<usize>,
+    /// The extracted nodes, with the order of their extraction.
+    extracted: HashMap<usize, usize>,
+    intermediate_degrees: BTreeMap<PolyID, usize>,
+}
+
+impl<T: FieldElement> Subexpressions<T> {
+    fn new(intermediate_degrees: BTreeMap<PolyID, usize>) -> Self {
+        Self {
+            nodes: vec![],
+            indices: HashMap::new(),
+            parents: vec![],
+            occurrences: vec![],
+            sizes: vec![],
+            degrees: vec![],
+            extracted: HashMap::new(),
+            intermediate_degrees,
+        }
+    }
+
+    /// Adds the expression and all its subexpressions and returns the index of its node.
+    fn insert(&mut self, expr: &AlgebraicExpression<T>) -> usize {
+        let node = match expr {
+            AlgebraicExpression::Reference(poly) => Node::Reference(poly.clone()),
+            AlgebraicExpression::PublicReference(name) => Node::PublicReference(name.clone()),
+            AlgebraicExpression::Number(n) => Node::Number(*n),
+            AlgebraicExpression::BinaryOperation(left, op, right) => {
+                Node::BinaryOperation(self.insert(left), *op, self.insert(right))
+            }
+            AlgebraicExpression::UnaryOperation(op, e) => Node::UnaryOperation(*op, self.insert(e)),
+        };
+        if let Some(index) = self.indices.get(&node) {
+            return *index;
+        }
+        let index = self.nodes.len();
+        let (size, degree) = match &node {
+            Node::Reference(poly) => match poly.poly_id.ptype {
+                PolynomialType::Intermediate => (1, self.intermediate_degrees[&poly.poly_id]),
+                PolynomialType::Committed | PolynomialType::Constant => (1, 1),
+            },
+            Node::PublicReference(_) | Node::Number(_) => (1, 0),
+            Node::BinaryOperation(left, op, right) => {
+                let (left_degree, right_degree) = (self.degrees[*left], self.degrees[*right]);
+                let degree = match op {
+                    AlgebraicBinaryOperator::Add | AlgebraicBinaryOperator::Sub => {
+                        left_degree.max(right_degree)
+                    }
+                    AlgebraicBinaryOperator::Mul => left_degree + right_degree,
+                    AlgebraicBinaryOperator::Pow => match &self.nodes[*right] {
+                        Node::Number(n) => left_degree * n.to_degree() as usize,
+                        _ => panic!("Exponent has to be a number."),
+                    },
+                };
+                (1 + self.sizes[*left] + self.sizes[*right], degree)
+            }
+            Node::UnaryOperation(_, e) => (1 + self.sizes[*e], self.degrees[*e]),
+        };
+        for operand in operands(&node) {
+            self.parents[operand].push(index);
+        }
+        self.nodes.push(node.clone());
+        self.indices.insert(node, index);
+        self.parents.push(vec![]);
+        self.occurrences.push(0);
+        self.sizes.push(size);
+        self.degrees.push(degree);
+        index
+    }
+
+    /// Counts the occurrences of all nodes in the expressions with the given root nodes.
+    fn count_occurrences<'a>(&mut self, roots: impl Iterator<Item = &'a usize>) {
+        for root in roots {
+            self.occurrences[*root] += 1;
+        }
+        // Every occurrence of a node contains its operands once.
+        for index in (0..self.nodes.len()).rev() {
+            for operand in operands(&self.nodes[index]) {
+                self.occurrences[operand] += self.occurrences[index];
+            }
+        }
+    }
+
+    /// @returns by how much the cost decreases if the node is extracted, if it decreases.
+    fn saving(&self, index: usize, cost_model: &CseCostModel) -> Option<usize> {
+        let is_trivial = matches!(
+            self.nodes[index],
+            Node::Reference(_) | Node::PublicReference(_) | Node::Number(_)
+        );
+        if is_trivial || self.extracted.contains_key(&index) || self.occurrences[index] < 2 {
+            return None;
+        }
+        cost_model.saving(
+            self.sizes[index],
+            self.degrees[index],
+            self.occurrences[index],
+        )
+    }
+
+    /// Repeatedly extracts the node with the largest saving.
+    /// @returns the extracted nodes, with the order of their extraction.
+    fn extract_all(&mut self, cost_model: &CseCostModel) -> HashMap<usize, usize> {
+        // Extracting a node only decreases the savings of other nodes, so the savings in
+        // the queue are upper bounds and only need to be updated once they are at the top.
+        // Among the nodes with the same saving, the one inserted first is extracted.
+        let mut queue = (0..self.nodes.len())
+            .filter_map(|index| Some((self.saving(index, cost_model)?, Reverse(index))))
+            .collect::<BinaryHeap<_>>();
+        while let Some((saving, Reverse(index))) = queue.pop() {
+            match self.saving(index, cost_model) {
+                Some(current) if current == saving => self.extract(index),
+                Some(current) => queue.push((current, Reverse(index))),
+                None => {}
+            }
+        }
+        std::mem::take(&mut self.extracted)
+    }
+
+    /// Replaces all occurrences of the node by a single occurrence in the definition
+    /// of an intermediate polynomial and updates the occurrences and sizes of the
+    /// affected nodes.
+    fn extract(&mut self, index: usize) {
+        let removed_occurrences = self.occurrences[index] - 1;
+        let extracted_size = self.sizes[index];
+        self.extracted.insert(index, self.extracted.len());
+
+        // The operands of the node occur once per occurrence of the node.
+        for (operand, count) in self.occurrences_in(index) {
+            self.occurrences[operand] -= count * removed_occurrences;
+        }
+
+        // Every node that contains the node shrinks by its size, except for the one
+        // node that is left in the reference.
+        let mut ancestors = BTreeSet::new();
+        let mut stack = vec![index];
+        while let Some(node) = stack.pop() {
+            for parent in &self.parents[node] {
+                if !self.extracted.contains_key(parent) && ancestors.insert(*parent) {
+                    stack.push(*parent);
+                }
+            }
+        }
+        let mut counts = HashMap::from([(index, 1)]);
+        for ancestor in ancestors {
+            let count = operands(&self.nodes[ancestor])
+                .filter_map(|operand| counts.get(&operand))
+                .sum::<usize>();
+            self.sizes[ancestor] -= count * (extracted_size - 1);
+            counts.insert(ancestor, count);
+        }
+    }
+
+    /// @returns how often each node occurs in the given node (excluding the node itself),
+    /// where extracted nodes are not expanded.
+    fn occurrences_in(&self, index: usize) -> BTreeMap<usize, usize> {
+        let mut counts = BTreeMap::from([(index, 1)]);
+        let mut nodes = BTreeSet::from([index]);
+        while let Some(node) = nodes.pop_last() {
+            let count = counts[&node];
+            if node != index && self.extracted.contains_key(&node) {
+                continue;
+            }
+            for operand in operands(&self.nodes[node]) {
+                *counts.entry(operand).or_default() += count;
+                nodes.insert(operand);
+            }
+        }
+        counts.remove(&index);
+        counts
+    }
+
+    /// @returns the extracted nodes that are (not through other extracted nodes)
+    /// operands of the given nodes, ordered by their extraction.
+    fn extracted_operands(&self, roots: &[usize], extracted: &HashMap<usize, usize>) -> Vec<usize> {
+        let mut result = BTreeSet::new();
+        let mut visited = HashSet::new();
+        let mut stack = roots.to_vec();
+        while let Some(node) = stack.pop() {
+            if !visited.insert(node) {
+                continue;
+            }
+            match extracted.get(&node) {
+                Some(order) => {
+                    result.insert((*order, node));
+                }
+                None => stack.extend(operands(&self.nodes[node])),
+            }
+        }
+        result.into_iter().map(|(_, node)| node).collect()
+    }
+
+    /// Appends the extracted node and the extracted nodes used in its definition
+    /// to the source order, unless they have been added already.
+    fn order_intermediates(
+        &self,
+        node: usize,
+        first_use: usize,
+        extracted: &HashMap<usize, usize>,
+        ordered: &mut HashSet<usize>,
+        source_order: &mut Vec<SourceOrderItem>,
+    ) {
+        if !ordered.insert(node) {
+            return;
+        }
+        let operands = operands(&self.nodes[node]).collect::<Vec<_>>();
+        for operand in self.extracted_operands(&operands, extracted) {
+            self.order_intermediates(operand, first_use, extracted, ordered, source_order);
+        }
+        source_order.push(SourceOrderItem::Intermediate(node, first_use));
+    }
+
+    /// @returns the expression of a node, where extracted nodes are replaced by references.
+    fn expression(
+        &self,
+        index: usize,
+        references: &HashMap<usize, AlgebraicReference>,
+    ) -> AlgebraicExpression<T> {
+        match references.get(&index) {
+            Some(reference) => AlgebraicExpression::Reference(reference.clone()),
+            None => self.definition(index, references),
+        }
+    }
+
+    /// @returns the expression of a node, where extracted operands are replaced by references.
+    fn definition(
+        &self,
+        index: usize,
+        references: &HashMap<usize, AlgebraicReference>,
+    ) -> AlgebraicExpression<T> {
+        match &self.nodes[index] {
+            Node::Reference(poly) => AlgebraicExpression::Reference(poly.clone()),
+            Node::PublicReference(name) => AlgebraicExpression::PublicReference(name.clone()),
+            Node::Number(n) => AlgebraicExpression::Number(*n),
+            Node::BinaryOperation(left, op, right) => AlgebraicExpression::BinaryOperation(
+                Box::new(self.expression(*left, references)),
+                *op,
+                Box::new(self.expression(*right, references)),
+            ),
+            Node::UnaryOperation(op, e) => {
+                AlgebraicExpression::UnaryOperation(*op, Box::new(self.expression(*e, references)))
+            }
+        }
+    }
+
+    /// @returns the first column referenced in the expression of the node.
+    fn first_reference(&self, index: usize) -> Option<&AlgebraicReference> {
+        match &self.nodes[index] {
+            Node::Reference(poly) => Some(poly),
+            Node::PublicReference(_) | Node::Number(_) => None,
+            Node::BinaryOperation(left, _, right) => self
+                .first_reference(*left)
+                .or_else(|| self.first_reference(*right)),
+            Node::UnaryOperation(_, e) => self.first_reference(*e),
+        }
+    }
+}
+
+/// @returns the operands of a node.
+fn operands<T>(node: &Node<T>) -> impl Iterator<Item = usize> {
+    match node {
+        Node::BinaryOperation(left, _, right) => vec![*left, *right],
+        Node::UnaryOperation(_, e) => vec![*e],
+        Node::Reference(_) | Node::PublicReference(_) | Node::Number(_) => vec![],
+    }
+    .into_iter()
+}
+
+/// Calls `f` on all top-level expressions of the statement that can reference
+/// intermediate polynomials.
+fn visit_statement_expressions<T, F>(
+    pil_file: &Analyzed<T>,
+    statement: &StatementIdentifier,
+    f: &mut F,
+) where
+    F: FnMut(&AlgebraicExpression<T>),
+{
+    match statement {
+        StatementIdentifier::Definition(name) => {
+            if let Some((_, definition)) = pil_file.intermediate_columns.get(name) {
+                f(definition);
+            }
+        }
+        StatementIdentifier::Identity(i) => {
+            let identity = &pil_file.identities[*i];
+            // Connect identities can only reference columns directly.
+            if identity.kind != IdentityKind::Connect {
+                identity
+                    .left
+                    .selector
+                    .iter()
+                    .chain(&identity.left.expressions)
+                    .chain(&identity.right.selector)
+                    .chain(&identity.right.expressions)
+                    .for_each(f);
+            }
+        }
+        StatementIdentifier::PublicDeclaration(_) => {}
+    }
+}
+
+/// Calls `f` on the same expressions as [visit_statement_expressions], in the same order.
+fn visit_statement_expressions_mut<T, F>(
+    pil_file: &mut Analyzed<T>,
+    statement: &StatementIdentifier,
+    f: &mut F,
+) where
+    F: FnMut(&mut AlgebraicExpression<T>),
+{
+    match statement {
+        StatementIdentifier::Definition(name) => {
+            if let Some((_, definition)) = pil_file.intermediate_columns.get_mut(name) {
+                f(definition);
+            }
+        }
+        StatementIdentifier::Identity(i) => {
+            let identity = &mut pil_file.identities[*i];
+            if identity.kind != IdentityKind::Connect {
+                identity
+                    .left
+                    .selector
+                    .iter_mut()
+                    .chain(identity.left.expressions.iter_mut())
+                    .chain(identity.right.selector.iter_mut())
+                    .chain(identity.right.expressions.iter_mut())
+                    .for_each(f);
+            }
+        }
+        StatementIdentifier::PublicDeclaration(_) => {}
+    }
+}
+
+/// @returns the source reference of a statement that has expressions.
+fn statement_source<'a, T>(
+    pil_file: &'a Analyzed<T>,
+    statement: &StatementIdentifier,
+) -> &'a SourceRef {
+    match statement {
+        StatementIdentifier::Definition(name) => &pil_file.intermediate_columns[name].0.source,
+        StatementIdentifier::Identity(i) => &pil_file.identities[*i].source,
+        StatementIdentifier::PublicDeclaration(_) => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const LINEAR_ONLY: CseCostModel = CseCostModel {
+        intermediate_cost: 1,
+        nonlinear_intermediate_cost: None,
+    };
+
+    fn extract(input: &str, cost_model: &CseCostModel) -> String {
+        let mut pil_file = analyze_string::<GoldilocksField>(input);
+        extract_common_subexpressions(&mut pil_file, cost_model);
+        pil_file.to_string()
+    }
+
+    #[test]
+    fn no_repetition() {
+        let input = r#"namespace N(8);
+    col witness x;
+    col witness y;
+    (N.x + N.y) = 1;
+    (N.x - N.y) = 2;
+"#;
+        assert_eq!(extract(input, &LINEAR_ONLY), input);
+    }
+
+    #[test]
+    fn linear() {
+        let input = r#"namespace N(8);
+    col witness x;
+    col witness y;
+    col witness a;
+    col witness b;
+    N.a * (N.x + 2 * N.y) = 1;
+    N.b * (N.x + 2 * N.y) = 2;
+    (N.x + 2 * N.y) = N.a + N.b;
+"#;
+        let expectation = r#"namespace N(8);
+    col witness x;
+    col witness y;
+    col witness a;
+    col witness b;
+    col cse_0 = (N.x + (2 * N.y));
+    (N.a * N.cse_0) = 1;
+    (N.b * N.cse_0) = 2;
+    N.cse_0 = (N.a + N.b);
+"#;
+        assert_eq!(extract(input, &LINEAR_ONLY), expectation);
+    }
+
+    #[test]
+    fn nonlinear() {
+        let input = r#"namespace N(8);
+    col witness instr_x;
+    col witness a;
+    col witness b;
+    col witness c;
+    instr_x * (a - b) * c = 0;
+    instr_x * (a - b) * b = 0;
+    instr_x * (a - b) * a = 0;
+    instr_x * (a - b) * (a + b) = 0;
+"#;
+        // Only the linear subexpression is extracted.
+        let expectation = r#"namespace N(8);
+    col witness instr_x;
+    col witness a;
+    col witness b;
+    col witness c;
+    col cse_0 = (N.a - N.b);
+    ((N.instr_x * N.cse_0) * N.c) = 0;
+    ((N.instr_x * N.cse_0) * N.b) = 0;
+    ((N.instr_x * N.cse_0) * N.a) = 0;
+    ((N.instr_x * N.cse_0) * (N.a + N.b)) = 0;
+"#;
+        assert_eq!(extract(input, &LINEAR_ONLY), expectation);
+
+        let expectation = r#"namespace N(8);
+    col witness instr_x;
+    col witness a;
+    col witness b;
+    col witness c;
+    col cse_0 = (N.instr_x * (N.a - N.b));
+    (N.cse_0 * N.c) = 0;
+    (N.cse_0 * N.b) = 0;
+    (N.cse_0 * N.a) = 0;
+    (N.cse_0 * (N.a + N.b)) = 0;
+"#;
+        let cost_model = CseCostModel {
+            intermediate_cost: 1,
+            nonlinear_intermediate_cost: Some(2),
+        };
+        assert_eq!(extract(input, &cost_model), expectation);
+    }
+
+    #[test]
+    fn inserted_before_first_use() {
+        let input = r#"namespace N(8);
+    col witness x;
+    col witness y;
+    (N.x + N.y) = 1;
+    col fixed FIRST = [1] + [0]*;
+    col inter = (N.x + N.y) * FIRST;
+    col inter2 = (N.x + N.y) * (1 - FIRST);
+    (N.x + N.y) = N.inter + N.inter2;
+"#;
+        let expectation = r#"namespace N(8);
+    col witness x;
+    col witness y;
+    col cse_0 = (N.x + N.y);
+    N.cse_0 = 1;
+    col fixed FIRST = [1] + [0]*;
+    col inter = (N.cse_0 * N.FIRST);
+    col inter2 = (N.cse_0 * (1 - N.FIRST));
+    N.cse_0 = (N.inter + N.inter2);
+"#;
+        assert_eq!(extract(input, &LINEAR_ONLY), expectation);
+    }
+
+    #[test]
+    fn nested() {
+        // The larger expression is extracted first, the smaller one afterwards,
+        // but its definition is inserted first.
+        let input = r#"namespace N(8);
+    col witness x;
+    col witness y;
+    col witness z;
+    N.z' = ((N.x + 2 * N.y) + (N.z + 3)) * 3;
+    N.x' = ((N.x + 2 * N.y) + (N.z + 3)) * 4;
+    N.y' = ((N.x + 2 * N.y) + (N.z + 3)) * 5;
+    N.z = (N.x + 2 * N.y) - 1;
+"#;
+        let expectation = r#"namespace N(8);
+    col witness x;
+    col witness y;
+    col witness z;
+    col cse_0 = (N.x + (2 * N.y));
+    col cse_1 = (N.cse_0 + (N.z + 3));
+    N.z' = (N.cse_1 * 3);
+    N.x' = (N.cse_1 * 4);
+    N.y' = (N.cse_1 * 5);
+    N.z = (N.cse_0 - 1);
+"#;
+        assert_eq!(extract(input, &LINEAR_ONLY), expectation);
+    }
+}
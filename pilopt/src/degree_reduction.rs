@@ -0,0 +1,367 @@
+//! Reduces the degree of all constraints to a given maximum by introducing
+//! new witness columns for sub-expressions.
+
+use std::collections::{BTreeMap, HashSet};
+
+use powdr_ast::analyzed::{
+    AlgebraicBinaryOperator, AlgebraicExpression, AlgebraicReference, Analyzed, IdentityKind,
+    PolyID, PolynomialType,
+};
+use powdr_ast::parsed::visitor::ExpressionVisitable;
+use powdr_ast::SourceRef;
+use powdr_number::FieldElement;
+
+/// Makes sure that every polynomial identity and every lookup or permutation
+/// (selector times expression) has a degree of at most `max_degree`.
+///
+/// Products whose degree is too large are split by introducing a new witness column `w`
+/// together with the identity `w = f` for one of the factors `f`. Since `f` only
+/// references columns that are also referenced in the original constraint,
+/// witness generation can compute `w` once these values are known.
+pub fn reduce_degree<T: FieldElement>(pil_file: &mut Analyzed<T>, max_degree: usize) {
+    assert!(max_degree >= 2, "Cannot reduce the degree below 2.");
+    let mut reducer = DegreeReducer::new(pil_file, max_degree);
+
+    let intermediates = pil_file
+        .intermediate_polys_in_source_order()
+        .into_iter()
+        .map(|(symbol, _)| symbol.absolute_name.clone())
+        .collect::<Vec<_>>();
+    // Intermediate polynomials can only reference earlier intermediate polynomials,
+    // so we can compute their degrees in source order.
+    for name in intermediates {
+        let (symbol, mut definition) = pil_file.intermediate_columns.remove(&name).unwrap();
+        definition = reducer.reduce(definition, max_degree, &symbol.source);
+        reducer
+            .intermediate_degrees
+            .insert(PolyID::from(&symbol), reducer.degree(&definition));
+        pil_file
+            .intermediate_columns
+            .insert(name, (symbol, definition));
+    }
+
+    for identity in &mut pil_file.identities {
+        let source = identity.source.clone();
+        match identity.kind {
+            IdentityKind::Polynomial => {
+                let expression = identity.left.selector.take().unwrap();
+                identity.left.selector = Some(reducer.reduce(expression, max_degree, &source));
+            }
+            IdentityKind::Plookup | IdentityKind::Permutation => {
+                for side in [&mut identity.left, &mut identity.right] {
+                    let selector_degree = match side.selector.take() {
+                        Some(selector) => {
+                            let selector = reducer.reduce(selector, max_degree - 1, &source);
+                            let degree = reducer.degree(&selector);
+                            side.selector = Some(selector);
+                            degree
+                        }
+                        None => 0,
+                    };
+                    side.expressions = std::mem::take(&mut side.expressions)
+                        .into_iter()
+                        .map(|e| reducer.reduce(e, max_degree - selector_degree, &source))
+                        .collect();
+                }
+            }
+            // Connect identities only reference columns directly.
+            IdentityKind::Connect => {}
+        }
+    }
+
+    let (new_columns, first_column_id) = (reducer.new_columns, reducer.first_column_id);
+    if !new_columns.is_empty() {
+        log::info!(
+            "Introduced {} witness columns to reduce the constraint degree to {max_degree}.",
+            new_columns.len()
+        );
+    }
+    for (i, (name, definition, source)) in new_columns.into_iter().enumerate() {
+        let column = pil_file.append_witness_column(name, source.clone());
+        debug_assert_eq!(column.poly_id.id, first_column_id + i as u64);
+        pil_file.append_polynomial_identity(
+            AlgebraicExpression::Reference(column) - definition,
+            source,
+        );
+    }
+}
+
+/// @returns the degree of an expression, where references to intermediate polynomials
+/// have the degree of their definition.
+pub fn expression_degree<T: FieldElement>(
+    expr: &AlgebraicExpression<T>,
+    intermediate_degrees: &BTreeMap<PolyID, usize>,
+) -> usize {
+    match expr {
+        AlgebraicExpression::Reference(poly) => match poly.poly_id.ptype {
+            PolynomialType::Intermediate => intermediate_degrees[&poly.poly_id],
+            PolynomialType::Committed | PolynomialType::Constant => 1,
+        },
+        AlgebraicExpression::PublicReference(_) | AlgebraicExpression::Number(_) => 0,
+        AlgebraicExpression::BinaryOperation(left, op, right) => {
+            let left = expression_degree(left, intermediate_degrees);
+            match op {
+                AlgebraicBinaryOperator::Add | AlgebraicBinaryOperator::Sub => {
+                    left.max(expression_degree(right, intermediate_degrees))
+                }
+                AlgebraicBinaryOperator::Mul => {
+                    left + expression_degree(right, intermediate_degrees)
+                }
+                AlgebraicBinaryOperator::Pow => match right.as_ref() {
+                    AlgebraicExpression::Number(n) => left * n.to_degree() as usize,
+                    _ => panic!("Exponent has to be a number."),
+                },
+            }
+        }
+        AlgebraicExpression::UnaryOperation(_, e) => expression_degree(e, intermediate_degrees),
+    }
+}
+
+struct DegreeReducer<T> {
+    max_degree: usize,
+    intermediate_degrees: BTreeMap<PolyID, usize>,
+    /// Names that are already taken.
+    used_names: HashSet<String>,
+    /// Name, definition and source of the witness columns to be created.
+    new_columns: Vec<(String, AlgebraicExpression<T>, SourceRef)>,
+    /// The ID of the first new witness column.
+    first_column_id: u64,
+    next_column_id: usize,
+}
+
+impl<T: FieldElement> DegreeReducer<T> {
+    fn new(pil_file: &Analyzed<T>, max_degree: usize) -> Self {
+        DegreeReducer {
+            max_degree,
+            intermediate_degrees: Default::default(),
+            used_names: pil_file
+                .definitions
+                .keys()
+                .chain(pil_file.intermediate_columns.keys())
+                .cloned()
+                .collect(),
+            new_columns: vec![],
+            first_column_id: pil_file.commitment_count() as u64,
+            next_column_id: 0,
+        }
+    }
+
+    fn degree(&self, expr: &AlgebraicExpression<T>) -> usize {
+        expression_degree(expr, &self.intermediate_degrees)
+    }
+
+    /// Transforms the expression such that its degree is at most `target_degree`.
+    fn reduce(
+        &mut self,
+        expr: AlgebraicExpression<T>,
+        target_degree: usize,
+        source: &SourceRef,
+    ) -> AlgebraicExpression<T> {
+        let expr = self.reduce_to_max_degree(expr, source);
+        if self.degree(&expr) > target_degree {
+            assert!(target_degree >= 1);
+            self.new_column(expr, source)
+        } else {
+            expr
+        }
+    }
+
+    /// Transforms the expression such that its degree is at most `self.max_degree`.
+    fn reduce_to_max_degree(
+        &mut self,
+        expr: AlgebraicExpression<T>,
+        source: &SourceRef,
+    ) -> AlgebraicExpression<T> {
+        if self.degree(&expr) <= self.max_degree {
+            return expr;
+        }
+        match expr {
+            AlgebraicExpression::BinaryOperation(left, AlgebraicBinaryOperator::Pow, right) => {
+                let AlgebraicExpression::Number(exponent) = *right else {
+                    panic!("Exponent has to be a number.");
+                };
+                // Expand into a product and reduce that instead.
+                let product = (1..exponent.to_degree())
+                    .fold(left.as_ref().clone(), |acc, _| acc * left.as_ref().clone());
+                self.reduce_to_max_degree(product, source)
+            }
+            AlgebraicExpression::BinaryOperation(left, AlgebraicBinaryOperator::Mul, right) => {
+                let mut left = self.reduce_to_max_degree(*left, source);
+                let mut right = self.reduce_to_max_degree(*right, source);
+                // Both factors have a degree of at most max_degree >= 2, so we need to
+                // replace at most two of them by a column.
+                while self.degree(&left) + self.degree(&right) > self.max_degree {
+                    if self.degree(&left) >= self.degree(&right) {
+                        left = self.new_column(left, source);
+                    } else {
+                        right = self.new_column(right, source);
+                    }
+                }
+                left * right
+            }
+            AlgebraicExpression::BinaryOperation(left, op, right) => {
+                AlgebraicExpression::new_binary(
+                    self.reduce_to_max_degree(*left, source),
+                    op,
+                    self.reduce_to_max_degree(*right, source),
+                )
+            }
+            AlgebraicExpression::UnaryOperation(op, e) => AlgebraicExpression::UnaryOperation(
+                op,
+                Box::new(self.reduce_to_max_degree(*e, source)),
+            ),
+            AlgebraicExpression::Reference(_) => {
+                // This is a reference to an intermediate polynomial of too high degree.
+                self.new_column(expr, source)
+            }
+            AlgebraicExpression::PublicReference(_) | AlgebraicExpression::Number(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Schedules the creation of a new witness column that is constrained to be equal to
+    /// the given expression and returns a reference to it.
+    fn new_column(
+        &mut self,
+        definition: AlgebraicExpression<T>,
+        source: &SourceRef,
+    ) -> AlgebraicExpression<T> {
+        let namespace = namespace_of(&definition);
+        let name = loop {
+            let name = format!("{namespace}degree_reduction_{}", self.next_column_id);
+            self.next_column_id += 1;
+            if self.used_names.insert(name.clone()) {
+                break name;
+            }
+        };
+        // The columns are appended in this order, see `reduce_degree`.
+        let poly_id = PolyID {
+            id: self.first_column_id + self.new_columns.len() as u64,
+            ptype: PolynomialType::Committed,
+        };
+        self.new_columns
+            .push((name.clone(), definition, source.clone()));
+        AlgebraicExpression::Reference(AlgebraicReference {
+            name,
+            poly_id,
+            next: false,
+        })
+    }
+}
+
+/// @returns the namespace (including the trailing dot) of the first column referenced
+/// in the expression.
+pub(crate) fn namespace_of<T>(expr: &AlgebraicExpression<T>) -> String {
+    let mut namespace = None;
+    expr.pre_visit_expressions(&mut |e| {
+        if let (None, AlgebraicExpression::Reference(poly)) = (&namespace, e) {
+            namespace = Some(match poly.name.rfind('.') {
+                Some(pos) => poly.name[..=pos].to_string(),
+                None => String::new(),
+            });
+        }
+    });
+    namespace.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn reduce(input: &str, max_degree: usize) -> Analyzed<GoldilocksField> {
+        let mut pil_file = analyze_string::<GoldilocksField>(input);
+        reduce_degree(&mut pil_file, max_degree);
+        pil_file
+    }
+
+    #[test]
+    fn low_degree_is_unchanged() {
+        let input = r#"namespace N(8);
+    col witness x;
+    col witness y;
+    (N.x * N.y) = N.x;
+"#;
+        assert_eq!(reduce(input, 2).to_string(), input);
+    }
+
+    #[test]
+    fn split_products() {
+        let input = r#"namespace N(8);
+    col fixed SEL = [1]*;
+    col witness x;
+    col witness y;
+    col witness z;
+    SEL * x * y * z = 1;
+    SEL * x ** 3 = y;
+"#;
+        let expectation = r#"namespace N(8);
+    col fixed SEL = [1]*;
+    col witness x;
+    col witness y;
+    col witness z;
+    (N.degree_reduction_0 * N.z) = 1;
+    (N.SEL * N.degree_reduction_1) = N.y;
+    col witness degree_reduction_0;
+    N.degree_reduction_0 = ((N.SEL * N.x) * N.y);
+    col witness degree_reduction_1;
+    N.degree_reduction_1 = (N.x ** 3);
+"#;
+        let reduced = reduce(input, 3);
+        assert_eq!(reduced.to_string(), expectation);
+        assert_eq!(reduced.commitment_count(), 5);
+    }
+
+    #[test]
+    fn intermediates_and_lookups() {
+        let input = r#"namespace N(8);
+    col fixed BYTE(i) { i & 0xff };
+    col witness x;
+    col witness y;
+    col witness sel;
+    col sq = x * x;
+    col cube = sq * x;
+    sel { cube } in { BYTE };
+    y = cube * x;
+"#;
+        let reduced = reduce(input, 2);
+        let mut intermediate_degrees = BTreeMap::new();
+        for (symbol, definition) in reduced.intermediate_polys_in_source_order() {
+            let degree = expression_degree(definition, &intermediate_degrees);
+            intermediate_degrees.insert(PolyID::from(symbol), degree);
+        }
+        let degree = |e: &AlgebraicExpression<_>| expression_degree(e, &intermediate_degrees);
+        let max_degree = reduced
+            .identities
+            .iter()
+            .flat_map(|identity| [&identity.left, &identity.right])
+            .map(|side| {
+                side.selector.as_ref().map(degree).unwrap_or_default()
+                    + side
+                        .expressions
+                        .iter()
+                        .map(degree)
+                        .max()
+                        .unwrap_or_default()
+            })
+            .max()
+            .unwrap();
+        assert_eq!(max_degree, 2);
+        // Every witness column ID is used exactly once.
+        let mut ids = reduced
+            .committed_polys_in_source_order()
+            .into_iter()
+            .map(|(symbol, _)| symbol.id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(
+            ids,
+            (0..reduced.commitment_count() as u64).collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,36 +1,32 @@
 //! PIL-based optimizer
 #![deny(clippy::print_stdout)]
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 use powdr_ast::analyzed::{
     AlgebraicBinaryOperator, AlgebraicExpression, AlgebraicUnaryOperator, Reference,
 };
 use powdr_ast::analyzed::{
     AlgebraicReference, Analyzed, Expression, FunctionValueDefinition, IdentityKind, PolyID,
-    PolynomialReference,
+    PolynomialReference, PolynomialType, Symbol, SymbolKind,
 };
 use powdr_ast::parsed::visitor::ExpressionVisitable;
 
 use powdr_number::FieldElement;
 
-pub fn optimize<T: FieldElement>(mut pil_file: Analyzed<T>) -> Analyzed<T> {
-    let col_count_pre = (pil_file.commitment_count(), pil_file.constant_count());
-    remove_constant_fixed_columns(&mut pil_file);
-    simplify_identities(&mut pil_file);
-    extract_constant_lookups(&mut pil_file);
-    remove_constant_witness_columns(&mut pil_file);
-    simplify_identities(&mut pil_file);
-    remove_trivial_identities(&mut pil_file);
-    let col_count_post = (pil_file.commitment_count(), pil_file.constant_count());
-    log::info!(
-        "Removed {} witness and {} fixed columns. Total count now: {} witness and {} fixed columns.",
-        col_count_pre.0 - col_count_post.0,
-        col_count_pre.1 - col_count_post.1,
-        col_count_post.0,
-        col_count_post.1
-    );
-    pil_file
+mod common_subexpressions;
+mod degree_reduction;
+mod lookup_batching;
+mod passes;
+pub mod stats;
+
+pub use common_subexpressions::{extract_common_subexpressions, CseCostModel};
+pub use degree_reduction::{expression_degree, reduce_degree};
+pub use passes::{optimize_with_passes, BackendParameters, OptLevel, OptimizerPass};
+
+/// Runs the optimizer passes of the default optimization level.
+pub fn optimize<T: FieldElement>(pil_file: Analyzed<T>) -> Analyzed<T> {
+    optimize_with_passes(pil_file, &OptLevel::default().passes(), &Default::default())
 }
 
 /// Identifies fixed columns that only have a single value, replaces every
@@ -57,6 +53,154 @@ fn remove_constant_fixed_columns<T: FieldElement>(pil_file: &mut Analyzed<T>) {
     pil_file.remove_polynomials(&constant_polys.keys().cloned().collect());
 }
 
+/// Identifies fixed columns with identical definitions, replaces every reference
+/// to such a column by a reference to the first one and deletes the others.
+fn deduplicate_fixed_columns<T: FieldElement>(pil_file: &mut Analyzed<T>) {
+    let mut representatives: Vec<(&Symbol, FunctionValueDefinition<T>)> = vec![];
+    let mut replacements = BTreeMap::new();
+    for (poly, definition) in pil_file.constant_polys_in_source_order() {
+        // We cannot remove arrays or array elements.
+        let (false, Some(definition)) = (poly.is_array(), definition) else {
+            continue;
+        };
+        let definition = without_reference_names(definition);
+        match representatives.iter().find(|(_, d)| *d == definition) {
+            Some((representative, _)) => {
+                log::debug!(
+                    "Fixed column {} has the same definition as {}. Removing.",
+                    poly.absolute_name,
+                    representative.absolute_name
+                );
+                replacements.insert(
+                    PolyID::from(poly),
+                    (
+                        PolyID::from(*representative),
+                        representative.absolute_name.clone(),
+                    ),
+                );
+            }
+            None => representatives.push((poly, definition)),
+        }
+    }
+
+    pil_file.post_visit_expressions_in_definitions_mut(&mut |e: &mut Expression<_>| {
+        if let Expression::Reference(Reference::Poly(poly)) = e {
+            if let Some((poly_id, name)) = poly.poly_id.and_then(|id| replacements.get(&id)) {
+                poly.poly_id = Some(*poly_id);
+                poly.name = name.clone();
+            }
+        }
+    });
+    pil_file.post_visit_expressions_in_identities_mut(&mut |e: &mut AlgebraicExpression<_>| {
+        if let AlgebraicExpression::Reference(poly) = e {
+            if let Some((poly_id, name)) = replacements.get(&poly.poly_id) {
+                poly.poly_id = *poly_id;
+                poly.name = name.clone();
+            }
+        }
+    });
+    for decl in pil_file.public_declarations.values_mut() {
+        if let Some((poly_id, name)) = decl.polynomial.poly_id.and_then(|id| replacements.get(&id))
+        {
+            decl.polynomial.poly_id = Some(*poly_id);
+            decl.polynomial.name = name.clone();
+        }
+    }
+    pil_file.remove_polynomials(&replacements.keys().cloned().collect());
+}
+
+/// @returns a copy of the definition without the names of polynomial references that
+/// have an ID, so that comparing definitions compares the IDs of referenced polynomials.
+fn without_reference_names<T: Clone>(
+    definition: &FunctionValueDefinition<T>,
+) -> FunctionValueDefinition<T> {
+    let mut definition = definition.clone();
+    definition.post_visit_expressions_mut(&mut |e: &mut Expression<_>| {
+        if let Expression::Reference(Reference::Poly(poly)) = e {
+            if poly.poly_id.is_some() {
+                poly.name.clear();
+            }
+        }
+    });
+    definition
+}
+
+/// Removes witness, fixed and intermediate columns that are not referenced
+/// in any identity, public declaration or definition.
+fn remove_unreferenced_columns<T: FieldElement>(pil_file: &mut Analyzed<T>) {
+    let mut referenced = BTreeSet::new();
+    for identity in &pil_file.identities {
+        identity.pre_visit_expressions(&mut |e| {
+            if let AlgebraicExpression::Reference(poly) = e {
+                referenced.insert(poly.poly_id);
+            }
+        });
+    }
+    for decl in pil_file.public_declarations.values() {
+        let poly_id = decl.polynomial.poly_id.unwrap();
+        referenced.insert(PolyID {
+            id: poly_id.id + decl.array_index.unwrap_or_default() as u64,
+            ..poly_id
+        });
+    }
+    // Definitions of fixed columns and queries of witness columns can reference
+    // other columns.
+    pil_file.post_visit_expressions_in_definitions_mut(&mut |e: &mut Expression<_>| {
+        if let Expression::Reference(Reference::Poly(poly)) = e {
+            referenced.extend(poly.poly_id);
+        }
+    });
+    // Intermediate columns are only referenced if they are used in something
+    // that is referenced, so we have to propagate from the identities.
+    let intermediates = pil_file
+        .intermediate_columns
+        .values()
+        .map(|(poly, definition)| (PolyID::from(poly), definition))
+        .collect::<BTreeMap<_, _>>();
+    let mut to_visit = referenced
+        .iter()
+        .filter(|poly_id| intermediates.contains_key(poly_id))
+        .cloned()
+        .collect::<Vec<_>>();
+    while let Some(poly_id) = to_visit.pop() {
+        intermediates[&poly_id].pre_visit_expressions(&mut |e| {
+            if let AlgebraicExpression::Reference(poly) = e {
+                if referenced.insert(poly.poly_id)
+                    && poly.poly_id.ptype == PolynomialType::Intermediate
+                {
+                    to_visit.push(poly.poly_id);
+                }
+            }
+        });
+    }
+
+    // Some parts of the toolchain still require at least one fixed column.
+    if !referenced
+        .iter()
+        .any(|poly_id| poly_id.ptype == PolynomialType::Constant)
+    {
+        if let Some((poly, _)) = pil_file.constant_polys_in_source_order().first() {
+            referenced.insert(PolyID::from(poly));
+        }
+    }
+
+    let to_remove = pil_file
+        .definitions
+        .values()
+        .filter_map(|(poly, _)| match poly.kind {
+            // We cannot remove arrays or array elements.
+            SymbolKind::Poly(_) if !poly.is_array() => Some(PolyID::from(poly)),
+            _ => None,
+        })
+        .chain(intermediates.keys().cloned())
+        .filter(|poly_id| !referenced.contains(poly_id))
+        .collect::<BTreeSet<_>>();
+    if !to_remove.is_empty() {
+        log::debug!("Removing {} unreferenced columns.", to_remove.len());
+    }
+    pil_file.remove_polynomials(&to_remove);
+}
+
 /// Checks if a fixed column defined through a function has a constant
 /// value and returns it in that case.
 fn constant_value<T: FieldElement>(function: &FunctionValueDefinition<T>) -> Option<T> {
@@ -339,13 +483,19 @@ fn remove_trivial_identities<T: FieldElement>(pil_file: &mut Analyzed<T>) {
 
 #[cfg(test)]
 mod test {
+    use powdr_ast::analyzed::Analyzed;
     use powdr_number::GoldilocksField;
     use powdr_pil_analyzer::analyze_string;
 
-    use crate::optimize;
+    use crate::{optimize, optimize_with_passes, OptLevel};
 
     use pretty_assertions::assert_eq;
 
+    /// Runs all passes, including the ones that are not run by default.
+    fn optimize_full(pil_file: Analyzed<GoldilocksField>) -> Analyzed<GoldilocksField> {
+        optimize_with_passes(pil_file, &OptLevel::Full.passes(), &Default::default())
+    }
+
     #[test]
     fn replace_fixed() {
         let input = r#"namespace N(65536);
@@ -415,4 +565,83 @@ mod test {
         let optimized = optimize(analyze_string::<GoldilocksField>(input)).to_string();
         assert_eq!(optimized, expectation);
     }
+
+    #[test]
+    fn remove_unreferenced() {
+        let input = r#"namespace N(65536);
+        col fixed FIRST = [1] + [0]*;
+        col fixed UNUSED = [1, 2] + [0]*;
+        col witness unused;
+        col witness x;
+        col witness y;
+        col witness arr[2];
+        col unused_inter = unused * 2;
+        col inter = y * y;
+        col inter2 = inter + FIRST;
+        x = inter2;
+        public out = y(3);
+    "#;
+        let expectation = r#"namespace N(65536);
+    col fixed FIRST = [1] + [0]*;
+    col witness x;
+    col witness y;
+    col witness arr[2];
+    col inter = (N.y * N.y);
+    col inter2 = (N.inter + N.FIRST);
+    N.x = N.inter2;
+namespace (65536);
+public out = N.y(3);
+"#;
+        let optimized = optimize_full(analyze_string::<GoldilocksField>(input));
+        assert_eq!(optimized.to_string(), expectation);
+        assert_eq!(optimized.intermediate_columns["N.inter2"].0.id, 1);
+        let public = optimized.public_declarations.values().next().unwrap();
+        assert_eq!(public.polynomial.poly_id.unwrap().id, 1);
+    }
+
+    #[test]
+    fn deduplicate_fixed() {
+        let input = r#"namespace N(65536);
+        col fixed LATCH(i) { if i % 4 == 3 { 1 } else { 0 } };
+        col fixed FIRST = [1] + [0]*;
+        col fixed LATCH2(i) { if i % 4 == 3 { 1 } else { 0 } };
+        col fixed FIRST2 = [1] + [0]*;
+        col fixed OTHER(i) { if i % 4 == 2 { 1 } else { 0 } };
+        col witness x;
+        col witness y;
+        LATCH * x + LATCH2 * y + OTHER = 0;
+        (1 - FIRST2) * (x - y) = FIRST;
+    "#;
+        let expectation = r#"namespace N(65536);
+    col fixed LATCH(i) { if ((i % 4) == 3) { 1 } else { 0 } };
+    col fixed FIRST = [1] + [0]*;
+    col fixed OTHER(i) { if ((i % 4) == 2) { 1 } else { 0 } };
+    col witness x;
+    col witness y;
+    (((N.LATCH * N.x) + (N.LATCH * N.y)) + N.OTHER) = 0;
+    ((1 - N.FIRST) * (N.x - N.y)) = N.FIRST;
+"#;
+        let optimized = optimize_full(analyze_string::<GoldilocksField>(input));
+        assert_eq!(optimized.to_string(), expectation);
+        assert_eq!(optimized.constant_count(), 3);
+    }
+
+    #[test]
+    fn keep_one_fixed_column() {
+        let input = r#"namespace N(65536);
+        col fixed L1 = [1] + [0]*;
+        col fixed L2 = [0, 1] + [0]*;
+        col witness a;
+        col witness b;
+        b = a + 1;
+    "#;
+        let expectation = r#"namespace N(65536);
+    col fixed L1 = [1] + [0]*;
+    col witness a;
+    col witness b;
+    N.b = (N.a + 1);
+"#;
+        let optimized = optimize_full(analyze_string::<GoldilocksField>(input)).to_string();
+        assert_eq!(optimized, expectation);
+    }
 }
@@ -0,0 +1,296 @@
+//! Merges lookups into the same table whose selectors are mutually exclusive.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use powdr_ast::analyzed::{
+    AlgebraicExpression, Analyzed, Expression, FunctionValueDefinition, Identity, IdentityKind,
+    PolyID,
+};
+use powdr_number::{DegreeType, FieldElement};
+
+use crate::simplify_expression;
+
+/// Combines plookup identities that have the same right hand side and whose
+/// left hand side selectors are mutually exclusive into a single identity.
+///
+/// `s_1 { a_1, b_1 } in t` and `s_2 { a_2, b_2 } in t` become
+/// `(s_1 + s_2) { s_1 * a_1 + s_2 * a_2, s_1 * b_1 + s_2 * b_2 } in t`.
+///
+/// Selectors are considered mutually exclusive if they are witness columns that are
+/// looked up (without selectors) in boolean fixed columns that are never both non-zero
+/// in the same row, as it is the case for instruction flags in the ROM of a VM.
+pub(crate) fn batch_lookups<T: FieldElement>(pil_file: &mut Analyzed<T>) {
+    let exclusive_flags = ExclusiveFlags::new(pil_file);
+    if exclusive_flags.is_empty() {
+        return;
+    }
+
+    // Indices of identities that are merged into the first one of the batch.
+    let mut batches: Vec<Vec<(usize, PolyID)>> = vec![];
+    for (index, identity) in pil_file.identities.iter().enumerate() {
+        let Some(flag) = flag_selector(identity) else {
+            continue;
+        };
+        let batch = batches.iter_mut().find(|batch| {
+            pil_file.identities[batch[0].0].right == identity.right
+                && batch
+                    .iter()
+                    .all(|(_, other)| exclusive_flags.are_exclusive(flag, *other))
+        });
+        match batch {
+            Some(batch) => batch.push((index, flag)),
+            None => batches.push(vec![(index, flag)]),
+        }
+    }
+
+    let mut to_remove = BTreeSet::new();
+    for batch in batches.into_iter().filter(|batch| batch.len() > 1) {
+        let merged = merge(batch.iter().map(|(index, _)| &pil_file.identities[*index]));
+        log::debug!(
+            "Merging {} lookups into the same table into {merged}",
+            batch.len()
+        );
+        pil_file.identities[batch[0].0] = merged;
+        to_remove.extend(batch[1..].iter().map(|(index, _)| *index));
+    }
+    if !to_remove.is_empty() {
+        log::info!("Removed {} lookups by batching.", to_remove.len());
+    }
+    pil_file.remove_identities(&to_remove);
+}
+
+/// @returns the witness column used as the left hand side selector if `identity`
+/// is a plookup with such a selector.
+fn flag_selector<T>(identity: &Identity<AlgebraicExpression<T>>) -> Option<PolyID> {
+    match (&identity.kind, &identity.left.selector) {
+        (IdentityKind::Plookup, Some(AlgebraicExpression::Reference(poly)))
+            if poly.is_witness() && !poly.next =>
+        {
+            Some(poly.poly_id)
+        }
+        _ => None,
+    }
+}
+
+/// Merges lookups with the same right hand side and mutually exclusive selectors.
+fn merge<'a, T: FieldElement + 'a>(
+    identities: impl Iterator<Item = &'a Identity<AlgebraicExpression<T>>>,
+) -> Identity<AlgebraicExpression<T>> {
+    let mut identities = identities.peekable();
+    let mut merged = (*identities.peek().unwrap()).clone();
+    merged.left.selector = None;
+    merged.left.expressions = vec![];
+    for identity in identities {
+        let selector = identity.left.selector.clone().unwrap();
+        let terms = identity
+            .left
+            .expressions
+            .iter()
+            .map(|e| simplify_expression(selector.clone() * e.clone()));
+        if merged.left.expressions.is_empty() {
+            merged.left.expressions = terms.collect();
+        } else {
+            merged.left.expressions = std::mem::take(&mut merged.left.expressions)
+                .into_iter()
+                .zip(terms)
+                .map(|(sum, term)| simplify_expression(sum + term))
+                .collect();
+        }
+        merged.left.selector = Some(match merged.left.selector {
+            Some(sum) => sum + selector,
+            None => selector,
+        });
+    }
+    merged
+}
+
+/// Witness columns whose values are taken from boolean fixed columns in a lookup.
+struct ExclusiveFlags {
+    /// For each witness column, the indices of the lookups that determine its value.
+    lookups: BTreeMap<PolyID, BTreeSet<usize>>,
+    /// Pairs of witness columns that can both be non-zero, together with
+    /// the index of the lookup.
+    conflicts: BTreeSet<(usize, PolyID, PolyID)>,
+}
+
+impl ExclusiveFlags {
+    fn new<T: FieldElement>(pil_file: &Analyzed<T>) -> Self {
+        let mut columns = BTreeMap::new();
+        let mut flags = ExclusiveFlags {
+            lookups: BTreeMap::new(),
+            conflicts: BTreeSet::new(),
+        };
+        let Some(degree) = pil_file.degree else {
+            return flags;
+        };
+        for (index, identity) in pil_file.identities.iter().enumerate() {
+            if identity.kind != IdentityKind::Plookup
+                || identity.left.selector.is_some()
+                || identity.right.selector.is_some()
+            {
+                continue;
+            }
+            // The rows in which each boolean fixed column is one.
+            let mut flags_per_row: BTreeMap<DegreeType, Vec<PolyID>> = BTreeMap::new();
+            for (left, right) in identity
+                .left
+                .expressions
+                .iter()
+                .zip(&identity.right.expressions)
+            {
+                let (AlgebraicExpression::Reference(flag), AlgebraicExpression::Reference(fixed)) =
+                    (left, right)
+                else {
+                    continue;
+                };
+                if !flag.is_witness() || flag.next || !fixed.is_fixed() || fixed.next {
+                    continue;
+                }
+                let ones = columns.entry(fixed.poly_id).or_insert_with(|| {
+                    let (_, definition) = &pil_file.definitions[&fixed.name];
+                    definition
+                        .as_ref()
+                        .and_then(|definition| rows_with_one(definition, degree))
+                });
+                let Some(ones) = ones else {
+                    continue;
+                };
+                flags.lookups.entry(flag.poly_id).or_default().insert(index);
+                for row in ones {
+                    flags_per_row.entry(*row).or_default().push(flag.poly_id);
+                }
+            }
+            for flags_in_row in flags_per_row.values() {
+                for a in flags_in_row {
+                    for b in flags_in_row {
+                        flags.conflicts.insert((index, *a, *b));
+                    }
+                }
+            }
+        }
+        flags
+    }
+
+    fn is_empty(&self) -> bool {
+        self.lookups.is_empty()
+    }
+
+    /// @returns true if the two witness columns are never both non-zero.
+    fn are_exclusive(&self, a: PolyID, b: PolyID) -> bool {
+        let (Some(lookups_a), Some(lookups_b)) = (self.lookups.get(&a), self.lookups.get(&b))
+        else {
+            return false;
+        };
+        lookups_a
+            .intersection(lookups_b)
+            .any(|index| !self.conflicts.contains(&(*index, a, b)))
+    }
+}
+
+/// @returns the rows in which the fixed column is one if the column is defined
+/// as an array and only takes the values zero and one.
+fn rows_with_one<T: FieldElement>(
+    definition: &FunctionValueDefinition<T>,
+    degree: DegreeType,
+) -> Option<Vec<DegreeType>> {
+    let FunctionValueDefinition::Array(arrays) = definition else {
+        return None;
+    };
+    let mut rows = vec![];
+    let mut offset = 0;
+    for array in arrays {
+        let pattern = array
+            .pattern()
+            .iter()
+            .map(|e| match e {
+                Expression::Number(n) if *n == 0.into() => Some(false),
+                Expression::Number(n) if *n == 1.into() => Some(true),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if pattern.iter().any(|one| *one) {
+            rows.extend(
+                (0..array.size())
+                    .filter(|i| pattern[(*i as usize) % pattern.len()])
+                    .map(|i| offset + i),
+            );
+        }
+        offset += array.size();
+    }
+    (offset == degree).then_some(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn batch(input: &str) -> String {
+        let mut pil_file = analyze_string::<GoldilocksField>(input);
+        batch_lookups(&mut pil_file);
+        pil_file.to_string()
+    }
+
+    #[test]
+    fn batch_instruction_lookups() {
+        let input = r#"namespace N(4);
+    col fixed p_line = [0, 1, 2, 3];
+    col fixed p_instr_a = [1, 0, 0, 0];
+    col fixed p_instr_b = [0, 1, 0, 1];
+    col fixed p_instr_c = [0, 1, 0, 0];
+    col fixed LATCH = [1]*;
+    col witness pc;
+    col witness instr_a;
+    col witness instr_b;
+    col witness instr_c;
+    col witness x;
+    col witness y;
+    col witness op;
+    col witness t;
+    { N.pc, N.instr_a, N.instr_b, N.instr_c } in { N.p_line, N.p_instr_a, N.p_instr_b, N.p_instr_c };
+    N.instr_a { 0, N.x } in N.LATCH { N.op, N.t };
+    N.instr_b { 1, N.y } in N.LATCH { N.op, N.t };
+    N.instr_c { 2, (N.x + N.y) } in N.LATCH { N.op, N.t };
+    N.instr_a { N.x } in { N.p_line };
+"#;
+        // instr_b and instr_c are both set in row 1, so they are not exclusive.
+        let expectation = r#"namespace N(4);
+    col fixed p_line = [0, 1, 2, 3];
+    col fixed p_instr_a = [1, 0, 0, 0];
+    col fixed p_instr_b = [0, 1, 0, 1];
+    col fixed p_instr_c = [0, 1, 0, 0];
+    col fixed LATCH = [1]*;
+    col witness pc;
+    col witness instr_a;
+    col witness instr_b;
+    col witness instr_c;
+    col witness x;
+    col witness y;
+    col witness op;
+    col witness t;
+    { N.pc, N.instr_a, N.instr_b, N.instr_c } in { N.p_line, N.p_instr_a, N.p_instr_b, N.p_instr_c };
+    (N.instr_a + N.instr_b) { N.instr_b, ((N.instr_a * N.x) + (N.instr_b * N.y)) } in N.LATCH { N.op, N.t };
+    N.instr_c { 2, (N.x + N.y) } in N.LATCH { N.op, N.t };
+    N.instr_a { N.x } in { N.p_line };
+"#;
+        assert_eq!(batch(input), expectation);
+    }
+
+    #[test]
+    fn no_exclusivity() {
+        let input = r#"namespace N(4);
+    col fixed LATCH = [1]*;
+    col witness instr_a;
+    col witness instr_b;
+    col witness x;
+    col witness op;
+    N.instr_a { 0, N.x } in N.LATCH { N.op, N.x };
+    N.instr_b { 1, N.x } in N.LATCH { N.op, N.x };
+"#;
+        assert_eq!(batch(input), input);
+    }
+}
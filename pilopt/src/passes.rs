@@ -0,0 +1,316 @@
+//! Named optimizer passes and optimization levels.
+
+use std::time::Instant;
+
+use powdr_ast::analyzed::Analyzed;
+use powdr_number::FieldElement;
+use strum::{Display, EnumIter, EnumString, EnumVariantNames, IntoEnumIterator};
+
+use crate::{lookup_batching, CseCostModel};
+
+/// A single optimization step on an analyzed PIL file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, EnumVariantNames, EnumIter, Display)]
+pub enum OptimizerPass {
+    /// Replaces fixed columns that only have a single value by that value.
+    #[strum(serialize = "remove-constant-fixed-columns")]
+    RemoveConstantFixedColumns,
+    /// Evaluates constant subexpressions and removes additions of zero
+    /// and multiplications by one.
+    #[strum(serialize = "simplify-identities")]
+    SimplifyIdentities,
+    /// Turns lookups of constants into fixed columns into polynomial identities.
+    #[strum(serialize = "extract-constant-lookups")]
+    ExtractConstantLookups,
+    /// Replaces witness columns that are constrained to a constant by that constant.
+    #[strum(serialize = "remove-constant-witness-columns")]
+    RemoveConstantWitnessColumns,
+    /// Removes identities that are trivially satisfied.
+    #[strum(serialize = "remove-trivial-identities")]
+    RemoveTrivialIdentities,
+    /// Replaces fixed columns with identical definitions by a single column.
+    #[strum(serialize = "deduplicate-fixed-columns")]
+    DeduplicateFixedColumns,
+    /// Merges lookups into the same table whose selectors are mutually exclusive.
+    #[strum(serialize = "batch-lookups")]
+    BatchLookups,
+    /// Removes columns that are not referenced anywhere.
+    #[strum(serialize = "remove-unreferenced-columns")]
+    RemoveUnreferencedColumns,
+    /// Reduces the degree of all constraints to the maximum degree supported
+    /// by the backend, if there is one.
+    #[strum(serialize = "reduce-degree")]
+    ReduceDegree,
+    /// Extracts common subexpressions into intermediate polynomials, if the
+    /// backend benefits from it.
+    #[strum(serialize = "extract-common-subexpressions")]
+    ExtractCommonSubexpressions,
+}
+
+/// The parameters of the passes that depend on the backend.
+/// The passes that need a parameter that is not set do nothing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackendParameters {
+    /// The maximum degree of constraints, see `OptimizerPass::ReduceDegree`.
+    pub max_constraint_degree: Option<usize>,
+    /// The cost model to extract common subexpressions with,
+    /// see `OptimizerPass::ExtractCommonSubexpressions`.
+    pub cse_cost_model: Option<CseCostModel>,
+}
+
+impl OptimizerPass {
+    pub fn run<T: FieldElement>(&self, pil_file: &mut Analyzed<T>, parameters: &BackendParameters) {
+        match self {
+            OptimizerPass::RemoveConstantFixedColumns => {
+                crate::remove_constant_fixed_columns(pil_file)
+            }
+            OptimizerPass::SimplifyIdentities => crate::simplify_identities(pil_file),
+            OptimizerPass::ExtractConstantLookups => crate::extract_constant_lookups(pil_file),
+            OptimizerPass::RemoveConstantWitnessColumns => {
+                crate::remove_constant_witness_columns(pil_file)
+            }
+            OptimizerPass::RemoveTrivialIdentities => crate::remove_trivial_identities(pil_file),
+            OptimizerPass::DeduplicateFixedColumns => crate::deduplicate_fixed_columns(pil_file),
+            OptimizerPass::BatchLookups => lookup_batching::batch_lookups(pil_file),
+            OptimizerPass::RemoveUnreferencedColumns => {
+                crate::remove_unreferenced_columns(pil_file)
+            }
+            OptimizerPass::ReduceDegree => {
+                if let Some(max_degree) = parameters.max_constraint_degree {
+                    crate::reduce_degree(pil_file, max_degree)
+                }
+            }
+            OptimizerPass::ExtractCommonSubexpressions => {
+                if let Some(cost_model) = &parameters.cse_cost_model {
+                    crate::extract_common_subexpressions(pil_file, cost_model)
+                }
+            }
+        }
+    }
+
+    /// @returns all passes in their default order.
+    pub fn all() -> impl Iterator<Item = OptimizerPass> {
+        OptimizerPass::iter()
+    }
+}
+
+/// A predefined sequence of optimizer passes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, EnumVariantNames, Display)]
+pub enum OptLevel {
+    /// No optimization.
+    #[strum(serialize = "0")]
+    None,
+    /// Removes constant columns and simplifies identities.
+    #[default]
+    #[strum(serialize = "1")]
+    Basic,
+    /// All passes, including the backend-specific ones.
+    /// They add witness columns and merge lookups, so they are opt-in.
+    #[strum(serialize = "2")]
+    Full,
+}
+
+impl OptLevel {
+    pub fn passes(&self) -> Vec<OptimizerPass> {
+        use OptimizerPass::*;
+        let basic = [
+            RemoveConstantFixedColumns,
+            SimplifyIdentities,
+            ExtractConstantLookups,
+            RemoveConstantWitnessColumns,
+            // Removing columns can create new opportunities for simplification.
+            SimplifyIdentities,
+            RemoveTrivialIdentities,
+        ];
+        match self {
+            OptLevel::None => vec![],
+            OptLevel::Basic => basic.to_vec(),
+            OptLevel::Full => basic
+                .into_iter()
+                .chain([
+                    DeduplicateFixedColumns,
+                    BatchLookups,
+                    RemoveUnreferencedColumns,
+                    ReduceDegree,
+                    ExtractCommonSubexpressions,
+                ])
+                .collect(),
+        }
+    }
+}
+
+/// Runs the given passes in order, logging the time taken by each pass and
+/// how it changed the number of columns and identities.
+pub fn optimize_with_passes<T: FieldElement>(
+    mut pil_file: Analyzed<T>,
+    passes: &[OptimizerPass],
+    parameters: &BackendParameters,
+) -> Analyzed<T> {
+    let col_count_pre = (pil_file.commitment_count(), pil_file.constant_count());
+    for pass in passes {
+        let before = Counts::new(&pil_file);
+        let start = Instant::now();
+        pass.run(&mut pil_file, parameters);
+        let duration = start.elapsed();
+        let after = Counts::new(&pil_file);
+        log::debug!(
+            "Optimizer pass {pass} took {:.2}s: {}",
+            duration.as_secs_f64(),
+            before.diff(&after)
+        );
+    }
+    let col_count_post = (pil_file.commitment_count(), pil_file.constant_count());
+    log::info!(
+        "Removed {} witness and {} fixed columns. Total count now: {} witness and {} fixed columns.",
+        col_count_pre.0 - col_count_post.0,
+        col_count_pre.1 - col_count_post.1,
+        col_count_post.0,
+        col_count_post.1
+    );
+    pil_file
+}
+
+/// The sizes of a PIL file that are reported after each pass.
+#[derive(PartialEq, Eq)]
+struct Counts {
+    witness: usize,
+    fixed: usize,
+    intermediate: usize,
+    identities: usize,
+}
+
+impl Counts {
+    fn new<T>(pil_file: &Analyzed<T>) -> Self {
+        Counts {
+            witness: pil_file.commitment_count(),
+            fixed: pil_file.constant_count(),
+            intermediate: pil_file.intermediate_count(),
+            identities: pil_file.identities.len(),
+        }
+    }
+
+    fn diff(&self, after: &Counts) -> String {
+        if self == after {
+            return "no change".to_string();
+        }
+        [
+            ("witness columns", self.witness, after.witness),
+            ("fixed columns", self.fixed, after.fixed),
+            (
+                "intermediate columns",
+                self.intermediate,
+                after.intermediate,
+            ),
+            ("identities", self.identities, after.identities),
+        ]
+        .into_iter()
+        .map(|(name, before, after)| format!("{name} {before} -> {after}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const INPUT: &str = r#"namespace N(65536);
+    col fixed one = [1]*;
+    col fixed BYTE(i) { i & 0xff };
+    col fixed BYTE2(i) { i & 0xff };
+    col witness x;
+    col witness y;
+    col witness z;
+    N.z = 7;
+    { N.x } in { N.BYTE };
+    { N.y } in { N.BYTE2 };
+    (N.x * N.one) = (N.y + N.z);
+"#;
+
+    fn run(passes: &[OptimizerPass]) -> String {
+        optimize_with_passes(
+            analyze_string::<GoldilocksField>(INPUT),
+            passes,
+            &Default::default(),
+        )
+        .to_string()
+    }
+
+    #[test]
+    fn no_passes() {
+        assert_eq!(
+            run(&OptLevel::None.passes()),
+            analyze_string::<GoldilocksField>(INPUT).to_string()
+        );
+    }
+
+    #[test]
+    fn single_pass() {
+        let expectation = r#"namespace N(65536);
+    col fixed one = [1]*;
+    col fixed BYTE(i) { (i & 255) };
+    col witness x;
+    col witness y;
+    col witness z;
+    N.z = 7;
+    { N.x } in { N.BYTE };
+    { N.y } in { N.BYTE };
+    (N.x * N.one) = (N.y + N.z);
+"#;
+        assert_eq!(run(&[OptimizerPass::DeduplicateFixedColumns]), expectation);
+    }
+
+    #[test]
+    fn levels() {
+        let expectation = r#"namespace N(65536);
+    col fixed BYTE(i) { (i & 255) };
+    col fixed BYTE2(i) { (i & 255) };
+    col witness x;
+    col witness y;
+    { N.x } in { N.BYTE };
+    { N.y } in { N.BYTE2 };
+    N.x = (N.y + 7);
+"#;
+        assert_eq!(run(&OptLevel::Basic.passes()), expectation);
+        assert_eq!(OptLevel::default(), OptLevel::Basic);
+        let expectation = r#"namespace N(65536);
+    col fixed BYTE(i) { (i & 255) };
+    col witness x;
+    col witness y;
+    { N.x } in { N.BYTE };
+    { N.y } in { N.BYTE };
+    N.x = (N.y + 7);
+"#;
+        assert_eq!(run(&OptLevel::Full.passes()), expectation);
+    }
+
+    #[test]
+    fn backend_passes() {
+        let input = analyze_string::<GoldilocksField>(
+            "namespace N(4); col witness x; col witness y; x * x * x = y;",
+        );
+        let passes = [OptimizerPass::ReduceDegree];
+        // Without a maximum degree, the degree is not reduced.
+        let optimized = optimize_with_passes(input.clone(), &passes, &Default::default());
+        assert_eq!(optimized.to_string(), input.to_string());
+        let parameters = BackendParameters {
+            max_constraint_degree: Some(2),
+            cse_cost_model: None,
+        };
+        let optimized = optimize_with_passes(input.clone(), &passes, &parameters);
+        assert_eq!(optimized.commitment_count(), 3);
+    }
+
+    #[test]
+    fn parse_passes() {
+        for pass in OptimizerPass::all() {
+            assert_eq!(pass.to_string().parse::<OptimizerPass>(), Ok(pass));
+        }
+        assert_eq!("1".parse::<OptLevel>(), Ok(OptLevel::Basic));
+        assert!("unknown-pass".parse::<OptimizerPass>().is_err());
+    }
+}
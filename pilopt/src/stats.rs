@@ -0,0 +1,360 @@
+//! Statistics about the size and cost of a circuit.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+
+use powdr_ast::analyzed::{
+    AlgebraicExpression, Analyzed, Identity, IdentityKind, PolyID, PolynomialType, SymbolKind,
+};
+use powdr_number::{DegreeType, FieldElement};
+use serde::Serialize;
+
+use crate::expression_degree;
+
+/// Statistics about the constraints of a PIL file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CircuitStats {
+    /// The number of rows.
+    pub degree: Option<DegreeType>,
+    /// The number of columns of each kind, per namespace.
+    pub columns: BTreeMap<String, ColumnCounts>,
+    pub identities: IdentityCounts,
+    /// The maximum degree of all identities, see [identity_degree].
+    pub max_constraint_degree: usize,
+    /// The number of identities for each degree.
+    pub degree_histogram: BTreeMap<usize, usize>,
+    /// The number of expressions in each plookup identity.
+    pub lookup_widths: Vec<usize>,
+    /// The number of expressions in each permutation identity.
+    pub permutation_widths: Vec<usize>,
+    /// The number of distinct right hand sides of plookup and permutation identities.
+    pub distinct_tables: usize,
+    pub halo2_cost: BackendCost,
+    pub estark_cost: BackendCost,
+}
+
+/// The number of columns of each kind, with multiplicities for arrays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ColumnCounts {
+    pub fixed: usize,
+    pub witness: usize,
+    pub intermediate: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct IdentityCounts {
+    pub polynomial: usize,
+    pub plookup: usize,
+    pub permutation: usize,
+    pub connect: usize,
+}
+
+/// A rough estimate of the cost of proving the circuit with a backend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BackendCost {
+    /// The number of committed columns, including the ones needed for
+    /// lookups, permutations and the quotient polynomial.
+    pub committed_columns: usize,
+    /// The factor by which the evaluation domain is extended.
+    pub blowup_factor: usize,
+    /// The number of committed cells in the extended evaluation domain.
+    pub committed_cells: u64,
+}
+
+impl BackendCost {
+    fn new(committed_columns: usize, blowup_factor: usize, degree: Option<DegreeType>) -> Self {
+        BackendCost {
+            committed_columns,
+            blowup_factor,
+            committed_cells: committed_columns as u64
+                * blowup_factor as u64
+                * degree.unwrap_or_default(),
+        }
+    }
+}
+
+/// Computes statistics about the constraints of the given PIL file.
+pub fn circuit_stats<T: FieldElement>(pil_file: &Analyzed<T>) -> CircuitStats {
+    let mut stats = CircuitStats {
+        degree: pil_file.degree,
+        ..Default::default()
+    };
+
+    for (name, (symbol, _)) in &pil_file.definitions {
+        let SymbolKind::Poly(ptype) = symbol.kind else {
+            continue;
+        };
+        let counts = stats.columns.entry(namespace(name)).or_default();
+        let count = symbol.length.unwrap_or(1) as usize;
+        match ptype {
+            PolynomialType::Committed => counts.witness += count,
+            PolynomialType::Constant => counts.fixed += count,
+            PolynomialType::Intermediate => unreachable!(),
+        }
+    }
+    for name in pil_file.intermediate_columns.keys() {
+        stats
+            .columns
+            .entry(namespace(name))
+            .or_default()
+            .intermediate += 1;
+    }
+
+    let mut intermediate_degrees = BTreeMap::new();
+    for (symbol, definition) in pil_file.intermediate_polys_in_source_order() {
+        let degree = expression_degree(definition, &intermediate_degrees);
+        intermediate_degrees.insert(PolyID::from(symbol), degree);
+    }
+
+    let mut tables = BTreeSet::new();
+    for identity in &pil_file.identities {
+        match identity.kind {
+            IdentityKind::Polynomial => stats.identities.polynomial += 1,
+            IdentityKind::Plookup => {
+                stats.identities.plookup += 1;
+                stats.lookup_widths.push(identity.left.expressions.len());
+                tables.insert(&identity.right);
+            }
+            IdentityKind::Permutation => {
+                stats.identities.permutation += 1;
+                stats
+                    .permutation_widths
+                    .push(identity.left.expressions.len());
+                tables.insert(&identity.right);
+            }
+            IdentityKind::Connect => stats.identities.connect += 1,
+        }
+        let degree = identity_degree(identity, &intermediate_degrees);
+        *stats.degree_histogram.entry(degree).or_default() += 1;
+        stats.max_constraint_degree = stats.max_constraint_degree.max(degree);
+    }
+    stats.distinct_tables = tables.len();
+
+    let witness_columns = pil_file.commitment_count();
+    let lookups = stats.identities.plookup + stats.identities.permutation;
+
+    // Halo2 commits to the permuted input and table columns and a grand product column
+    // for each lookup. The extended domain has to be large enough for the quotient polynomial.
+    let halo2_degree = stats
+        .max_constraint_degree
+        .max(if lookups > 0 { 3 } else { 1 });
+    stats.halo2_cost = BackendCost::new(
+        witness_columns + 3 * lookups,
+        (halo2_degree.max(2) - 1).next_power_of_two(),
+        stats.degree,
+    );
+
+    // eSTARK commits to two columns and a grand product for each plookup, a grand product
+    // for each permutation and connect identity, and to the pieces of the quotient polynomial.
+    // The blowup factor is fixed to 2, so constraints of a higher degree split the quotient
+    // polynomial into more pieces.
+    stats.estark_cost = BackendCost::new(
+        witness_columns
+            + 3 * stats.identities.plookup
+            + stats.identities.permutation
+            + stats.identities.connect
+            + stats.max_constraint_degree.max(2)
+            - 1,
+        2,
+        stats.degree,
+    );
+
+    stats
+}
+
+/// @returns the degree of an identity. For plookup and permutation identities,
+/// this is the maximum of the degrees of the selector times each expression.
+pub fn identity_degree<T: FieldElement>(
+    identity: &Identity<AlgebraicExpression<T>>,
+    intermediate_degrees: &BTreeMap<PolyID, usize>,
+) -> usize {
+    let degree = |e: &AlgebraicExpression<T>| expression_degree(e, intermediate_degrees);
+    [&identity.left, &identity.right]
+        .into_iter()
+        .map(|side| {
+            side.selector.as_ref().map(degree).unwrap_or_default()
+                + side
+                    .expressions
+                    .iter()
+                    .map(degree)
+                    .max()
+                    .unwrap_or_default()
+        })
+        .max()
+        .unwrap()
+}
+
+/// @returns the namespace of an absolute name, without the trailing dot.
+fn namespace(name: &str) -> String {
+    name.rfind('.')
+        .map(|pos| name[..pos].to_string())
+        .unwrap_or_default()
+}
+
+impl Display for CircuitStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.degree {
+            Some(degree) => writeln!(f, "Degree: {degree}")?,
+            None => writeln!(f, "Degree: unknown")?,
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<40} {:>8} {:>8} {:>12}",
+            "Namespace", "fixed", "witness", "intermediate"
+        )?;
+        let mut total = ColumnCounts::default();
+        for (namespace, counts) in &self.columns {
+            writeln!(
+                f,
+                "{:<40} {:>8} {:>8} {:>12}",
+                namespace, counts.fixed, counts.witness, counts.intermediate
+            )?;
+            total.fixed += counts.fixed;
+            total.witness += counts.witness;
+            total.intermediate += counts.intermediate;
+        }
+        writeln!(
+            f,
+            "{:<40} {:>8} {:>8} {:>12}",
+            "total", total.fixed, total.witness, total.intermediate
+        )?;
+
+        writeln!(f)?;
+        writeln!(f, "Identities:")?;
+        writeln!(f, "  polynomial:  {}", self.identities.polynomial)?;
+        writeln!(f, "  plookup:     {}", self.identities.plookup)?;
+        writeln!(f, "  permutation: {}", self.identities.permutation)?;
+        writeln!(f, "  connect:     {}", self.identities.connect)?;
+
+        writeln!(f)?;
+        writeln!(f, "Max constraint degree: {}", self.max_constraint_degree)?;
+        for (degree, count) in &self.degree_histogram {
+            writeln!(f, "  degree {degree:>3}: {count}")?;
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Distinct lookup and permutation tables: {}",
+            self.distinct_tables
+        )?;
+        for (name, widths) in [
+            ("Lookup", &self.lookup_widths),
+            ("Permutation", &self.permutation_widths),
+        ] {
+            let mut histogram = BTreeMap::<usize, usize>::new();
+            for width in widths {
+                *histogram.entry(*width).or_default() += 1;
+            }
+            writeln!(f, "{name} widths:")?;
+            for (width, count) in histogram {
+                writeln!(f, "  width {width:>3}: {count}")?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<10} {:>18} {:>8} {:>16}",
+            "Backend", "committed columns", "blowup", "committed cells"
+        )?;
+        for (name, cost) in [("halo2", &self.halo2_cost), ("estark", &self.estark_cost)] {
+            writeln!(
+                f,
+                "{:<10} {:>18} {:>8} {:>16}",
+                name, cost.committed_columns, cost.blowup_factor, cost.committed_cells
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use powdr_number::GoldilocksField;
+    use powdr_pil_analyzer::analyze_string;
+
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let input = r#"namespace Main(8);
+    col fixed FIRST = [1] + [0]*;
+    col fixed BYTE(i) { i & 0xff };
+    col witness x;
+    col witness y[2];
+    col sq = x * x;
+    FIRST * (sq * x - 1) = 0;
+    x' = y[0] + y[1];
+    { x } in { BYTE };
+    { y[0] + 1 } in FIRST { BYTE };
+    { y[1] } is { Other.a };
+namespace Other(8);
+    col witness a;
+"#;
+        let stats = circuit_stats(&analyze_string::<GoldilocksField>(input));
+        assert_eq!(
+            stats.columns,
+            [
+                (
+                    "Main".to_string(),
+                    ColumnCounts {
+                        fixed: 2,
+                        witness: 3,
+                        intermediate: 1
+                    }
+                ),
+                (
+                    "Other".to_string(),
+                    ColumnCounts {
+                        fixed: 0,
+                        witness: 1,
+                        intermediate: 0
+                    }
+                )
+            ]
+            .into_iter()
+            .collect()
+        );
+        assert_eq!(
+            stats.identities,
+            IdentityCounts {
+                polynomial: 2,
+                plookup: 2,
+                permutation: 1,
+                connect: 0
+            }
+        );
+        assert_eq!(stats.max_constraint_degree, 4);
+        assert_eq!(
+            stats.degree_histogram,
+            [(1, 3), (2, 1), (4, 1)].into_iter().collect()
+        );
+        assert_eq!(stats.lookup_widths, vec![1, 1]);
+        assert_eq!(stats.permutation_widths, vec![1]);
+        assert_eq!(stats.distinct_tables, 3);
+        assert_eq!(
+            stats.halo2_cost,
+            BackendCost {
+                committed_columns: 13,
+                blowup_factor: 4,
+                committed_cells: 13 * 4 * 8
+            }
+        );
+        assert_eq!(
+            stats.estark_cost,
+            BackendCost {
+                committed_columns: 14,
+                blowup_factor: 2,
+                committed_cells: 14 * 2 * 8
+            }
+        );
+        // Make sure all sections are printed.
+        let table = stats.to_string();
+        assert!(table.contains("Main"));
+        assert!(table.contains("Max constraint degree: 4"));
+        assert!(table.contains("estark"));
+    }
+}
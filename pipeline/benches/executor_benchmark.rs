@@ -18,10 +18,12 @@ fn run_witgen<T: FieldElement>(
     analyzed: &Analyzed<T>,
     constants: &[(String, Vec<T>)],
     external_witness_values: Vec<(String, Vec<T>)>,
+    compiled_identities: bool,
 ) {
     let query_callback = inputs_to_query_callback(vec![]);
     powdr_executor::witgen::WitnessGenerator::new(analyzed, constants, &query_callback)
         .with_external_witness_values(external_witness_values)
+        .with_compiled_identities(compiled_identities)
         .generate();
 }
 
@@ -45,6 +47,19 @@ fn executor_benchmark(c: &mut Criterion) {
                 &pil_with_constants.pil,
                 &pil_with_constants.fixed_cols,
                 vec![],
+                true,
+            )
+        })
+    });
+
+    // The same, but evaluating identities through the `ExpressionEvaluator` on every row.
+    group.bench_function("keccak_uncompiled", |b| {
+        b.iter(|| {
+            run_witgen(
+                &pil_with_constants.pil,
+                &pil_with_constants.fixed_cols,
+                vec![],
+                false,
             )
         })
     });
@@ -67,6 +82,21 @@ fn executor_benchmark(c: &mut Criterion) {
                     "main.bootloader_input_value".to_string(),
                     default_input(&[63, 64, 65]),
                 )],
+                true,
+            )
+        })
+    });
+
+    group.bench_function("many_chunks_chunk_0_uncompiled", |b| {
+        b.iter(|| {
+            run_witgen(
+                &pil_with_constants.pil,
+                &pil_with_constants.fixed_cols,
+                vec![(
+                    "main.bootloader_input_value".to_string(),
+                    default_input(&[63, 64, 65]),
+                )],
+                false,
             )
         })
     });
@@ -12,29 +12,21 @@ pub mod verify;
 pub use pipeline::Pipeline;
 pub use pipeline::Stage;
 
-use itertools::Itertools;
 pub use powdr_backend::{BackendType, Proof};
-use powdr_executor::witgen::QueryCallback;
+pub use powdr_executor::witgen::CheckpointSettings;
+use powdr_executor::witgen::{Query, QueryCallback};
+pub use powdr_pilopt::{OptLevel, OptimizerPass};
 
 use powdr_number::FieldElement;
 
-pub fn parse_query(query: &str) -> Result<Vec<&str>, String> {
-    // We are expecting a tuple
-    let query = query
-        .strip_prefix('(')
-        .and_then(|q| q.strip_suffix(')'))
-        .ok_or_else(|| "Prover query has to be a tuple".to_string())?;
-    Ok(query.split(',').map(|s| s.trim()).collect::<Vec<_>>())
-}
-
 pub fn access_element<T: FieldElement>(
     name: &str,
     elements: &[T],
-    index_str: &str,
+    index: &Query<T>,
 ) -> Result<Option<T>, String> {
-    let index = index_str
-        .parse::<usize>()
-        .map_err(|e| format!("Error parsing index: {e})"))?;
+    let index = index
+        .to_u64()
+        .map_err(|e| format!("Error parsing index: {e})"))? as usize;
     let value = elements.get(index).cloned();
     if let Some(value) = value {
         log::trace!("Query for {name}: Index {index} -> {value}");
@@ -53,20 +45,21 @@ pub fn serde_data_to_query_callback<T: FieldElement, S: serde::Serialize + Send
     data: &S,
 ) -> impl QueryCallback<T> {
     let bytes = serde_cbor::to_vec(&data).unwrap();
-    move |query: &str| -> Result<Option<T>, String> {
-        match &parse_query(query)?[..] {
-            ["\"data_identifier\"", index, cb_channel] => {
+    move |query: &Query<T>| -> Result<Option<T>, String> {
+        match query.as_tuple()? {
+            [Query::String(id), index, cb_channel] if id == "data_identifier" => {
                 let cb_channel = cb_channel
-                    .parse::<u32>()
+                    .to_u64()
                     .map_err(|e| format!("Error parsing callback data channel: {e})"))?;
 
-                if channel != cb_channel {
+                if channel as u64 != cb_channel {
                     return Ok(None);
                 }
 
                 let index = index
-                    .parse::<usize>()
-                    .map_err(|e| format!("Error parsing index: {e})"))?;
+                    .to_u64()
+                    .map_err(|e| format!("Error parsing index: {e})"))?
+                    as usize;
 
                 // query index 0 means the length
                 Ok(Some(match index {
@@ -74,33 +67,34 @@ pub fn serde_data_to_query_callback<T: FieldElement, S: serde::Serialize + Send
                     index => (bytes[index - 1] as u64).into(),
                 }))
             }
-            k => Err(format!("Unsupported query: {}", k.iter().format(", "))),
+            _ => Err(format!("Unsupported query: {query}")),
         }
     }
 }
 
 #[allow(clippy::print_stdout)]
 pub fn inputs_to_query_callback<T: FieldElement>(inputs: Vec<T>) -> impl QueryCallback<T> {
-    move |query: &str| -> Result<Option<T>, String> {
+    move |query: &Query<T>| -> Result<Option<T>, String> {
         // TODO In the future, when match statements need to be exhaustive,
         // This function probably gets an Option as argument and it should
         // answer None by Ok(None).
 
-        match &parse_query(query)?[..] {
-            ["\"input\"", index] => access_element("prover inputs", &inputs, index),
-            ["\"print_char\"", ch] => {
+        match query.as_tuple()? {
+            [Query::String(id), index] if id == "input" => {
+                access_element("prover inputs", &inputs, index)
+            }
+            [Query::String(id), ch] if id == "print_char" => {
                 print!(
                     "{}",
-                    ch.parse::<u8>()
-                        .map_err(|e| format!("Invalid char to print: {e}"))?
+                    u8::try_from(ch.to_u64()?).map_err(|e| format!("Invalid char to print: {e}"))?
                         as char
                 );
                 // We do not answer None because we don't want this function to be
                 // called again.
                 Ok(Some(0.into()))
             }
-            ["\"hint\"", value] => Ok(Some(T::from_str(value).unwrap())),
-            k => Err(format!("Unsupported query: {}", k.iter().format(", "))),
+            [Query::String(id), value] if id == "hint" => Ok(Some(value.to_field_element()?)),
+            _ => Err(format!("Unsupported query: {query}")),
         }
     }
 }
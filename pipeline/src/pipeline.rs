@@ -1,5 +1,6 @@
 use std::{
     borrow::Borrow,
+    collections::HashSet,
     fmt::Display,
     fs,
     io::{BufWriter, Read, Write},
@@ -18,12 +19,13 @@ use powdr_ast::{
     parsed::{asm::ASMProgram, PILFile},
     DiffMonitor,
 };
-use powdr_backend::{BackendType, Proof};
+use powdr_backend::{public_values, BackendType, Proof};
 use powdr_executor::{
     constant_evaluator,
-    witgen::{chain_callbacks, QueryCallback},
+    witgen::{chain_callbacks, Checkpoint, CheckpointSettings, QueryCallback},
 };
 use powdr_number::{write_polys_csv_file, write_polys_file, CsvRenderMode, FieldElement};
+use powdr_pilopt::{BackendParameters, CseCostModel, OptLevel, OptimizerPass};
 
 use crate::{
     inputs_to_query_callback, serde_data_to_query_callback,
@@ -53,6 +55,8 @@ pub struct ProofResult<T: FieldElement> {
     pub proof: Option<Proof>,
     /// Serialized low level constraints, potentially None (if success is false)
     pub constraints_serialization: Option<String>,
+    /// Values of the public declarations in source order, empty if there is no witness
+    pub publics: Vec<(String, T)>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -170,14 +174,29 @@ impl<T: FieldElement> Artifact<T> {
 struct Arguments<T: FieldElement> {
     /// Externally computed witness values for witness generation.
     external_witness_values: Vec<(String, Vec<T>)>,
+    /// Witness values computed from an execution trace. Unlike the external witness
+    /// values, they are ignored for columns that do not exist in the optimized PIL.
+    trace_witness_values: Vec<(String, Vec<T>)>,
     /// Callback for queries for witness generation.
     query_callback: Option<Arc<dyn QueryCallback<T>>>,
     /// Backend to use for proving. If None, proving will fail.
     backend: Option<BackendType>,
+    /// The optimizer passes to run on the analyzed PIL file. If None, the passes
+    /// of the default optimization level are used.
+    optimizer_passes: Option<Vec<OptimizerPass>>,
+    /// The maximum degree of constraints after optimization. If None, the maximum
+    /// degree supported by the backend is used.
+    max_constraint_degree: Option<usize>,
     /// CSV render mode for witness generation.
     csv_render_mode: CsvRenderMode,
     /// Whether to export the witness as a CSV file.
     export_witness_csv: bool,
+    /// The file to write the witness generation profile to, if any.
+    witgen_profile: Option<PathBuf>,
+    /// Where and how often to write witness generation checkpoints, if at all.
+    witgen_checkpoints: Option<CheckpointSettings>,
+    /// The checkpoint file to resume witness generation from, if any.
+    witgen_resume: Option<PathBuf>,
     /// The optional setup file to use for proving.
     setup_file: Option<PathBuf>,
     /// The optional verification key file to use for proving.
@@ -300,6 +319,16 @@ impl<T: FieldElement> Pipeline<T> {
         self
     }
 
+    /// Adds witness values that were computed by executing the program, e.g. the register
+    /// columns of the main machine. Witness generation uses them as known values and only
+    /// computes the remaining columns. Columns that were removed by the optimizer are ignored.
+    pub fn add_trace_witness_values(mut self, trace_witness_values: Vec<(String, Vec<T>)>) -> Self {
+        self.arguments
+            .trace_witness_values
+            .extend(trace_witness_values);
+        self
+    }
+
     pub fn with_witness_csv_settings(
         mut self,
         export_witness_csv: bool,
@@ -336,6 +365,41 @@ impl<T: FieldElement> Pipeline<T> {
         self
     }
 
+    /// Sets the optimizer passes to run on the analyzed PIL file, in order.
+    pub fn with_optimizer_passes(mut self, passes: Vec<OptimizerPass>) -> Self {
+        self.arguments.optimizer_passes = Some(passes);
+        self
+    }
+
+    /// Writes a profile of the time spent in each machine during witness generation to the
+    /// given file, in the Chrome Trace Event format if the file ends in `.json` and as
+    /// folded stacks for flamegraphs otherwise.
+    pub fn with_witgen_profile(mut self, witgen_profile: Option<PathBuf>) -> Self {
+        self.arguments.witgen_profile = witgen_profile;
+        self
+    }
+
+    /// Periodically writes the state of witness generation to a checkpoint file,
+    /// so that an interrupted run can be continued with [Pipeline::with_witgen_resume].
+    pub fn with_witgen_checkpoints(mut self, checkpoints: Option<CheckpointSettings>) -> Self {
+        self.arguments.witgen_checkpoints = checkpoints;
+        self
+    }
+
+    /// Continues witness generation from the given checkpoint file. The checkpoint
+    /// has to be written for the same program and inputs.
+    pub fn with_witgen_resume(mut self, checkpoint_file: Option<PathBuf>) -> Self {
+        self.arguments.witgen_resume = checkpoint_file;
+        self
+    }
+
+    /// Reduces the degree of all constraints to at most `max_degree` in the `reduce-degree`
+    /// optimizer pass. If not set, the maximum degree supported by the backend (if any) is used.
+    pub fn with_max_constraint_degree(mut self, max_degree: Option<usize>) -> Self {
+        self.arguments.max_constraint_degree = max_degree;
+        self
+    }
+
     pub fn with_setup_file(mut self, setup_file: Option<PathBuf>) -> Self {
         self.arguments.setup_file = setup_file;
         self
@@ -534,7 +598,21 @@ impl<T: FieldElement> Pipeline<T> {
             }
             Artifact::AnalyzedPil(analyzed_pil) => {
                 self.log("Optimizing pil...");
-                let optimized = powdr_pilopt::optimize(analyzed_pil);
+                let parameters = BackendParameters {
+                    max_constraint_degree: self.arguments.max_constraint_degree.or_else(|| {
+                        self.arguments
+                            .backend
+                            .and_then(|backend| backend.max_constraint_degree())
+                    }),
+                    cse_cost_model: self.arguments.backend.and_then(cse_cost_model),
+                };
+                let passes = self
+                    .arguments
+                    .optimizer_passes
+                    .clone()
+                    .unwrap_or_else(|| OptLevel::default().passes());
+                let optimized =
+                    powdr_pilopt::optimize_with_passes(analyzed_pil, &passes, &parameters);
                 self.maybe_write_pil(&optimized, "_opt")?;
                 Artifact::OptimzedPil(optimized)
             }
@@ -554,22 +632,64 @@ impl<T: FieldElement> Pipeline<T> {
                 })
             }
             Artifact::PilWithEvaluatedFixedCols(PilWithEvaluatedFixedCols { pil, fixed_cols }) => {
+                let resume_from = self
+                    .arguments
+                    .witgen_resume
+                    .as_ref()
+                    .map(|path| {
+                        Checkpoint::read_from_file(path).map_err(|e| {
+                            vec![format!("Could not read checkpoint {}: {e}", path.display())]
+                        })
+                    })
+                    .transpose()?;
                 let witness = (pil.constant_count() == fixed_cols.len()).then(|| {
                     self.log("Deducing witness columns...");
                     let start = Instant::now();
-                    let external_witness_values =
+                    let witness_columns = pil
+                        .committed_polys_in_source_order()
+                        .iter()
+                        .flat_map(|(p, _)| p.array_elements().map(|(name, _)| name))
+                        .collect::<HashSet<_>>();
+                    let mut external_witness_values =
                         std::mem::take(&mut self.arguments.external_witness_values);
+                    // Explicitly provided external values take precedence over trace values.
+                    let trace_witness_values =
+                        std::mem::take(&mut self.arguments.trace_witness_values)
+                            .into_iter()
+                            .filter(|(name, _)| {
+                                witness_columns.contains(name)
+                                    && !external_witness_values.iter().any(|(n, _)| n == name)
+                            })
+                            .collect::<Vec<_>>();
+                    external_witness_values.extend(trace_witness_values);
                     let query_callback =
                         self.arguments.query_callback.take().unwrap_or_else(|| {
                             Arc::new(powdr_executor::witgen::unused_query_callback())
                         });
-                    let witness = powdr_executor::witgen::WitnessGenerator::new(
+                    let mut generator = powdr_executor::witgen::WitnessGenerator::new(
                         &pil,
                         &fixed_cols,
                         query_callback.borrow(),
                     )
-                    .with_external_witness_values(external_witness_values)
-                    .generate();
+                    .with_external_witness_values(external_witness_values);
+                    if let Some(profile) = self.arguments.witgen_profile.clone() {
+                        generator = generator.with_profile_output(profile);
+                    }
+                    if let Some(checkpoints) = self.arguments.witgen_checkpoints.clone() {
+                        generator = generator.with_checkpoints(checkpoints);
+                    }
+                    if let (Some(output_dir), Some(name)) = (&self.output_dir, &self.name) {
+                        // The indices of fixed lookups only depend on the fixed columns,
+                        // so they can be reused across runs of the same program.
+                        generator = generator.with_fixed_lookup_cache(
+                            output_dir.join(format!("{name}_fixed_lookup_cache.bin")),
+                        );
+                    }
+                    if let Some(checkpoint) = resume_from {
+                        self.log("Resuming from checkpoint...");
+                        generator = generator.with_resume_from(checkpoint);
+                    }
+                    let witness = generator.generate();
 
                     self.log(&format!("Took {}", start.elapsed().as_secs_f32()));
                     witness
@@ -597,7 +717,9 @@ impl<T: FieldElement> Pipeline<T> {
                 let factory = backend.factory::<T>();
                 let mut backend = if let Some(path) = self.arguments.setup_file.as_ref() {
                     let mut file = fs::File::open(path).unwrap();
-                    factory.create_from_setup(&mut file).unwrap()
+                    factory
+                        .create_from_setup(&mut file)
+                        .map_err(|e| vec![e.to_string()])?
                 } else {
                     factory.create(pil.degree())
                 };
@@ -629,11 +751,16 @@ impl<T: FieldElement> Pipeline<T> {
                     _ => panic!(),
                 };
 
+                let publics = witness
+                    .as_ref()
+                    .map(|witness| public_values(&pil, witness))
+                    .unwrap_or_default();
                 let proof_result = ProofResult {
                     fixed_cols,
                     witness,
                     proof: Some(proof),
                     constraints_serialization,
+                    publics,
                 };
 
                 self.maybe_write_proof(&proof_result)?;
@@ -744,6 +871,16 @@ impl<T: FieldElement> Pipeline<T> {
                 proof_file.write_all(proof).unwrap();
             }
         }
+        if !proof_result.publics.is_empty() {
+            if let Some(path) = self.path_if_should_write(|name| format!("{name}_publics.json"))? {
+                let publics = proof_result
+                    .publics
+                    .iter()
+                    .map(|(name, value)| (name, value.to_string()))
+                    .collect::<Vec<_>>();
+                fs::write(path, serde_json::to_string(&publics).unwrap()).unwrap();
+            }
+        }
 
         Ok(())
     }
@@ -849,6 +986,24 @@ impl<T: FieldElement> Pipeline<T> {
         Ok(proof)
     }
 
+    /// Returns the values of the public declarations in source order,
+    /// generating the witness if necessary.
+    pub fn publics(&mut self) -> Result<Vec<(String, T)>, Vec<String>> {
+        if let Some(Artifact::Proof(proof)) = self.artifact.as_ref() {
+            return Ok(proof.publics.clone());
+        }
+        self.advance_to(Stage::GeneratedWitness)?;
+        let Artifact::GeneratedWitness(GeneratedWitness { pil, witness, .. }) =
+            self.artifact.as_ref().unwrap()
+        else {
+            panic!()
+        };
+        let witness = witness
+            .as_ref()
+            .ok_or_else(|| vec!["Witness generation failed.".to_string()])?;
+        Ok(public_values(pil, witness))
+    }
+
     pub fn output_dir(&self) -> Option<&Path> {
         self.output_dir.as_ref().map(|p| p.as_ref())
     }
@@ -876,7 +1031,9 @@ impl<T: FieldElement> Pipeline<T> {
                 let factory = backend.factory::<T>();
                 let backend = if let Some(path) = self.arguments.setup_file.as_ref() {
                     let mut file = fs::File::open(path).unwrap();
-                    factory.create_from_setup(&mut file).unwrap()
+                    factory
+                        .create_from_setup(&mut file)
+                        .map_err(|e| vec![e.to_string()])?
                 } else {
                     factory.create(pil.degree())
                 };
@@ -884,7 +1041,7 @@ impl<T: FieldElement> Pipeline<T> {
                 match backend.verification_key(pil, fixed_cols) {
                     Ok(vkey) => Ok(vkey),
                     Err(powdr_backend::Error::VerificationKeyFailed(e)) => Err(vec![e]),
-                    _ => panic!(),
+                    Err(e) => Err(vec![e.to_string()]),
                 }
             }
             _ => panic!(),
@@ -901,28 +1058,47 @@ impl<T: FieldElement> Pipeline<T> {
                     .expect("backend must be set before generating verification key!");
                 let factory = backend.factory::<T>();
 
+                // Backends without setup, like the mock backend, are created from scratch.
                 let mut backend = if let Some(path) = self.arguments.setup_file.as_ref() {
                     let mut file = fs::File::open(path).unwrap();
-                    factory.create_from_setup(&mut file).unwrap()
+                    factory
+                        .create_from_setup(&mut file)
+                        .map_err(|e| vec![e.to_string()])?
                 } else {
-                    panic!("Setup should have been provided for verification")
+                    factory.create(pil.degree())
                 };
 
+                // Backends that need a verification key fail to verify without one.
                 if let Some(ref path) = self.arguments.vkey_file {
                     let mut buf = Vec::new();
                     fs::File::open(path).unwrap().read_to_end(&mut buf).unwrap();
                     backend.add_verification_key(pil, fixed_cols, buf)
-                } else {
-                    panic!("Verification key should have been provided for verification")
                 }
 
                 match backend.verify(&proof, instances) {
                     Ok(_) => Ok(()),
                     Err(powdr_backend::Error::VerificationFailed(e)) => Err(vec![e]),
-                    _ => panic!(),
+                    Err(e) => Err(vec![e.to_string()]),
                 }
             }
             _ => panic!(),
         }
     }
 }
+
+/// @returns the cost model used to decide which common subexpressions to extract
+/// into intermediate polynomials, or None if the backend does not benefit from it.
+fn cse_cost_model(backend: BackendType) -> Option<CseCostModel> {
+    match backend {
+        // Intermediate polynomials are exported as separate expressions, but the ones
+        // of degree larger than one are committed to.
+        BackendType::EStark | BackendType::PilStarkCli => Some(CseCostModel {
+            intermediate_cost: 1,
+            nonlinear_intermediate_cost: None,
+        }),
+        // Intermediate polynomials are inlined.
+        #[cfg(feature = "halo2")]
+        BackendType::Halo2 | BackendType::Halo2Mock => None,
+        BackendType::Mock => None,
+    }
+}
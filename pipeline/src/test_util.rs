@@ -73,6 +73,16 @@ pub fn gen_estark_proof(file_name: &str, inputs: Vec<GoldilocksField>) {
         .unwrap();
 }
 
+/// Checks all identities of the test file with the mock backend.
+pub fn check_with_mock_backend(file_name: &str, inputs: Vec<GoldilocksField>) {
+    Pipeline::default()
+        .from_file(resolve_test_file(file_name))
+        .with_prover_inputs(inputs)
+        .with_backend(BackendType::Mock)
+        .proof()
+        .unwrap();
+}
+
 #[cfg(feature = "halo2")]
 pub fn gen_halo2_proof(file_name: &str, inputs: Vec<Bn254Field>) {
     let file_name = format!("{}/../test_data/{file_name}", env!("CARGO_MANIFEST_DIR"));
@@ -106,7 +116,13 @@ pub fn gen_halo2_proof(file_name: &str, inputs: Vec<Bn254Field>) {
 
     // Create the proof before adding the setup and vkey to the backend,
     // so that they're generated during the proof
-    let proof = pipeline.clone().proof().unwrap().proof.unwrap();
+    let proof_result = pipeline.clone().proof().unwrap();
+    let proof = proof_result.proof.unwrap();
+    let publics = proof_result
+        .publics
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect::<Vec<_>>();
 
     // Now we add the previously generated setup and verification key
     // and verify the proof.
@@ -114,7 +130,15 @@ pub fn gen_halo2_proof(file_name: &str, inputs: Vec<Bn254Field>) {
         .with_setup_file(Some(setup_file_path))
         .with_vkey_file(Some(vkey_file_path));
 
-    pipeline.verify(proof, &[vec![]]).unwrap();
+    pipeline.verify(proof.clone(), &[publics.clone()]).unwrap();
+
+    // Wrong public values have to be rejected.
+    if let Some((first, rest)) = publics.split_first() {
+        let wrong_publics = std::iter::once(*first + Bn254Field::from(1))
+            .chain(rest.iter().copied())
+            .collect();
+        assert!(pipeline.verify(proof, &[wrong_publics]).is_err());
+    }
 
     // We can also run the same proof path as the first proof generation above,
     // to make sure the proof also works when the setup and vkey are given
@@ -1,6 +1,11 @@
+use powdr_executor::witgen::string_query_callback;
 use powdr_number::GoldilocksField;
-use powdr_pipeline::test_util::{gen_estark_proof, gen_halo2_proof, verify_test_file};
+use powdr_pipeline::test_util::{
+    check_with_mock_backend, gen_estark_proof, gen_halo2_proof, resolve_test_file, verify_test_file,
+};
+use powdr_pipeline::{BackendType, OptLevel, Pipeline};
 use std::path::Path;
+use std::sync::Arc;
 use test_log::test;
 
 pub fn verify_pil(file_name: &str, inputs: Vec<GoldilocksField>) {
@@ -11,14 +16,36 @@ pub fn verify_pil(file_name: &str, inputs: Vec<GoldilocksField>) {
 fn test_fibonacci() {
     let f = "pil/fibonacci.pil";
     verify_pil(f, Default::default());
+    check_with_mock_backend(f, Default::default());
     gen_halo2_proof(f, Default::default());
     gen_estark_proof(f, Default::default());
 }
 
+#[test]
+fn test_fibonacci_mock_verify_publics() {
+    let mut pipeline = Pipeline::<GoldilocksField>::default()
+        .from_file(resolve_test_file("pil/fibonacci.pil"))
+        .with_prover_inputs(Default::default())
+        .with_backend(BackendType::Mock);
+    let proof_result = pipeline.clone().proof().unwrap();
+    let publics = proof_result
+        .publics
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect::<Vec<_>>();
+    assert_eq!(publics, vec![1597.into()]);
+    let proof = proof_result.proof.unwrap();
+
+    pipeline.verify(proof.clone(), &[publics]).unwrap();
+    let errors = pipeline.verify(proof, &[vec![1598.into()]]).unwrap_err();
+    assert!(errors[0].contains("do not match the proven public values"));
+}
+
 #[test]
 fn test_constant_in_identity() {
     let f = "pil/constant_in_identity.pil";
     verify_pil(f, Default::default());
+    check_with_mock_backend(f, Default::default());
     gen_halo2_proof(f, Default::default());
     gen_estark_proof(f, Default::default());
 }
@@ -27,6 +54,16 @@ fn test_constant_in_identity() {
 fn fib_arrays() {
     let f = "pil/fib_arrays.pil";
     verify_pil(f, Default::default());
+    check_with_mock_backend(f, Default::default());
+    gen_halo2_proof(f, Default::default());
+    gen_estark_proof(f, Default::default());
+}
+
+#[test]
+fn batched_lookup() {
+    let f = "pil/batched_lookup.pil";
+    verify_pil(f, Default::default());
+    check_with_mock_backend(f, Default::default());
     gen_halo2_proof(f, Default::default());
     gen_estark_proof(f, Default::default());
 }
@@ -92,6 +129,25 @@ fn test_sum_via_witness_query() {
     // so we cannot directly use the halo2_proof and estark functions here.
 }
 
+#[test]
+fn test_sum_via_string_query_callback() {
+    let f = "pil/sum_via_witness_query.pil";
+    let callback = |query: &str| -> Result<Option<GoldilocksField>, String> {
+        match query {
+            "(\"input\", 0)" => Ok(Some(7.into())),
+            "(\"input\", 1)" => Ok(Some(8.into())),
+            "(\"input\", 2)" => Ok(Some(2.into())),
+            _ => Ok(None),
+        }
+    };
+    Pipeline::<GoldilocksField>::default()
+        .from_file(resolve_test_file(f))
+        .add_query_callback(Arc::new(string_query_callback(callback)))
+        .with_backend(BackendType::Mock)
+        .proof()
+        .unwrap();
+}
+
 #[test]
 fn test_witness_lookup() {
     let f = "pil/witness_lookup.pil";
@@ -193,6 +249,19 @@ fn referencing_arrays() {
     gen_estark_proof(f, Default::default());
 }
 
+#[test]
+fn optimizer_levels() {
+    let f = "pil/batched_lookup.pil";
+    for level in [OptLevel::None, OptLevel::Basic, OptLevel::Full] {
+        Pipeline::<GoldilocksField>::default()
+            .from_file(resolve_test_file(f))
+            .with_optimizer_passes(level.passes())
+            .with_backend(BackendType::Mock)
+            .proof()
+            .unwrap();
+    }
+}
+
 mod book {
     use super::*;
     use test_log::test;
@@ -0,0 +1,77 @@
+/// Computes `a * b + c` for 256-bit numbers given as 8 32-bit limbs in little-endian order.
+/// Returns the high and the low 256 bits of the result.
+/// It's equivalent to the `affine_256` operation of std::arith::Arith from the Powdr standard library.
+pub fn affine_256(a: &[u32; 8], b: &[u32; 8], c: &[u32; 8]) -> ([u32; 8], [u32; 8]) {
+    let mut result = [0u32; 16];
+    result[..8].copy_from_slice(c);
+
+    for (i, &a) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &b) in b.iter().enumerate() {
+            let v = a as u64 * b as u64 + result[i + j] as u64 + carry;
+            result[i + j] = v as u32;
+            carry = v >> 32;
+        }
+        // Propagate the carry, which is needed as `c` was already added.
+        for limb in &mut result[i + 8..] {
+            let v = *limb as u64 + carry;
+            *limb = v as u32;
+            carry = v >> 32;
+        }
+    }
+
+    let (lo, hi) = result.split_at(8);
+    (hi.try_into().unwrap(), lo.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_affine_256() {
+        // Same as the test vectors in test_data/std/arith_test.asm.
+        let (hi, lo) = affine_256(
+            &[
+                0x77777777, 0x66666666, 0x55555555, 0x44444444, 0x33333333, 0x22222222, 0x11111111,
+                0x00000000,
+            ],
+            &[
+                0xffffffff, 0xeeeeeeee, 0xdddddddd, 0xcccccccc, 0xbbbbbbbb, 0xaaaaaaaa, 0x99999999,
+                0x88888888,
+            ],
+            &[
+                0xaaaaaaaa, 0xbbbbbbbb, 0xbbbbbbbb, 0xaaaaaaaa, 0xaaaaaaaa, 0xbbbbbbbb, 0xbbbbbbbb,
+                0xaaaaaaaa,
+            ],
+        );
+        assert_eq!(
+            hi,
+            [
+                0x9be02469, 0xf258bf25, 0x38e38e38, 0xe6f8091a, 0x740da740, 0x579be024, 0x091a2b3c,
+                0x00000000
+            ]
+        );
+        assert_eq!(
+            lo,
+            [
+                0x33333333, 0xa1907f6e, 0xca8641fd, 0x369d0369, 0x907f6e5d, 0x60b60b60, 0x0da740da,
+                0x1fdb9753
+            ]
+        );
+
+        // (2**256 - 1) * (2**256 - 1) + (2**256 - 1) = 2**256 * (2**256 - 1)
+        let max = [u32::MAX; 8];
+        assert_eq!(affine_256(&max, &max, &max), (max, [0; 8]));
+
+        // (2**256 - 1) * 1 + (2**256 - 1) = 2**256 + (2**256 - 2)
+        let one = [1, 0, 0, 0, 0, 0, 0, 0];
+        let mut max_minus_one = max;
+        max_minus_one[0] -= 1;
+        assert_eq!(affine_256(&max, &one, &max), (one, max_minus_one));
+
+        // 2 * 3 + 5 = 11
+        let n = |v| [v, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(affine_256(&n(2), &n(3), &n(5)), ([0; 8], n(11)));
+    }
+}
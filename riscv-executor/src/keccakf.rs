@@ -0,0 +1,105 @@
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001,
+    0x0000000000008082,
+    0x800000000000808a,
+    0x8000000080008000,
+    0x000000000000808b,
+    0x0000000080000001,
+    0x8000000080008081,
+    0x8000000000008009,
+    0x000000000000008a,
+    0x0000000000000088,
+    0x0000000080008009,
+    0x000000008000000a,
+    0x000000008000808b,
+    0x800000000000008b,
+    0x8000000000008089,
+    0x8000000000008003,
+    0x8000000000008002,
+    0x8000000000000080,
+    0x000000000000800a,
+    0x800000008000000a,
+    0x8000000080008081,
+    0x8000000000008080,
+    0x0000000080000001,
+    0x8000000080008008,
+];
+
+/// Rotation offsets of the rho step, indexed by `x + 5 * y`.
+const RHO_OFFSETS: [u32; 25] = [
+    0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56, 14,
+];
+
+/// Naive implementation of the Keccak-f[1600] permutation.
+/// The lane at position (x, y) of the state is stored at index `x + 5 * y`.
+/// It's equivalent to std::hash::keccakf::KeccakF from the Powdr standard library.
+pub fn keccakf(state: &mut [u64; 25]) {
+    for rc in ROUND_CONSTANTS {
+        // Theta
+        let c: [u64; 5] = std::array::from_fn(|x| (0..5).fold(0, |acc, y| acc ^ state[x + 5 * y]));
+        for x in 0..5 {
+            let d = c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                state[x + 5 * y] ^= d;
+            }
+        }
+
+        // Rho and pi: the lane at (x, y) is rotated and moved to (y, 2x + 3y).
+        let b: [u64; 25] = std::array::from_fn(|i| {
+            let (x, y) = (i % 5, i / 5);
+            let source = (x + 3 * y) % 5 + 5 * x;
+            state[source].rotate_left(RHO_OFFSETS[source])
+        });
+
+        // Chi
+        for (i, lane) in state.iter_mut().enumerate() {
+            let (x, y) = (i % 5, i / 5);
+            *lane = b[i] ^ (!b[(x + 1) % 5 + 5 * y] & b[(x + 2) % 5 + 5 * y]);
+        }
+
+        // Iota
+        state[0] ^= rc;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_test(state: [u64; 25]) {
+        let mut expected = state;
+        tiny_keccak::keccakf(&mut expected);
+        let mut output = state;
+        keccakf(&mut output);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_keccakf() {
+        run_test([0; 25]);
+        run_test([u64::MAX; 25]);
+        run_test(std::array::from_fn(|i| i as u64));
+
+        // Some pseudo-random states.
+        let mut x: u64 = 0x2545f4914f6cdd1d;
+        for _ in 0..10 {
+            run_test(std::array::from_fn(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x
+            }));
+        }
+    }
+
+    #[test]
+    fn test_keccakf_zero_state() {
+        // Known answer for the all-zero state, from the Keccak team's
+        // KeccakF-1600-IntermediateValues.txt.
+        let mut state = [0; 25];
+        keccakf(&mut state);
+        assert_eq!(state[0], 0xf1258f7940e1dde7);
+        assert_eq!(state[1], 0x84d5ccf933c0478a);
+        assert_eq!(state[24], 0xeaf1ff7b5ceca249);
+    }
+}
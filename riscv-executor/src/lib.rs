@@ -9,7 +9,11 @@
 //! TODO: perform determinism verification for each instruction independently
 //! from execution.
 
-use std::{collections::HashMap, io};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, io,
+    sync::Arc,
+};
 
 use builder::TraceBuilder;
 use powdr_ast::{
@@ -18,9 +22,13 @@ use powdr_ast::{
     },
     parsed::{asm::DebugDirective, Expression, FunctionCall},
 };
+use powdr_executor::witgen::Query;
 use powdr_number::{BigInt, FieldElement, GoldilocksField};
 
+pub mod arith;
+pub mod keccakf;
 pub mod poseidon_gl;
+pub mod sha256;
 
 /// Initial value of the PC.
 ///
@@ -91,6 +99,18 @@ impl From<usize> for Elem {
 
 pub type MemoryState = HashMap<u32, u32>;
 
+/// Native implementation of an instruction the executor does not know about,
+/// typically one provided by a user-defined coprocessor.
+pub trait InstructionHook: Send + Sync {
+    /// Returns the values assigned to the outputs of the instruction, given
+    /// the values of its inputs.
+    fn execute(&self, args: &[Elem]) -> Vec<Elem>;
+}
+
+/// The native implementations of instructions unknown to the executor, by
+/// instruction name.
+pub type InstructionHooks = BTreeMap<String, Arc<dyn InstructionHook>>;
+
 #[derive(Debug)]
 pub enum MemOperationKind {
     Read,
@@ -115,6 +135,31 @@ pub struct RegWrite {
     val: Elem,
 }
 
+/// How the guest program finished its execution.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuestExit {
+    /// The exit code, 0 if the program returned from its entry point.
+    pub code: u32,
+    /// The message the guest panicked with, if it did.
+    pub panic_message: Option<String>,
+}
+
+impl GuestExit {
+    /// Only programs that exit successfully can be proven.
+    pub fn is_success(&self) -> bool {
+        self.code == 0
+    }
+}
+
+impl fmt::Display for GuestExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.panic_message {
+            Some(message) => write!(f, "Guest panicked with exit code {}: {message}", self.code),
+            None => write!(f, "Guest exited with code {}", self.code),
+        }
+    }
+}
+
 pub struct ExecutionTrace {
     pub reg_map: HashMap<String, u16>,
 
@@ -129,6 +174,9 @@ pub struct ExecutionTrace {
 
     /// The length of the trace, after applying the reg_writes.
     pub len: usize,
+
+    /// How the guest finished, None if the execution was stopped before.
+    pub exit: Option<GuestExit>,
 }
 
 impl ExecutionTrace {
@@ -186,8 +234,8 @@ mod builder {
     use powdr_number::FieldElement;
 
     use crate::{
-        Elem, ExecMode, ExecutionTrace, MemOperation, MemOperationKind, MemoryState, RegWrite,
-        PC_INITIAL_VAL,
+        Elem, ExecMode, ExecutionTrace, GuestExit, MemOperation, MemOperationKind, MemoryState,
+        RegWrite, PC_INITIAL_VAL,
     };
 
     fn register_names<T: FieldElement>(main: &Machine<T>) -> Vec<&str> {
@@ -281,6 +329,7 @@ mod builder {
                     reg_writes,
                     mem_ops: Vec::new(),
                     len: PC_INITIAL_VAL + 1,
+                    exit: None,
                 },
                 next_statement_line: 1,
                 batch_to_line_map,
@@ -395,6 +444,20 @@ mod builder {
             *self.mem.get(&addr).unwrap_or(&0)
         }
 
+        /// Reads memory without recording the access in the trace.
+        pub(crate) fn peek_mem(&self, addr: u32) -> u32 {
+            *self.mem.get(&addr).unwrap_or(&0)
+        }
+
+        pub(crate) fn has_exited(&self) -> bool {
+            self.trace.exit.is_some()
+        }
+
+        /// Records how the guest finished.
+        pub(crate) fn set_exit(&mut self, exit: GuestExit) {
+            self.trace.exit = Some(exit);
+        }
+
         pub fn finish(self) -> (ExecutionTrace, MemoryState) {
             (self.trace, self.mem)
         }
@@ -518,6 +581,7 @@ struct Executor<'a, 'b, F: FieldElement> {
     label_map: HashMap<&'a str, Elem>,
     inputs: &'b Callback<'b, F>,
     bootloader_inputs: &'b [F],
+    hooks: &'b InstructionHooks,
     _stdout: io::Stdout,
 }
 
@@ -645,6 +709,24 @@ impl<'a, 'b, F: FieldElement> Executor<'a, 'b, F> {
                 // TODO: handle it better
                 panic!("reached a fail instruction")
             }
+            "exit" => {
+                // The code is followed by the address and length of the panic
+                // message, which is empty if the guest did not panic. The message
+                // cannot extend beyond the end of the address space.
+                let (addr, len) = (args[1].u(), args[2].u());
+                let panic_message = (len > 0).then(|| {
+                    let bytes = (addr..addr.saturating_add(len))
+                        .map(|a| (self.proc.peek_mem(a & !3) >> (8 * (a % 4))) as u8)
+                        .collect::<Vec<_>>();
+                    String::from_utf8_lossy(&bytes).into_owned()
+                });
+                self.proc.set_exit(GuestExit {
+                    code: args[0].u(),
+                    panic_message,
+                });
+
+                Vec::new()
+            }
             "divremu" => {
                 let y = args[0].u();
                 let x = args[1].u();
@@ -688,9 +770,33 @@ impl<'a, 'b, F: FieldElement> Executor<'a, 'b, F> {
                 let result = poseidon_gl::poseidon_gl(&inputs);
                 result.into_iter().map(Elem::from_fe).collect()
             }
-            instr => {
-                panic!("unknown instruction: {instr}");
+            "keccakf" => {
+                let mut state: [u64; 25] = std::array::from_fn(|i| {
+                    args[2 * i].u() as u64 | (args[2 * i + 1].u() as u64) << 32
+                });
+                keccakf::keccakf(&mut state);
+                state
+                    .into_iter()
+                    .flat_map(|lane| [(lane as u32).into(), ((lane >> 32) as u32).into()])
+                    .collect()
+            }
+            "affine_256" => {
+                let a = std::array::from_fn(|i| args[i].u());
+                let b = std::array::from_fn(|i| args[8 + i].u());
+                let c = std::array::from_fn(|i| args[16 + i].u());
+                let (hi, lo) = arith::affine_256(&a, &b, &c);
+                hi.into_iter().chain(lo).map(Elem::from).collect()
+            }
+            "sha256_compress" => {
+                let mut state: [u32; 8] = std::array::from_fn(|i| args[i].u());
+                let block: [u32; 16] = std::array::from_fn(|i| args[8 + i].u());
+                sha256::sha256_compress(&mut state, &block);
+                state.into_iter().map(Elem::from).collect()
             }
+            instr => match self.hooks.get(instr) {
+                Some(hook) => hook.execute(&args),
+                None => panic!("unknown instruction: {instr}"),
+            },
         }
     }
 
@@ -777,16 +883,14 @@ impl<'a, 'b, F: FieldElement> Executor<'a, 'b, F> {
             },
             Expression::FreeInput(expr) => {
                 if let Expression::Tuple(t) = &**expr {
-                    let mut all_strings: Vec<String> = Vec::new();
-                    for expr in t {
-                        if let Expression::String(_) = expr {
-                            all_strings.push(expr.to_string());
-                        } else {
-                            let val = self.eval_expression(expr)[0];
-                            all_strings.push(val.0.to_string());
-                        }
-                    }
-                    let query = format!("({})", all_strings.join(","));
+                    let query = Query::Tuple(
+                        t.iter()
+                            .map(|expr| match expr {
+                                Expression::String(s) => Query::String(s.clone()),
+                                _ => Query::Integer(self.eval_expression(expr)[0].0.into()),
+                            })
+                            .collect(),
+                    );
                     match (self.inputs)(&query).unwrap() {
                         Some(val) => vec![Elem::from_fe(val)],
                         None => {
@@ -808,6 +912,7 @@ pub fn execute_ast<T: FieldElement>(
     program: &AnalysisASMFile<T>,
     inputs: &Callback<T>,
     bootloader_inputs: &[T],
+    hooks: &InstructionHooks,
     max_steps_to_execute: usize,
     mode: ExecMode,
 ) -> (ExecutionTrace, MemoryState) {
@@ -830,6 +935,7 @@ pub fn execute_ast<T: FieldElement>(
         label_map,
         inputs,
         bootloader_inputs,
+        hooks,
         _stdout: io::stdout(),
     };
 
@@ -850,7 +956,15 @@ pub fn execute_ast<T: FieldElement>(
             FunctionStatement::Instruction(i) => {
                 e.exec_instruction(&i.instruction, &i.inputs);
             }
-            FunctionStatement::Return(_) => break,
+            FunctionStatement::Return(_) => {
+                if !e.proc.has_exited() {
+                    e.proc.set_exit(GuestExit {
+                        code: 0,
+                        panic_message: None,
+                    });
+                }
+                break;
+            }
             FunctionStatement::DebugDirective(dd) => {
                 match &dd.directive {
                     DebugDirective::Loc(file, line, column) => {
@@ -882,7 +996,8 @@ pub enum ExecMode {
     Trace,
 }
 
-/// Execute a Powdr/RISCV assembly source.
+/// Execute a Powdr/RISCV assembly source, using `hooks` for the instructions
+/// of custom coprocessors.
 ///
 /// Generic argument F is just used by the powdr_parser, before everything is
 /// converted to i64, so it is important to the execution itself.
@@ -890,6 +1005,7 @@ pub fn execute<F: FieldElement>(
     asm_source: &str,
     inputs: &Callback<F>,
     bootloader_inputs: &[F],
+    hooks: &InstructionHooks,
     mode: ExecMode,
 ) -> (ExecutionTrace, MemoryState) {
     log::info!("Parsing...");
@@ -901,7 +1017,14 @@ pub fn execute<F: FieldElement>(
         powdr_analysis::analyze(resolved, &mut powdr_ast::DiffMonitor::default()).unwrap();
 
     log::info!("Executing...");
-    execute_ast(&analyzed, inputs, bootloader_inputs, usize::MAX, mode)
+    execute_ast(
+        &analyzed,
+        inputs,
+        bootloader_inputs,
+        hooks,
+        usize::MAX,
+        mode,
+    )
 }
 
 fn to_u32<F: FieldElement>(val: &F) -> Option<u32> {
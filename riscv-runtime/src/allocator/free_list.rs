@@ -0,0 +1,132 @@
+//! An allocator that reuses freed memory.
+//!
+//! Every allocation is rounded up to a power of two, its size class, and
+//! freed blocks are kept in a linked list per size class, so that the next
+//! allocation of the same class reuses the most recently freed block. Only if
+//! the list is empty, a new block is taken from a global array, like in the
+//! bump allocator. Blocks are never split or merged, so programs that keep
+//! allocating and freeing memory of similar sizes touch a bounded number of
+//! pages.
+//!
+//! Because blocks are aligned to their size, large size classes waste a lot
+//! of the heap on padding: in the 1 GB heap, a block of more than 256 MB has to
+//! start at a 512 MB-aligned address, so it only fits while the heap is
+//! still unused up to that address. Programs with allocations of that size
+//! should use the bump allocator.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::Cell,
+    ptr::{self, addr_of},
+};
+
+/// The smallest block has 8 bytes, which holds the pointer to the next free
+/// block and covers the most common alignments.
+const MIN_BLOCK_SIZE_LOG: usize = 3;
+
+/// Blocks of up to half the address space.
+const NUM_SIZE_CLASSES: usize = usize::BITS as usize - MIN_BLOCK_SIZE_LOG;
+
+// Only used to initialize the array of free lists.
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_LIST: Cell<usize> = Cell::new(0);
+
+// Force C representation so that the large buffer is at the end.
+// This might avoid access to memory with large gaps.
+#[repr(C)]
+pub(super) struct FreeListAllocator<const SIZE: usize> {
+    /// Address of the first free block of each size class, 0 if there is none.
+    /// The first word of a free block holds the address of the next one.
+    free_lists: [Cell<usize>; NUM_SIZE_CLASSES],
+    next_available: Cell<usize>,
+    mem_buffer: [u8; SIZE],
+}
+
+impl<const SIZE: usize> FreeListAllocator<SIZE> {
+    pub(super) const fn new() -> Self {
+        Self {
+            free_lists: [EMPTY_LIST; NUM_SIZE_CLASSES],
+            next_available: Cell::new(0),
+            mem_buffer: [0; SIZE],
+        }
+    }
+
+    /// Returns a block of the size class and whether it was never used before,
+    /// in which case it is still zeroed.
+    unsafe fn take_block(&self, size_class: usize) -> Option<(*mut u8, bool)> {
+        let free_list = &self.free_lists[size_class];
+        let head = free_list.get();
+        if head != 0 {
+            free_list.set(*(head as *const usize));
+            return Some((head as *mut u8, false));
+        }
+
+        // Blocks are aligned to their size, so that they satisfy the alignment
+        // of every layout of their size class. The padding is never touched.
+        let block_size = 1 << (size_class + MIN_BLOCK_SIZE_LOG);
+        let array_start = addr_of!(self.mem_buffer) as usize;
+        let next_ptr = array_start + self.next_available.get();
+        let aligned_ptr = next_ptr.checked_add(block_size - 1)? & !(block_size - 1);
+        let new_next_available = aligned_ptr.checked_add(block_size)? - array_start;
+
+        if new_next_available <= SIZE {
+            self.next_available.set(new_next_available);
+            Some((aligned_ptr as *mut u8, true))
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the index of the smallest size class that fits the layout.
+fn size_class(layout: &Layout) -> Option<usize> {
+    let block_size = layout
+        .size()
+        .max(layout.align())
+        .checked_next_power_of_two()?;
+    let size_class = (block_size.trailing_zeros() as usize).saturating_sub(MIN_BLOCK_SIZE_LOG);
+    (size_class < NUM_SIZE_CLASSES).then_some(size_class)
+}
+
+unsafe impl<const SIZE: usize> GlobalAlloc for FreeListAllocator<SIZE> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        size_class(&layout)
+            .and_then(|size_class| self.take_block(size_class))
+            .map_or(ptr::null_mut(), |(block, _)| block)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        match size_class(&layout).and_then(|size_class| self.take_block(size_class)) {
+            Some((block, is_new)) => {
+                // New blocks are zeroed already, writing to them would only
+                // touch more pages.
+                if !is_new {
+                    ptr::write_bytes(block, 0, layout.size());
+                }
+                block
+            }
+            None => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let free_list = &self.free_lists[size_class(&layout).unwrap()];
+        *(ptr as *mut usize) = free_list.get();
+        free_list.set(ptr as usize);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        if size_class(&layout) == size_class(&new_layout) {
+            // The block is large enough already.
+            return ptr;
+        }
+
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
@@ -0,0 +1,138 @@
+//! 256-bit modular arithmetic and secp256k1 point operations, built on the
+//! `affine_256` operation of the Arith coprocessor.
+//! All numbers are given as 8 32-bit limbs in little-endian order.
+
+use crate::coprocessors::affine_256;
+
+/// The modulus of the secp256k1 base field, 2**256 - 2**32 - 977.
+pub const SECP256K1_P: [u32; 8] = [
+    0xfffffc2f, 0xfffffffe, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff,
+];
+
+/// 2**256 - SECP256K1_P, i.e. 2**256 mod SECP256K1_P.
+const SECP256K1_C: [u32; 8] = [0x3d1, 1, 0, 0, 0, 0, 0, 0];
+
+const ZERO: [u32; 8] = [0; 8];
+
+/// Computes `a * b mod m`. The modulus must not be zero.
+pub fn mulmod(a: &[u32; 8], b: &[u32; 8], m: &[u32; 8]) -> [u32; 8] {
+    assert!(*m != ZERO, "Modulus must not be zero");
+    let (hi, lo) = affine_256(a, b, &ZERO);
+
+    // Binary long division of the 512-bit product by m, only keeping the remainder.
+    let mut r = ZERO;
+    for limb in hi.iter().rev().chain(lo.iter().rev()) {
+        for bit in (0..32).rev() {
+            // r = 2 * r + bit, which can exceed 256 bits, but not 2 * m.
+            let overflow = r[7] >> 31 == 1;
+            for i in (1..8).rev() {
+                r[i] = r[i] << 1 | r[i - 1] >> 31;
+            }
+            r[0] = r[0] << 1 | (limb >> bit) & 1;
+            if overflow || !lt(&r, m) {
+                r = sub(&r, m).0;
+            }
+        }
+    }
+    r
+}
+
+/// Adds the points (x1, y1) and (x2, y2) on the secp256k1 curve.
+/// The coordinates have to be reduced and the points must have different x coordinates.
+pub fn ec_add(x1: &[u32; 8], y1: &[u32; 8], x2: &[u32; 8], y2: &[u32; 8]) -> ([u32; 8], [u32; 8]) {
+    assert!(x1 != x2, "Points must have different x coordinates");
+    let s = mul_p(&sub_p(y2, y1), &inv_p(&sub_p(x2, x1)));
+    let x3 = sub_p(&sub_p(&mul_p(&s, &s), x1), x2);
+    let y3 = sub_p(&mul_p(&s, &sub_p(x1, &x3)), y1);
+    (x3, y3)
+}
+
+/// Doubles the point (x1, y1) on the secp256k1 curve.
+/// The coordinates have to be reduced and y1 must not be zero.
+pub fn ec_double(x1: &[u32; 8], y1: &[u32; 8]) -> ([u32; 8], [u32; 8]) {
+    assert!(*y1 != ZERO, "Cannot double a point with y = 0");
+    let x1_squared = mul_p(x1, x1);
+    let numerator = add_p(&add_p(&x1_squared, &x1_squared), &x1_squared);
+    let s = mul_p(&numerator, &inv_p(&add_p(y1, y1)));
+    let x3 = sub_p(&sub_p(&mul_p(&s, &s), x1), x1);
+    let y3 = sub_p(&mul_p(&s, &sub_p(x1, &x3)), y1);
+    (x3, y3)
+}
+
+/// Multiplication modulo SECP256K1_P, which uses that 2**256 = SECP256K1_C (mod SECP256K1_P)
+/// to fold the high part of the product into the low part.
+fn mul_p(a: &[u32; 8], b: &[u32; 8]) -> [u32; 8] {
+    let (mut hi, mut lo) = affine_256(a, b, &ZERO);
+    // Terminates after at most three iterations.
+    while hi != ZERO {
+        (hi, lo) = affine_256(&hi, &SECP256K1_C, &lo);
+    }
+    if lt(&lo, &SECP256K1_P) {
+        lo
+    } else {
+        sub(&lo, &SECP256K1_P).0
+    }
+}
+
+/// Inversion modulo SECP256K1_P, using Fermat's little theorem.
+fn inv_p(a: &[u32; 8]) -> [u32; 8] {
+    let exponent = sub(&SECP256K1_P, &[2, 0, 0, 0, 0, 0, 0, 0]).0;
+    let mut result = [1, 0, 0, 0, 0, 0, 0, 0];
+    for limb in exponent.iter().rev() {
+        for bit in (0..32).rev() {
+            result = mul_p(&result, &result);
+            if (limb >> bit) & 1 == 1 {
+                result = mul_p(&result, a);
+            }
+        }
+    }
+    result
+}
+
+fn add_p(a: &[u32; 8], b: &[u32; 8]) -> [u32; 8] {
+    let (sum, carry) = add(a, b);
+    if carry || !lt(&sum, &SECP256K1_P) {
+        sub(&sum, &SECP256K1_P).0
+    } else {
+        sum
+    }
+}
+
+fn sub_p(a: &[u32; 8], b: &[u32; 8]) -> [u32; 8] {
+    let (difference, borrow) = sub(a, b);
+    if borrow {
+        add(&difference, &SECP256K1_P).0
+    } else {
+        difference
+    }
+}
+
+/// Returns a + b mod 2**256 and whether the addition overflowed.
+fn add(a: &[u32; 8], b: &[u32; 8]) -> ([u32; 8], bool) {
+    let mut result = ZERO;
+    let mut carry = false;
+    for i in 0..8 {
+        let (sum, c1) = a[i].overflowing_add(b[i]);
+        let (sum, c2) = sum.overflowing_add(carry as u32);
+        result[i] = sum;
+        carry = c1 || c2;
+    }
+    (result, carry)
+}
+
+/// Returns a - b mod 2**256 and whether the subtraction underflowed.
+fn sub(a: &[u32; 8], b: &[u32; 8]) -> ([u32; 8], bool) {
+    let mut result = ZERO;
+    let mut borrow = false;
+    for i in 0..8 {
+        let (difference, b1) = a[i].overflowing_sub(b[i]);
+        let (difference, b2) = difference.overflowing_sub(borrow as u32);
+        result[i] = difference;
+        borrow = b1 || b2;
+    }
+    (result, borrow)
+}
+
+fn lt(a: &[u32; 8], b: &[u32; 8]) -> bool {
+    a.iter().rev().lt(b.iter().rev())
+}
@@ -6,8 +6,23 @@ extern "C" {
     // during the reachability analysis.
     fn poseidon_gl_coprocessor(data: *mut [u64; 12]);
 
+    // Dummy implementation of the Keccak-f[1600] permutation,
+    // replaced with a call to the Keccak-f coprocessor.
+    fn keccakf_coprocessor(data: *mut [u64; 25]);
+
+    // Dummy implementation of the SHA-256 compression function,
+    // replaced with a call to the SHA-256 coprocessor.
+    fn sha256_compress_coprocessor(data: *mut [u32; 24]);
+
+    // Dummy implementation of the affine_256 operation,
+    // replaced with a call to the Arith coprocessor.
+    fn affine_256_coprocessor(data: *mut [u32; 24]);
+
     // This will be replaced by a call to prover input.
     fn input_coprocessor(index: u32, channel: u32) -> u32;
+
+    // Replaced by an assignment to the public output register with the given index.
+    fn commit_coprocessor(index: u32, value: u32);
 }
 
 extern crate alloc;
@@ -38,6 +53,40 @@ pub fn get_data_serde<T: DeserializeOwned>(channel: u32) -> T {
     serde_cbor::from_slice(&data.as_slice()).unwrap()
 }
 
+use serde::Serialize;
+
+/// The number of words that can be committed, `powdr_riscv::PUBLIC_OUTPUT_WORDS`.
+const PUBLIC_OUTPUT_WORDS: u32 = 32;
+
+static mut NEXT_PUBLIC_OUTPUT: u32 = 0;
+
+/// Appends the words to the public outputs of the program, which are revealed
+/// to the verifier. Panics if more than 32 words are committed in total.
+pub fn commit(data: &[u32]) {
+    for &word in data {
+        unsafe {
+            assert!(
+                NEXT_PUBLIC_OUTPUT < PUBLIC_OUTPUT_WORDS,
+                "Too many public outputs committed."
+            );
+            commit_coprocessor(NEXT_PUBLIC_OUTPUT, word);
+            NEXT_PUBLIC_OUTPUT += 1;
+        }
+    }
+}
+
+/// Commits the CBOR serialization of `value`: its length in bytes, followed
+/// by the bytes packed into little-endian words.
+pub fn commit_serde<T: Serialize>(value: &T) {
+    let bytes = serde_cbor::to_vec(value).unwrap();
+    commit(&[bytes.len() as u32]);
+    for chunk in bytes.chunks(4) {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        commit(&[u32::from_le_bytes(word)]);
+    }
+}
+
 const GOLDILOCKS: u64 = 0xffffffff00000001;
 
 /// Calls the low level Poseidon coprocessor in PIL, where
@@ -65,3 +114,47 @@ pub fn poseidon_gl_unsafe(mut data: [u64; 12]) -> [u64; 4] {
 
     [data[0], data[1], data[2], data[3]]
 }
+
+/// Calls the Keccak-f[1600] coprocessor in PIL, which applies the
+/// permutation to the state in place. The lane at position (x, y)
+/// is stored at index x + 5 * y.
+pub fn keccakf(state: &mut [u64; 25]) {
+    unsafe {
+        keccakf_coprocessor(state as *mut [u64; 25]);
+    }
+}
+
+/// Calls the SHA-256 coprocessor in PIL, which applies the compression
+/// function to the state and a block of 16 message words. The message
+/// words are big-endian decoded, as in the specification.
+pub fn sha256_compress(state: &mut [u32; 8], block: &[u32; 16]) {
+    let mut data = [0u32; 24];
+    data[..8].copy_from_slice(state);
+    data[8..].copy_from_slice(block);
+
+    unsafe {
+        sha256_compress_coprocessor(&mut data as *mut [u32; 24]);
+    }
+
+    state.copy_from_slice(&data[..8]);
+}
+
+/// Calls the Arith coprocessor in PIL, which computes `a * b + c` for 256-bit
+/// numbers given as 8 32-bit limbs in little-endian order.
+/// Returns the high and the low 256 bits of the result.
+pub fn affine_256(a: &[u32; 8], b: &[u32; 8], c: &[u32; 8]) -> ([u32; 8], [u32; 8]) {
+    let mut data = [0u32; 24];
+    data[..8].copy_from_slice(a);
+    data[8..16].copy_from_slice(b);
+    data[16..].copy_from_slice(c);
+
+    unsafe {
+        affine_256_coprocessor(&mut data as *mut [u32; 24]);
+    }
+
+    let mut hi = [0u32; 8];
+    let mut lo = [0u32; 8];
+    hi.copy_from_slice(&data[..8]);
+    lo.copy_from_slice(&data[8..16]);
+    (hi, lo)
+}
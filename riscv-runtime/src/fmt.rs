@@ -21,6 +21,29 @@ impl fmt::Write for ProverWriter {
     }
 }
 
+/// Formats `args` into `buf`, truncating the output if it does not fit, and
+/// returns the number of bytes written.
+pub(crate) fn format_into(buf: &mut [u8], args: fmt::Arguments) -> usize {
+    let mut writer = BufferWriter { buf, len: 0 };
+    // Truncation is not an error, so this cannot fail.
+    let _ = fmt::write(&mut writer, args);
+    writer.len
+}
+
+struct BufferWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl fmt::Write for BufferWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
 pub fn print_str(s: &str) {
     // DEAR DEV, please don't allow this function to panic.
     //
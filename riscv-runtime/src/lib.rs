@@ -12,23 +12,44 @@ use core::panic::PanicInfo;
 use crate::fmt::print_str;
 
 mod allocator;
+pub mod arith;
 pub mod coprocessors;
 pub mod fmt;
+pub mod sha256;
+
+pub use coprocessors::{commit, commit_serde};
+
+extern "C" {
+    // Replaced by the exit syscall, which ends the execution with the given
+    // code. `message` and `len` describe the panic message, if any.
+    fn exit_coprocessor(code: u32, message: *const u8, len: usize) -> !;
+}
+
+/// The exit code of a panicking guest, as for Rust programs on other targets.
+const PANIC_EXIT_CODE: u32 = 101;
+
+/// Ends the execution of the guest with the given exit code. Only an exit code
+/// of 0 can be proven.
+pub fn exit(code: u32) -> ! {
+    unsafe { exit_coprocessor(code, core::ptr::null(), 0) }
+}
 
 #[panic_handler]
 unsafe fn panic(panic: &PanicInfo<'_>) -> ! {
     static mut IS_PANICKING: bool = false;
+    static mut MESSAGE: [u8; 1024] = [0; 1024];
 
     if !IS_PANICKING {
         IS_PANICKING = true;
 
         print!("{panic}\n");
+        let message = &mut *core::ptr::addr_of_mut!(MESSAGE);
+        let len = fmt::format_into(message, format_args!("{panic}"));
+        exit_coprocessor(PANIC_EXIT_CODE, message.as_ptr(), len);
     } else {
         print_str("Panic handler has panicked! Things are very dire indeed...\n");
+        exit(PANIC_EXIT_CODE);
     }
-
-    asm!("unimp");
-    loop {}
 }
 
 #[inline]
@@ -0,0 +1,92 @@
+//! A SHA-256 hasher backed by the SHA-256 coprocessor.
+//!
+//! It offers the `new`, `update`, `finalize` and `digest` methods of
+//! `sha2::Sha256` as inherent methods, but does not implement the `Digest`
+//! trait, and `finalize` returns a plain `[u8; 32]` instead of a
+//! `GenericArray`. Guests using the `sha2` crate have to drop the `Digest`
+//! import and may need to adapt code that relies on `GenericArray`.
+
+use crate::coprocessors::sha256_compress;
+
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: [u8; 64],
+    buffer_len: usize,
+    /// The number of bytes hashed so far.
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self {
+            state: INITIAL_STATE,
+            buffer: [0; 64],
+            buffer_len: 0,
+            len: 0,
+        }
+    }
+
+    /// Computes the hash of `data` in one go.
+    pub fn digest(data: impl AsRef<[u8]>) -> [u8; 32] {
+        let mut hasher = Self::new();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    pub fn update(&mut self, data: impl AsRef<[u8]>) {
+        let mut data = data.as_ref();
+        self.len += data.len() as u64;
+
+        while !data.is_empty() {
+            let n = data.len().min(64 - self.buffer_len);
+            self.buffer[self.buffer_len..self.buffer_len + n].copy_from_slice(&data[..n]);
+            self.buffer_len += n;
+            data = &data[n..];
+
+            if self.buffer_len == 64 {
+                self.compress_buffer();
+            }
+        }
+    }
+
+    pub fn finalize(mut self) -> [u8; 32] {
+        let bit_len = self.len * 8;
+
+        // Padding: a single one bit, zeros and the message length in bits,
+        // such that the total length is a multiple of the block size.
+        self.buffer[self.buffer_len] = 0x80;
+        self.buffer[self.buffer_len + 1..].fill(0);
+        if self.buffer_len >= 56 {
+            self.compress_buffer();
+            self.buffer.fill(0);
+        }
+        self.buffer[56..].copy_from_slice(&bit_len.to_be_bytes());
+        self.compress_buffer();
+
+        let mut output = [0u8; 32];
+        for (chunk, word) in output.chunks_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        output
+    }
+
+    fn compress_buffer(&mut self) {
+        let mut block = [0u32; 16];
+        for (word, chunk) in block.iter_mut().zip(self.buffer.chunks(4)) {
+            *word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        sha256_compress(&mut self.state, &block);
+        self.buffer_len = 0;
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::{BTreeMap, BTreeSet, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt,
 };
 
@@ -20,7 +20,7 @@ use crate::continuations::bootloader::{bootloader_and_shutdown_routine, bootload
 use crate::coprocessors::*;
 use crate::disambiguator;
 use crate::parser::RiscParser;
-use crate::{Argument, Expression, Statement};
+use crate::{Argument, DataWord, Expression, Program, Statement};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Register {
@@ -35,6 +35,10 @@ impl Register {
     pub fn is_zero(&self) -> bool {
         self.value == 0
     }
+
+    pub fn value(&self) -> u8 {
+        self.value
+    }
 }
 
 impl powdr_asm_utils::ast::Register for Register {}
@@ -49,6 +53,8 @@ impl fmt::Display for Register {
 pub enum FunctionKind {
     HiDataRef,
     LoDataRef,
+    PcRelHi,
+    PcRelLo,
 }
 
 impl powdr_asm_utils::ast::FunctionOpKind for FunctionKind {}
@@ -58,6 +64,8 @@ impl fmt::Display for FunctionKind {
         match self {
             FunctionKind::HiDataRef => write!(f, "%hi"),
             FunctionKind::LoDataRef => write!(f, "%lo"),
+            FunctionKind::PcRelHi => write!(f, "%pcrel_hi"),
+            FunctionKind::PcRelLo => write!(f, "%pcrel_lo"),
         }
     }
 }
@@ -67,13 +75,14 @@ struct RiscvArchitecture {}
 impl Architecture for RiscvArchitecture {
     fn instruction_ends_control_flow(instr: &str) -> bool {
         match instr {
-            "li" | "lui" | "la" | "mv" | "add" | "addi" | "sub" | "neg" | "mul" | "mulh"
-            | "mulhu" | "mulhsu" | "divu" | "remu" | "xor" | "xori" | "and" | "andi" | "or"
-            | "ori" | "not" | "slli" | "sll" | "srli" | "srl" | "srai" | "seqz" | "snez"
-            | "slt" | "slti" | "sltu" | "sltiu" | "sgtz" | "beq" | "beqz" | "bgeu" | "bltu"
-            | "blt" | "bge" | "bltz" | "blez" | "bgtz" | "bgez" | "bne" | "bnez" | "jal"
-            | "jalr" | "call" | "ecall" | "ebreak" | "lw" | "lb" | "lbu" | "lh" | "lhu" | "sw"
-            | "sh" | "sb" | "nop" | "fence" | "fence.i" | "amoadd.w" | "amoadd.w.aq"
+            "li" | "lui" | "la" | "lla" | "mv" | "add" | "addi" | "sub" | "neg" | "mul"
+            | "mulh" | "mulhu" | "mulhsu" | "divu" | "remu" | "div" | "rem" | "xor" | "xori"
+            | "and" | "andi" | "or" | "ori" | "zext.b" | "not" | "slli" | "sll" | "srli"
+            | "srl" | "srai" | "sra" | "seqz" | "snez" | "sltz" | "slt" | "slti" | "sltu"
+            | "sltiu" | "sgtz" | "beq" | "beqz" | "bgeu" | "bltu" | "blt" | "bge" | "bgt"
+            | "ble" | "bgtu" | "bleu" | "bltz" | "blez" | "bgtz" | "bgez" | "bne" | "bnez"
+            | "jal" | "jalr" | "call" | "ecall" | "ebreak" | "lw" | "lb" | "lbu" | "lh" | "lhu"
+            | "sw" | "sh" | "sb" | "nop" | "fence" | "fence.i" | "amoadd.w" | "amoadd.w.aq"
             | "amoadd.w.rl" | "amoadd.w.aqrl" | "lr.w" | "lr.w.aq" | "lr.w.rl" | "lr.w.aqrl"
             | "sc.w" | "sc.w.aq" | "sc.w.rl" | "sc.w.aqrl" => false,
             "j" | "jr" | "tail" | "ret" | "unimp" => true,
@@ -101,14 +110,59 @@ impl Architecture for RiscvArchitecture {
     }
 }
 
+/// The initial value of the stack pointer. The stack grows towards zero.
+pub(crate) const STACK_START: u32 = 0x10000;
+
 /// Compiles riscv assembly to a powdr assembly file. Adds required library routines.
 pub fn compile(
-    mut assemblies: BTreeMap<String, String>,
+    assemblies: BTreeMap<String, String>,
     coprocessors: &CoProcessors,
     with_bootloader: bool,
 ) -> String {
-    // stack grows towards zero
-    let stack_start = 0x10000;
+    let (statements, data_code, debug_files) =
+        process_assemblies(assemblies, coprocessors, &mut store_data_value);
+
+    translate_program(
+        debug_files,
+        statements,
+        "__runtime_start",
+        data_code,
+        coprocessors,
+        with_bootloader,
+    )
+}
+
+/// Parses riscv assembly into a program with the same code and memory layout
+/// as [compile] produces, without translating it to powdr assembly.
+pub fn load_assembly(assemblies: BTreeMap<String, String>, coprocessors: &CoProcessors) -> Program {
+    let mut data = BTreeMap::new();
+    let (statements, _, _) = process_assemblies(assemblies, coprocessors, &mut |addr, value| {
+        let word = match value {
+            SingleDataValue::Value(v) => DataWord::Value(v),
+            SingleDataValue::LabelReference(label) => DataWord::Label(label.to_string()),
+            SingleDataValue::Offset(_, _) => unimplemented!(),
+        };
+        data.insert(addr, word);
+        vec![]
+    });
+
+    Program {
+        statements,
+        data,
+        entry_point: "__runtime_start".to_string(),
+    }
+}
+
+/// Parses the assembly files together with the runtime, reduces them to the
+/// code reachable from `__runtime_start` and places the data objects in
+/// memory, using `store_data` to generate the data initialization code.
+/// Returns the statements with data references resolved, the data
+/// initialization code and the debug file declarations.
+fn process_assemblies(
+    mut assemblies: BTreeMap<String, String>,
+    coprocessors: &CoProcessors,
+    store_data: &mut dyn FnMut(u32, SingleDataValue) -> Vec<String>,
+) -> (Vec<Statement>, Vec<String>, Vec<String>) {
     // data grows away from zero
     let data_start = 0x10100;
 
@@ -123,6 +177,7 @@ pub fn compile(
             .map(|(name, contents)| (name, parse_asm(RiscParser::default(), &contents)))
             .collect(),
     );
+    replace_pc_relative_references(&mut statements);
     let mut data_sections = data_parser::extract_data_objects(&statements);
 
     // Reduce to the code that is actually reachable from main
@@ -140,37 +195,91 @@ pub fn compile(
     // for compilation, and will not be called.
     statements = replace_coprocessor_stubs(statements, coprocessors).collect::<Vec<_>>();
 
-    let (data_code, data_positions) =
-        store_data_objects(data_sections, data_start, &mut |addr, value| match value {
-            SingleDataValue::Value(v) => {
-                vec![format!("mstore 0x{addr:x}, 0x{v:x};")]
-            }
-            SingleDataValue::LabelReference(sym) => {
-                // TODO should be possible without temporary
-                vec![
-                    format!("tmp1 <== load_label({});", escape_label(sym)),
-                    format!("mstore 0x{addr:x}, tmp1;"),
-                ]
-            }
-            SingleDataValue::Offset(_, _) => {
-                unimplemented!();
-                /*
-                object_code.push(format!("addr <=X= 0x{pos:x};"));
-
-                I think this solution should be fine but hard to say without
-                an actual code snippet that uses it.
-
-                // TODO should be possible without temporary
-                object_code.extend([
-                    format!("tmp1 <== load_label({});", escape_label(a)),
-                    format!("tmp2 <== load_label({});", escape_label(b)),
-                    // TODO check if registers match
-                    "mstore wrap(tmp1 - tmp2);".to_string(),
-                ]);
-                */
-            }
-        });
+    let (data_code, data_positions) = store_data_objects(data_sections, data_start, store_data);
+
+    let debug_files = file_ids
+        .into_iter()
+        .map(|(id, dir, file)| format!(".debug file {id} {} {};", quote(&dir), quote(&file)))
+        .collect();
+
+    (
+        substitute_symbols_with_values(statements, &data_positions),
+        data_code,
+        debug_files,
+    )
+}
+
+/// Compiles a RISC-V program loaded from an ELF file to a powdr assembly file.
+pub fn compile_elf(program: Program, coprocessors: &CoProcessors, with_bootloader: bool) -> String {
+    let data_code = program
+        .data
+        .iter()
+        .flat_map(|(addr, word)| {
+            assert!(
+                *addr >= STACK_START,
+                "Data at 0x{addr:08x} overlaps with the stack."
+            );
+            let value = match word {
+                DataWord::Value(v) => SingleDataValue::Value(*v),
+                DataWord::Label(label) => SingleDataValue::LabelReference(label),
+            };
+            store_data_value(*addr, value)
+        })
+        .collect();
+
+    translate_program(
+        vec![],
+        program.statements,
+        &program.entry_point,
+        data_code,
+        coprocessors,
+        with_bootloader,
+    )
+}
+
+/// Returns the code that stores the value in memory at the data initialization.
+fn store_data_value(addr: u32, value: SingleDataValue) -> Vec<String> {
+    match value {
+        SingleDataValue::Value(v) => {
+            vec![format!("mstore 0x{addr:x}, 0x{v:x};")]
+        }
+        SingleDataValue::LabelReference(sym) => {
+            // TODO should be possible without temporary
+            vec![
+                format!("tmp1 <== load_label({});", escape_label(sym)),
+                format!("mstore 0x{addr:x}, tmp1;"),
+            ]
+        }
+        SingleDataValue::Offset(_, _) => {
+            unimplemented!();
+            /*
+            object_code.push(format!("addr <=X= 0x{pos:x};"));
+
+            I think this solution should be fine but hard to say without
+            an actual code snippet that uses it.
+
+            // TODO should be possible without temporary
+            object_code.extend([
+                format!("tmp1 <== load_label({});", escape_label(a)),
+                format!("tmp2 <== load_label({});", escape_label(b)),
+                // TODO check if registers match
+                "mstore wrap(tmp1 - tmp2);".to_string(),
+            ]);
+            */
+        }
+    }
+}
 
+/// Translates the statements of the program and wraps them into the RISC-V machine,
+/// together with the code that initializes the memory and calls `entry_point`.
+fn translate_program(
+    debug_files: Vec<String>,
+    statements: Vec<Statement>,
+    entry_point: &str,
+    data_code: Vec<String>,
+    coprocessors: &CoProcessors,
+    with_bootloader: bool,
+) -> String {
     let submachine_init = call_every_submachine(coprocessors);
     let bootloader_and_shutdown_routine_lines = if with_bootloader {
         let bootloader_and_shutdown_routine = bootloader_and_shutdown_routine(&submachine_init);
@@ -183,18 +292,19 @@ pub fn compile(
         submachine_init
     };
 
-    let program: Vec<String> = file_ids
+    let program: Vec<String> = debug_files
         .into_iter()
-        .map(|(id, dir, file)| format!(".debug file {id} {} {};", quote(&dir), quote(&file)))
         .chain(bootloader_and_shutdown_routine_lines)
         .chain(["x1 <== jump(__data_init);".to_string()])
         .chain([
-            format!("// Set stack pointer\nx2 <=X= {stack_start};"),
-            "x1 <== jump(__runtime_start);".to_string(),
+            format!("// Set stack pointer\nx2 <=X= {STACK_START};"),
+            format!("x1 <== jump({});", escape_label(entry_point)),
+            // The exit syscall jumps here.
+            "__runtime_exit:".to_string(),
             "return;".to_string(), // This is not "riscv ret", but "return from powdr asm function".
         ])
         .chain(
-            substitute_symbols_with_values(statements, &data_positions)
+            statements
                 .into_iter()
                 .flat_map(|v| process_statement(v, coprocessors)),
         )
@@ -230,6 +340,72 @@ pub fn compile(
     )
 }
 
+/// Replaces `auipc` with `%pcrel_hi(SYMBOL)` by `lui` with `%hi(SYMBOL)` and the
+/// corresponding `%pcrel_lo(LABEL)` by `%lo(SYMBOL)`, where `LABEL` is the label
+/// of the `auipc` instruction. This is possible because we use absolute addresses
+/// for everything. Other uses of `auipc` are not supported, since code addresses
+/// are labels and not byte addresses.
+fn replace_pc_relative_references(statements: &mut [Statement]) {
+    /*
+    Find patterns of the form
+    .Lpcrel_hi0:
+    auipc	a0, %pcrel_hi(SYMBOL)
+    addi	a0, a0, %pcrel_lo(.Lpcrel_hi0)
+    -
+    turn this into
+    lui	a0, %hi(SYMBOL)
+    addi	a0, a0, %lo(SYMBOL)
+    which is then handled like any other absolute reference.
+    */
+    let mut symbols = HashMap::new();
+    let mut labels = vec![];
+    for s in statements.iter_mut() {
+        match s {
+            Statement::Label(label) => labels.push(label.clone()),
+            Statement::Instruction(instr, args) if instr == "auipc" => {
+                let [Argument::Register(_), Argument::Expression(Expression::FunctionOp(FunctionKind::PcRelHi, symbol))] =
+                    &args[..]
+                else {
+                    panic!(
+                        "auipc is only supported with %pcrel_hi(<symbol>), but got: {}",
+                        args.iter().format(", ")
+                    );
+                };
+                let symbol = symbol.as_ref().clone();
+                for label in labels.drain(..) {
+                    symbols.insert(label, symbol.clone());
+                }
+                *instr = "lui".to_string();
+                args[1] = Argument::Expression(Expression::FunctionOp(
+                    FunctionKind::HiDataRef,
+                    Box::new(symbol),
+                ));
+            }
+            Statement::Instruction(..) => labels.clear(),
+            _ => {}
+        }
+    }
+    for s in statements.iter_mut() {
+        let Statement::Instruction(_, args) = s else {
+            continue;
+        };
+        for arg in args {
+            arg.post_visit_expressions_mut(&mut |expression| {
+                if let Expression::FunctionOp(FunctionKind::PcRelLo, label) = expression {
+                    let Expression::Symbol(label) = label.as_ref() else {
+                        panic!("Expected a label in %pcrel_lo, but got: {label}");
+                    };
+                    let symbol = symbols.get(label).unwrap_or_else(|| {
+                        panic!("%pcrel_lo({label}) does not refer to an auipc instruction")
+                    });
+                    *expression =
+                        Expression::FunctionOp(FunctionKind::LoDataRef, Box::new(symbol.clone()));
+                }
+            });
+        }
+    }
+}
+
 /// Replace certain patterns of references to code labels by
 /// special instructions. We ignore any references to data objects
 /// because they will be handled differently.
@@ -390,6 +566,9 @@ fn substitute_symbols_with_values(
                         let result = match op {
                             FunctionKind::HiDataRef => num >> 12,
                             FunctionKind::LoDataRef => num & 0xfff,
+                            FunctionKind::PcRelHi | FunctionKind::PcRelLo => {
+                                unreachable!("Replaced by %hi and %lo.")
+                            }
                         };
                         *expression = Expression::Number(result);
                     };
@@ -449,6 +628,8 @@ fn preamble(degree: u64, coprocessors: &CoProcessors, with_bootloader: bool) ->
     reg Z[<=];
     reg W[<=];
 "# + &coprocessors.registers()
+        + "\n"
+        + &coprocessors.public_declarations(degree)
         + &r#"
     reg tmp1;
     reg tmp2;
@@ -923,6 +1104,24 @@ fn only_if_no_write_to_zero_vec(statements: Vec<String>, reg: Register) -> Vec<S
     }
 }
 
+/// Computes the unsigned quotient (in tmp1) and remainder (in tmp2) of the absolute values
+/// of the signed operands. Sets tmp3 to 1 if the dividend is negative and tmp4 to 1 if the
+/// divisor is negative.
+fn divremu_of_absolute_values(r1: Register, r2: Register) -> Vec<String> {
+    vec![
+        format!("tmp1 <== to_signed({r1});"),
+        format!("tmp2 <== to_signed({r2});"),
+        "tmp3 <== is_positive(0 - tmp1);".into(),
+        "tmp4 <== is_positive(0 - tmp2);".into(),
+        "skip_if_zero tmp3, 1;".into(),
+        "tmp1 <=X= 0 - tmp1;".into(),
+        "skip_if_zero tmp4, 1;".into(),
+        "tmp2 <=X= 0 - tmp2;".into(),
+        // The absolute value of -2**31 still fits into 32 bits.
+        "tmp1, tmp2 <== divremu(tmp1, tmp2);".into(),
+    ]
+}
+
 fn try_coprocessor_substitution(label: &str, coprocessors: &CoProcessors) -> Option<String> {
     coprocessors
         .substitutions()
@@ -934,7 +1133,7 @@ fn try_coprocessor_substitution(label: &str, coprocessors: &CoProcessors) -> Opt
 fn process_instruction(instr: &str, args: &[Argument], coprocessors: &CoProcessors) -> Vec<String> {
     match instr {
         // load/store registers
-        "li" | "la" => {
+        "li" | "la" | "lla" => {
             // The difference between "li" and "la" in RISC-V is that the former
             // is for loading values as is, and the later is for loading PC
             // relative values. But since we work on a higher abstraction level,
@@ -1038,6 +1237,46 @@ fn process_instruction(instr: &str, args: &[Argument], coprocessors: &CoProcesso
             let (rd, r1, r2) = rrr(args);
             only_if_no_write_to_zero(format!("tmp1, {rd} <== divremu({r1}, {r2});"), rd)
         }
+        "div" => {
+            let (rd, r1, r2) = rrr(args);
+            only_if_no_write_to_zero_vec(
+                [
+                    divremu_of_absolute_values(r1, r2),
+                    vec![
+                        // The quotient of a division by zero is -1 independent of the sign
+                        // of the dividend, so we pretend the divisor has the same sign.
+                        format!("skip_if_zero {r2}, 1;"),
+                        "skip_if_zero 0, 1;".into(),
+                        "tmp4 <=X= tmp3;".into(),
+                        // Negate the quotient if exactly one of the operands is negative.
+                        // Note that this also handles the overflow of -2**31 / -1 correctly.
+                        "tmp3 <== is_not_equal_zero(tmp3 - tmp4);".into(),
+                        "skip_if_zero tmp3, 1;".into(),
+                        "tmp1 <=X= 0 - tmp1;".into(),
+                        format!("{rd} <== wrap_signed(tmp1);"),
+                    ],
+                ]
+                .concat(),
+                rd,
+            )
+        }
+        "rem" => {
+            let (rd, r1, r2) = rrr(args);
+            only_if_no_write_to_zero_vec(
+                [
+                    divremu_of_absolute_values(r1, r2),
+                    vec![
+                        // The remainder has the sign of the dividend. For a division by zero,
+                        // this results in the dividend, as required.
+                        "skip_if_zero tmp3, 1;".into(),
+                        "tmp2 <=X= 0 - tmp2;".into(),
+                        format!("{rd} <== wrap_signed(tmp2);"),
+                    ],
+                ]
+                .concat(),
+                rd,
+            )
+        }
 
         // bitwise
         "xor" => {
@@ -1064,6 +1303,10 @@ fn process_instruction(instr: &str, args: &[Argument], coprocessors: &CoProcesso
             let (rd, r1, imm) = rri(args);
             only_if_no_write_to_zero(format!("{rd} <== or({r1}, {imm});"), rd)
         }
+        "zext.b" => {
+            let (rd, rs) = rr(args);
+            only_if_no_write_to_zero(format!("{rd} <== and({rs}, 0xff);"), rd)
+        }
         "not" => {
             let (rd, rs) = rr(args);
             only_if_no_write_to_zero(format!("{rd} <== wrap_signed(-{rs} - 1);"), rd)
@@ -1134,6 +1377,29 @@ fn process_instruction(instr: &str, args: &[Argument], coprocessors: &CoProcesso
             )
         }
 
+        "sra" => {
+            // arithmetic shift right by a register, like "srai"
+            if let [_, _, Argument::Expression(_)] = args {
+                // The assembler also accepts an immediate shift amount.
+                return process_instruction("srai", args, coprocessors);
+            }
+            let (rd, r1, r2) = rrr(args);
+            only_if_no_write_to_zero_vec(
+                vec![
+                    format!("tmp1 <== to_signed({r1});"),
+                    "tmp1 <== is_positive(0 - tmp1);".into(),
+                    "tmp1 <=X= tmp1 * 0xffffffff;".into(),
+                    // Here, tmp1 is the full bit mask if r1 is negative
+                    // and zero otherwise.
+                    format!("tmp2 <== and({r2}, 0x1f);"),
+                    format!("{rd} <== xor(tmp1, {r1});"),
+                    format!("{rd} <== shr({rd}, tmp2);"),
+                    format!("{rd} <== xor(tmp1, {rd});"),
+                ],
+                rd,
+            )
+        }
+
         // comparison
         "seqz" => {
             let (rd, rs) = rr(args);
@@ -1143,6 +1409,16 @@ fn process_instruction(instr: &str, args: &[Argument], coprocessors: &CoProcesso
             let (rd, rs) = rr(args);
             only_if_no_write_to_zero(format!("{rd} <=Y= is_not_equal_zero({rs});"), rd)
         }
+        "sltz" => {
+            let (rd, rs) = rr(args);
+            only_if_no_write_to_zero_vec(
+                vec![
+                    format!("tmp1 <== to_signed({rs});"),
+                    format!("{rd} <=Y= is_positive(0 - tmp1);"),
+                ],
+                rd,
+            )
+        }
         "slti" => {
             let (rd, rs, imm) = rri(args);
             only_if_no_write_to_zero_vec(
@@ -1228,6 +1504,32 @@ fn process_instruction(instr: &str, args: &[Argument], coprocessors: &CoProcesso
                 format!("branch_if_positive tmp1 - tmp2 + 1, {label};"),
             ]
         }
+        "bgt" => {
+            let (r1, r2, label) = rrl(args);
+            // Branch if r1 > r2 (signed).
+            vec![
+                format!("tmp1 <== to_signed({r1});"),
+                format!("tmp2 <== to_signed({r2});"),
+                format!("branch_if_positive tmp1 - tmp2, {label};"),
+            ]
+        }
+        "ble" => {
+            let (r1, r2, label) = rrl(args);
+            // Branch if r1 <= r2 (signed).
+            vec![
+                format!("tmp1 <== to_signed({r1});"),
+                format!("tmp2 <== to_signed({r2});"),
+                format!("branch_if_positive tmp2 - tmp1 + 1, {label};"),
+            ]
+        }
+        "bgtu" => {
+            let (r1, r2, label) = rrl(args);
+            vec![format!("branch_if_positive {r1} - {r2}, {label};")]
+        }
+        "bleu" => {
+            let (r1, r2, label) = rrl(args);
+            vec![format!("branch_if_positive {r2} - {r1} + 1, {label};")]
+        }
         "bltz" => {
             // branch if 2**31 <= r1 < 2**32
             let (r1, label) = rl(args);
@@ -1291,9 +1593,20 @@ fn process_instruction(instr: &str, args: &[Argument], coprocessors: &CoProcesso
             }
         }
         "jalr" => {
-            // TODO there is also a form that takes more arguments
-            let rs = r(args);
-            vec![format!("x1 <== jump_dyn({rs});")]
+            let (rd, rs, off) = match args {
+                [Argument::Register(rs)] => (Register::new(1), *rs, 0),
+                [Argument::Register(rd), Argument::Register(rs)] => (*rd, *rs, 0),
+                [_, Argument::RegOffset(..)] => rro(args),
+                _ => rri(args),
+            };
+            // Code addresses are labels and not byte addresses, so offsets are meaningless.
+            assert_eq!(off, 0, "jalr with a non-zero offset is not supported");
+            let statement = if rd.is_zero() {
+                format!("tmp1 <== jump_dyn({rs});")
+            } else {
+                format!("{rd} <== jump_dyn({rs});")
+            };
+            vec![statement]
         }
         "call" | "tail" => {
             // Depending on what symbol is called, the call is replaced by a
@@ -1487,6 +1800,39 @@ fn process_instruction(instr: &str, args: &[Argument], coprocessors: &CoProcesso
 mod test {
     use super::*;
 
+    #[test]
+    fn test_replace_pc_relative_references() {
+        let mut statements = parse_asm(
+            RiscParser::default(),
+            r#"
+.Lpcrel_hi0:
+    auipc a0, %pcrel_hi(data)
+    addi a1, a0, %pcrel_lo(.Lpcrel_hi0)
+.Lpcrel_hi1:
+    auipc a2, %pcrel_hi(code)
+    lw a3, %pcrel_lo(.Lpcrel_hi1)(a2)
+"#,
+        );
+        replace_pc_relative_references(&mut statements);
+        assert_eq!(
+            statements.iter().join(""),
+            r#".Lpcrel_hi0:
+  lui x10, %hi(data)
+  addi x11, x10, %lo(data)
+.Lpcrel_hi1:
+  lui x12, %hi(code)
+  lw x13, %lo(code)(x12)
+"#
+        );
+    }
+
+    #[test]
+    #[should_panic = "auipc is only supported with %pcrel_hi(<symbol>), but got: x10, 1"]
+    fn test_auipc_with_immediate() {
+        let mut statements = parse_asm(RiscParser::default(), "auipc a0, 1");
+        replace_pc_relative_references(&mut statements);
+    }
+
     #[test]
     fn test_remove_matching_and_next_integers() {
         assert_eq!(
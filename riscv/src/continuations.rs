@@ -1,4 +1,4 @@
-use std::collections::{BTreeSet, HashMap};
+use std::collections::BTreeSet;
 
 use powdr_ast::{
     asm_analysis::{AnalysisASMFile, RegisterTy},
@@ -6,7 +6,7 @@ use powdr_ast::{
 };
 use powdr_number::FieldElement;
 use powdr_pipeline::{Pipeline, Stage};
-use powdr_riscv_executor::ExecutionTrace;
+use powdr_riscv_executor::{GuestExit, InstructionHooks};
 
 pub mod bootloader;
 mod memory_merkle_tree;
@@ -19,25 +19,7 @@ use crate::continuations::bootloader::{
     BOOTLOADER_SPECIFIC_INSTRUCTION_NAMES, DEFAULT_PC, MEMORY_HASH_START_INDEX, PAGE_INPUTS_OFFSET,
     WORDS_PER_PAGE,
 };
-
-fn transposed_trace<F: FieldElement>(trace: &ExecutionTrace) -> HashMap<String, Vec<F>> {
-    let mut reg_values: HashMap<&str, Vec<F>> = HashMap::with_capacity(trace.reg_map.len());
-
-    let mut rows = trace.replay();
-    while let Some(row) = rows.next_row() {
-        for (reg_name, &index) in trace.reg_map.iter() {
-            reg_values
-                .entry(reg_name)
-                .or_default()
-                .push(row[index as usize].0.into());
-        }
-    }
-
-    reg_values
-        .into_iter()
-        .map(|(n, c)| (format!("main.{}", n), c))
-        .collect()
-}
+use crate::trace_witgen::transposed_trace;
 
 fn render_hash<F: FieldElement>(hash: &[F]) -> String {
     hash.iter()
@@ -141,9 +123,15 @@ fn sanity_check<T>(program: &AnalysisASMFile<T>) {
 /// Runs the entire execution using the RISC-V executor. For each chunk, it collects:
 /// - The inputs to the bootloader, needed to restore the correct state.
 /// - The number of rows after which the prover should jump to the shutdown routine.
+///
+/// `hooks` implement the instructions of custom coprocessors.
+///
+/// Returns how the guest exited if it panicked or exited with a non-zero code,
+/// because such an execution cannot be proven.
 pub fn rust_continuations_dry_run<F: FieldElement>(
     pipeline: &mut Pipeline<F>,
-) -> Vec<(Vec<F>, u64)> {
+    hooks: &InstructionHooks,
+) -> Result<Vec<(Vec<F>, u64)>, GuestExit> {
     log::info!("Initializing memory merkle tree...");
     let mut merkle_tree = MerkleTree::<F>::new();
 
@@ -167,10 +155,14 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
             // cell has never been accessed). We can't pass the accessed pages here, because
             // we only know them after the full trace has been generated.
             &default_input(&[]),
+            hooks,
             usize::MAX,
             powdr_riscv_executor::ExecMode::Trace,
         )
         .0;
+        if let Some(exit) = trace.exit.as_ref().filter(|exit| !exit.is_success()) {
+            return Err(exit.clone());
+        }
         (transposed_trace::<F>(&trace), trace.mem_ops)
     };
 
@@ -264,6 +256,7 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
                 program,
                 pipeline.data_callback().unwrap(),
                 &bootloader_inputs,
+                hooks,
                 num_rows,
                 powdr_riscv_executor::ExecMode::Trace,
             );
@@ -389,5 +382,5 @@ pub fn rust_continuations_dry_run<F: FieldElement>(
 
         chunk_index += 1;
     }
-    bootloader_inputs_and_num_rows
+    Ok(bootloader_inputs_and_num_rows)
 }
@@ -1,8 +1,15 @@
 use std::{
+    cmp::max,
     collections::{BTreeMap, BTreeSet},
     convert::TryFrom,
+    fs,
+    path::Path,
+    sync::Arc,
 };
 
+use powdr_riscv_executor::{InstructionHook, InstructionHooks};
+use serde_json::Value;
+
 type RuntimeFunctionImpl = (&'static str, fn() -> String);
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
@@ -65,6 +72,53 @@ instr poseidon_gl A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11 -> X, Y, Z, W
     runtime_function_impl: Some(("poseidon_gl_coprocessor", poseidon_gl_call)),
 };
 
+static KECCAKF_COPROCESSOR: CoProcessor = CoProcessor {
+    name: "keccakf",
+    ty: "KeccakF",
+    import: "use std::hash::keccakf::KeccakF;",
+    instructions: r#"
+// ================== hashing instructions ==============
+instr keccakf A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12, A13, A14, A15, A16, A17, A18, A19, A20, A21, A22, A23, A24, A25, A26, A27, A28, A29, A30, A31, A32, A33, A34, A35, A36, A37, A38, A39, A40, A41, A42, A43, A44, A45, A46, A47, A48, A49 -> B0, B1, B2, B3, B4, B5, B6, B7, B8, B9, B10, B11, B12, B13, B14, B15, B16, B17, B18, B19, B20, B21, B22, B23, B24, B25, B26, B27, B28, B29, B30, B31, B32, B33, B34, B35, B36, B37, B38, B39, B40, B41, B42, B43, B44, B45, B46, B47, B48, B49 = keccakf.keccakf;
+
+"#,
+    runtime_function_impl: Some(("keccakf_coprocessor", keccakf_call)),
+};
+
+static SHA256_COPROCESSOR: CoProcessor = CoProcessor {
+    name: "sha256",
+    ty: "Sha256",
+    import: "use std::hash::sha256::Sha256;",
+    instructions: r#"
+// ================== hashing instructions ==============
+instr sha256_compress A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12, A13, A14, A15, A16, A17, A18, A19, A20, A21, A22, A23 -> B0, B1, B2, B3, B4, B5, B6, B7 = sha256.compress;
+
+"#,
+    runtime_function_impl: Some(("sha256_compress_coprocessor", sha256_compress_call)),
+};
+
+static ARITH_COPROCESSOR: CoProcessor = CoProcessor {
+    name: "arith",
+    ty: "Arith",
+    import: "use std::arith::Arith;",
+    instructions: r#"
+// ================== 256-bit arithmetic instructions ==============
+instr affine_256 A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12, A13, A14, A15, A16, A17, A18, A19, A20, A21, A22, A23 -> B0, B1, B2, B3, B4, B5, B6, B7, B8, B9, B10, B11, B12, B13, B14, B15 = arith.affine_256;
+
+"#,
+    runtime_function_impl: Some(("affine_256_coprocessor", affine_256_call)),
+};
+
+/// The number of 32-bit words a guest can commit to its public outputs.
+pub const PUBLIC_OUTPUT_WORDS: usize = 32;
+
+static PUBLIC_OUTPUTS_COPROCESSOR: CoProcessor = CoProcessor {
+    name: "public_outputs",
+    ty: "",
+    import: "",
+    instructions: "",
+    runtime_function_impl: Some(("commit_coprocessor", commit_call)),
+};
+
 static INPUT_COPROCESSOR: CoProcessor = CoProcessor {
     name: "prover_input",
     ty: "",
@@ -73,27 +127,197 @@ static INPUT_COPROCESSOR: CoProcessor = CoProcessor {
     runtime_function_impl: Some(("input_coprocessor", prover_input_call)),
 };
 
-static ALL_COPROCESSORS: [(&str, &CoProcessor); 5] = [
+static EXIT_COPROCESSOR: CoProcessor = CoProcessor {
+    name: "exit",
+    ty: "",
+    import: "",
+    instructions: r#"
+    // ================= exit syscall =================
+    // X is the exit code, Y and Z the address and length of the panic message,
+    // which is only used by the executor. Only a successful exit can be proven.
+    instr exit X, Y, Z { X = 0 }
+
+"#,
+    runtime_function_impl: Some(("exit_coprocessor", exit_call)),
+};
+
+static ALL_COPROCESSORS: [(&str, &CoProcessor); 10] = [
     (BINARY_COPROCESSOR.name, &BINARY_COPROCESSOR),
     (SHIFT_COPROCESSOR.name, &SHIFT_COPROCESSOR),
     (SPLIT_GL_COPROCESSOR.name, &SPLIT_GL_COPROCESSOR),
     (POSEIDON_GL_COPROCESSOR.name, &POSEIDON_GL_COPROCESSOR),
+    (KECCAKF_COPROCESSOR.name, &KECCAKF_COPROCESSOR),
+    (SHA256_COPROCESSOR.name, &SHA256_COPROCESSOR),
+    (ARITH_COPROCESSOR.name, &ARITH_COPROCESSOR),
+    (PUBLIC_OUTPUTS_COPROCESSOR.name, &PUBLIC_OUTPUTS_COPROCESSOR),
     (INPUT_COPROCESSOR.name, &INPUT_COPROCESSOR),
+    (EXIT_COPROCESSOR.name, &EXIT_COPROCESSOR),
 ];
 
+/// A coprocessor that is not built into this crate, declared either through
+/// the builder methods below or in a JSON file (see [`CustomCoProcessor::from_json`]).
+#[derive(Clone)]
+pub struct CustomCoProcessor {
+    name: String,
+    ty: String,
+    import: String,
+    instructions: String,
+    runtime_function_impl: Option<(String, String)>,
+    inputs: usize,
+    outputs: usize,
+    submachine_call: Option<String>,
+    executor_hooks: InstructionHooks,
+}
+
+impl CustomCoProcessor {
+    /// Creates a coprocessor whose machine of type `ty` is available as `name`
+    /// in the RISCV machine. `import` is emitted before the RISCV machine and
+    /// typically imports the machine type, e.g. `use std::arith::Arith;`, while
+    /// `instructions` are the powdr-asm instructions that call the machine.
+    pub fn new(name: &str, ty: &str, import: &str, instructions: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ty: ty.to_string(),
+            import: import.to_string(),
+            instructions: instructions.to_string(),
+            runtime_function_impl: None,
+            inputs: 0,
+            outputs: 0,
+            submachine_call: None,
+            executor_hooks: Default::default(),
+        }
+    }
+
+    /// Replaces calls to the runtime function `symbol` (usually an `extern "C"`
+    /// function declared by the guest) by the powdr-asm code `implementation`.
+    pub fn with_runtime_function(mut self, symbol: &str, implementation: &str) -> Self {
+        self.runtime_function_impl = Some((symbol.to_string(), implementation.to_string()));
+        self
+    }
+
+    /// Requests the assignment registers `A0..A{inputs}` and `B0..B{outputs}`,
+    /// as well as the general purpose registers `P0..P{inputs}`, which are shared
+    /// with the other coprocessors.
+    pub fn with_registers(mut self, inputs: usize, outputs: usize) -> Self {
+        self.inputs = inputs;
+        self.outputs = outputs;
+        self
+    }
+
+    /// Adds powdr-asm code that calls the machine once at the start of the
+    /// program, which witness generation currently needs for every submachine.
+    pub fn with_submachine_call(mut self, call: &str) -> Self {
+        self.submachine_call = Some(call.to_string());
+        self
+    }
+
+    /// Provides the native implementation of `instruction` used by the RISCV
+    /// executor, see [`CoProcessors::instruction_hooks`]. Every instruction
+    /// of the coprocessor needs its own hook.
+    pub fn with_executor_hook(mut self, instruction: &str, hook: Arc<dyn InstructionHook>) -> Self {
+        self.executor_hooks.insert(instruction.to_string(), hook);
+        self
+    }
+
+    /// The names of the instructions declared in `instructions`.
+    fn instruction_names(&self) -> impl Iterator<Item = &str> {
+        self.instructions
+            .split("instr ")
+            .skip(1)
+            .filter_map(|declaration| {
+                let name = declaration
+                    .trim_start()
+                    .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .next()?;
+                (!name.is_empty()).then_some(name)
+            })
+    }
+
+    /// Parses a declaration of the form
+    /// ```json
+    /// {
+    ///     "name": "my_accel",
+    ///     "type": "MyAccel",
+    ///     "import": "use std::my_accel::MyAccel;",
+    ///     "instructions": "instr my_op A0, A1 -> B0 = my_accel.op;",
+    ///     "runtime_function": { "symbol": "my_op_coprocessor", "implementation": "..." },
+    ///     "registers": { "inputs": 2, "outputs": 1 },
+    ///     "submachine_call": "..."
+    /// }
+    /// ```
+    /// where only `name`, `type`, `import` and `instructions` are mandatory.
+    /// Executor hooks can only be added through [`Self::with_executor_hook`].
+    pub fn from_json(declaration: &Value) -> Result<Self, String> {
+        let string = |value: &Value, key: &str| -> Result<String, String> {
+            value[key]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("Expected string field \"{key}\" in {value}"))
+        };
+        let number = |value: &Value, key: &str| -> Result<usize, String> {
+            value[key]
+                .as_u64()
+                .map(|n| n as usize)
+                .ok_or_else(|| format!("Expected integer field \"{key}\" in {value}"))
+        };
+
+        let mut coprocessor = Self::new(
+            &string(declaration, "name")?,
+            &string(declaration, "type")?,
+            &string(declaration, "import")?,
+            &string(declaration, "instructions")?,
+        );
+        let runtime_function = &declaration["runtime_function"];
+        if !runtime_function.is_null() {
+            coprocessor = coprocessor.with_runtime_function(
+                &string(runtime_function, "symbol")?,
+                &string(runtime_function, "implementation")?,
+            );
+        }
+        let registers = &declaration["registers"];
+        if !registers.is_null() {
+            coprocessor = coprocessor
+                .with_registers(number(registers, "inputs")?, number(registers, "outputs")?);
+        }
+        if !declaration["submachine_call"].is_null() {
+            coprocessor =
+                coprocessor.with_submachine_call(&string(declaration, "submachine_call")?);
+        }
+        Ok(coprocessor)
+    }
+
+    /// Reads a JSON file containing either a single declaration or an array of
+    /// declarations, see [`Self::from_json`].
+    pub fn from_file(path: &Path) -> Result<Vec<Self>, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+        let json: Value = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid JSON in {}: {e}", path.display()))?;
+        match &json {
+            Value::Array(declarations) => declarations.iter().map(Self::from_json).collect(),
+            declaration => Ok(vec![Self::from_json(declaration)?]),
+        }
+    }
+}
+
 /// Defines which coprocessors should be used by the RISCV machine.
 /// It is important to not add unused coprocessors since they may
 /// lead to many extra columns in PIL.
 #[derive(Default)]
 pub struct CoProcessors {
     coprocessors: BTreeMap<&'static str, &'static CoProcessor>,
+    custom: BTreeMap<String, CustomCoProcessor>,
 }
 
 impl TryFrom<Vec<&str>> for CoProcessors {
     type Error = String;
 
+    /// Each item is either the name of a built-in coprocessor or the path to a
+    /// JSON file declaring custom coprocessors.
     fn try_from(list: Vec<&str>) -> Result<Self, Self::Error> {
-        let items: BTreeSet<&str> = list.into_iter().collect();
+        let (files, items): (Vec<&str>, Vec<&str>) =
+            list.into_iter().partition(|item| item.ends_with(".json"));
+        let items: BTreeSet<&str> = items.into_iter().collect();
 
         if !items.iter().all(|co_processor| {
             ALL_COPROCESSORS
@@ -103,18 +327,27 @@ impl TryFrom<Vec<&str>> for CoProcessors {
             return Err("Invalid co-processor specified.".to_string());
         }
 
-        Ok(Self {
+        // The exit syscall is used by the panic handler of the runtime, so it is
+        // always included.
+        let mut coprocessors = Self {
             coprocessors: ALL_COPROCESSORS
                 .iter()
                 .filter_map(|(name, co_processor)| {
-                    if items.contains(name) {
+                    if items.contains(name) || *name == EXIT_COPROCESSOR.name {
                         Some((*name, *co_processor))
                     } else {
                         None
                     }
                 })
                 .collect(),
-        })
+            custom: Default::default(),
+        };
+        for file in files {
+            for coprocessor in CustomCoProcessor::from_file(Path::new(file))? {
+                coprocessors = coprocessors.try_with(coprocessor)?;
+            }
+        }
+        Ok(coprocessors)
     }
 }
 
@@ -126,7 +359,9 @@ impl CoProcessors {
                 (BINARY_COPROCESSOR.name, &BINARY_COPROCESSOR),
                 (SHIFT_COPROCESSOR.name, &SHIFT_COPROCESSOR),
                 (INPUT_COPROCESSOR.name, &INPUT_COPROCESSOR),
+                (EXIT_COPROCESSOR.name, &EXIT_COPROCESSOR),
             ]),
+            custom: Default::default(),
         }
     }
 
@@ -139,26 +374,117 @@ impl CoProcessors {
         self
     }
 
+    pub fn with_keccakf(mut self) -> Self {
+        self.coprocessors
+            .insert(KECCAKF_COPROCESSOR.name, &KECCAKF_COPROCESSOR);
+        self
+    }
+
+    pub fn with_sha256(mut self) -> Self {
+        self.coprocessors
+            .insert(SHA256_COPROCESSOR.name, &SHA256_COPROCESSOR);
+        self
+    }
+
+    pub fn with_arith(mut self) -> Self {
+        self.coprocessors
+            .insert(ARITH_COPROCESSOR.name, &ARITH_COPROCESSOR);
+        self
+    }
+
+    /// Public outputs are stored in dedicated registers, which are exposed
+    /// as public values once the program has finished.
+    pub fn with_public_outputs(mut self) -> Self {
+        self.coprocessors
+            .insert(PUBLIC_OUTPUTS_COPROCESSOR.name, &PUBLIC_OUTPUTS_COPROCESSOR);
+        self
+    }
+
+    /// Adds a custom coprocessor.
+    /// Panics if a coprocessor with the same name is already present.
+    pub fn with(self, coprocessor: CustomCoProcessor) -> Self {
+        self.try_with(coprocessor).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Adds a custom coprocessor, failing if a coprocessor with the same name
+    /// is already present.
+    pub fn try_with(mut self, coprocessor: CustomCoProcessor) -> Result<Self, String> {
+        if self.has(&coprocessor.name)
+            || ALL_COPROCESSORS
+                .iter()
+                .any(|(name, _)| *name == coprocessor.name)
+        {
+            return Err(format!(
+                "Co-processor {} is already defined.",
+                coprocessor.name
+            ));
+        }
+        self.custom.insert(coprocessor.name.clone(), coprocessor);
+        Ok(self)
+    }
+
+    /// The executor hooks of the custom coprocessors, which have to be passed
+    /// to the RISCV executor to run programs using them.
+    pub fn instruction_hooks(&self) -> InstructionHooks {
+        self.custom
+            .values()
+            .flat_map(|c| c.executor_hooks.clone())
+            .collect()
+    }
+
+    /// Fails if the program uses instructions of custom coprocessors without an
+    /// executor hook, e.g. those declared in JSON files, which the RISCV executor
+    /// cannot run.
+    pub fn check_executable(&self) -> Result<(), String> {
+        let missing = self
+            .custom
+            .values()
+            .flat_map(|c| {
+                c.instruction_names()
+                    .filter(|name| !c.executor_hooks.contains_key(*name))
+                    .map(move |name| format!("{}.{name}", c.name))
+            })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "The RISCV executor has no implementation of the co-processor instructions {}.",
+                missing.join(", ")
+            ))
+        }
+    }
+
+    pub fn has_custom(&self) -> bool {
+        !self.custom.is_empty()
+    }
+
     pub fn has(&self, key: &str) -> bool {
-        self.coprocessors.contains_key(key)
+        self.coprocessors.contains_key(key) || self.custom.contains_key(key)
     }
 
-    pub fn declarations(&self) -> Vec<(&'static str, &'static str)> {
+    pub fn declarations(&self) -> Vec<(&str, &str)> {
         self.coprocessors
             .values()
             .filter(|c| !c.ty.is_empty())
             .map(|c| (c.name, c.ty))
+            .chain(self.custom.values().map(|c| (&c.name[..], &c.ty[..])))
             .collect()
     }
 
-    pub fn machine_imports(&self) -> Vec<&'static str> {
-        self.coprocessors.values().map(|c| c.import).collect()
+    pub fn machine_imports(&self) -> Vec<&str> {
+        self.coprocessors
+            .values()
+            .map(|c| c.import)
+            .chain(self.custom.values().map(|c| &c.import[..]))
+            .collect()
     }
 
     pub fn instructions(&self) -> String {
         self.coprocessors
             .values()
             .map(|c| c.instructions)
+            .chain(self.custom.values().map(|c| &c.instructions[..]))
             .collect::<Vec<&str>>()
             .join("")
     }
@@ -168,6 +494,12 @@ impl CoProcessors {
             .values()
             .filter_map(|c| c.runtime_function_impl)
             .map(|f| f.0)
+            .chain(
+                self.custom
+                    .values()
+                    .filter_map(|c| c.runtime_function_impl.as_ref())
+                    .map(|f| &f.0[..]),
+            )
             .collect()
     }
 
@@ -188,29 +520,91 @@ impl CoProcessors {
             .join("\n")
     }
 
-    pub fn substitutions(&self) -> Vec<(&'static str, String)> {
+    pub fn substitutions(&self) -> Vec<(&str, String)> {
         self.coprocessors
             .values()
             .filter_map(|c| c.runtime_function_impl)
             .map(|f| (f.0, f.1()))
+            .chain(
+                self.custom
+                    .values()
+                    .filter_map(|c| c.runtime_function_impl.as_ref())
+                    .map(|f| (&f.0[..], f.1.clone())),
+            )
             .collect()
     }
 
     pub fn registers(&self) -> String {
-        // Poseidon has 12 inputs and 4 outputs.
-        // The base RISCV machine has 4 assignment registers.
-        // Therefore we need to add 12 assignment registers when using Poseidon.
-        // Moreover, we also need 12 extra general purpose registers to store the
-        // input values.
+        // Poseidon has 12 inputs and 4 outputs, Keccak-f has 50 inputs and 50 outputs,
+        // SHA-256 has 24 inputs and 8 outputs and affine_256 has 24 inputs and 16 outputs.
+        // Custom coprocessors declare their own numbers.
+        // The base RISCV machine has 4 assignment registers, which suffice for the
+        // outputs of Poseidon. Therefore we need to add an assignment register for each
+        // input and, for the other coprocessors, also for each output.
+        // Moreover, we also need extra general purpose registers to store the
+        // input values. The registers are shared between the coprocessors.
+        let (inputs, outputs) = [
+            (POSEIDON_GL_COPROCESSOR.name, 12, 0),
+            (KECCAKF_COPROCESSOR.name, 50, 50),
+            (SHA256_COPROCESSOR.name, 24, 8),
+            (ARITH_COPROCESSOR.name, 24, 16),
+        ]
+        .into_iter()
+        .filter(|(name, _, _)| self.has(name))
+        .chain(
+            self.custom
+                .values()
+                .map(|c| (&c.name[..], c.inputs, c.outputs)),
+        )
+        .fold((0, 0), |(inputs, outputs), (_, i, o)| {
+            (max(inputs, i), max(outputs, o))
+        });
+
+        let a_regs: Vec<String> = (0..inputs).map(|i| format!("reg A{}[<=];", i)).collect();
+        let b_regs: Vec<String> = (0..outputs).map(|i| format!("reg B{}[<=];", i)).collect();
+        let p_regs: Vec<String> = (0..inputs).map(|i| format!("reg P{};", i)).collect();
+        let output_regs: Vec<String> = if self.has(PUBLIC_OUTPUTS_COPROCESSOR.name) {
+            (0..PUBLIC_OUTPUT_WORDS)
+                .map(|i| format!("reg public_output_{i};"))
+                .collect()
+        } else {
+            vec![]
+        };
+
+        [a_regs, b_regs, p_regs, output_regs].concat().join("\n")
+    }
 
-        if !self.coprocessors.contains_key(POSEIDON_GL_COPROCESSOR.name) {
+    /// Declares the public outputs. The registers are zeroed by `_reset` once
+    /// the program has finished, so their values are latched into columns that
+    /// are only updated when the next row resets the machine (the only rows
+    /// where `pc` is zero), and the publics are taken from the last row.
+    pub fn public_declarations(&self, degree: u64) -> String {
+        if !self.has(PUBLIC_OUTPUTS_COPROCESSOR.name) {
             return String::new();
         }
-
-        let a_regs: Vec<String> = (0..12).map(|i| format!("reg A{}[<=];", i)).collect();
-        let p_regs: Vec<String> = (0..12).map(|i| format!("reg P{};", i)).collect();
-
-        [a_regs, p_regs].concat().join("\n")
+        let is_reset = r#"
+    col witness pc_inv(i) query ("hint", if std::convert::int(pc(i)) == 0 {
+        0
+    } else {
+        std::math::ff::inverse(std::convert::int(pc(i)), std::field::modulus())
+    });
+    col witness pc_is_zero;
+    pc_is_zero' = 1 - pc' * pc_inv';
+    pc_is_zero' * pc' = 0;
+"#;
+        let latches: String = (0..PUBLIC_OUTPUT_WORDS)
+            .map(|i| {
+                format!(
+                    r#"
+    col witness final_public_output_{i};
+    final_public_output_{i}' = (1 - pc_is_zero') * final_public_output_{i} + pc_is_zero' * public_output_{i};
+    public output_{i} = final_public_output_{i}({});
+"#,
+                    degree - 1
+                )
+            })
+            .collect();
+        is_reset.to_string() + &latches
     }
 }
 
@@ -253,6 +647,87 @@ fn poseidon_gl_call() -> String {
         .collect()
 }
 
+fn keccakf_call() -> String {
+    // The x10 register holds the address of the state, 25 u64 lanes which are
+    // stored as 50 little-endian u32 words. This is exactly the layout the
+    // Keccak-f machine expects, so the words can be passed on unchanged.
+    let state = (0..50)
+        .map(|i| format!("P{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    (0..50)
+        .map(|i| format!("P{i}, tmp2 <== mload({} + x10);\n", i * 4))
+        .chain(std::iter::once(format!("{state} <== keccakf({state});\n")))
+        .chain((0..50).map(|i| format!("mstore {} + x10, P{i};\n", i * 4)))
+        .collect()
+}
+
+fn sha256_compress_call() -> String {
+    // The x10 register holds the address of 24 u32 words: the state of 8 words,
+    // followed by the message block of 16 words. The new state is written back
+    // to the first 8 words.
+    let inputs = (0..24)
+        .map(|i| format!("P{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let outputs = (0..8)
+        .map(|i| format!("P{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    (0..24)
+        .map(|i| format!("P{i}, tmp2 <== mload({} + x10);\n", i * 4))
+        .chain(std::iter::once(format!(
+            "{outputs} <== sha256_compress({inputs});\n"
+        )))
+        .chain((0..8).map(|i| format!("mstore {} + x10, P{i};\n", i * 4)))
+        .collect()
+}
+
+fn affine_256_call() -> String {
+    // The x10 register holds the address of 24 u32 words: the three 256-bit
+    // arguments a, b and c in little-endian limbs. The high and the low 256 bits
+    // of a * b + c are written back to the first 16 words.
+    let inputs = (0..24)
+        .map(|i| format!("P{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let outputs = (0..16)
+        .map(|i| format!("P{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    (0..24)
+        .map(|i| format!("P{i}, tmp2 <== mload({} + x10);\n", i * 4))
+        .chain(std::iter::once(format!(
+            "{outputs} <== affine_256({inputs});\n"
+        )))
+        .chain((0..16).map(|i| format!("mstore {} + x10, P{i};\n", i * 4)))
+        .collect()
+}
+
+fn commit_call() -> String {
+    // The x10 register holds the index of the public output and x11 the value.
+    // We jump into a table with two instructions per output: the first one
+    // assigns the value to the output register, the second one skips the
+    // rest of the table. The runtime ensures that the index is in range.
+    std::iter::once("skip_if_zero 0, x10 * 2;\n".to_string())
+        .chain((0..PUBLIC_OUTPUT_WORDS).flat_map(|i| {
+            [
+                format!("public_output_{i} <=X= x11;\n"),
+                format!("skip_if_zero 0, {};\n", 2 * (PUBLIC_OUTPUT_WORDS - i - 1)),
+            ]
+        }))
+        .collect()
+}
+
+fn exit_call() -> String {
+    // The exit code is in x10, the panic message in x11 and x12. The program
+    // then continues where it would have returned from its entry point.
+    "exit x10, x11, x12;\ntmp1 <== jump(__runtime_exit);".to_string()
+}
+
 fn prover_input_call() -> String {
     "x10 <=X= ${ (\"data_identifier\", x11, x10) };".to_string()
 }
@@ -285,7 +760,196 @@ pub fn call_every_submachine(coprocessors: &CoProcessors) -> Vec<String> {
         ]);
     }
 
+    if coprocessors.has(KECCAKF_COPROCESSOR.name) {
+        let state = (0..50)
+            .map(|i| format!("P{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        calls.push(format!("{state} <== keccakf({state});"));
+        calls.extend((0..50).map(|i| format!("P{i} <=X= 0;")));
+    }
+
+    if coprocessors.has(SHA256_COPROCESSOR.name) {
+        let inputs = (0..24)
+            .map(|i| format!("P{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let outputs = (0..8)
+            .map(|i| format!("P{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        calls.push(format!("{outputs} <== sha256_compress({inputs});"));
+        calls.extend((0..8).map(|i| format!("P{i} <=X= 0;")));
+    }
+
+    if coprocessors.has(ARITH_COPROCESSOR.name) {
+        let inputs = (0..24)
+            .map(|i| format!("P{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let outputs = (0..16)
+            .map(|i| format!("P{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        calls.push(format!("{outputs} <== affine_256({inputs});"));
+        calls.extend((0..16).map(|i| format!("P{i} <=X= 0;")));
+    }
+
+    calls.extend(
+        coprocessors
+            .custom
+            .values()
+            .filter_map(|c| c.submachine_call.clone()),
+    );
+
     calls.extend(vec!["x10 <=X= 0;".to_string(), "x11 <=X= 0;".to_string()]);
 
     calls
 }
+
+#[cfg(test)]
+mod tests {
+    use powdr_riscv_executor::Elem;
+
+    use super::*;
+
+    #[test]
+    fn custom_coprocessor_from_file() {
+        let declarations = r#"[
+            {
+                "name": "my_arith",
+                "type": "Arith",
+                "import": "use std::arith::Arith;",
+                "instructions": "instr mul_add A0, A1, A2 -> B0 = my_arith.affine_256;",
+                "runtime_function": {
+                    "symbol": "mul_add_coprocessor",
+                    "implementation": "x10 <== mul_add(x10, x11, x12);"
+                },
+                "registers": { "inputs": 3, "outputs": 1 }
+            },
+            {
+                "name": "my_shift",
+                "type": "Shift",
+                "import": "use std::shift::Shift;",
+                "instructions": "instr my_shl Y, Z -> X = my_shift.shl;"
+            }
+        ]"#;
+        let temp_dir = mktemp::Temp::new_dir().unwrap();
+        let file = temp_dir.join("coprocessors.json");
+        fs::write(&file, declarations).unwrap();
+
+        let coprocessors = CoProcessors::try_from(vec!["binary", file.to_str().unwrap()]).unwrap();
+        assert!(coprocessors.has("binary"));
+        assert!(coprocessors.has("my_arith"));
+        assert!(coprocessors.has("my_shift"));
+        assert!(!coprocessors.has("shift"));
+        assert_eq!(
+            coprocessors.declarations(),
+            vec![
+                ("binary", "Binary"),
+                ("my_arith", "Arith"),
+                ("my_shift", "Shift")
+            ]
+        );
+        // The exit syscall is always included.
+        assert_eq!(
+            coprocessors.substitutions(),
+            vec![
+                ("exit_coprocessor", exit_call()),
+                (
+                    "mul_add_coprocessor",
+                    "x10 <== mul_add(x10, x11, x12);".to_string()
+                )
+            ]
+        );
+        assert_eq!(
+            coprocessors.registers(),
+            "reg A0[<=];\nreg A1[<=];\nreg A2[<=];\nreg B0[<=];\nreg P0;\nreg P1;\nreg P2;"
+        );
+        // JSON files cannot provide executor hooks.
+        assert!(coprocessors.instruction_hooks().is_empty());
+        assert_eq!(
+            coprocessors.check_executable().err().unwrap(),
+            "The RISCV executor has no implementation of the co-processor instructions my_arith.mul_add, my_shift.my_shl."
+        );
+    }
+
+    #[test]
+    fn custom_coprocessor_from_file_name_clash() {
+        let temp_dir = mktemp::Temp::new_dir().unwrap();
+        let file = temp_dir.join("coprocessors.json");
+        let declaration =
+            r#"{ "name": "binary", "type": "Binary", "import": "", "instructions": "" }"#;
+        fs::write(&file, declaration).unwrap();
+
+        assert_eq!(
+            CoProcessors::try_from(vec![file.to_str().unwrap()])
+                .err()
+                .unwrap(),
+            "Co-processor binary is already defined."
+        );
+    }
+
+    #[test]
+    fn custom_coprocessor_executor_hook() {
+        struct Double;
+        impl InstructionHook for Double {
+            fn execute(&self, args: &[Elem]) -> Vec<Elem> {
+                vec![Elem(args[0].0 * 2)]
+            }
+        }
+
+        let coprocessors = CoProcessors::base().with(
+            CustomCoProcessor::new("double", "", "", "instr double X -> Y;")
+                .with_executor_hook("double", Arc::new(Double)),
+        );
+        assert!(coprocessors.check_executable().is_ok());
+        let hooks = coprocessors.instruction_hooks();
+        assert_eq!(hooks.keys().collect::<Vec<_>>(), vec!["double"]);
+        assert!(hooks["double"].execute(&[Elem(21)]) == vec![Elem(42)]);
+    }
+
+    #[test]
+    fn custom_coprocessor_missing_executor_hook() {
+        struct Double;
+        impl InstructionHook for Double {
+            fn execute(&self, args: &[Elem]) -> Vec<Elem> {
+                vec![Elem(args[0].0 * 2)]
+            }
+        }
+
+        let coprocessors = CoProcessors::base().with(
+            CustomCoProcessor::new(
+                "my_arith",
+                "",
+                "",
+                "instr double X -> Y;\ninstr triple X -> Y;",
+            )
+            .with_executor_hook("double", Arc::new(Double)),
+        );
+        assert_eq!(
+            coprocessors.check_executable().err().unwrap(),
+            "The RISCV executor has no implementation of the co-processor instructions my_arith.triple."
+        );
+    }
+
+    #[test]
+    fn invalid_custom_coprocessor() {
+        let declaration = serde_json::json!({ "name": "my_arith", "type": "Arith" });
+        assert_eq!(
+            CustomCoProcessor::from_json(&declaration).err().unwrap(),
+            r#"Expected string field "import" in {"name":"my_arith","type":"Arith"}"#
+        );
+    }
+
+    #[test]
+    #[should_panic = "Co-processor binary is already defined."]
+    fn custom_coprocessor_name_clash() {
+        CoProcessors::base().with(CustomCoProcessor::new(
+            "binary",
+            "Binary",
+            "use std::binary::Binary;",
+            "",
+        ));
+    }
+}
@@ -0,0 +1,699 @@
+//! Loads statically linked RISC-V ELF executables, as an alternative to
+//! compiling the textual assembly emitted by rustc.
+//!
+//! The instructions of all executable sections are decoded into the same
+//! statements the assembly parser produces, so that they can be translated
+//! to powdr-asm by the same compiler. The allocated non-executable sections
+//! are turned into the initial memory image.
+//!
+//! Since code addresses in powdr-asm are labels and not byte addresses, all
+//! code addresses the program uses have to be identified at compile time:
+//! - targets of branches and jumps are read from the instructions,
+//! - addresses computed by `auipc` / `lui` followed by `jalr` or `addi` are
+//!   resolved from the instruction pair,
+//! - words in the data sections that hold the address of an instruction and
+//!   have a relocation are code pointers (e.g. in vtables). The relocations are
+//!   only kept if the executable was linked with `--emit-relocs`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use goblin::elf::{
+    header::EM_RISCV,
+    reloc::R_RISCV_32,
+    section_header::{SHF_ALLOC, SHF_EXECINSTR, SHT_NOBITS},
+    Elf,
+};
+use itertools::Itertools;
+
+use crate::compiler::Register;
+use crate::{Argument, DataWord, Expression, Program, Statement};
+
+/// An instruction operand, before code addresses are replaced by labels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand {
+    Reg(u8),
+    Imm(i64),
+    /// `offset(register)`
+    Mem(i64, u8),
+    /// An address in the code.
+    Code(u32),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Instruction {
+    name: &'static str,
+    args: Vec<Operand>,
+}
+
+impl Instruction {
+    fn new(name: &'static str, args: Vec<Operand>) -> Self {
+        Self { name, args }
+    }
+}
+
+use Operand::*;
+
+/// Parses an ELF file and decodes its code and data.
+/// Panics if the file is not a 32-bit little-endian RISC-V executable or
+/// contains unsupported instructions.
+pub fn load_elf(file_contents: &[u8]) -> Program {
+    let elf = Elf::parse(file_contents).unwrap();
+    assert!(
+        !elf.is_64 && elf.little_endian && elf.header.e_machine == EM_RISCV,
+        "Expected a 32-bit little-endian RISC-V ELF file."
+    );
+
+    // Decode the executable sections and collect the contents of the data sections.
+    let mut instructions = BTreeMap::new();
+    let mut data_bytes = BTreeMap::new();
+    for section in &elf.section_headers {
+        if section.sh_flags & u64::from(SHF_ALLOC) == 0
+            || section.sh_type == SHT_NOBITS
+            || section.sh_size == 0
+        {
+            // The .bss section is zero-initialized, which is the default
+            // for the memory anyway.
+            continue;
+        }
+        let contents = &file_contents[section.file_range().unwrap()];
+        let addr = section.sh_addr as u32;
+        if section.sh_flags & u64::from(SHF_EXECINSTR) != 0 {
+            instructions.extend(decode_section(addr, contents));
+        } else {
+            data_bytes.extend(
+                contents
+                    .iter()
+                    .enumerate()
+                    .map(|(i, b)| (addr + i as u32, *b)),
+            );
+        }
+    }
+
+    // Symbols that uniquely name the address of an instruction are used as labels.
+    let mut symbols: HashMap<&str, BTreeSet<u32>> = HashMap::new();
+    for sym in elf.syms.iter() {
+        // Skip section symbols and mapping symbols like "$x".
+        if let Some(name) = elf
+            .strtab
+            .get_at(sym.st_name)
+            .filter(|n| !n.is_empty() && !n.starts_with('$'))
+        {
+            symbols.entry(name).or_default().insert(sym.st_value as u32);
+        }
+    }
+    let mut symbol_names: BTreeMap<u32, &str> = BTreeMap::new();
+    for (name, addresses) in symbols {
+        if let Ok(&addr) = addresses.iter().exactly_one() {
+            if instructions.contains_key(&addr) {
+                let n = symbol_names.entry(addr).or_insert(name);
+                *n = std::cmp::min(*n, name);
+            }
+        }
+    }
+
+    let entry = elf.entry as u32;
+    assert!(
+        instructions.contains_key(&entry),
+        "Entry point 0x{entry:08x} is not an instruction."
+    );
+
+    let data = data_words(&data_bytes);
+    let relocated = data_relocations(&elf);
+    if elf.shdr_relocs.is_empty() {
+        log::warn!(
+            "The ELF file contains no relocations, so code addresses in data sections \
+            cannot be identified. Link with `--emit-relocs` if the program stores code \
+            addresses in data, e.g. in vtables."
+        );
+    }
+
+    // Only pairs that are not the target of a jump in between can be merged.
+    let jump_targets = code_references(instructions.values());
+    let instructions = resolve_address_computations(instructions, &jump_targets);
+
+    let mut referenced: BTreeSet<u32> = code_references(instructions.values());
+    referenced.insert(entry);
+    let data = data
+        .into_iter()
+        .map(|(addr, value)| {
+            if relocated.contains(&addr) && instructions.contains_key(&value) {
+                referenced.insert(value);
+                (addr, DataWord::Label(code_label(value, &symbol_names)))
+            } else {
+                (addr, DataWord::Value(value))
+            }
+        })
+        .collect();
+
+    let statements = instructions
+        .into_iter()
+        .flat_map(|(addr, instr)| {
+            let label = referenced
+                .contains(&addr)
+                .then(|| Statement::Label(code_label(addr, &symbol_names)));
+            let name = match (instr.name, &instr.args[..]) {
+                // Jumps to functions are tail calls, which could be calls
+                // to coprocessors.
+                ("j", [Code(target)]) if symbol_names.contains_key(target) => "tail",
+                (name, _) => name,
+            };
+            label
+                .into_iter()
+                .chain(std::iter::once(Statement::Instruction(
+                    name.to_string(),
+                    instr
+                        .args
+                        .into_iter()
+                        .map(|arg| to_argument(arg, &symbol_names))
+                        .collect(),
+                )))
+        })
+        .collect();
+
+    Program {
+        statements,
+        data,
+        entry_point: code_label(entry, &symbol_names),
+    }
+}
+
+fn code_label(addr: u32, symbol_names: &BTreeMap<u32, &str>) -> String {
+    match symbol_names.get(&addr) {
+        Some(name) => name.to_string(),
+        None => format!("__elf_0x{addr:08x}"),
+    }
+}
+
+fn to_argument(operand: Operand, symbol_names: &BTreeMap<u32, &str>) -> Argument {
+    match operand {
+        Reg(r) => Argument::Register(Register::new(r)),
+        Imm(v) => Argument::Expression(Expression::Number(v)),
+        Mem(off, r) => Argument::RegOffset(Some(Expression::Number(off)), Register::new(r)),
+        Code(addr) => Argument::Expression(Expression::Symbol(code_label(addr, symbol_names))),
+    }
+}
+
+/// Returns the addresses of the words in non-executable sections that the linker
+/// filled with an absolute address, according to the relocations kept by `--emit-relocs`.
+fn data_relocations(elf: &Elf) -> BTreeSet<u32> {
+    elf.shdr_relocs
+        .iter()
+        .filter(|(index, _)| {
+            let target = elf.section_headers[*index].sh_info as usize;
+            elf.section_headers
+                .get(target)
+                .is_some_and(|section| section.sh_flags & u64::from(SHF_EXECINSTR) == 0)
+        })
+        .flat_map(|(_, relocs)| relocs.iter())
+        .filter(|reloc| reloc.r_type == R_RISCV_32)
+        .map(|reloc| reloc.r_offset as u32)
+        .collect()
+}
+
+/// Combines the bytes into words, dropping all words that are zero.
+fn data_words(bytes: &BTreeMap<u32, u8>) -> BTreeMap<u32, u32> {
+    let mut words: BTreeMap<u32, u32> = BTreeMap::new();
+    for (addr, byte) in bytes {
+        *words.entry(addr & !3).or_default() |= (*byte as u32) << (8 * (addr % 4));
+    }
+    words.retain(|_, v| *v != 0);
+    words
+}
+
+fn code_references<'a>(instructions: impl Iterator<Item = &'a Instruction>) -> BTreeSet<u32> {
+    instructions
+        .flat_map(|instr| &instr.args)
+        .filter_map(|arg| match arg {
+            Code(addr) => Some(*addr),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Replaces `auipc` and `lui` by constant loads. If they are followed by a
+/// `jalr` or an `addi` that computes a code address, the pair is replaced by
+/// a jump or a load of the label, respectively.
+fn resolve_address_computations(
+    instructions: BTreeMap<u32, Instruction>,
+    jump_targets: &BTreeSet<u32>,
+) -> BTreeMap<u32, Instruction> {
+    let is_code = |addr: &u32| instructions.contains_key(addr);
+    let mut result = BTreeMap::new();
+    let mut iter = instructions.iter().peekable();
+    while let Some((&pc, instr)) = iter.next() {
+        let (rd, value) = match (instr.name, &instr.args[..]) {
+            ("auipc", [Reg(rd), Imm(imm)]) => (*rd, pc.wrapping_add(*imm as u32)),
+            ("lui", [Reg(rd), Imm(imm)]) => (*rd, (*imm as u32) << 12),
+            _ => {
+                result.insert(pc, instr.clone());
+                continue;
+            }
+        };
+        let next = iter
+            .peek()
+            .map(|(next_pc, next)| (**next_pc, *next))
+            .filter(|(next_pc, _)| *next_pc == pc + 4 && !jump_targets.contains(next_pc));
+        let merged = next.and_then(|(_, next)| match (next.name, &next.args[..]) {
+            ("jalr", [Reg(link), Mem(off, rs)]) if *rs == rd => {
+                let target = value.wrapping_add(*off as u32);
+                is_code(&target).then(|| jump(*link, target))
+            }
+            ("jr", [Reg(rs)]) if *rs == rd => is_code(&value).then(|| jump(0, value)),
+            ("addi", [Reg(rd2), Reg(rs), Imm(off)]) if *rs == rd && *rd2 == rd => {
+                let target = value.wrapping_add(*off as u32);
+                is_code(&target).then(|| Instruction::new("li", vec![Reg(rd), Code(target)]))
+            }
+            _ => None,
+        });
+        if let Some(merged) = merged {
+            result.insert(pc, merged);
+            iter.next();
+        } else if instr.name == "auipc" {
+            result.insert(pc, Instruction::new("li", vec![Reg(rd), Imm(value as i64)]));
+        } else {
+            result.insert(pc, instr.clone());
+        }
+    }
+    result
+}
+
+/// A jump to a code address that stores the return address in `link`.
+fn jump(link: u8, target: u32) -> Instruction {
+    match link {
+        0 => Instruction::new("tail", vec![Code(target)]),
+        1 => Instruction::new("call", vec![Code(target)]),
+        _ => Instruction::new("jal", vec![Reg(link), Code(target)]),
+    }
+}
+
+fn decode_section(addr: u32, contents: &[u8]) -> Vec<(u32, Instruction)> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while offset + 2 <= contents.len() {
+        let pc = addr + offset as u32;
+        let half = u16::from_le_bytes([contents[offset], contents[offset + 1]]);
+        if half & 3 != 3 {
+            instructions.push((pc, decode_compressed(pc, half)));
+            offset += 2;
+        } else {
+            assert!(
+                offset + 4 <= contents.len(),
+                "Truncated instruction at 0x{pc:08x}."
+            );
+            let word = u32::from_le_bytes(contents[offset..offset + 4].try_into().unwrap());
+            instructions.push((pc, decode(pc, word)));
+            offset += 4;
+        }
+    }
+    instructions
+}
+
+/// Sign-extends the lowest `bits` bits of `value`.
+fn sign_extend(value: u32, bits: u32) -> i64 {
+    (((value << (32 - bits)) as i32) >> (32 - bits)) as i64
+}
+
+fn bits(value: u32, hi: u32, lo: u32) -> u32 {
+    (value >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+/// Decodes a 32 bit instruction of the RV32IMA instruction set.
+fn decode(pc: u32, w: u32) -> Instruction {
+    let rd = bits(w, 11, 7) as u8;
+    let rs1 = bits(w, 19, 15) as u8;
+    let rs2 = bits(w, 24, 20) as u8;
+    let funct3 = bits(w, 14, 12);
+    let funct7 = bits(w, 31, 25);
+    let i_imm = sign_extend(bits(w, 31, 20), 12);
+    let unsupported = || -> ! {
+        panic!("Unsupported instruction 0x{w:08x} at 0x{pc:08x}.");
+    };
+
+    match bits(w, 6, 0) {
+        0x37 => Instruction::new("lui", vec![Reg(rd), Imm(bits(w, 31, 12) as i64)]),
+        0x17 => Instruction::new("auipc", vec![Reg(rd), Imm((w & 0xfffff000) as i64)]),
+        0x6f => {
+            let imm = sign_extend(
+                (bits(w, 31, 31) << 20)
+                    | (bits(w, 19, 12) << 12)
+                    | (bits(w, 20, 20) << 11)
+                    | (bits(w, 30, 21) << 1),
+                21,
+            );
+            jal(rd, pc.wrapping_add(imm as u32))
+        }
+        0x67 if funct3 == 0 => jalr(rd, rs1, i_imm),
+        0x63 => {
+            let imm = sign_extend(
+                (bits(w, 31, 31) << 12)
+                    | (bits(w, 7, 7) << 11)
+                    | (bits(w, 30, 25) << 5)
+                    | (bits(w, 11, 8) << 1),
+                13,
+            );
+            let name = match funct3 {
+                0 => "beq",
+                1 => "bne",
+                4 => "blt",
+                5 => "bge",
+                6 => "bltu",
+                7 => "bgeu",
+                _ => unsupported(),
+            };
+            let target = pc.wrapping_add(imm as u32);
+            Instruction::new(name, vec![Reg(rs1), Reg(rs2), Code(target)])
+        }
+        0x03 => {
+            let name = match funct3 {
+                0 => "lb",
+                1 => "lh",
+                2 => "lw",
+                4 => "lbu",
+                5 => "lhu",
+                _ => unsupported(),
+            };
+            Instruction::new(name, vec![Reg(rd), Mem(i_imm, rs1)])
+        }
+        0x23 => {
+            let imm = sign_extend((bits(w, 31, 25) << 5) | bits(w, 11, 7), 12);
+            let name = match funct3 {
+                0 => "sb",
+                1 => "sh",
+                2 => "sw",
+                _ => unsupported(),
+            };
+            Instruction::new(name, vec![Reg(rs2), Mem(imm, rs1)])
+        }
+        0x13 => {
+            let shamt = rs2 as i64;
+            match (funct3, funct7) {
+                (0, _) => addi(rd, rs1, i_imm),
+                (2, _) => Instruction::new("slti", vec![Reg(rd), Reg(rs1), Imm(i_imm)]),
+                (3, _) => Instruction::new("sltiu", vec![Reg(rd), Reg(rs1), Imm(i_imm)]),
+                (4, _) => Instruction::new("xori", vec![Reg(rd), Reg(rs1), Imm(i_imm)]),
+                (6, _) => Instruction::new("ori", vec![Reg(rd), Reg(rs1), Imm(i_imm)]),
+                (7, _) => Instruction::new("andi", vec![Reg(rd), Reg(rs1), Imm(i_imm)]),
+                (1, 0x00) => Instruction::new("slli", vec![Reg(rd), Reg(rs1), Imm(shamt)]),
+                (5, 0x00) => Instruction::new("srli", vec![Reg(rd), Reg(rs1), Imm(shamt)]),
+                (5, 0x20) => Instruction::new("srai", vec![Reg(rd), Reg(rs1), Imm(shamt)]),
+                _ => unsupported(),
+            }
+        }
+        0x33 => {
+            let name = match (funct7, funct3) {
+                (0x00, 0) => "add",
+                (0x20, 0) => "sub",
+                (0x00, 1) => "sll",
+                (0x00, 2) => "slt",
+                (0x00, 3) => "sltu",
+                (0x00, 4) => "xor",
+                (0x00, 5) => "srl",
+                (0x20, 5) => "sra",
+                (0x00, 6) => "or",
+                (0x00, 7) => "and",
+                (0x01, 0) => "mul",
+                (0x01, 1) => "mulh",
+                (0x01, 2) => "mulhsu",
+                (0x01, 3) => "mulhu",
+                (0x01, 4) => "div",
+                (0x01, 5) => "divu",
+                (0x01, 6) => "rem",
+                (0x01, 7) => "remu",
+                _ => unsupported(),
+            };
+            if name == "add" && rs1 == 0 {
+                Instruction::new("mv", vec![Reg(rd), Reg(rs2)])
+            } else {
+                Instruction::new(name, vec![Reg(rd), Reg(rs1), Reg(rs2)])
+            }
+        }
+        0x0f => match funct3 {
+            0 => Instruction::new("fence", vec![]),
+            1 => Instruction::new("fence.i", vec![]),
+            _ => unsupported(),
+        },
+        0x73 => match w {
+            0x00000073 => Instruction::new("ecall", vec![]),
+            0x00100073 => Instruction::new("ebreak", vec![]),
+            // "csrrw x0, cycle, x0", the canonical illegal instruction
+            0xc0001073 => Instruction::new("unimp", vec![]),
+            _ => unsupported(),
+        },
+        0x2f if funct3 == 2 => {
+            // The suffix depends on the "aq" and "rl" bits.
+            let ordering = bits(w, 26, 25) as usize;
+            match bits(w, 31, 27) {
+                0x02 if rs2 == 0 => Instruction::new(
+                    ["lr.w", "lr.w.rl", "lr.w.aq", "lr.w.aqrl"][ordering],
+                    vec![Reg(rd), Mem(0, rs1)],
+                ),
+                0x03 => Instruction::new(
+                    ["sc.w", "sc.w.rl", "sc.w.aq", "sc.w.aqrl"][ordering],
+                    vec![Reg(rd), Reg(rs2), Mem(0, rs1)],
+                ),
+                0x00 => Instruction::new(
+                    ["amoadd.w", "amoadd.w.rl", "amoadd.w.aq", "amoadd.w.aqrl"][ordering],
+                    vec![Reg(rd), Reg(rs2), Mem(0, rs1)],
+                ),
+                _ => unsupported(),
+            }
+        }
+        _ => unsupported(),
+    }
+}
+
+/// Decodes a 16 bit instruction of the RV32C instruction set into the
+/// equivalent uncompressed instruction.
+fn decode_compressed(pc: u32, h: u16) -> Instruction {
+    let h = h as u32;
+    let unsupported = || -> ! {
+        panic!("Unsupported compressed instruction 0x{h:04x} at 0x{pc:08x}.");
+    };
+    // Registers in the compressed three-bit encoding
+    let rs1_c = bits(h, 9, 7) as u8 + 8;
+    let rs2_c = bits(h, 4, 2) as u8 + 8;
+    let rd = bits(h, 11, 7) as u8;
+    let rs2 = bits(h, 6, 2) as u8;
+    let imm6 = sign_extend((bits(h, 12, 12) << 5) | bits(h, 6, 2), 6);
+    let j_target = || {
+        let imm = sign_extend(
+            (bits(h, 12, 12) << 11)
+                | (bits(h, 11, 11) << 4)
+                | (bits(h, 10, 9) << 8)
+                | (bits(h, 8, 8) << 10)
+                | (bits(h, 7, 7) << 6)
+                | (bits(h, 6, 6) << 7)
+                | (bits(h, 5, 3) << 1)
+                | (bits(h, 2, 2) << 5),
+            12,
+        );
+        pc.wrapping_add(imm as u32)
+    };
+    let b_target = || {
+        let imm = sign_extend(
+            (bits(h, 12, 12) << 8)
+                | (bits(h, 11, 10) << 3)
+                | (bits(h, 6, 5) << 6)
+                | (bits(h, 4, 3) << 1)
+                | (bits(h, 2, 2) << 5),
+            9,
+        );
+        pc.wrapping_add(imm as u32)
+    };
+
+    match (bits(h, 1, 0), bits(h, 15, 13)) {
+        // The all-zero instruction is defined to be illegal.
+        (0, 0) if h == 0 => Instruction::new("unimp", vec![]),
+        (0, 0) => {
+            let imm = (bits(h, 12, 11) << 4)
+                | (bits(h, 10, 7) << 6)
+                | (bits(h, 6, 6) << 2)
+                | (bits(h, 5, 5) << 3);
+            addi(rs2_c, 2, imm as i64)
+        }
+        (0, 2 | 6) => {
+            let imm = (bits(h, 12, 10) << 3) | (bits(h, 6, 6) << 2) | (bits(h, 5, 5) << 6);
+            if bits(h, 15, 13) == 2 {
+                Instruction::new("lw", vec![Reg(rs2_c), Mem(imm as i64, rs1_c)])
+            } else {
+                Instruction::new("sw", vec![Reg(rs2_c), Mem(imm as i64, rs1_c)])
+            }
+        }
+        (1, 0) => addi(rd, rd, imm6),
+        (1, 1) => jal(1, j_target()),
+        (1, 2) => addi(rd, 0, imm6),
+        (1, 3) if rd == 2 => {
+            let imm = sign_extend(
+                (bits(h, 12, 12) << 9)
+                    | (bits(h, 6, 6) << 4)
+                    | (bits(h, 5, 5) << 6)
+                    | (bits(h, 4, 3) << 7)
+                    | (bits(h, 2, 2) << 5),
+                10,
+            );
+            addi(2, 2, imm)
+        }
+        (1, 3) => Instruction::new("lui", vec![Reg(rd), Imm(imm6 & 0xfffff)]),
+        (1, 4) => {
+            let shamt = (bits(h, 12, 12) << 5 | bits(h, 6, 2)) as i64;
+            match (bits(h, 11, 10), bits(h, 12, 12), bits(h, 6, 5)) {
+                (0, _, _) => Instruction::new("srli", vec![Reg(rs1_c), Reg(rs1_c), Imm(shamt)]),
+                (1, _, _) => Instruction::new("srai", vec![Reg(rs1_c), Reg(rs1_c), Imm(shamt)]),
+                (2, _, _) => Instruction::new("andi", vec![Reg(rs1_c), Reg(rs1_c), Imm(imm6)]),
+                (3, 0, op) => Instruction::new(
+                    ["sub", "xor", "or", "and"][op as usize],
+                    vec![Reg(rs1_c), Reg(rs1_c), Reg(rs2_c)],
+                ),
+                _ => unsupported(),
+            }
+        }
+        (1, 5) => jal(0, j_target()),
+        (1, 6) => Instruction::new("beq", vec![Reg(rs1_c), Reg(0), Code(b_target())]),
+        (1, 7) => Instruction::new("bne", vec![Reg(rs1_c), Reg(0), Code(b_target())]),
+        (2, 0) => {
+            let shamt = (bits(h, 12, 12) << 5 | bits(h, 6, 2)) as i64;
+            Instruction::new("slli", vec![Reg(rd), Reg(rd), Imm(shamt)])
+        }
+        (2, 2) => {
+            let imm = (bits(h, 12, 12) << 5) | (bits(h, 6, 4) << 2) | (bits(h, 3, 2) << 6);
+            Instruction::new("lw", vec![Reg(rd), Mem(imm as i64, 2)])
+        }
+        (2, 4) => match (bits(h, 12, 12), rd, rs2) {
+            (0, _, 0) => jalr(0, rd, 0),
+            (0, _, _) => Instruction::new("mv", vec![Reg(rd), Reg(rs2)]),
+            (1, 0, 0) => Instruction::new("ebreak", vec![]),
+            (1, _, 0) => jalr(1, rd, 0),
+            _ => Instruction::new("add", vec![Reg(rd), Reg(rd), Reg(rs2)]),
+        },
+        (2, 6) => {
+            let imm = (bits(h, 12, 9) << 2) | (bits(h, 8, 7) << 6);
+            Instruction::new("sw", vec![Reg(rs2), Mem(imm as i64, 2)])
+        }
+        _ => unsupported(),
+    }
+}
+
+fn addi(rd: u8, rs: u8, imm: i64) -> Instruction {
+    match (rs, imm) {
+        (0, _) => Instruction::new("li", vec![Reg(rd), Imm(imm)]),
+        (_, 0) => Instruction::new("mv", vec![Reg(rd), Reg(rs)]),
+        _ => Instruction::new("addi", vec![Reg(rd), Reg(rs), Imm(imm)]),
+    }
+}
+
+fn jal(rd: u8, target: u32) -> Instruction {
+    match rd {
+        0 => Instruction::new("j", vec![Code(target)]),
+        _ => jump(rd, target),
+    }
+}
+
+fn jalr(rd: u8, rs: u8, off: i64) -> Instruction {
+    match (rd, rs, off) {
+        (0, 1, 0) => Instruction::new("ret", vec![]),
+        (0, _, 0) => Instruction::new("jr", vec![Reg(rs)]),
+        _ => Instruction::new("jalr", vec![Reg(rd), Mem(off, rs)]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn i(name: &'static str, args: &[Operand]) -> Instruction {
+        Instruction::new(name, args.to_vec())
+    }
+
+    #[test]
+    fn decode_instructions() {
+        let cases = [
+            (0x02b54633, i("div", &[Reg(12), Reg(10), Reg(11)])),
+            (0x40155693, i("srai", &[Reg(13), Reg(10), Imm(1)])),
+            (0x34550513, i("addi", &[Reg(10), Reg(10), Imm(837)])),
+            (0x00001517, i("auipc", &[Reg(10), Imm(0x1000)])),
+            (0xffc12583, i("lw", &[Reg(11), Mem(-4, 2)])),
+            (0xfeb50fa3, i("sb", &[Reg(11), Mem(-1, 10)])),
+            (0x00351593, i("slli", &[Reg(11), Reg(10), Imm(3)])),
+            (0x1405a52f, i("lr.w.aq", &[Reg(10), Mem(0, 11)])),
+            (0x1ab5262f, i("sc.w.rl", &[Reg(12), Reg(11), Mem(0, 10)])),
+            (0x00c5a6af, i("amoadd.w", &[Reg(13), Reg(12), Mem(0, 11)])),
+            (0x00000073, i("ecall", &[])),
+            (0x00008067, i("ret", &[])),
+            (
+                0x04561763,
+                i("bne", &[Reg(12), Reg(5), Code(0x1000 + 0x4e)]),
+            ),
+            (0xff9ff0ef, i("call", &[Code(0x1000 - 8)])),
+        ];
+        for (word, expected) in cases {
+            assert_eq!(decode(0x1000, word), expected, "0x{word:08x}");
+        }
+    }
+
+    #[test]
+    fn decode_compressed_instructions() {
+        let cases = [
+            (0x1141, i("addi", &[Reg(2), Reg(2), Imm(-16)])),
+            (0xc606, i("sw", &[Reg(1), Mem(12, 2)])),
+            (0x40b2, i("lw", &[Reg(1), Mem(12, 2)])),
+            (0x410c, i("lw", &[Reg(11), Mem(0, 10)])),
+            (0x4515, i("li", &[Reg(10), Imm(5)])),
+            (0x6549, i("lui", &[Reg(10), Imm(18)])),
+            (0x9582, i("jalr", &[Reg(1), Mem(0, 11)])),
+            (0x952e, i("add", &[Reg(10), Reg(10), Reg(11)])),
+            (0x8082, i("ret", &[])),
+            (0xfdf5, i("bne", &[Reg(11), Reg(0), Code(0x1000 - 4)])),
+            (0x0000, i("unimp", &[])),
+        ];
+        for (half, expected) in cases {
+            assert_eq!(decode_compressed(0x1000, half), expected, "0x{half:04x}");
+        }
+    }
+
+    #[test]
+    fn resolve_code_addresses() {
+        let words = [
+            0x00000097, // auipc ra, 0
+            0x030080e7, // jalr ra, 48(ra)
+            0x00000617, // auipc a2, 0
+            0x02860613, // addi a2, a2, 40
+            0x00001517, // auipc a0, 1
+            0x00850513, // addi a0, a0, 8
+            0x00000317, // auipc t1, 0
+            0x01830067, // jr 24(t1)
+        ];
+        let ret = 0x00008067;
+        let instructions = words
+            .iter()
+            .enumerate()
+            .map(|(n, w)| {
+                let pc = 0x100 + 4 * n as u32;
+                (pc, decode(pc, *w))
+            })
+            .chain([(0x130, decode(0x130, ret))])
+            .collect();
+        let resolved = resolve_address_computations(instructions, &BTreeSet::new());
+        assert_eq!(resolved[&0x100], i("call", &[Code(0x130)]));
+        assert!(!resolved.contains_key(&0x104));
+        assert_eq!(resolved[&0x108], i("li", &[Reg(12), Code(0x130)]));
+        // Data addresses stay numbers.
+        assert_eq!(resolved[&0x110], i("li", &[Reg(10), Imm(0x1110)]));
+        assert_eq!(resolved[&0x114], i("addi", &[Reg(10), Reg(10), Imm(8)]));
+        assert_eq!(resolved[&0x118], i("tail", &[Code(0x130)]));
+
+        // Jumping between the two instructions prevents merging them.
+        let instructions = [(0x100, words[0]), (0x104, words[1]), (0x130, ret)]
+            .into_iter()
+            .map(|(pc, w)| (pc, decode(pc, w)))
+            .collect();
+        let resolved = resolve_address_computations(instructions, &BTreeSet::from([0x104]));
+        assert_eq!(resolved[&0x100], i("li", &[Reg(1), Imm(0x100)]));
+        assert_eq!(resolved[&0x104], i("jalr", &[Reg(1), Mem(48, 1)]));
+    }
+
+    #[test]
+    fn data_words_are_little_endian() {
+        let bytes = BTreeMap::from([(0x1001, 0x56), (0x1002, 0x34), (0x1003, 0x12), (0x1004, 0)]);
+        assert_eq!(data_words(&bytes), BTreeMap::from([(0x1000, 0x12345600)]));
+    }
+}
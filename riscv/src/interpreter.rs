@@ -0,0 +1,510 @@
+//! A native interpreter for RISC-V programs.
+//!
+//! It executes the RV32IMA instructions of a [Program] directly, without
+//! translating them to powdr-asm, so that it can serve as a reference for the
+//! compiler: a bug in the translation is reproduced faithfully by
+//! `powdr_riscv_executor`, but not by this interpreter.
+//!
+//! Code addresses in powdr-asm are labels and not byte addresses. The
+//! interpreter assigns the address `CODE_START + 4 * i` to the `i`-th
+//! instruction, so code addresses computed by the program differ from those
+//! of the executor. [ExecutionResult::is_code_address] can be used to tell
+//! them apart from data.
+
+use std::collections::HashMap;
+
+use powdr_executor::witgen::{Query, QueryCallback};
+use powdr_number::FieldElement;
+use powdr_riscv_executor::arith::affine_256;
+use powdr_riscv_executor::keccakf::keccakf;
+use powdr_riscv_executor::poseidon_gl::poseidon_gl;
+use powdr_riscv_executor::sha256::sha256_compress;
+use powdr_riscv_executor::GuestExit;
+
+use crate::compiler::STACK_START;
+use crate::{
+    Argument, CoProcessors, DataWord, Expression, Program, Statement, PUBLIC_OUTPUT_WORDS,
+};
+
+/// The address of the first instruction.
+const CODE_START: u32 = 0x8000_0000;
+
+/// The registers and memory after the program has returned from its entry
+/// point or exited.
+pub struct ExecutionResult {
+    pub registers: [u32; 32],
+    /// The memory, by word-aligned address. Words that are not present are zero.
+    pub memory: HashMap<u32, u32>,
+    /// The words committed to the public outputs.
+    pub public_outputs: [u32; PUBLIC_OUTPUT_WORDS],
+    pub exit: GuestExit,
+    /// The address the entry point returns to. Code addresses are below it.
+    exit_address: u32,
+}
+
+impl ExecutionResult {
+    /// Returns true if the value is the address of an instruction (or of the
+    /// end of the program).
+    pub fn is_code_address(&self, value: u32) -> bool {
+        (CODE_START..=self.exit_address).contains(&value)
+    }
+}
+
+/// Executes the program, starting at its entry point, until it returns or
+/// exits.
+///
+/// Registers are initialized to zero, except for the stack pointer, and the
+/// memory to the data of the program. Queries for inputs and printing are
+/// answered by `inputs`, the same way as in `powdr_riscv_executor`. Calls to
+/// the functions of the given coprocessors are executed natively.
+pub fn execute<F: FieldElement>(
+    program: &Program,
+    coprocessors: &CoProcessors,
+    inputs: &dyn QueryCallback<F>,
+) -> ExecutionResult {
+    let mut instructions = vec![];
+    let mut labels = HashMap::new();
+    for statement in &program.statements {
+        match statement {
+            Statement::Label(label) => {
+                labels.insert(label.as_str(), code_address(instructions.len()));
+            }
+            Statement::Instruction(name, args) => instructions.push((name.as_str(), &args[..])),
+            Statement::Directive(..) => {}
+        }
+    }
+    // One past the end, so that falling off the end of the code is an error.
+    let exit_address = code_address(instructions.len() + 1);
+
+    let mut interpreter = Interpreter {
+        labels,
+        registers: [0; 32],
+        memory: HashMap::new(),
+        lr_sc_reservation: false,
+        public_outputs: [0; PUBLIC_OUTPUT_WORDS],
+        exit: None,
+        coprocessor_functions: coprocessors.runtime_names(),
+        inputs,
+    };
+    for (addr, word) in &program.data {
+        let value = match word {
+            DataWord::Value(v) => *v,
+            DataWord::Label(label) => interpreter.label(label),
+        };
+        interpreter.memory.insert(*addr, value);
+    }
+    interpreter.set_reg(1, exit_address);
+    interpreter.set_reg(2, STACK_START);
+
+    let mut pc = interpreter.label(&program.entry_point);
+    while pc != exit_address && interpreter.exit.is_none() {
+        let index = (pc.wrapping_sub(CODE_START) / 4) as usize;
+        let Some((name, args)) = instructions
+            .get(index)
+            .filter(|_| code_address(index) == pc)
+        else {
+            panic!("Jump to 0x{pc:08x}, which is not a code address.");
+        };
+        pc = interpreter
+            .execute_instruction(name, args, pc + 4)
+            .unwrap_or(pc + 4);
+    }
+
+    ExecutionResult {
+        registers: interpreter.registers,
+        memory: interpreter.memory,
+        public_outputs: interpreter.public_outputs,
+        exit: interpreter.exit.unwrap_or(GuestExit {
+            code: 0,
+            panic_message: None,
+        }),
+        exit_address,
+    }
+}
+
+fn code_address(index: usize) -> u32 {
+    CODE_START + 4 * index as u32
+}
+
+struct Interpreter<'a, F> {
+    labels: HashMap<&'a str, u32>,
+    registers: [u32; 32],
+    memory: HashMap<u32, u32>,
+    lr_sc_reservation: bool,
+    public_outputs: [u32; PUBLIC_OUTPUT_WORDS],
+    exit: Option<GuestExit>,
+    coprocessor_functions: Vec<&'a str>,
+    inputs: &'a dyn QueryCallback<F>,
+}
+
+impl<'a, F: FieldElement> Interpreter<'a, F> {
+    fn label(&self, label: &str) -> u32 {
+        *self
+            .labels
+            .get(label)
+            .unwrap_or_else(|| panic!("Unknown label: {label}"))
+    }
+
+    fn reg(&self, arg: &Argument) -> u32 {
+        let Argument::Register(r) = arg else {
+            panic!("Expected register, got {arg}");
+        };
+        self.registers[r.value() as usize]
+    }
+
+    fn set_reg(&mut self, index: u8, value: u32) {
+        if index != 0 {
+            self.registers[index as usize] = value;
+        }
+    }
+
+    /// Evaluates a numeric argument or a code label.
+    fn imm(&self, arg: &Argument) -> u32 {
+        match arg {
+            Argument::Expression(Expression::Number(n)) => *n as u32,
+            Argument::Expression(Expression::Symbol(label)) => self.label(label),
+            _ => panic!("Expected a number or a label, got {arg}"),
+        }
+    }
+
+    /// Evaluates a memory operand `offset(register)`.
+    fn address(&self, arg: &Argument) -> u32 {
+        match arg {
+            Argument::RegOffset(offset, r) => {
+                let offset = match offset {
+                    Some(Expression::Number(n)) => *n as u32,
+                    None => 0,
+                    Some(offset) => panic!("Expected a numeric offset, got {offset}"),
+                };
+                self.registers[r.value() as usize].wrapping_add(offset)
+            }
+            // If the register is not specified, it defaults to x0.
+            _ => self.imm(arg),
+        }
+    }
+
+    fn load(&self, addr: u32, bytes: u32) -> u32 {
+        (0..bytes).rev().fold(0, |value, i| {
+            let addr = addr.wrapping_add(i);
+            let word = self.memory.get(&(addr & !3)).copied().unwrap_or_default();
+            (value << 8) | ((word >> (8 * (addr % 4))) & 0xff)
+        })
+    }
+
+    fn store(&mut self, addr: u32, bytes: u32, value: u32) {
+        for i in 0..bytes {
+            let addr = addr.wrapping_add(i);
+            let shift = 8 * (addr % 4);
+            let word = self.memory.entry(addr & !3).or_default();
+            *word = (*word & !(0xff << shift)) | (((value >> (8 * i)) & 0xff) << shift);
+        }
+    }
+
+    fn query(&self, query: Vec<Query<F>>) -> F {
+        let query = Query::Tuple(query);
+        match (self.inputs)(&query).unwrap() {
+            Some(value) => value,
+            None => panic!("unknown query command: {query}"),
+        }
+    }
+
+    /// Executes a single instruction. Returns the address of the next
+    /// instruction if it is not `next_pc`.
+    fn execute_instruction(&mut self, name: &str, args: &[Argument], next_pc: u32) -> Option<u32> {
+        let rd = match args.first() {
+            Some(Argument::Register(r)) => r.value(),
+            _ => 0,
+        };
+        let binary_op = |op: fn(u32, u32) -> u32| op(self.reg(&args[1]), self.reg(&args[2]));
+        let immediate_op = |op: fn(u32, u32) -> u32| op(self.reg(&args[1]), self.imm(&args[2]));
+        let unary_op = |op: fn(u32) -> u32| op(self.reg(&args[1]));
+        let branch = |taken: bool, label: &Argument| taken.then(|| self.imm(label));
+
+        let value = match name {
+            // load/store registers
+            "li" | "la" | "lla" => self.imm(&args[1]),
+            "lui" => self.imm(&args[1]) << 12,
+            "mv" => self.reg(&args[1]),
+
+            // Arithmetic
+            "add" => binary_op(u32::wrapping_add),
+            "addi" => immediate_op(u32::wrapping_add),
+            "sub" => binary_op(u32::wrapping_sub),
+            "neg" => unary_op(u32::wrapping_neg),
+            "mul" => binary_op(u32::wrapping_mul),
+            "mulhu" => binary_op(|a, b| ((a as u64 * b as u64) >> 32) as u32),
+            "mulh" => binary_op(|a, b| ((a as i32 as i64 * b as i32 as i64) >> 32) as u32),
+            "mulhsu" => binary_op(|a, b| ((a as i32 as i64 * b as i64) >> 32) as u32),
+            "divu" => binary_op(|a, b| a.checked_div(b).unwrap_or(u32::MAX)),
+            "remu" => binary_op(|a, b| a.checked_rem(b).unwrap_or(a)),
+            "div" => binary_op(|a, b| match b {
+                0 => u32::MAX,
+                _ => (a as i32).wrapping_div(b as i32) as u32,
+            }),
+            "rem" => binary_op(|a, b| match b {
+                0 => a,
+                _ => (a as i32).wrapping_rem(b as i32) as u32,
+            }),
+
+            // bitwise
+            "xor" => binary_op(|a, b| a ^ b),
+            "xori" => immediate_op(|a, b| a ^ b),
+            "and" => binary_op(|a, b| a & b),
+            "andi" => immediate_op(|a, b| a & b),
+            "or" => binary_op(|a, b| a | b),
+            "ori" => immediate_op(|a, b| a | b),
+            "zext.b" => unary_op(|a| a & 0xff),
+            "not" => unary_op(|a| !a),
+
+            // shift
+            "slli" => immediate_op(|a, b| a << (b & 0x1f)),
+            "sll" => binary_op(|a, b| a << (b & 0x1f)),
+            "srli" => immediate_op(|a, b| a >> (b & 0x1f)),
+            "srl" => binary_op(|a, b| a >> (b & 0x1f)),
+            "srai" => immediate_op(|a, b| ((a as i32) >> (b & 0x1f)) as u32),
+            "sra" => binary_op(|a, b| ((a as i32) >> (b & 0x1f)) as u32),
+
+            // comparison
+            "seqz" => unary_op(|a| (a == 0) as u32),
+            "snez" => unary_op(|a| (a != 0) as u32),
+            "sltz" => unary_op(|a| ((a as i32) < 0) as u32),
+            "sgtz" => unary_op(|a| ((a as i32) > 0) as u32),
+            "slti" => immediate_op(|a, b| ((a as i32) < (b as i32)) as u32),
+            "slt" => binary_op(|a, b| ((a as i32) < (b as i32)) as u32),
+            "sltiu" => immediate_op(|a, b| (a < b) as u32),
+            "sltu" => binary_op(|a, b| (a < b) as u32),
+
+            // branches
+            "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" | "bgt" | "ble" | "bgtu" | "bleu" => {
+                let (a, b) = (self.reg(&args[0]), self.reg(&args[1]));
+                let taken = match name {
+                    "beq" => a == b,
+                    "bne" => a != b,
+                    "blt" => (a as i32) < (b as i32),
+                    "bge" => (a as i32) >= (b as i32),
+                    "bltu" => a < b,
+                    "bgeu" => a >= b,
+                    "bgt" => (a as i32) > (b as i32),
+                    "ble" => (a as i32) <= (b as i32),
+                    "bgtu" => a > b,
+                    "bleu" => a <= b,
+                    _ => unreachable!(),
+                };
+                return branch(taken, &args[2]);
+            }
+            "beqz" | "bnez" | "bltz" | "bgez" | "blez" | "bgtz" => {
+                let a = self.reg(&args[0]) as i32;
+                let taken = match name {
+                    "beqz" => a == 0,
+                    "bnez" => a != 0,
+                    "bltz" => a < 0,
+                    "bgez" => a >= 0,
+                    "blez" => a <= 0,
+                    "bgtz" => a > 0,
+                    _ => unreachable!(),
+                };
+                return branch(taken, &args[1]);
+            }
+
+            // jump and call
+            "j" => return Some(self.imm(&args[0])),
+            "jr" => return Some(self.reg(&args[0])),
+            "ret" => return Some(self.registers[1]),
+            "jal" => {
+                let (rd, label) = match args {
+                    [label] => (1, label),
+                    [_, label] => (rd, label),
+                    _ => panic!(),
+                };
+                let target = self.imm(label);
+                self.set_reg(rd, next_pc);
+                return Some(target);
+            }
+            "jalr" => {
+                let (rd, target) = match args {
+                    [rs] => (1, self.reg(rs)),
+                    [_, Argument::Register(_)] => (rd, self.reg(&args[1])),
+                    [_, offset] => (rd, self.address(offset)),
+                    [_, rs, offset] => (rd, self.reg(rs).wrapping_add(self.imm(offset))),
+                    _ => panic!(),
+                };
+                self.set_reg(rd, next_pc);
+                return Some(target);
+            }
+            "call" | "tail" => {
+                let [label] = args else { panic!() };
+                if let Argument::Expression(Expression::Symbol(function)) = label {
+                    if self.coprocessor_functions.contains(&function.as_str()) {
+                        self.call_coprocessor(function);
+                        return (name == "tail").then_some(self.registers[1]);
+                    }
+                }
+                let target = self.imm(label);
+                if name == "call" {
+                    self.set_reg(1, next_pc);
+                }
+                return Some(target);
+            }
+            "ecall" => {
+                let value = self.query(vec![
+                    Query::String("input".to_string()),
+                    Query::Integer(self.registers[10].into()),
+                ]);
+                to_u32(value)
+            }
+            "ebreak" => {
+                self.query(vec![
+                    Query::String("print_char".to_string()),
+                    Query::Integer(self.registers[10].into()),
+                ]);
+                return None;
+            }
+
+            // memory access
+            "lw" => self.load(self.address(&args[1]), 4),
+            "lb" => self.load(self.address(&args[1]), 1) as i8 as u32,
+            "lbu" => self.load(self.address(&args[1]), 1),
+            "lh" => self.load(self.address(&args[1]), 2) as i16 as u32,
+            "lhu" => self.load(self.address(&args[1]), 2),
+            "sw" | "sh" | "sb" => {
+                let bytes = match name {
+                    "sw" => 4,
+                    "sh" => 2,
+                    _ => 1,
+                };
+                self.store(self.address(&args[1]), bytes, self.reg(&args[0]));
+                return None;
+            }
+            "fence" | "fence.i" | "nop" => return None,
+            "unimp" => panic!("reached an unimp instruction"),
+
+            // atomic instructions
+            insn if insn.starts_with("amoadd.w") => {
+                let addr = self.address(&args[2]);
+                let value = self.load(addr, 4);
+                self.store(addr, 4, value.wrapping_add(self.reg(&args[1])));
+                value
+            }
+            insn if insn.starts_with("lr.w") => {
+                self.lr_sc_reservation = true;
+                self.load(self.address(&args[1]), 4)
+            }
+            insn if insn.starts_with("sc.w") => {
+                let success = std::mem::take(&mut self.lr_sc_reservation);
+                if success {
+                    self.store(self.address(&args[2]), 4, self.reg(&args[1]));
+                }
+                (!success) as u32
+            }
+
+            _ => panic!("Unknown instruction: {name}"),
+        };
+        self.set_reg(rd, value);
+        None
+    }
+
+    fn call_coprocessor(&mut self, function: &str) {
+        match function {
+            "input_coprocessor" => {
+                let value = self.query(vec![
+                    Query::String("data_identifier".to_string()),
+                    Query::Integer(self.registers[11].into()),
+                    Query::Integer(self.registers[10].into()),
+                ]);
+                self.set_reg(10, to_u32(value));
+            }
+            "poseidon_gl_coprocessor" => {
+                // The state consists of 12 field elements, each stored as two
+                // little-endian words, at the address in x10.
+                let addr = self.registers[10];
+                let inputs = (0..12)
+                    .map(|i| {
+                        let lo = self.load(addr + 8 * i, 4) as u64;
+                        let hi = self.load(addr + 8 * i + 4, 4) as u64;
+                        F::from(lo) + F::from(hi) * F::from(1u64 << 32)
+                    })
+                    .collect::<Vec<_>>();
+                for (i, output) in poseidon_gl(&inputs).into_iter().enumerate() {
+                    let value = output.to_degree();
+                    self.store(addr + 8 * i as u32, 4, value as u32);
+                    self.store(addr + 8 * i as u32 + 4, 4, (value >> 32) as u32);
+                }
+            }
+            "keccakf_coprocessor" => {
+                // The state consists of 25 lanes, each stored as two
+                // little-endian words, at the address in x10.
+                let addr = self.registers[10];
+                let mut state: [u64; 25] = std::array::from_fn(|i| {
+                    let lo = self.load(addr + 8 * i as u32, 4) as u64;
+                    let hi = self.load(addr + 8 * i as u32 + 4, 4) as u64;
+                    lo | hi << 32
+                });
+                keccakf(&mut state);
+                for (i, lane) in state.into_iter().enumerate() {
+                    self.store(addr + 8 * i as u32, 4, lane as u32);
+                    self.store(addr + 8 * i as u32 + 4, 4, (lane >> 32) as u32);
+                }
+            }
+            "sha256_compress_coprocessor" => {
+                // The state of 8 words is followed by the message block of 16 words.
+                let addr = self.registers[10];
+                let mut state: [u32; 8] =
+                    std::array::from_fn(|i| self.load(addr + 4 * i as u32, 4));
+                let block: [u32; 16] =
+                    std::array::from_fn(|i| self.load(addr + 32 + 4 * i as u32, 4));
+                sha256_compress(&mut state, &block);
+                for (i, word) in state.into_iter().enumerate() {
+                    self.store(addr + 4 * i as u32, 4, word);
+                }
+            }
+            "affine_256_coprocessor" => {
+                // The arguments a, b and c are followed by each other, the high
+                // and the low part of the result overwrite a and b.
+                let addr = self.registers[10];
+                let [a, b, c]: [[u32; 8]; 3] = std::array::from_fn(|i| {
+                    std::array::from_fn(|j| self.load(addr + 32 * i as u32 + 4 * j as u32, 4))
+                });
+                let (hi, lo) = affine_256(&a, &b, &c);
+                for (i, limb) in hi.into_iter().chain(lo).enumerate() {
+                    self.store(addr + 4 * i as u32, 4, limb);
+                }
+            }
+            "commit_coprocessor" => {
+                let index = self.registers[10];
+                match self.public_outputs.get_mut(index as usize) {
+                    Some(output) => *output = self.registers[11],
+                    // The guest fails like on a panic, because the runtime
+                    // checks the index before calling the coprocessor.
+                    None => {
+                        self.exit = Some(GuestExit {
+                            code: 101,
+                            panic_message: Some(format!(
+                                "Public output index {index} is out of range, there are only {PUBLIC_OUTPUT_WORDS} public outputs."
+                            )),
+                        })
+                    }
+                }
+            }
+            "exit_coprocessor" => {
+                let (addr, len) = (self.registers[11], self.registers[12]);
+                // The message cannot extend beyond the end of the address space.
+                let panic_message = (len > 0).then(|| {
+                    let bytes = (addr..addr.saturating_add(len))
+                        .map(|a| self.load(a, 1) as u8)
+                        .collect::<Vec<_>>();
+                    String::from_utf8_lossy(&bytes).into_owned()
+                });
+                self.exit = Some(GuestExit {
+                    code: self.registers[10],
+                    panic_message,
+                });
+            }
+            _ => panic!("Unknown coprocessor function: {function}"),
+        }
+    }
+}
+
+fn to_u32<F: FieldElement>(value: F) -> u32 {
+    let value = value.to_degree();
+    u32::try_from(value).unwrap_or_else(|_| panic!("Value {value} does not fit into 32 bits."))
+}
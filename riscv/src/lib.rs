@@ -13,18 +13,40 @@ use serde_json::Value as JsonValue;
 use std::fs;
 
 use crate::compiler::{FunctionKind, Register};
-pub use crate::coprocessors::CoProcessors;
+pub use crate::coprocessors::{CoProcessors, CustomCoProcessor, PUBLIC_OUTPUT_WORDS};
 
 pub mod compiler;
 pub mod continuations;
 mod coprocessors;
 mod disambiguator;
+pub mod elf;
+pub mod interpreter;
 pub mod parser;
+pub mod trace_witgen;
 
 type Statement = powdr_asm_utils::ast::Statement<Register, FunctionKind>;
 type Argument = powdr_asm_utils::ast::Argument<Register, FunctionKind>;
 type Expression = powdr_asm_utils::ast::Expression<FunctionKind>;
 
+/// A RISC-V program together with its initial memory image, as produced by
+/// the assembly and ELF frontends.
+pub struct Program {
+    /// The instructions, with labels at all code addresses that are referenced.
+    pub statements: Vec<Statement>,
+    /// The non-zero words of the initial memory image, by address.
+    pub data: BTreeMap<u32, DataWord>,
+    /// The label of the entry point.
+    pub entry_point: String,
+}
+
+/// A word of the initial memory image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DataWord {
+    Value(u32),
+    /// The address of the code at the given label.
+    Label(String),
+}
+
 /// Compiles a rust file all the way down to PIL and generates
 /// fixed and witness columns.
 #[allow(clippy::print_stderr)]
@@ -40,6 +62,17 @@ pub fn compile_rust(
             coprocessors.has("poseidon_gl"),
             "PoseidonGL coprocessor is required for bootloader"
         );
+        // The bootloader only saves the registers used by PoseidonGL.
+        for name in ["keccakf", "sha256", "arith", "public_outputs"] {
+            assert!(
+                !coprocessors.has(name),
+                "The {name} coprocessor is not supported with the bootloader yet"
+            );
+        }
+        assert!(
+            !coprocessors.has_custom(),
+            "Custom coprocessors are not supported with the bootloader yet"
+        );
     }
 
     let riscv_asm = if file_name.ends_with("Cargo.toml") {
@@ -112,6 +145,36 @@ pub fn compile_riscv_asm_bundle(
     Some((powdr_asm_file_name, powdr_asm))
 }
 
+/// Compiles a statically linked RISC-V ELF executable to powdr assembly.
+#[allow(clippy::print_stderr)]
+pub fn compile_riscv_elf(
+    input_file: &str,
+    output_dir: &Path,
+    force_overwrite: bool,
+    coprocessors: &CoProcessors,
+    with_bootloader: bool,
+) -> Option<(PathBuf, String)> {
+    let powdr_asm_file_name = output_dir.join(format!(
+        "{}.asm",
+        Path::new(input_file).file_stem().unwrap().to_str().unwrap()
+    ));
+    if powdr_asm_file_name.exists() && !force_overwrite {
+        eprintln!(
+            "Target file {} already exists. Not overwriting.",
+            powdr_asm_file_name.to_str().unwrap()
+        );
+        return None;
+    }
+
+    let program = elf::load_elf(&fs::read(input_file).unwrap());
+    let powdr_asm = compiler::compile_elf(program, coprocessors, with_bootloader);
+
+    fs::write(powdr_asm_file_name.clone(), &powdr_asm).unwrap();
+    log::info!("Wrote {}", powdr_asm_file_name.to_str().unwrap());
+
+    Some((powdr_asm_file_name, powdr_asm))
+}
+
 /// Compiles a riscv asm file all the way down to PIL and generates
 /// fixed and witness columns.
 pub fn compile_riscv_asm(
@@ -0,0 +1,103 @@
+//! Witness generation for the main machine guided by the execution trace of the RISC-V executor.
+
+use std::collections::HashMap;
+
+use powdr_number::FieldElement;
+use powdr_pipeline::{Pipeline, Stage};
+use powdr_riscv_executor::{ExecMode, ExecutionTrace, GuestExit, InstructionHooks};
+
+/// Returns the values of all registers (including the pc) in each row of the
+/// execution trace, by the name of the corresponding column of the main machine.
+pub(crate) fn transposed_trace<F: FieldElement>(trace: &ExecutionTrace) -> HashMap<String, Vec<F>> {
+    let mut reg_values: HashMap<&str, Vec<F>> = HashMap::with_capacity(trace.reg_map.len());
+
+    let mut rows = trace.replay();
+    while let Some(row) = rows.next_row() {
+        for (reg_name, &index) in trace.reg_map.iter() {
+            reg_values
+                .entry(reg_name)
+                .or_default()
+                .push(row[index as usize].0.into());
+        }
+    }
+
+    reg_values
+        .into_iter()
+        .map(|(n, c)| (format!("main.{}", n), c))
+        .collect()
+}
+
+/// Runs the program of the pipeline in the RISC-V executor and passes the register
+/// and pc columns of the main machine to witness generation as known values.
+/// Witness generation then only computes the derived columns (instruction flags,
+/// ROM lookups and secondary machines) and checks the trace against the constraints,
+/// which is much faster than solving the main machine row by row.
+///
+/// Fails before witness generation if the guest panicked or exited with a
+/// non-zero code, because such an execution cannot be proven.
+///
+/// The prover inputs have to be set on the pipeline and `hooks` have to implement
+/// the instructions of custom coprocessors, see [crate::CoProcessors::instruction_hooks].
+/// Only works for programs without continuations, because the bootloader is not executed.
+pub fn with_execution_trace<F: FieldElement>(
+    pipeline: Pipeline<F>,
+    hooks: &InstructionHooks,
+) -> Result<Pipeline<F>, Vec<String>> {
+    let (pipeline, exit) = with_execution_trace_and_exit(pipeline, hooks)?;
+    match exit {
+        Some(exit) if !exit.is_success() => Err(vec![exit.to_string()]),
+        _ => Ok(pipeline),
+    }
+}
+
+/// Like [with_execution_trace], but leaves it to the caller to handle how the
+/// guest exited, which is None if it did not finish within the degree.
+pub fn with_execution_trace_and_exit<F: FieldElement>(
+    pipeline: Pipeline<F>,
+    hooks: &InstructionHooks,
+) -> Result<(Pipeline<F>, Option<GuestExit>), Vec<String>> {
+    let (pipeline, trace) = execute(pipeline, hooks, ExecMode::Trace)?;
+    log::info!("Execution trace has {} rows.", trace.len);
+
+    let columns = transposed_trace::<F>(&trace);
+    Ok((
+        pipeline.add_trace_witness_values(columns.into_iter().collect()),
+        trace.exit,
+    ))
+}
+
+/// Runs the program of the pipeline in the RISC-V executor without recording a
+/// trace and returns how the guest exited, which is None if it did not finish
+/// within the degree. This reports a failing guest before witness generation,
+/// which would otherwise only fail on the constraint of the exit syscall.
+pub fn guest_exit<F: FieldElement>(
+    pipeline: Pipeline<F>,
+    hooks: &InstructionHooks,
+) -> Result<(Pipeline<F>, Option<GuestExit>), Vec<String>> {
+    let (pipeline, trace) = execute(pipeline, hooks, ExecMode::Fast)?;
+    Ok((pipeline, trace.exit))
+}
+
+/// Executes the program of the pipeline for at most as many steps as the degree
+/// of the main machine.
+fn execute<F: FieldElement>(
+    mut pipeline: Pipeline<F>,
+    hooks: &InstructionHooks,
+    mode: ExecMode,
+) -> Result<(Pipeline<F>, ExecutionTrace), Vec<String>> {
+    pipeline.advance_to(Stage::AnalyzedAsm)?;
+    let program = pipeline.artifact().unwrap().to_analyzed_asm().unwrap();
+    let degree = program
+        .machines()
+        .fold(None, |acc, (_, m)| acc.or(m.degree.clone()))
+        .unwrap()
+        .degree;
+    let degree = F::from(degree).to_degree() as usize;
+    let inputs = pipeline
+        .data_callback()
+        .ok_or_else(|| vec!["Prover inputs are required to execute the program.".to_string()])?;
+
+    log::info!("Executing powdr-asm...");
+    let (trace, _) = powdr_riscv_executor::execute_ast(program, inputs, &[], hooks, degree, mode);
+    Ok((pipeline, trace))
+}
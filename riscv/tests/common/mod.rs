@@ -17,6 +17,7 @@ pub fn verify_riscv_asm_string(file_name: &str, contents: &str, inputs: Vec<Gold
         pipeline.data_callback().unwrap(),
         // Assume the RISC-V program was compiled without a bootloader, otherwise this will fail.
         &[],
+        &Default::default(),
         usize::MAX,
         powdr_riscv_executor::ExecMode::Fast,
     );
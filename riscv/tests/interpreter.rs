@@ -0,0 +1,602 @@
+//! Differential tests: programs are run natively by the interpreter and, after
+//! compilation to powdr-asm, by the RISC-V executor. Both have to end in the
+//! same state and print the same output.
+
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Mutex;
+
+use mktemp::Temp;
+use powdr_executor::witgen::{Query, QueryCallback};
+use powdr_number::GoldilocksField;
+use powdr_pipeline::{inputs_to_query_callback, serde_data_to_query_callback};
+use powdr_riscv::{
+    compiler::{compile, load_assembly},
+    interpreter, CoProcessors, PUBLIC_OUTPUT_WORDS,
+};
+use powdr_riscv_executor::{ExecMode, ExecutionTrace, GuestExit};
+use test_log::test;
+
+#[test]
+#[ignore = "Too slow"]
+fn trivial() {
+    compare_rust_file("trivial.rs", &[], &CoProcessors::base());
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn zero_with_values() {
+    compare_rust_file("zero_with_values.rs", &[], &CoProcessors::base());
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn poseidon_gl() {
+    compare_rust_file(
+        "poseidon_gl_via_coprocessor.rs",
+        &[],
+        &CoProcessors::base().with_poseidon(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn arith() {
+    compare_rust_file(
+        "arith_via_coprocessor.rs",
+        &[],
+        &CoProcessors::base().with_arith(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn sum() {
+    compare_rust_file(
+        "sum.rs",
+        &[16, 4, 1, 2, 8, 5].map(|x| x.into()),
+        &CoProcessors::base(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn byte_access() {
+    compare_rust_file(
+        "byte_access.rs",
+        &[0, 104, 707].map(|x| x.into()),
+        &CoProcessors::base(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn double_word() {
+    let a0 = 0x01000000u32;
+    let a1 = 0x010000ffu32;
+    let b0 = 0xf100b00fu32;
+    let b1 = 0x0100f0f0u32;
+    let c = ((a0 as u64) | ((a1 as u64) << 32)).wrapping_mul((b0 as u64) | ((b1 as u64) << 32));
+    compare_rust_file(
+        "double_word.rs",
+        &[a0, a1, b0, b1, c as u32, (c >> 32) as u32].map(|x| x.into()),
+        &CoProcessors::base(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn print() {
+    // Fails after printing, which has to happen on both sides.
+    compare_rust_file("print.rs", &[0x2a.into()], &CoProcessors::base());
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn many_chunks() {
+    compare_rust_file("many_chunks.rs", &[], &CoProcessors::base().with_poseidon());
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn many_chunks_memory() {
+    compare_rust_file(
+        "many_chunks_memory.rs",
+        &[],
+        &CoProcessors::base().with_poseidon(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn memfuncs() {
+    compare_rust_crate("memfuncs", &[], &[], &CoProcessors::base());
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn keccak() {
+    compare_rust_crate("keccak", &[], &[], &CoProcessors::base());
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn keccakf_via_coprocessor() {
+    compare_rust_crate(
+        "keccakf_via_coprocessor",
+        &[],
+        &[],
+        &CoProcessors::base().with_keccakf(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn sha256_via_coprocessor() {
+    compare_rust_crate(
+        "sha256_via_coprocessor",
+        &[],
+        &[],
+        &CoProcessors::base().with_sha256(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn vec_median() {
+    compare_rust_crate(
+        "vec_median",
+        &[5, 11, 15, 75, 6, 5, 1, 4, 7, 3, 2, 9, 2].map(|x| x.into()),
+        &[],
+        &CoProcessors::base(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn password_checker() {
+    compare_rust_crate("password_checker", &[], &[], &CoProcessors::base());
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn function_pointer() {
+    compare_rust_crate(
+        "function_pointer",
+        &[2734, 735, 1999].map(|x| x.into()),
+        &[],
+        &CoProcessors::base(),
+    );
+}
+
+#[cfg(feature = "complex-tests")]
+#[test]
+#[ignore = "Too slow"]
+fn evm() {
+    // mstore(0, 666)
+    // return(0, 32)
+    let bytecode = hex::decode("61029a60005260206000f3").unwrap();
+    compare_rust_crate("evm", &[], &[(666, bytecode)], &CoProcessors::base());
+}
+
+#[test]
+fn public_outputs() {
+    // Commits 7 to output 0, the stack pointer to output 1 and overwrites
+    // output 31, as in riscv_runtime::commit.
+    let assembly = r#"
+.globl __runtime_start
+__runtime_start:
+    li a0, 0
+    li a1, 7
+    call commit_coprocessor
+    li a0, 1
+    mv a1, sp
+    call commit_coprocessor
+    li a0, 31
+    li a1, 3
+    call commit_coprocessor
+    li a1, 0xffffffff
+    call commit_coprocessor
+    ret
+"#;
+    compare_with_executor(
+        [("public_outputs".to_string(), assembly.to_string())].into(),
+        &CoProcessors::base().with_public_outputs(),
+        &[],
+        &[],
+    );
+}
+
+#[test]
+fn public_output_out_of_range() {
+    let assembly = r#"
+.globl __runtime_start
+__runtime_start:
+    li a0, 32
+    li a1, 7
+    call commit_coprocessor
+    ret
+"#;
+    let coprocessors = CoProcessors::base().with_public_outputs();
+    let program = load_assembly(
+        [("public_outputs".to_string(), assembly.to_string())].into(),
+        &coprocessors,
+    );
+    let output = Mutex::new(String::new());
+    let callback = query_callback(&[], &[], &output);
+    let result = interpreter::execute(&program, &coprocessors, &callback);
+    assert_eq!(
+        result.exit,
+        GuestExit {
+            code: 101,
+            panic_message: Some(
+                "Public output index 32 is out of range, there are only 32 public outputs."
+                    .to_string()
+            )
+        }
+    );
+}
+
+#[test]
+fn guest_exit() {
+    // Exits like the panic handler of riscv_runtime, skipping the rest of the
+    // program.
+    let assembly = r#"
+.globl __runtime_start
+__runtime_start:
+    li a0, 101
+    lui a1, %hi(message)
+    addi a1, a1, %lo(message)
+    li a2, 5
+    call exit_coprocessor
+    li a0, 1
+    ret
+.data
+message:
+    .ascii "oops!"
+"#;
+    let assemblies: BTreeMap<_, _> = [("guest_exit".to_string(), assembly.to_string())].into();
+    compare_with_executor(assemblies.clone(), &CoProcessors::base(), &[], &[]);
+
+    let powdr_asm = compile(assemblies, &CoProcessors::base(), false);
+    let output = Mutex::new(String::new());
+    let callback = query_callback(&[], &[], &output);
+    let (trace, _) = powdr_riscv_executor::execute(
+        &powdr_asm,
+        &callback,
+        &[],
+        &Default::default(),
+        ExecMode::Fast,
+    );
+    assert_eq!(
+        trace.exit,
+        Some(GuestExit {
+            code: 101,
+            panic_message: Some("oops!".to_string())
+        })
+    );
+}
+
+#[test]
+fn failures() {
+    // Both have to fail in the same way, after printing the same output.
+    let unimp = r#"
+.globl __runtime_start
+__runtime_start:
+    li a0, 33
+    ebreak
+    unimp
+    ret
+"#;
+    let unknown_query = r#"
+.globl __runtime_start
+__runtime_start:
+    li a0, 0
+    li a1, 7
+    call input_coprocessor
+    ret
+"#;
+    for (name, assembly) in [("unimp", unimp), ("unknown_query", unknown_query)] {
+        compare_with_executor(
+            [(name.to_string(), assembly.to_string())].into(),
+            &CoProcessors::base(),
+            &[],
+            &[],
+        );
+    }
+}
+
+#[test]
+fn random_instruction_sequences() {
+    for seed in 1..=30 {
+        let assembly = random_program(seed, 100);
+        log::debug!("Program for seed {seed}:\n{assembly}");
+        compare_with_executor(
+            [(format!("random_{seed}"), assembly)].into(),
+            &CoProcessors::base(),
+            &[],
+            &[],
+        );
+    }
+}
+
+fn compare_rust_file(case: &str, inputs: &[GoldilocksField], coprocessors: &CoProcessors) {
+    let temp_dir = Temp::new_dir().unwrap();
+    let riscv_asm =
+        powdr_riscv::compile_rust_to_riscv_asm(&format!("tests/riscv_data/{case}"), &temp_dir);
+    compare_with_executor(riscv_asm, coprocessors, inputs, &[]);
+}
+
+fn compare_rust_crate(
+    case: &str,
+    inputs: &[GoldilocksField],
+    data: &[(u32, Vec<u8>)],
+    coprocessors: &CoProcessors,
+) {
+    let temp_dir = Temp::new_dir().unwrap();
+    let riscv_asm = powdr_riscv::compile_rust_crate_to_riscv_asm(
+        &format!("tests/riscv_data/{case}/Cargo.toml"),
+        &temp_dir,
+    );
+    compare_with_executor(riscv_asm, coprocessors, inputs, data);
+}
+
+/// Runs the assembly on the interpreter and compiled to powdr-asm on the
+/// executor. Asserts that both print the same output and that either both
+/// fail or both end with the same registers and memory. Words that hold code
+/// addresses are not compared, because powdr-asm uses different ones.
+fn compare_with_executor(
+    assemblies: BTreeMap<String, String>,
+    coprocessors: &CoProcessors,
+    inputs: &[GoldilocksField],
+    data: &[(u32, Vec<u8>)],
+) {
+    let program = load_assembly(assemblies.clone(), coprocessors);
+    let powdr_asm = compile(assemblies, coprocessors, false);
+
+    let interpreter_output = Mutex::new(String::new());
+    let interpreted = catch_unwind(AssertUnwindSafe(|| {
+        let callback = query_callback(inputs, data, &interpreter_output);
+        interpreter::execute(&program, coprocessors, &callback)
+    }));
+    let executor_output = Mutex::new(String::new());
+    let executed = catch_unwind(AssertUnwindSafe(|| {
+        let callback = query_callback(inputs, data, &executor_output);
+        let (trace, memory) = powdr_riscv_executor::execute(
+            &powdr_asm,
+            &callback,
+            &[],
+            &coprocessors.instruction_hooks(),
+            ExecMode::Trace,
+        );
+        (
+            final_registers(&trace),
+            final_public_outputs(&trace),
+            trace.exit.unwrap(),
+            memory,
+        )
+    }));
+
+    assert_eq!(
+        executor_output.into_inner().unwrap(),
+        interpreter_output.into_inner().unwrap(),
+        "Printed output differs."
+    );
+    let (expected, (registers, public_outputs, exit, memory)) = match (interpreted, executed) {
+        (Ok(interpreted), Ok(executed)) => (interpreted, executed),
+        (Err(interpreted), Err(executed)) => {
+            assert_eq!(
+                failure(&*executed),
+                failure(&*interpreted),
+                "Both failed, but differently."
+            );
+            return;
+        }
+        (Ok(_), Err(_)) => panic!("The executor failed, but the interpreter did not."),
+        (Err(_), Ok(_)) => panic!("The interpreter failed, but the executor did not."),
+    };
+
+    for (i, (&value, &expected_value)) in registers.iter().zip(&expected.registers).enumerate() {
+        if !expected.is_code_address(expected_value) {
+            assert_eq!(value, expected_value, "Register x{i} differs.");
+        }
+    }
+    assert_eq!(
+        public_outputs, expected.public_outputs,
+        "Public outputs differ."
+    );
+    assert_eq!(exit, expected.exit, "Guest exit differs.");
+    let addresses = memory
+        .keys()
+        .chain(expected.memory.keys())
+        .collect::<BTreeSet<_>>();
+    for addr in addresses {
+        let value = memory.get(addr).copied().unwrap_or_default();
+        let expected_value = expected.memory.get(addr).copied().unwrap_or_default();
+        if !expected.is_code_address(expected_value) {
+            assert_eq!(value, expected_value, "Memory at 0x{addr:08x} differs.");
+        }
+    }
+}
+
+/// Returns the message of a panic, with the messages for an unimp instruction,
+/// which is compiled to a fail instruction, unified.
+fn failure(payload: &(dyn Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| payload.downcast_ref::<&str>().copied())
+        .unwrap_or_default();
+    match message {
+        "reached an unimp instruction" => "reached a fail instruction",
+        message => message,
+    }
+    .to_string()
+}
+
+/// Returns the values of x0 to x31 in the last row of the trace.
+fn final_registers(trace: &ExecutionTrace) -> [u32; 32] {
+    let mut replay = trace.replay();
+    let mut last_row = vec![];
+    while let Some(row) = replay.next_row() {
+        last_row = row.to_vec();
+    }
+    std::array::from_fn(|i| last_row[trace.reg_map[&format!("x{i}")] as usize].0 as u32)
+}
+
+/// Returns the values of the public output registers in the last row of the
+/// trace, which are all zero if the program was compiled without them.
+fn final_public_outputs(trace: &ExecutionTrace) -> [u32; PUBLIC_OUTPUT_WORDS] {
+    let mut replay = trace.replay();
+    let mut last_row = vec![];
+    while let Some(row) = replay.next_row() {
+        last_row = row.to_vec();
+    }
+    std::array::from_fn(|i| {
+        trace
+            .reg_map
+            .get(&format!("public_output_{i}"))
+            .map_or(0, |&index| last_row[index as usize].0 as u32)
+    })
+}
+
+/// Answers input and data queries like the pipeline, but collects the printed
+/// characters in `output`.
+fn query_callback<'a>(
+    inputs: &[GoldilocksField],
+    data: &[(u32, Vec<u8>)],
+    output: &'a Mutex<String>,
+) -> impl QueryCallback<GoldilocksField> + 'a {
+    let inputs = inputs_to_query_callback(inputs.to_vec());
+    let data = data
+        .iter()
+        .map(|(channel, bytes)| serde_data_to_query_callback(*channel, bytes))
+        .collect::<Vec<_>>();
+    move |query: &Query<GoldilocksField>| match query.as_tuple()? {
+        [Query::String(id), ch] if id == "print_char" => {
+            output.lock().unwrap().push(ch.to_u64()? as u8 as char);
+            Ok(Some(0.into()))
+        }
+        [Query::String(id), ..] if id == "data_identifier" => data
+            .iter()
+            .find_map(|callback| callback(query).transpose())
+            .transpose(),
+        _ => inputs(query),
+    }
+}
+
+/// A xorshift generator, so that the random programs are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u32
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+
+    fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u32) as usize]
+    }
+
+    fn value(&mut self) -> u32 {
+        match self.below(3) {
+            0 => *self.choose(&[
+                0, 1, 2, 31, 32, 0x7fffffff, 0x80000000, 0xfffffffe, 0xffffffff,
+            ]),
+            1 => self.below(64).wrapping_sub(32),
+            _ => self.next(),
+        }
+    }
+}
+
+/// Generates a program that initializes the registers with random values and
+/// executes a random sequence of arithmetic, memory and branch instructions.
+/// The return address and the stack pointer are left untouched, memory is
+/// accessed below the stack pointer.
+fn random_program(seed: u64, len: usize) -> String {
+    let mut rng = Rng(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1);
+    let registers = [0]
+        .into_iter()
+        .chain(3..32)
+        .map(|i| format!("x{i}"))
+        .collect::<Vec<_>>();
+
+    let mut lines = vec![
+        ".globl __runtime_start".to_string(),
+        "__runtime_start:".to_string(),
+    ];
+    for r in &registers[1..] {
+        lines.push(format!("li {r}, {}", rng.value() as i32));
+    }
+    for i in 0..len {
+        let [rd, rs1, rs2] = [(); 3].map(|_| rng.choose(&registers).clone());
+        let instruction = match rng.below(8) {
+            0 | 1 => {
+                let op = rng.choose(&[
+                    "add", "sub", "mul", "mulh", "mulhu", "mulhsu", "div", "divu", "rem", "remu",
+                    "xor", "and", "or", "sll", "srl", "sra", "slt", "sltu",
+                ]);
+                format!("{op} {rd}, {rs1}, {rs2}")
+            }
+            2 => {
+                let op = rng.choose(&["addi", "xori", "andi", "ori", "slti", "sltiu"]);
+                format!("{op} {rd}, {rs1}, {}", rng.below(4096) as i32 - 2048)
+            }
+            3 => {
+                let op = rng.choose(&["slli", "srli", "srai"]);
+                format!("{op} {rd}, {rs1}, {}", rng.below(32))
+            }
+            4 => {
+                let op =
+                    rng.choose(&["mv", "neg", "not", "seqz", "snez", "sltz", "sgtz", "zext.b"]);
+                format!("{op} {rd}, {rs1}")
+            }
+            5 => match rng.below(2) {
+                0 => format!("li {rd}, {}", rng.value() as i32),
+                _ => format!("lui {rd}, {}", rng.below(1 << 20)),
+            },
+            6 => {
+                let (op, size) = *rng.choose(&[
+                    ("lw", 4),
+                    ("lh", 2),
+                    ("lhu", 2),
+                    ("lb", 1),
+                    ("lbu", 1),
+                    ("sw", 4),
+                    ("sh", 2),
+                    ("sb", 1),
+                ]);
+                let offset = -((size * (rng.below(64 / size) + 1)) as i32);
+                let r = if op.starts_with('s') { rs1 } else { rd };
+                format!("{op} {r}, {offset}(x2)")
+            }
+            _ => {
+                // Conditionally skip the next instruction.
+                let branch = match rng.below(2) {
+                    0 => {
+                        let op = rng.choose(&[
+                            "beq", "bne", "blt", "bge", "bltu", "bgeu", "bgt", "ble", "bgtu",
+                            "bleu",
+                        ]);
+                        format!("{op} {rs1}, {rs2}")
+                    }
+                    _ => {
+                        let op = rng.choose(&["beqz", "bnez", "bltz", "bgez", "blez", "bgtz"]);
+                        format!("{op} {rs1}")
+                    }
+                };
+                format!("{branch}, .Lskip{i}\naddi {rd}, {rd}, 1\n.Lskip{i}:")
+            }
+        };
+        lines.push(instruction);
+    }
+    lines.push("ret".to_string());
+    lines.join("\n") + "\n"
+}
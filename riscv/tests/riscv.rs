@@ -4,13 +4,27 @@ use common::verify_riscv_asm_string;
 use mktemp::Temp;
 use powdr_backend::BackendType;
 use powdr_number::GoldilocksField;
-use powdr_pipeline::{test_util::verify_asm_string, verify::verify, Pipeline, Stage};
-use std::path::PathBuf;
+use powdr_pipeline::{
+    inputs_to_query_callback,
+    test_util::{verify_asm_string, verify_pipeline},
+    verify::verify,
+    Pipeline, Stage,
+};
+use powdr_riscv_executor::{Elem, ExecMode, InstructionHook};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+};
 use test_log::test;
 
 use powdr_riscv::{
-    continuations::{rust_continuations, rust_continuations_dry_run},
-    CoProcessors,
+    continuations::{
+        bootloader::PAGE_SIZE_BYTES_LOG, rust_continuations, rust_continuations_dry_run,
+    },
+    CoProcessors, CustomCoProcessor,
 };
 
 /// Compiles and runs a rust file with continuations, runs the full
@@ -38,7 +52,8 @@ pub fn test_continuations(case: &str) {
         verify(pipeline.output_dir().unwrap(), pipeline.name(), Some(case));
         Ok(())
     };
-    let bootloader_inputs = rust_continuations_dry_run(&mut pipeline);
+    let bootloader_inputs =
+        rust_continuations_dry_run(&mut pipeline, &coprocessors.instruction_hooks()).unwrap();
     rust_continuations(pipeline, pipeline_callback, bootloader_inputs).unwrap();
 }
 
@@ -67,6 +82,88 @@ fn test_poseidon_gl() {
     );
 }
 
+#[test]
+#[ignore = "Too slow"]
+fn test_arith() {
+    let case = "arith_via_coprocessor.rs";
+    verify_riscv_file(case, Default::default(), &CoProcessors::base().with_arith());
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_keccakf() {
+    let case = "keccakf_via_coprocessor";
+    verify_riscv_crate(
+        case,
+        Default::default(),
+        &CoProcessors::base().with_keccakf(),
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_sha256() {
+    let case = "sha256_via_coprocessor";
+    verify_riscv_crate(
+        case,
+        Default::default(),
+        &CoProcessors::base().with_sha256(),
+    );
+}
+
+#[test]
+fn test_custom_coprocessor_executor() {
+    // Writes a = 2**224 * 0xffffffff, b = 2**224 * 3 and c = 7 to 0x10000, calls the
+    // coprocessor and leaves the result in memory.
+    let assembly = r#"
+.globl __runtime_start
+__runtime_start:
+    li a0, 0x10000
+    li t0, 0xffffffff
+    sw t0, 28(a0)
+    li t0, 3
+    sw t0, 60(a0)
+    li t0, 7
+    sw t0, 64(a0)
+    call affine_256_coprocessor
+    ret
+"#;
+    let coprocessors = CoProcessors::base().with(custom_arith_coprocessor());
+    let powdr_asm = powdr_riscv::compiler::compile(
+        [("main".to_string(), assembly.to_string())].into(),
+        &coprocessors,
+        false,
+    );
+    let inputs = inputs_to_query_callback::<GoldilocksField>(vec![]);
+    let (_, memory) = powdr_riscv_executor::execute(
+        &powdr_asm,
+        &inputs,
+        &[],
+        &coprocessors.instruction_hooks(),
+        ExecMode::Fast,
+    );
+
+    let word = |i: u32| memory.get(&(0x10000 + 4 * i)).copied().unwrap_or_default();
+    let (mut a, mut b, mut c) = ([0; 8], [0; 8], [0; 8]);
+    a[7] = 0xffffffff;
+    b[7] = 3;
+    c[0] = 7;
+    let (hi, lo) = powdr_riscv_executor::arith::affine_256(&a, &b, &c);
+    assert_eq!((0..8).map(word).collect::<Vec<_>>(), hi);
+    assert_eq!((8..16).map(word).collect::<Vec<_>>(), lo);
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_custom_coprocessor() {
+    let case = "arith_via_coprocessor.rs";
+    verify_riscv_file(
+        case,
+        Default::default(),
+        &CoProcessors::base().with(custom_arith_coprocessor()),
+    );
+}
+
 #[test]
 #[ignore = "Too slow"]
 fn test_sum() {
@@ -208,7 +305,33 @@ fn test_many_chunks_dry() {
     let mut pipeline = Pipeline::default()
         .from_asm_string(powdr_asm, Some(PathBuf::from(case)))
         .with_prover_inputs(Default::default());
-    rust_continuations_dry_run::<GoldilocksField>(&mut pipeline);
+    rust_continuations_dry_run::<GoldilocksField>(&mut pipeline, &Default::default()).unwrap();
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_print_continuations_dry_run() {
+    // The guest panics, so the dry run returns how it exited.
+    let case = "print.rs";
+    let coprocessors = CoProcessors::base().with_poseidon();
+    let temp_dir = Temp::new_dir().unwrap();
+    let riscv_asm =
+        powdr_riscv::compile_rust_to_riscv_asm(&format!("tests/riscv_data/{case}"), &temp_dir);
+    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, &coprocessors, true);
+
+    let mut pipeline = Pipeline::default()
+        .from_asm_string(powdr_asm, Some(PathBuf::from(case)))
+        .with_prover_inputs(vec![42.into()]);
+    let Err(exit) =
+        rust_continuations_dry_run::<GoldilocksField>(&mut pipeline, &Default::default())
+    else {
+        panic!("The guest should have panicked.");
+    };
+    assert_eq!(exit.code, 101);
+    assert!(exit
+        .panic_message
+        .unwrap()
+        .contains("assertion `left == right` failed"));
 }
 
 #[test]
@@ -242,6 +365,168 @@ fn test_print_rv32_executor() {
     verify_riscv_file(case, Default::default(), &CoProcessors::base());
 }
 
+#[test]
+fn test_free_list_allocator_pages() {
+    // Builds and drops 200 vectors of up to 4 KB each. The bump allocator
+    // touches about a thousand pages for them, while the free list allocator
+    // keeps reusing the same blocks.
+    let iterations = 200u32;
+    let checksum = (0..iterations).fold(0u32, |acc, i| {
+        (0..100 + (i * 37) % 900).fold(acc, |acc, j| acc.wrapping_add(i ^ j))
+    });
+    let touched_pages = |case: &str| {
+        let powdr_asm = compile_riscv_crate(case, &CoProcessors::base());
+        let inputs =
+            inputs_to_query_callback::<GoldilocksField>(vec![iterations.into(), checksum.into()]);
+        let (trace, _) = powdr_riscv_executor::execute(
+            &powdr_asm,
+            &inputs,
+            &[],
+            &Default::default(),
+            ExecMode::Trace,
+        );
+        assert!(trace.exit.unwrap().is_success());
+
+        trace
+            .mem_ops
+            .iter()
+            .map(|op| op.address >> PAGE_SIZE_BYTES_LOG)
+            .collect::<BTreeSet<_>>()
+            .len()
+    };
+
+    // Both crates build the same guest, only with different allocators.
+    let free_list_pages = touched_pages("free_list_allocator");
+    let bump_pages = touched_pages("bump_allocator");
+    assert!(free_list_pages < 64, "{free_list_pages} pages touched");
+    assert!(
+        bump_pages > 8 * free_list_pages,
+        "{bump_pages} pages touched with the bump allocator, \
+         {free_list_pages} with the free list allocator"
+    );
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_sum_trace_witgen() {
+    // The register columns of the main machine are taken from the executor trace.
+    let case = "sum.rs";
+    let temp_dir = Temp::new_dir().unwrap();
+    let riscv_asm =
+        powdr_riscv::compile_rust_to_riscv_asm(&format!("tests/riscv_data/{case}"), &temp_dir);
+    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, &CoProcessors::base(), false);
+    let inputs = [16, 4, 1, 2, 8, 5].iter().map(|&x| x.into()).collect();
+
+    let pipeline = Pipeline::<GoldilocksField>::default()
+        .with_prover_inputs(inputs)
+        .with_output(temp_dir.to_path_buf(), false)
+        .from_asm_string(powdr_asm, Some(PathBuf::from(format!("{case}.asm"))));
+    let pipeline =
+        powdr_riscv::trace_witgen::with_execution_trace(pipeline, &Default::default()).unwrap();
+    verify_pipeline(pipeline);
+}
+
+#[test]
+#[ignore = "Too slow"]
+fn test_print_trace_witgen() {
+    // The guest panics, so the pipeline stops before witness generation.
+    let case = "print.rs";
+    let temp_dir = Temp::new_dir().unwrap();
+    let riscv_asm =
+        powdr_riscv::compile_rust_to_riscv_asm(&format!("tests/riscv_data/{case}"), &temp_dir);
+    let powdr_asm = powdr_riscv::compiler::compile(riscv_asm, &CoProcessors::base(), false);
+
+    let pipeline = Pipeline::<GoldilocksField>::default()
+        .with_prover_inputs(vec![42.into()])
+        .with_output(temp_dir.to_path_buf(), false)
+        .from_asm_string(powdr_asm, Some(PathBuf::from(format!("{case}.asm"))));
+    let (pipeline, exit) =
+        powdr_riscv::trace_witgen::guest_exit(pipeline, &Default::default()).unwrap();
+    assert_eq!(exit.unwrap().code, 101);
+    let Err(errors) =
+        powdr_riscv::trace_witgen::with_execution_trace(pipeline, &Default::default())
+    else {
+        panic!("The guest should have panicked.");
+    };
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].starts_with("Guest panicked with exit code 101: "));
+    assert!(errors[0].contains("assertion `left == right` failed"));
+}
+
+#[test]
+fn test_elf_matches_asm() {
+    // vec_median prints, which uses vtables in the data sections, and function_pointer
+    // passes a code address around.
+    let cases = [
+        (
+            "vec_median",
+            vec![5, 11, 15, 75, 6, 5, 1, 4, 7, 3, 2, 9, 2],
+            0,
+        ),
+        ("function_pointer", vec![2734, 735, 1999], 2),
+    ];
+    let coprocessors = CoProcessors::base();
+    for (case, inputs, expected_index) in cases {
+        let powdr_asm = compile_riscv_crate(case, &coprocessors);
+        let temp_dir = Temp::new_dir().unwrap();
+        let elf = build_riscv_crate_elf(case, &coprocessors, &temp_dir);
+        let program = powdr_riscv::elf::load_elf(&fs::read(elf).unwrap());
+        let elf_powdr_asm = powdr_riscv::compiler::compile_elf(program, &coprocessors, false);
+
+        // The guest succeeds with the right inputs and fails with a wrong expected value.
+        let mut wrong_inputs = inputs.clone();
+        wrong_inputs[expected_index] += 1;
+        for (inputs, success) in [(inputs, true), (wrong_inputs, false)] {
+            let inputs = inputs.into_iter().map(GoldilocksField::from).collect();
+            let inputs = inputs_to_query_callback::<GoldilocksField>(inputs);
+            let exit = |asm: &str| {
+                let hooks = Default::default();
+                let (trace, _) =
+                    powdr_riscv_executor::execute(asm, &inputs, &[], &hooks, ExecMode::Fast);
+                trace.exit.unwrap()
+            };
+            let asm_exit = exit(&powdr_asm);
+            assert_eq!(asm_exit.is_success(), success, "{case}: {asm_exit}");
+            assert_eq!(asm_exit, exit(&elf_powdr_asm), "{case}");
+        }
+    }
+}
+
+#[test]
+fn test_trace_witgen_matches_witgen() {
+    // A small program, so that witness generation is fast without the executor trace.
+    let case = "add";
+    let assembly =
+        std::fs::read_to_string(format!("tests/instruction_tests/generated/{case}.S")).unwrap();
+    let powdr_asm = powdr_riscv::compiler::compile(
+        [(case.to_string(), assembly)].into(),
+        &CoProcessors::base(),
+        false,
+    );
+    let pipeline = || {
+        Pipeline::<GoldilocksField>::default()
+            .with_prover_inputs(Default::default())
+            .from_asm_string(
+                powdr_asm.clone(),
+                Some(PathBuf::from(format!("{case}.asm"))),
+            )
+    };
+
+    let witness = pipeline().generated_witness().unwrap().witness.unwrap();
+    let trace_witness =
+        powdr_riscv::trace_witgen::with_execution_trace(pipeline(), &Default::default())
+            .unwrap()
+            .generated_witness()
+            .unwrap()
+            .witness
+            .unwrap();
+    assert_eq!(witness.len(), trace_witness.len());
+    for ((name, values), (trace_name, trace_values)) in witness.iter().zip(&trace_witness) {
+        assert_eq!(name, trace_name);
+        assert!(values == trace_values, "Column {name} differs.");
+    }
+}
+
 fn verify_riscv_file(case: &str, inputs: Vec<GoldilocksField>, coprocessors: &CoProcessors) {
     let temp_dir = Temp::new_dir().unwrap();
     let riscv_asm =
@@ -265,3 +550,131 @@ fn compile_riscv_crate(case: &str, coprocessors: &CoProcessors) -> String {
     );
     powdr_riscv::compiler::compile(riscv_asm, coprocessors, false)
 }
+
+/// Builds a crate in `riscv_data` as a statically linked ELF executable. The coprocessor
+/// functions are implemented by stubs, since the compiler replaces calls to them anyway.
+fn build_riscv_crate_elf(case: &str, coprocessors: &CoProcessors, output_dir: &Path) -> PathBuf {
+    let toolchain = "+nightly-2023-01-03";
+    let target = "riscv32imac-unknown-none-elf";
+    let target_dir = output_dir.join("cargo_target");
+    let status = Command::new("cargo")
+        .args([
+            toolchain,
+            "rustc",
+            "--release",
+            "-Z",
+            "build-std=core,alloc",
+        ])
+        .args(["--target", target, "--lib", "--crate-type", "staticlib"])
+        .arg("--manifest-path")
+        .arg(format!("tests/riscv_data/{case}/Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let library = target_dir.join(format!("{target}/release/lib{case}.a"));
+
+    // The data has to be above the stack, the code addresses do not matter.
+    // Each stub is a single `ret` instruction.
+    let stubs = coprocessors
+        .runtime_names()
+        .iter()
+        .map(|name| format!("{name} = .; LONG(0x00008067);"))
+        .collect::<Vec<_>>()
+        .join("\n        ");
+    let linker_script = output_dir.join("link.ld");
+    fs::write(
+        &linker_script,
+        format!(
+            r#"
+ENTRY(__runtime_start)
+SECTIONS {{
+    . = 0x10100;
+    .text : {{
+        *(.text .text.*)
+        {stubs}
+    }}
+    .rodata : {{ *(.srodata .srodata.* .rodata .rodata.*) }}
+    .data : {{ *(.sdata .sdata.* .data .data.*) }}
+    .bss : {{ *(.sbss .sbss.* .bss .bss.*) }}
+}}
+"#
+        ),
+    )
+    .unwrap();
+
+    // Use the linker that comes with the toolchain.
+    let sysroot = Command::new("rustc")
+        .args([toolchain, "--print", "sysroot"])
+        .output()
+        .unwrap();
+    let sysroot = PathBuf::from(String::from_utf8(sysroot.stdout).unwrap().trim());
+    let linker = fs::read_dir(sysroot.join("lib/rustlib"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path().join("bin/rust-lld"))
+        .find(|path| path.exists())
+        .expect("rust-lld not found");
+
+    // The relocations are needed to identify code addresses in the data sections.
+    let elf = output_dir.join(format!("{case}.elf"));
+    let status = Command::new(linker)
+        .args(["-flavor", "gnu", "--emit-relocs", "--gc-sections", "-T"])
+        .arg(&linker_script)
+        .arg("-o")
+        .arg(&elf)
+        .arg(&library)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    elf
+}
+
+/// Connects std::arith::Arith like the built-in arith coprocessor does, but
+/// through the API for coprocessors defined outside of the riscv crate.
+fn custom_arith_coprocessor() -> CustomCoProcessor {
+    struct MulAdd256;
+    impl InstructionHook for MulAdd256 {
+        fn execute(&self, args: &[Elem]) -> Vec<Elem> {
+            let limbs = |i: usize| std::array::from_fn(|j| args[8 * i + j].0 as u32);
+            let (hi, lo) = powdr_riscv_executor::arith::affine_256(&limbs(0), &limbs(1), &limbs(2));
+            hi.into_iter().chain(lo).map(Elem::from).collect()
+        }
+    }
+
+    let registers = |n: usize, prefix: &str| {
+        (0..n)
+            .map(|i| format!("{prefix}{i}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let instruction = format!(
+        "instr mul_add_256 {} -> {} = custom_arith.affine_256;\n",
+        registers(24, "A"),
+        registers(16, "B")
+    );
+    let call = format!(
+        "{} <== mul_add_256({});\n",
+        registers(16, "P"),
+        registers(24, "P")
+    );
+    let implementation = (0..24)
+        .map(|i| format!("P{i}, tmp2 <== mload({} + x10);\n", i * 4))
+        .chain(std::iter::once(call.clone()))
+        .chain((0..16).map(|i| format!("mstore {} + x10, P{i};\n", i * 4)))
+        .collect::<String>();
+    let submachine_call = std::iter::once(call)
+        .chain((0..16).map(|i| format!("P{i} <=X= 0;\n")))
+        .collect::<String>();
+
+    CustomCoProcessor::new(
+        "custom_arith",
+        "Arith",
+        "use std::arith::Arith;",
+        &instruction,
+    )
+    .with_runtime_function("affine_256_coprocessor", &implementation)
+    .with_registers(24, 16)
+    .with_submachine_call(&submachine_call)
+    .with_executor_hook("mul_add_256", Arc::new(MulAdd256))
+}
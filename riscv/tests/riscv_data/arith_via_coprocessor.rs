@@ -0,0 +1,62 @@
+#![no_std]
+
+use powdr_riscv_runtime::arith::{ec_add, ec_double, mulmod, SECP256K1_P};
+use powdr_riscv_runtime::coprocessors::affine_256;
+
+#[no_mangle]
+fn main() {
+    // (2**256 - 1) * (2**256 - 1) + (2**256 - 1) = 2**256 * (2**256 - 1)
+    let max = [0xffffffff; 8];
+    assert_eq!(affine_256(&max, &max, &max), (max, [0; 8]));
+
+    // 3000000 * 2000000 + 5000000 = 6000005000000
+    let n = |v| [v, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(
+        affine_256(&n(3000000), &n(2000000), &n(5000000)),
+        ([0; 8], [0xfc2aab40, 0x574, 0, 0, 0, 0, 0, 0])
+    );
+
+    assert_eq!(mulmod(&n(7), &n(8), &n(10)), n(6));
+    // (2**256 - 1)**2 mod p
+    assert_eq!(
+        mulmod(&max, &max, &SECP256K1_P),
+        [
+            0x000e8900, 0x000007a0, 0x00000001, 0x00000000, 0x00000000, 0x00000000, 0x00000000,
+            0x00000000
+        ]
+    );
+
+    // The generator of secp256k1, and 2 * G and 3 * G.
+    let g = (
+        [
+            0x16f81798, 0x59f2815b, 0x2dce28d9, 0x029bfcdb, 0xce870b07, 0x55a06295, 0xf9dcbbac,
+            0x79be667e,
+        ],
+        [
+            0xfb10d4b8, 0x9c47d08f, 0xa6855419, 0xfd17b448, 0x0e1108a8, 0x5da4fbfc, 0x26a3c465,
+            0x483ada77,
+        ],
+    );
+    let g2 = (
+        [
+            0x5c709ee5, 0xabac09b9, 0x8cef3ca7, 0x5c778e4b, 0x95c07cd8, 0x3045406e, 0x41ed7d6d,
+            0xc6047f94,
+        ],
+        [
+            0x50cfe52a, 0x236431a9, 0x3266d0e1, 0xf7f63265, 0x466ceaee, 0xa3c58419, 0xa63dc339,
+            0x1ae168fe,
+        ],
+    );
+    let g3 = (
+        [
+            0xbce036f9, 0x8601f113, 0x836f99b0, 0xb531c845, 0xf89d5229, 0x49344f85, 0x9258c310,
+            0xf9308a01,
+        ],
+        [
+            0x84b8e672, 0x6cb9fd75, 0x34c2231b, 0x6500a999, 0x2a37f356, 0x0fe337e6, 0x632de814,
+            0x388f7b0f,
+        ],
+    );
+    assert_eq!(ec_double(&g.0, &g.1), g2);
+    assert_eq!(ec_add(&g.0, &g.1, &g2.0, &g2.1), g3);
+}
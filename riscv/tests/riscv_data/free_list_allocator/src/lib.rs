@@ -0,0 +1,30 @@
+//! Repeatedly builds and drops vectors of varying length, growing them one
+//! element at a time, and compares a checksum of their contents with the
+//! expected value.
+//!
+//! First argument is the number of vectors, second the expected checksum.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use powdr_riscv_runtime::get_prover_input;
+
+#[no_mangle]
+fn main() {
+    let iterations = get_prover_input(0);
+    let expected = get_prover_input(1);
+
+    let mut checksum = 0u32;
+    for i in 0..iterations {
+        let len = 100 + (i * 37) % 900;
+        let mut vec = Vec::new();
+        for j in 0..len {
+            vec.push(i ^ j);
+        }
+        checksum = vec.iter().fold(checksum, |acc, x| acc.wrapping_add(*x));
+    }
+
+    assert_eq!(checksum, expected);
+}
@@ -0,0 +1,20 @@
+#![no_std]
+
+use powdr_riscv_runtime::coprocessors::keccakf;
+
+#[no_mangle]
+pub fn main() {
+    let mut state = [0u64; 25];
+    keccakf(&mut state);
+    assert_eq!(state[0], 0xf1258f7940e1dde7);
+    assert_eq!(state[24], 0xeaf1ff7b5ceca249);
+
+    // Compare against the software implementation on a few more states.
+    for i in 0..25 {
+        state[i] ^= (i as u64).wrapping_mul(0x9e3779b97f4a7c15);
+        let mut expected = state;
+        tiny_keccak::keccakf(&mut expected);
+        keccakf(&mut state);
+        assert_eq!(state, expected);
+    }
+}
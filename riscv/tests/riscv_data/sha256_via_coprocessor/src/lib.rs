@@ -0,0 +1,27 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use powdr_riscv_runtime::sha256::Sha256;
+use sha2::Digest;
+
+#[no_mangle]
+pub fn main() {
+    assert_eq!(
+        Sha256::digest(b"abc"),
+        [
+            0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae,
+            0x22, 0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61,
+            0xf2, 0x00, 0x15, 0xad,
+        ]
+    );
+
+    // Compare against the software implementation for messages around
+    // the block boundaries.
+    for len in [0, 1, 55, 56, 63, 64, 65, 119, 120, 200] {
+        let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        let expected = sha2::Sha256::digest(&data);
+        assert_eq!(Sha256::digest(&data), expected.as_slice());
+    }
+}
@@ -4,7 +4,17 @@
 let len = [];
 
 /// Evaluates to the array [f(0), f(1), ..., f(length - 1)].
-let new = |length, f| std::utils::fold(length, f, [], |acc, e| (acc + [e]));
+let new = |length, f| new_range(0, length, f);
+
+/// Evaluates to the array [f(start), f(start + 1), ..., f(end - 1)].
+/// The range is split in halves instead of appending one element at a time,
+/// which keeps the evaluation of long arrays (e.g. of constraints) fast.
+let new_range = |start, end, f|
+    if end - start <= 1 {
+        if end - start == 1 { [f(start)] } else { [] }
+    } else {
+        new_range(start, (start + end) / 2, f) + new_range((start + end) / 2, end, f)
+    };
 
 /// Evaluates to the array [f(arr[0]), f(arr[1]), ..., f(arr[len(arr) - 1])].
 let map = |arr, f| new(len(arr), |i| f(arr[i]));
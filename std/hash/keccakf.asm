@@ -0,0 +1,98 @@
+use std::array;
+use std::utils::force_bool;
+use std::utils::sum;
+
+// Implements the Keccak-f[1600] permutation by decomposing the state into bits.
+machine KeccakF(LASTBLOCK, operation_id) {
+
+    // Applies the permutation to a state of 25 64-bit lanes. Each lane is passed
+    // as two 32-bit words in little-endian order, i.e. the lane at position (x, y)
+    // is given by the words 2 * (x + 5 * y) (low) and 2 * (x + 5 * y) + 1 (high).
+    operation keccakf<0> input[0], input[1], input[2], input[3], input[4], input[5], input[6], input[7], input[8], input[9], input[10], input[11], input[12], input[13], input[14], input[15], input[16], input[17], input[18], input[19], input[20], input[21], input[22], input[23], input[24], input[25], input[26], input[27], input[28], input[29], input[30], input[31], input[32], input[33], input[34], input[35], input[36], input[37], input[38], input[39], input[40], input[41], input[42], input[43], input[44], input[45], input[46], input[47], input[48], input[49] -> output[0], output[1], output[2], output[3], output[4], output[5], output[6], output[7], output[8], output[9], output[10], output[11], output[12], output[13], output[14], output[15], output[16], output[17], output[18], output[19], output[20], output[21], output[22], output[23], output[24], output[25], output[26], output[27], output[28], output[29], output[30], output[31], output[32], output[33], output[34], output[35], output[36], output[37], output[38], output[39], output[40], output[41], output[42], output[43], output[44], output[45], output[46], output[47], output[48], output[49];
+
+    col witness operation_id;
+
+    // Each row computes one round, the last row of the block contains the result.
+    constant %rounds = 24;
+    constant %rowsPerHash = %rounds + 1;
+
+    pol constant FIRSTBLOCK(i) { match i % %rowsPerHash {
+        0 => 1,
+        _ => 0
+    }};
+
+    pol constant LASTBLOCK(i) { match i % %rowsPerHash {
+        %rowsPerHash - 1 => 1,
+        _ => 0
+    }};
+
+    // Like LASTBLOCK, but also 1 in the last row of the table
+    // Specified this way because we can't access the degree in the match statement
+    pol constant LAST = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]* + [1];
+
+    // The round constants of the iota step, one column per bit.
+    // Only the bits 0, 1, 3, 7, 15, 31 and 63 are ever set.
+    pol constant RC_BIT_0 = [1, 0, 0, 0, 1, 1, 1, 1, 0, 0, 1, 0, 1, 1, 1, 1, 0, 0, 0, 0, 1, 0, 1, 0, 0]*;
+    pol constant RC_BIT_1 = [0, 1, 1, 0, 1, 0, 0, 0, 1, 0, 0, 1, 1, 1, 0, 1, 1, 0, 1, 1, 0, 0, 0, 0, 0]*;
+    pol constant RC_BIT_3 = [0, 0, 1, 0, 1, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 1, 1, 0, 0, 0, 1, 0]*;
+    pol constant RC_BIT_7 = [0, 1, 1, 0, 1, 0, 1, 0, 1, 1, 0, 0, 1, 1, 1, 0, 0, 1, 0, 0, 1, 1, 0, 0, 0]*;
+    pol constant RC_BIT_15 = [0, 1, 1, 1, 1, 0, 1, 1, 0, 0, 1, 0, 1, 0, 1, 1, 1, 0, 1, 0, 1, 1, 0, 1, 0]*;
+    pol constant RC_BIT_31 = [0, 0, 0, 1, 0, 1, 1, 0, 0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 1, 0]*;
+    pol constant RC_BIT_63 = [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 0, 1, 1, 1, 0, 1, 0]*;
+
+    // The initial state, packed into 32-bit words
+    // (constrained to be equal to the state in the first row and
+    // then repeated until the end of the block)
+    pol commit input[50];
+
+    // The state of the permutation packed into 32-bit words.
+    // In the last row of the block, this is the result.
+    pol commit output[50];
+
+    // The bits of the state of the permutation.
+    // Bit z of the lane at position (x, y) is a[(x + 5 * y) * 64 + z].
+    pol commit a[1600];
+    array::map(a, |bit| force_bool(bit));
+    let bit = |x, y, z| a[((x % 5) + 5 * (y % 5)) * 64 + (z % 64)];
+
+    array::new(50, |w| output[w] = sum(32, |k| 2**k * a[32 * w + k]));
+
+    let xor = |x, y| x + y - 2 * x * y;
+
+    // Theta: the parities of the columns ...
+    pol commit c[320];
+    let parity = |x, z| c[(x % 5) * 64 + (z % 64)];
+    array::new(320, |i| c[i] = xor(xor(xor(xor(bit(i / 64, 0, i), bit(i / 64, 1, i)), bit(i / 64, 2, i)), bit(i / 64, 3, i)), bit(i / 64, 4, i)));
+
+    // ... are added to the neighbouring columns.
+    pol commit a_prime[1600];
+    array::new(1600, |i| a_prime[i] = xor(a[i], xor(parity(i / 64 + 4, i), parity(i / 64 + 1, i + 63))));
+
+    // Rho and pi: the lane at position (x, y) is rotated and moved to (y, 2x + 3y).
+    // This inverts the movement, i.e. b(x, y, z) refers to a bit of a_prime.
+    let RHO_OFFSETS = [0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56, 14];
+    let source = |x, y| ((x % 5) + 3 * (y % 5)) % 5 + 5 * (x % 5);
+    let b = |x, y, z| a_prime[source(x, y) * 64 + (z + 64 - RHO_OFFSETS[source(x, y)]) % 64];
+
+    // Chi and iota compute the state of the next row.
+    let chi = |x, y, z| xor(b(x, y, z), (1 - b(x + 1, y, z)) * b(x + 2, y, z));
+    // Iota only affects the lane at position (0, 0).
+    let iota = |z, v| match z {
+        0 => xor(v, RC_BIT_0),
+        1 => xor(v, RC_BIT_1),
+        3 => xor(v, RC_BIT_3),
+        7 => xor(v, RC_BIT_7),
+        15 => xor(v, RC_BIT_15),
+        31 => xor(v, RC_BIT_31),
+        63 => xor(v, RC_BIT_63),
+        _ => v
+    };
+    let next = |x, y, z| if x + 5 * y == 0 { iota(z, chi(x, y, z)) } else { chi(x, y, z) };
+
+    let equal_unless_last = |a, b| (1 - LAST) * (a - b) = 0;
+    array::new(1600, |i| equal_unless_last(a[i]', next((i / 64) % 5, i / 320, i % 64)));
+    array::map(input, |x| equal_unless_last(x, x'));
+
+    let equal_on_first_block = |a, b| FIRSTBLOCK * (a - b) = 0;
+    array::new(50, |i| equal_on_first_block(input[i], output[i]));
+}
@@ -0,0 +1,118 @@
+use std::array;
+use std::utils::force_bool;
+use std::utils::sum;
+
+// Implements the SHA-256 compression function by decomposing the words into bits.
+machine Sha256(LASTBLOCK, operation_id) {
+
+    // Updates the state (the first 8 words) with a message block (the remaining
+    // 16 words). The message words are expected to be decoded from big-endian
+    // bytes already, as in the specification.
+    operation compress<0> input[0], input[1], input[2], input[3], input[4], input[5], input[6], input[7], input[8], input[9], input[10], input[11], input[12], input[13], input[14], input[15], input[16], input[17], input[18], input[19], input[20], input[21], input[22], input[23] -> output[0], output[1], output[2], output[3], output[4], output[5], output[6], output[7];
+
+    col witness operation_id;
+
+    // Each of the first 64 rows computes one round, the next row adds the
+    // initial state and the last row of the block contains the result.
+    constant %rounds = 64;
+    constant %rowsPerHash = %rounds + 2;
+
+    pol constant FIRSTBLOCK(i) { match i % %rowsPerHash {
+        0 => 1,
+        _ => 0
+    }};
+
+    pol constant LASTBLOCK(i) { match i % %rowsPerHash {
+        %rowsPerHash - 1 => 1,
+        _ => 0
+    }};
+
+    // Like LASTBLOCK, but also 1 in the last row of the table
+    // Specified this way because we can't access the degree in the match statement
+    pol constant LAST = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]* + [1];
+
+    // The rows in which a round is computed and the row in which the initial
+    // state is added.
+    pol constant ROUND = [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0]*;
+    pol constant ADD = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0]*;
+
+    // The round constants.
+    pol constant K = [0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2, 0, 0]*;
+
+    // The initial state followed by the message block
+    // (constrained to be equal to the working variables and the message
+    // schedule in the first row and then repeated until the end of the block)
+    pol commit input[24];
+
+    // The working variables a, ..., h.
+    // In the last row of the block, this is the result.
+    pol commit output[8];
+
+    // The bits of the working variables.
+    // Bit k of the j-th working variable is s[32 * j + k].
+    pol commit s[256];
+    array::map(s, |bit| force_bool(bit));
+    let bit = |j, k| s[32 * j + k % 32];
+    let bit_next = |j, k| s[32 * j + k % 32]';
+
+    array::new(8, |j| output[j] = sum(32, |k| 2**k * bit(j, k)));
+
+    // The bits of the message schedule, in row r these are the words W[r], ..., W[r + 15].
+    // Bit k of W[r + i] is w[32 * i + k].
+    pol commit w[512];
+    array::map(w, |bit| force_bool(bit));
+    let w_bit = |i, k| w[32 * i + k % 32];
+    let w_shr_bit = |i, k| if k < 32 { w_bit(i, k) } else { 0 };
+
+    let xor = |x, y| x + y - 2 * x * y;
+    let xor3 = |x, y, z| xor(xor(x, y), z);
+
+    let W = sum(32, |k| 2**k * w_bit(0, k));
+    let sigma0 = sum(32, |k| 2**k * xor3(w_bit(1, k + 7), w_bit(1, k + 18), w_shr_bit(1, k + 3)));
+    let sigma1 = sum(32, |k| 2**k * xor3(w_bit(14, k + 17), w_bit(14, k + 19), w_shr_bit(14, k + 10)));
+
+    let Sigma0 = sum(32, |k| 2**k * xor3(bit(0, k + 2), bit(0, k + 13), bit(0, k + 22)));
+    let Sigma1 = sum(32, |k| 2**k * xor3(bit(4, k + 6), bit(4, k + 11), bit(4, k + 25)));
+    let Ch = sum(32, |k| 2**k * (bit(4, k) * bit(5, k) + (1 - bit(4, k)) * bit(6, k)));
+    let Maj = sum(32, |k| 2**k * (bit(0, k) * bit(1, k) + bit(0, k) * bit(2, k) + bit(1, k) * bit(2, k) - 2 * bit(0, k) * bit(1, k) * bit(2, k)));
+
+    // The sums are computed modulo 2**32, the carries are decomposed into bits.
+    // The new value of a is the sum of 7 words, the new value of e of 6 words
+    // and the next word of the message schedule of 4 words.
+    pol commit carry_a[3], carry_e[3], carry_w[2];
+    array::map(carry_a, |bit| force_bool(bit));
+    array::map(carry_e, |bit| force_bool(bit));
+    array::map(carry_w, |bit| force_bool(bit));
+    array::map(carry_a, |c| (1 - ROUND) * c = 0);
+    array::map(carry_e, |c| (1 - ROUND) * c = 0);
+    array::map(carry_w, |c| LASTBLOCK * c = 0);
+
+    // Round: T1 = h + Sigma1(e) + Ch(e, f, g) + K + W and T2 = Sigma0(a) + Maj(a, b, c),
+    // the new working variables are (T1 + T2, a, b, c, d + T1, e, f, g).
+    let T1 = output[7] + Sigma1 + Ch + K + W;
+    let T2 = Sigma0 + Maj;
+    // Transitions are not enforced in the last row of the table, as it is followed by the first row.
+    let round_step = |a, b| ROUND * (1 - LAST) * (a - b) = 0;
+    round_step(sum(32, |k| 2**k * bit_next(0, k)) + 2**32 * sum(3, |i| 2**i * carry_a[i]), T1 + T2);
+    round_step(sum(32, |k| 2**k * bit_next(4, k)) + 2**32 * sum(3, |i| 2**i * carry_e[i]), output[3] + T1);
+    array::new(96, |i| round_step(s[i + 32]', s[i]));
+    array::new(96, |i| round_step(s[i + 160]', s[i + 128]));
+
+    // After the last round, the initial state is added to the working variables.
+    pol commit carry_add[8];
+    array::map(carry_add, |bit| force_bool(bit));
+    array::map(carry_add, |c| (1 - ADD) * c = 0);
+    let add_step = |a, b| ADD * (1 - LAST) * (a - b) = 0;
+    array::new(8, |j| add_step(sum(32, |k| 2**k * bit_next(j, k)) + 2**32 * carry_add[j], output[j] + input[j]));
+
+    // The message schedule moves by one word in every row:
+    // W[r + 16] = sigma1(W[r + 14]) + W[r + 9] + sigma0(W[r + 1]) + W[r].
+    let equal_unless_last = |a, b| (1 - LAST) * (a - b) = 0;
+    array::new(480, |i| equal_unless_last(w[i]', w[i + 32]));
+    equal_unless_last(sum(32, |k| 2**k * w[480 + k]') + 2**32 * sum(2, |i| 2**i * carry_w[i]), sigma1 + sum(32, |k| 2**k * w_bit(9, k)) + sigma0 + W);
+    array::map(input, |x| equal_unless_last(x, x'));
+
+    let equal_on_first_block = |a, b| FIRSTBLOCK * (a - b) = 0;
+    array::new(8, |j| equal_on_first_block(input[j], output[j]));
+    array::new(16, |i| equal_on_first_block(input[8 + i], sum(32, |k| 2**k * w_bit(i, k))));
+}
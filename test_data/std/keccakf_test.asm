@@ -0,0 +1,382 @@
+use std::hash::keccakf::KeccakF;
+
+machine Main {
+    degree 256;
+
+    reg pc[@pc];
+    reg X0[<=];
+    reg X1[<=];
+    reg X2[<=];
+    reg X3[<=];
+    reg X4[<=];
+    reg X5[<=];
+    reg X6[<=];
+    reg X7[<=];
+    reg X8[<=];
+    reg X9[<=];
+    reg X10[<=];
+    reg X11[<=];
+    reg X12[<=];
+    reg X13[<=];
+    reg X14[<=];
+    reg X15[<=];
+    reg X16[<=];
+    reg X17[<=];
+    reg X18[<=];
+    reg X19[<=];
+    reg X20[<=];
+    reg X21[<=];
+    reg X22[<=];
+    reg X23[<=];
+    reg X24[<=];
+    reg X25[<=];
+    reg X26[<=];
+    reg X27[<=];
+    reg X28[<=];
+    reg X29[<=];
+    reg X30[<=];
+    reg X31[<=];
+    reg X32[<=];
+    reg X33[<=];
+    reg X34[<=];
+    reg X35[<=];
+    reg X36[<=];
+    reg X37[<=];
+    reg X38[<=];
+    reg X39[<=];
+    reg X40[<=];
+    reg X41[<=];
+    reg X42[<=];
+    reg X43[<=];
+    reg X44[<=];
+    reg X45[<=];
+    reg X46[<=];
+    reg X47[<=];
+    reg X48[<=];
+    reg X49[<=];
+    reg Y0[<=];
+    reg Y1[<=];
+    reg Y2[<=];
+    reg Y3[<=];
+    reg Y4[<=];
+    reg Y5[<=];
+    reg Y6[<=];
+    reg Y7[<=];
+    reg Y8[<=];
+    reg Y9[<=];
+    reg Y10[<=];
+    reg Y11[<=];
+    reg Y12[<=];
+    reg Y13[<=];
+    reg Y14[<=];
+    reg Y15[<=];
+    reg Y16[<=];
+    reg Y17[<=];
+    reg Y18[<=];
+    reg Y19[<=];
+    reg Y20[<=];
+    reg Y21[<=];
+    reg Y22[<=];
+    reg Y23[<=];
+    reg Y24[<=];
+    reg Y25[<=];
+    reg Y26[<=];
+    reg Y27[<=];
+    reg Y28[<=];
+    reg Y29[<=];
+    reg Y30[<=];
+    reg Y31[<=];
+    reg Y32[<=];
+    reg Y33[<=];
+    reg Y34[<=];
+    reg Y35[<=];
+    reg Y36[<=];
+    reg Y37[<=];
+    reg Y38[<=];
+    reg Y39[<=];
+    reg Y40[<=];
+    reg Y41[<=];
+    reg Y42[<=];
+    reg Y43[<=];
+    reg Y44[<=];
+    reg Y45[<=];
+    reg Y46[<=];
+    reg Y47[<=];
+    reg Y48[<=];
+    reg Y49[<=];
+    reg S0;
+    reg S1;
+    reg S2;
+    reg S3;
+    reg S4;
+    reg S5;
+    reg S6;
+    reg S7;
+    reg S8;
+    reg S9;
+    reg S10;
+    reg S11;
+    reg S12;
+    reg S13;
+    reg S14;
+    reg S15;
+    reg S16;
+    reg S17;
+    reg S18;
+    reg S19;
+    reg S20;
+    reg S21;
+    reg S22;
+    reg S23;
+    reg S24;
+    reg S25;
+    reg S26;
+    reg S27;
+    reg S28;
+    reg S29;
+    reg S30;
+    reg S31;
+    reg S32;
+    reg S33;
+    reg S34;
+    reg S35;
+    reg S36;
+    reg S37;
+    reg S38;
+    reg S39;
+    reg S40;
+    reg S41;
+    reg S42;
+    reg S43;
+    reg S44;
+    reg S45;
+    reg S46;
+    reg S47;
+    reg S48;
+    reg S49;
+
+    KeccakF keccakf;
+
+    instr keccakf X0, X1, X2, X3, X4, X5, X6, X7, X8, X9, X10, X11, X12, X13, X14, X15, X16, X17, X18, X19, X20, X21, X22, X23, X24, X25, X26, X27, X28, X29, X30, X31, X32, X33, X34, X35, X36, X37, X38, X39, X40, X41, X42, X43, X44, X45, X46, X47, X48, X49 -> Y0, Y1, Y2, Y3, Y4, Y5, Y6, Y7, Y8, Y9, Y10, Y11, Y12, Y13, Y14, Y15, Y16, Y17, Y18, Y19, Y20, Y21, Y22, Y23, Y24, Y25, Y26, Y27, Y28, Y29, Y30, Y31, Y32, Y33, Y34, Y35, Y36, Y37, Y38, Y39, Y40, Y41, Y42, Y43, Y44, Y45, Y46, Y47, Y48, Y49 = keccakf.keccakf;
+
+    instr assert_eq X0, X1 {
+        X0 = X1
+    }
+
+    function main {
+
+        // Test vectors from the Keccak team's KeccakF-1600-IntermediateValues.txt
+        S0, S1, S2, S3, S4, S5, S6, S7, S8, S9, S10, S11, S12, S13, S14, S15, S16, S17, S18, S19, S20, S21, S22, S23, S24, S25, S26, S27, S28, S29, S30, S31, S32, S33, S34, S35, S36, S37, S38, S39, S40, S41, S42, S43, S44, S45, S46, S47, S48, S49 <== keccakf(0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0);
+        assert_eq S0, 0x40e1dde7;
+        assert_eq S1, 0xf1258f79;
+        assert_eq S2, 0x33c0478a;
+        assert_eq S3, 0x84d5ccf9;
+        assert_eq S4, 0xa65aa9ee;
+        assert_eq S5, 0xd598261e;
+        assert_eq S6, 0x6f80494d;
+        assert_eq S7, 0xbd154730;
+        assert_eq S8, 0x6253d057;
+        assert_eq S9, 0x8b284e05;
+        assert_eq S10, 0x7f8e6fd4;
+        assert_eq S11, 0xff97a42d;
+        assert_eq S12, 0xa44647c4;
+        assert_eq S13, 0x90fee5a0;
+        assert_eq S14, 0xd6192e76;
+        assert_eq S15, 0x8c5bda0c;
+        assert_eq S16, 0x1b19059c;
+        assert_eq S17, 0xad30a6f7;
+        assert_eq S18, 0xd08ffc64;
+        assert_eq S19, 0x30935ab7;
+        assert_eq S20, 0x2317d635;
+        assert_eq S21, 0xeb5aa93f;
+        assert_eq S22, 0xd712103;
+        assert_eq S23, 0xa9a6e626;
+        assert_eq S24, 0xdbcf555f;
+        assert_eq S25, 0x81a57c16;
+        assert_eq S26, 0x347c826;
+        assert_eq S27, 0x43b831cd;
+        assert_eq S28, 0x11a5569f;
+        assert_eq S29, 0x1f22f1a;
+        assert_eq S30, 0x21d9ae61;
+        assert_eq S31, 0x5e5635a;
+        assert_eq S32, 0x8cc970f2;
+        assert_eq S33, 0x64befef2;
+        assert_eq S34, 0x7bc46611;
+        assert_eq S35, 0x61367095;
+        assert_eq S36, 0x4fd00ecb;
+        assert_eq S37, 0xb87c5a55;
+        assert_eq S38, 0x1ccf32c8;
+        assert_eq S39, 0x8c3ee88a;
+        assert_eq S40, 0xae3a2614;
+        assert_eq S41, 0x940c7922;
+        assert_eq S42, 0xa2c509e4;
+        assert_eq S43, 0x1841f924;
+        assert_eq S44, 0xe70465c2;
+        assert_eq S45, 0x16f53526;
+        assert_eq S46, 0x7f30a13b;
+        assert_eq S47, 0x75f644e9;
+        assert_eq S48, 0x5ceca249;
+        assert_eq S49, 0xeaf1ff7b;
+
+        // Applying the permutation again to the result
+        S0, S1, S2, S3, S4, S5, S6, S7, S8, S9, S10, S11, S12, S13, S14, S15, S16, S17, S18, S19, S20, S21, S22, S23, S24, S25, S26, S27, S28, S29, S30, S31, S32, S33, S34, S35, S36, S37, S38, S39, S40, S41, S42, S43, S44, S45, S46, S47, S48, S49 <== keccakf(S0, S1, S2, S3, S4, S5, S6, S7, S8, S9, S10, S11, S12, S13, S14, S15, S16, S17, S18, S19, S20, S21, S22, S23, S24, S25, S26, S27, S28, S29, S30, S31, S32, S33, S34, S35, S36, S37, S38, S39, S40, S41, S42, S43, S44, S45, S46, S47, S48, S49);
+        assert_eq S0, 0xf96ecb3c;
+        assert_eq S1, 0x2d5c954d;
+        assert_eq S2, 0x7057b56d;
+        assert_eq S3, 0x6a332cd0;
+        assert_eq S4, 0x70d76b6c;
+        assert_eq S5, 0x93d8d12;
+        assert_eq S6, 0x5569d094;
+        assert_eq S7, 0x8a20d9b2;
+        assert_eq S8, 0xe5e7f156;
+        assert_eq S9, 0x4f9c4f99;
+        assert_eq S10, 0xda65fb38;
+        assert_eq S11, 0xf957b9a2;
+        assert_eq S12, 0x1275af0d;
+        assert_eq S13, 0x85773dae;
+        assert_eq S14, 0xc3d810f7;
+        assert_eq S15, 0xfaf4f247;
+        assert_eq S16, 0xf79a8759;
+        assert_eq S17, 0x1f1b9ee6;
+        assert_eq S18, 0xee98b425;
+        assert_eq S19, 0xe4fecc0f;
+        assert_eq S20, 0xb9ce68a1;
+        assert_eq S21, 0x68ce61b6;
+        assert_eq S22, 0xba8f974f;
+        assert_eq S23, 0xdeea66c4;
+        assert_eq S24, 0x6eafb1f5;
+        assert_eq S25, 0x33c43d83;
+        assert_eq S26, 0x2719dbd9;
+        assert_eq S27, 0xe0065404;
+        assert_eq S28, 0x9831265;
+        assert_eq S29, 0x7cf8a9f0;
+        assert_eq S30, 0xbf174743;
+        assert_eq S31, 0xfd5449a6;
+        assert_eq S32, 0xd8994b40;
+        assert_eq S33, 0x97ddad33;
+        assert_eq S34, 0x5d0be774;
+        assert_eq S35, 0x48ead5fc;
+        assert_eq S36, 0x55b7b03c;
+        assert_eq S37, 0xe3b8c8ee;
+        assert_eq S38, 0x649e42e9;
+        assert_eq S39, 0x91a0226e;
+        assert_eq S40, 0xe7badd7b;
+        assert_eq S41, 0x900e3129;
+        assert_eq S42, 0xfaa3cce8;
+        assert_eq S43, 0x202a9ec5;
+        assert_eq S44, 0x4e1c3db6;
+        assert_eq S45, 0x5b340246;
+        assert_eq S46, 0xa44c1059;
+        assert_eq S47, 0x609f4e62;
+        assert_eq S48, 0x6a8fbf5c;
+        assert_eq S49, 0x20d06cd2;
+
+        // The state 0, 1, 2, ..., 49 (as 32-bit words)
+        S0, S1, S2, S3, S4, S5, S6, S7, S8, S9, S10, S11, S12, S13, S14, S15, S16, S17, S18, S19, S20, S21, S22, S23, S24, S25, S26, S27, S28, S29, S30, S31, S32, S33, S34, S35, S36, S37, S38, S39, S40, S41, S42, S43, S44, S45, S46, S47, S48, S49 <== keccakf(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49);
+        assert_eq S0, 0x171d70f5;
+        assert_eq S1, 0x410a76bb;
+        assert_eq S2, 0x42f3253f;
+        assert_eq S3, 0xca4f6a1;
+        assert_eq S4, 0xe0ca371a;
+        assert_eq S5, 0xfcf07117;
+        assert_eq S6, 0x6475d44f;
+        assert_eq S7, 0xcc99b642;
+        assert_eq S8, 0x7d74d453;
+        assert_eq S9, 0x706b05fb;
+        assert_eq S10, 0x58ad5a84;
+        assert_eq S11, 0xe10a3382;
+        assert_eq S12, 0x40b2eaa1;
+        assert_eq S13, 0xee160e05;
+        assert_eq S14, 0x8b76b5a1;
+        assert_eq S15, 0xab4aaf37;
+        assert_eq S16, 0x9774d56;
+        assert_eq S17, 0x685a38d1;
+        assert_eq S18, 0xabb2dbc7;
+        assert_eq S19, 0xcf3e043c;
+        assert_eq S20, 0xbb77a262;
+        assert_eq S21, 0x854abfd6;
+        assert_eq S22, 0xa9dbbde;
+        assert_eq S23, 0xe0539413;
+        assert_eq S24, 0x8de630b1;
+        assert_eq S25, 0x698d109e;
+        assert_eq S26, 0xc62ecd16;
+        assert_eq S27, 0x5430d800;
+        assert_eq S28, 0x36524257;
+        assert_eq S29, 0xaecd2d10;
+        assert_eq S30, 0x674146c4;
+        assert_eq S31, 0x1b2bdb52;
+        assert_eq S32, 0x1aa3764d;
+        assert_eq S33, 0x9cc20b7e;
+        assert_eq S34, 0xbbcb1371;
+        assert_eq S35, 0xe710ddf4;
+        assert_eq S36, 0x42a10f82;
+        assert_eq S37, 0x3fa8d05f;
+        assert_eq S38, 0xb207c2a8;
+        assert_eq S39, 0xa3c35da;
+        assert_eq S40, 0x61259530;
+        assert_eq S41, 0xf598a3e7;
+        assert_eq S42, 0x3a42e3cb;
+        assert_eq S43, 0xcaa4ba73;
+        assert_eq S44, 0xa9d3e647;
+        assert_eq S45, 0x4a889c59;
+        assert_eq S46, 0x3f61fed2;
+        assert_eq S47, 0xde74b0cf;
+        assert_eq S48, 0x27ab7e53;
+        assert_eq S49, 0xec6a63e1;
+
+        // All bits set
+        S0, S1, S2, S3, S4, S5, S6, S7, S8, S9, S10, S11, S12, S13, S14, S15, S16, S17, S18, S19, S20, S21, S22, S23, S24, S25, S26, S27, S28, S29, S30, S31, S32, S33, S34, S35, S36, S37, S38, S39, S40, S41, S42, S43, S44, S45, S46, S47, S48, S49 <== keccakf(0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff, 0xffffffff);
+        assert_eq S0, 0xba6817c4;
+        assert_eq S1, 0x9f00f21b;
+        assert_eq S2, 0x21af5e78;
+        assert_eq S3, 0xcdf5aa0d;
+        assert_eq S4, 0x24095b97;
+        assert_eq S5, 0xd6539abf;
+        assert_eq S6, 0x10f8228;
+        assert_eq S7, 0x8bb6f30a;
+        assert_eq S8, 0x547331d;
+        assert_eq S9, 0xf0f711ba;
+        assert_eq S10, 0x58eb182f;
+        assert_eq S11, 0x4f443305;
+        assert_eq S12, 0x9055207c;
+        assert_eq S13, 0x2213b79d;
+        assert_eq S14, 0xca4fb490;
+        assert_eq S15, 0xeb5e5b55;
+        assert_eq S16, 0xa299b5d4;
+        assert_eq S17, 0xbfaeb81;
+        assert_eq S18, 0x1a65ed48;
+        assert_eq S19, 0x9e5d924f;
+        assert_eq S20, 0x33b7bfb3;
+        assert_eq S21, 0x4650c5;
+        assert_eq S22, 0x84d7ab05;
+        assert_eq S23, 0xddad454b;
+        assert_eq S24, 0x3e82921;
+        assert_eq S25, 0xf03ce565;
+        assert_eq S26, 0xc6728660;
+        assert_eq S27, 0xce442e92;
+        assert_eq S28, 0xb37ddcd3;
+        assert_eq S29, 0x1a9ce5e4;
+        assert_eq S30, 0x7cea6f0e;
+        assert_eq S31, 0xf63b60e2;
+        assert_eq S32, 0xa665bfad;
+        assert_eq S33, 0xcc4cc7fc;
+        assert_eq S34, 0x54a2285d;
+        assert_eq S35, 0x40cf4eba;
+        assert_eq S36, 0x42304213;
+        assert_eq S37, 0x2725f1f1;
+        assert_eq S38, 0xe6fbad9b;
+        assert_eq S39, 0x554d327d;
+        assert_eq S40, 0xcbc8bdc2;
+        assert_eq S41, 0x19866a26;
+        assert_eq S42, 0xaf02c7f5;
+        assert_eq S43, 0xe8c3c28f;
+        assert_eq S44, 0x12a665ae;
+        assert_eq S45, 0xc6bc1f35;
+        assert_eq S46, 0xa5dc86ce;
+        assert_eq S47, 0xcaa831f1;
+        assert_eq S48, 0x1ca4b9b0;
+        assert_eq S49, 0x3f82afe9;
+
+        return;
+    }
+}
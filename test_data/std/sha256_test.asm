@@ -0,0 +1,84 @@
+use std::hash::sha256::Sha256;
+
+machine Main {
+    degree 512;
+
+    reg pc[@pc];
+    reg X0[<=];
+    reg X1[<=];
+    reg X2[<=];
+    reg X3[<=];
+    reg X4[<=];
+    reg X5[<=];
+    reg X6[<=];
+    reg X7[<=];
+    reg X8[<=];
+    reg X9[<=];
+    reg X10[<=];
+    reg X11[<=];
+    reg X12[<=];
+    reg X13[<=];
+    reg X14[<=];
+    reg X15[<=];
+    reg X16[<=];
+    reg X17[<=];
+    reg X18[<=];
+    reg X19[<=];
+    reg X20[<=];
+    reg X21[<=];
+    reg X22[<=];
+    reg X23[<=];
+    reg Y0[<=];
+    reg Y1[<=];
+    reg Y2[<=];
+    reg Y3[<=];
+    reg Y4[<=];
+    reg Y5[<=];
+    reg Y6[<=];
+    reg Y7[<=];
+    reg S0;
+    reg S1;
+    reg S2;
+    reg S3;
+    reg S4;
+    reg S5;
+    reg S6;
+    reg S7;
+
+    Sha256 sha256;
+
+    instr sha256_compress X0, X1, X2, X3, X4, X5, X6, X7, X8, X9, X10, X11, X12, X13, X14, X15, X16, X17, X18, X19, X20, X21, X22, X23 -> Y0, Y1, Y2, Y3, Y4, Y5, Y6, Y7 = sha256.compress;
+
+    instr assert_eq X0, X1 {
+        X0 = X1
+    }
+
+    function main {
+
+        // Test vectors from FIPS 180-2: "abc" ...
+        S0, S1, S2, S3, S4, S5, S6, S7 <== sha256_compress(0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19, 0x61626380, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00000018);
+        assert_eq S0, 0xba7816bf;
+        assert_eq S1, 0x8f01cfea;
+        assert_eq S2, 0x414140de;
+        assert_eq S3, 0x5dae2223;
+        assert_eq S4, 0xb00361a3;
+        assert_eq S5, 0x96177a9c;
+        assert_eq S6, 0xb410ff61;
+        assert_eq S7, 0xf20015ad;
+
+        // ... and a message of two blocks, where the second one is compressed
+        // into the result of the first one.
+        S0, S1, S2, S3, S4, S5, S6, S7 <== sha256_compress(0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19, 0x61626364, 0x62636465, 0x63646566, 0x64656667, 0x65666768, 0x66676869, 0x6768696a, 0x68696a6b, 0x696a6b6c, 0x6a6b6c6d, 0x6b6c6d6e, 0x6c6d6e6f, 0x6d6e6f70, 0x6e6f7071, 0x80000000, 0);
+        S0, S1, S2, S3, S4, S5, S6, S7 <== sha256_compress(S0, S1, S2, S3, S4, S5, S6, S7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x000001c0);
+        assert_eq S0, 0x248d6a61;
+        assert_eq S1, 0xd20638b8;
+        assert_eq S2, 0xe5c02693;
+        assert_eq S3, 0x0c3e6039;
+        assert_eq S4, 0xa33ce459;
+        assert_eq S5, 0x64ff2167;
+        assert_eq S6, 0xf6ecedd4;
+        assert_eq S7, 0x19db06c1;
+
+        return;
+    }
+}